@@ -0,0 +1,97 @@
+//! What every front-end (GUI, CLI, ...) does the same way: loading the
+//! config, getting a device ready, finding and applying profiles. Front-ends
+//! should only add how they show things and ask the user.
+
+use std::thread;
+use std::time::Duration;
+
+use librazer::cfg::Config;
+use librazer::common::ProtocolOptions;
+use librazer::device::{self, RazerDevice, RazerMouse, UsbDevice};
+use librazer::error::{Context, Result, USBError, USBResult};
+use librazer::profile::Profile;
+
+/// How many times a command is sent when the device is busy or doesn't
+/// answer in time
+pub const ATTEMPTS: u32 = 3;
+
+/// How long to wait before sending again, times the attempt
+const RETRY_BACKOFF: Duration = Duration::from_millis(50);
+
+/// Load the config; the error says which file failed
+pub fn load_config() -> Result<Config> {
+    let path = Config::path()
+        .map_or(String::from("<unknown path>"), |p| p.display().to_string());
+    Config::load().context(format!("failed to load configuration from {}", path))
+}
+
+/// Talk to `dav2` the way the config says: with its transaction id, timeouts
+/// and the receiver wait fixed there or learned last time. Returns the
+/// serial, to `remember()` by.
+pub fn prepare<D: RazerDevice + ?Sized>(dav2: &D, cfg: &Config) -> Option<String> {
+    dav2.set_tx_id(cfg.tx_id(dav2.vid(), dav2.pid()));
+    let fixed_wait = cfg.receiver_wait_ms.map(Duration::from_millis);
+    dav2.set_protocol_options(&ProtocolOptions {
+        receiver_wait: fixed_wait.unwrap_or(dav2.receiver_wait().get()),
+        adaptive_wait: fixed_wait.is_none(),
+        transfer_timeout: Duration::from_millis(cfg.transfer_timeout_ms),
+    });
+    let serial = dav2.get_serial().ok()?;
+    if let (None, Some(wait)) = (fixed_wait, cfg.receiver_wait(&serial)) {
+        dav2.receiver_wait().set(wait);
+    }
+    Some(serial)
+}
+
+/// Keep what was learned about `dav2` for next time; see `prepare()`
+pub fn remember<D: RazerDevice + ?Sized>(dav2: &D, serial: &str, cfg: &mut Config) {
+    if dav2.receiver_wait().is_adaptive() {
+        cfg.set_receiver_wait(serial, dav2.receiver_wait().get());
+    }
+}
+
+/// Which of `devices` has this serial. Each is opened to ask.
+pub fn find_by_serial(devices: &[UsbDevice], serial: &str) -> Option<usize> {
+    devices.iter().position(|dev| {
+        device::open(dev).and_then(|dav2| dav2.get_serial())
+            .is_ok_and(|s| s == serial)
+    })
+}
+
+/// Call `f` until it succeeds, fails for good, or `ATTEMPTS` run out. Only
+/// a busy device or a timeout are worth another try.
+pub fn with_retries<T, F>(mut f: F) -> USBResult<T>
+where
+    F: FnMut() -> USBResult<T>,
+{
+    let mut attempt = 1;
+    loop {
+        match f() {
+            Err(USBError::DeviceBusy | USBError::CommandTimeout) if attempt < ATTEMPTS => {
+                thread::sleep(RETRY_BACKOFF * attempt);
+                attempt += 1;
+            },
+            res => return res,
+        }
+    }
+}
+
+/// The saved profile by that name (case-insensitive), or else the profile
+/// file at that path
+pub fn find_profile(cfg: &Config, name: &str) -> Result<Profile> {
+    let saved = cfg.profile.as_ref()
+        .filter(|p| p.name.as_deref().is_some_and(|n| n.eq_ignore_ascii_case(name)));
+    match saved {
+        Some(profile) => Ok(profile.clone()),
+        None => Profile::load(name.as_ref())
+            .context(format!("no profile named '{}'", name)),
+    }
+}
+
+/// Apply `profile` and take its colors into `cfg`, since the device can't
+/// report them
+pub fn apply_profile(dav2: &dyn RazerMouse, profile: &Profile, cfg: &mut Config) -> USBResult<()> {
+    with_retries(|| profile.apply(dav2))?;
+    cfg.set_colors_from(profile, &dav2.zones());
+    Ok(())
+}
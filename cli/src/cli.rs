@@ -1,26 +1,701 @@
+use std::backtrace::Backtrace;
+use std::panic;
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+use std::sync::{Arc, Mutex};
 use rgb::RGB8;
 use librazer::cfg::Config;
-use librazer::common::rgb_from_hex;
-use librazer::device::{DeathAdderV2, RazerMouse};
+use librazer::diagnostics;
+use librazer::common::{rgb_from_str, Breathing, ChargingState, Led, PollingRate,
+    IDLE_TIMES, REACTIVE_SPEEDS};
+use librazer::device::{DeathAdderV2, RazerDevice, RazerMouse, dongles, enumerate};
+use librazer::animation::Animation;
+use librazer::buttons::{Button, ButtonFunction, Layer};
+use librazer::macros::Macro;
+use librazer::commands::RawCommand;
+use librazer::effects::{self, Blink, EffectEngine, FADE_DURATION};
+use librazer::error::{Context, DriverProblem, Error, Result, USBResult};
+use librazer::hooks::{RecentTransactions, Tracer};
+use librazer::hotplug::{watch_devices, DeviceEvent};
+use librazer::profile::Profile;
+use librazer::status::Status;
+use librazer::watchdog::Watchdog;
+use librazer::transport::replay_from_env;
+
+/// `config path|show|set <key> <value>`
+fn config_command(args: &[String]) -> Result<()> {
+    let path = Config::path().context("failed to locate configuration")?;
+    let load = || Config::load()
+        .context(format!("failed to load configuration from {}", path.display()));
+
+    match args.iter().map(String::as_str).collect::<Vec<&str>>()[..] {
+        ["path"] => println!("{}", path.display()),
+        ["show"] => {
+            // make sure it exists, then show it as is, comments and all
+            load()?;
+            let contents = std::fs::read_to_string(&path)
+                .context(format!("failed to read {}", path.display()))?;
+            print!("{}", contents);
+        },
+        ["set", key, value] => {
+            let mut cfg = load()?;
+            cfg.set(key, value)
+                .context(format!("failed to set '{}' (known keys: {})",
+                key, Config::KEYS.join(", ")))?;
+            cfg.save().context("failed to save config")?;
+        },
+        _ => panic!("usage: config path | config show | config set <key> <value>"),
+    }
+    Ok(())
+}
+
+/// On a panic after the device was opened, also write a crash log with the
+/// device and its last transactions. Earlier panics are usage errors.
+fn install_panic_hook(device: Arc<Mutex<String>>, transactions: Arc<RecentTransactions>) {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |panic_info| {
+        default_hook(panic_info);
+
+        let device = device.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        if device.is_empty() {
+            return;
+        }
+
+        let path = Config::crash_log_path()
+            .unwrap_or_else(|_| std::env::temp_dir().join("deathadder_v2-crash.log"));
+        let log = format!("{} {}\n{}\ndevice: {}\n\nlast transactions:\n{}\n\n\
+            backtrace:\n{}\n",
+            env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"), panic_info, device,
+            transactions.lines().join("\n"), Backtrace::force_capture());
+        match std::fs::write(&path, log) {
+            Ok(_) => eprintln!("crash log written to {}", path.display()),
+            Err(e) => eprintln!("failed to write crash log to {}: {}", path.display(), e),
+        }
+    }));
+}
+
+/// What to do about a device we can see but can't open
+fn driver_hint(problem: DriverProblem) -> &'static str {
+    match problem {
+        DriverProblem::NotBound => "install WinUSB for \"Razer DeathAdder V2 \
+            (Interface 3)\" with Zadig (https://zadig.akeo.ie/)",
+        DriverProblem::Access => "close Synapse or any other program using the \
+            device and try again",
+    }
+}
+
+/// Apply the profile to every connected supported device. Failures are
+/// reported per device and don't stop the rest.
+fn apply_all(profile: &Profile) {
+    let devices = enumerate().unwrap_or_else(|e| panic!("failed to list devices: {}", e));
+    if devices.is_empty() {
+        panic!("no supported devices found");
+    }
+
+    let mut failed = 0;
+    for device in &devices {
+        // other devices only take the lighting
+        let res = match device.as_mouse() {
+            Some(mouse) => profile.apply(mouse),
+            None => profile.apply_lighting(device.as_ref()),
+        };
+        match res {
+            Ok(_) => println!("{}: ok", device),
+            Err(e) => {
+                eprintln!("{}: {}", device, e);
+                failed += 1;
+            },
+        }
+    }
+    if failed > 0 {
+        panic!("failed to apply to {} out of {} devices", failed, devices.len());
+    }
+}
+
+/// Known programs that change mouse settings, and are running now
+fn running_suspects() -> Vec<&'static str> {
+    const SUSPECTS: [(&str, &str); 4] = [
+        ("razer synapse", "Razer Synapse"),
+        ("razercentral", "Razer Synapse"),
+        ("openrgb", "OpenRGB"),
+        ("signalrgb", "SignalRGB"),
+    ];
+
+    let output = if cfg!(windows) {
+        Command::new("tasklist").output()
+    } else {
+        Command::new("ps").arg("-A").output()
+    };
+    let processes = match output {
+        Ok(output) => String::from_utf8_lossy(&output.stdout).to_lowercase(),
+        Err(_) => return Vec::new(),
+    };
+
+    let mut found: Vec<&str> = Vec::new();
+    for (process, program) in SUSPECTS {
+        if processes.contains(process) && !found.contains(&program) {
+            found.push(program);
+        }
+    }
+    found
+}
+
+/// Whether `--trace` was given: print every report to stderr
+static TRACE: AtomicBool = AtomicBool::new(false);
+
+/// Open the device (or the session to replay), registering it for crash logs
+fn open_device(
+    crash_device: &Mutex<String>,
+    transactions: &Arc<RecentTransactions>
+) -> DeathAdderV2 {
+    let dav2 = match replay_from_env() {
+        Some(replay) => DeathAdderV2::with_transport(
+            Box::new(replay.expect("failed to load session to replay"))),
+        None => DeathAdderV2::new().unwrap_or_else(|e| match e.driver_problem() {
+            Some(problem) => panic!("failed to open device: {}\n{}; {}",
+                e, problem, driver_hint(problem)),
+            None => panic!("failed to open device: {}", e),
+        }),
+    };
+    dav2.set_tx_id(Config::load().ok().and_then(|cfg| cfg.tx_id(dav2.vid(), dav2.pid())));
+    dav2.hooks().register(Arc::clone(transactions));
+    if TRACE.load(Ordering::Relaxed) {
+        dav2.hooks().register(Tracer::new(|line| eprintln!("{}", line)));
+    }
+    *crash_device.lock().unwrap() =
+        format!("{:04x}:{:04x} {}", dav2.vid(), dav2.pid(), dav2.name());
+    dav2
+}
+
+/// The LEDs named in `names`, or all RGB ones if none are
+fn rgb_leds(dav2: &DeathAdderV2, names: &[String]) -> Result<Vec<Led>> {
+    if names.is_empty() {
+        let caps = dav2.capabilities();
+        return Ok(dav2.zones().into_iter().filter(|&led| caps.has_rgb(led)).collect());
+    }
+    names.iter().map(|name| name.parse::<Led>().map_err(Error::from)).collect()
+}
+
+/// `breathe random|<color> [<color>] [<LED>...]`: saved on the device
+fn breathe_command(dav2: &DeathAdderV2, args: &[String]) -> Result<()> {
+    let usage = "usage: breathe random|<color> [<color>] [<LED>...]";
+    let first = args.first().expect(usage);
+    let (breathing, rest) = if first.eq_ignore_ascii_case("random") {
+        (Breathing::Random, &args[1..])
+    } else {
+        let color = rgb_from_str(first).context("bad color")?;
+        match args.get(1).map(|arg| rgb_from_str(arg)) {
+            Some(Ok(second)) => (Breathing::Dual(color, second), &args[2..]),
+            _ => (Breathing::Single(color), &args[1..]),
+        }
+    };
+    let leds = rgb_leds(dav2, rest)?;
+    for led in leds {
+        dav2.set_led_breathing(led, breathing)
+            .context(format!("failed to set breathing on {}", led.name()))?;
+    }
+    Ok(())
+}
+
+/// `bind [--hypershift] <button> [<function>]`: saved on the device; without
+/// a function, show what the button does. With `--hypershift`, the binding
+/// used while the button bound to `hypershift` is held
+fn bind_command(dav2: &DeathAdderV2, args: &[String]) -> Result<()> {
+    let usage = "usage: bind [--hypershift] <button> \
+        [disabled|mouse:<button>|key:<keys>|dpi-up|dpi-down|macro:<id>|hypershift]";
+    let (layer, args) = match args.first().map(String::as_str) {
+        Some("--hypershift") => (Layer::Hypershift, &args[1..]),
+        _ => (Layer::Normal, args),
+    };
+    let button = args.first().expect(usage).parse::<Button>()?;
+    if let Some(function) = args.get(1) {
+        let function = function.parse::<ButtonFunction>()?;
+        dav2.set_button_on(layer, button, function)
+            .context(format!("failed to bind the {} button", button))?;
+    }
+    let prefix = if layer == Layer::Hypershift { "hypershift " } else { "" };
+    match dav2.get_button_on(layer, button)
+        .context(format!("failed to read the {} button", button))? {
+        Some(function) => println!("{}{}: {}", prefix, button, function),
+        None => println!("{}{}: set up by other software", prefix, button),
+    }
+    Ok(())
+}
+
+/// `macro create <id> <keys or delays>...` or `macro delete <id>`
+fn macro_command(dav2: &DeathAdderV2, args: &[String]) -> Result<()> {
+    let usage = "usage: macro create <id> <keys or delays>... | macro delete <id>";
+    let id = args.get(1).expect(usage).parse::<u16>()
+        .map_err(|_| Error::other(format!("'{}' is not a macro id (0-65535)", args[1])))?;
+    match args[0].as_str() {
+        "create" => {
+            let m = args[2..].join(" ").parse::<Macro>()?;
+            dav2.create_macro(id, &m).context(format!("failed to store macro {}", id))?;
+            println!("stored macro {} ({} steps); bind it with `bind <button> macro:{}`",
+                id, m.steps().len(), id);
+        },
+        "delete" => dav2.delete_macro(id).context(format!("failed to delete macro {}", id))?,
+        _ => panic!("{}", usage),
+    }
+    Ok(())
+}
+
+/// `spectrum [<LED>...]`: saved on the device
+fn spectrum_command(dav2: &DeathAdderV2, args: &[String]) -> Result<()> {
+    let leds = rgb_leds(dav2, args)?;
+    for led in leds {
+        dav2.set_spectrum(led)
+            .context(format!("failed to set spectrum cycling on {}", led.name()))?;
+    }
+    Ok(())
+}
+
+/// `reactive <color> [<speed 1-4>] [<LED>...]`: saved on the device
+fn reactive_command(dav2: &DeathAdderV2, args: &[String]) -> Result<()> {
+    let usage = "usage: reactive <color> [<speed 1-4>] [<LED>...]";
+    let color = rgb_from_str(args.first().expect(usage)).context("bad color")?;
+    let (speed, rest) = match args.get(1).and_then(|arg| arg.parse::<u8>().ok()) {
+        Some(speed) if REACTIVE_SPEEDS.contains(&speed) => (speed, &args[2..]),
+        Some(speed) => return Err(Error::other(format!("bad speed {}; {}", speed, usage))),
+        None => (*REACTIVE_SPEEDS.start(), &args[1..]),
+    };
+    let leds = rgb_leds(dav2, rest)?;
+    for led in leds {
+        dav2.set_reactive(led, color, speed)
+            .context(format!("failed to set reactive lighting on {}", led.name()))?;
+    }
+    Ok(())
+}
+
+/// `blink <color> [<Hz> [<duty %>]] [<LED>...]`, until interrupted
+fn blink_command(dav2: &DeathAdderV2, args: &[String]) -> Result<()> {
+    let usage = "usage: blink <color> [<Hz> [<duty %>]] [<LED>...]";
+    let color = rgb_from_str(args.first().expect(usage)).context("bad color")?;
+    // numbers first, then LED names
+    let numbers: Vec<f32> = args[1..].iter().map_while(|a| a.parse().ok()).collect();
+    let frequency = numbers.first().copied().unwrap_or(2.0);
+    let duty_cycle = numbers.get(1).copied().unwrap_or(50.0) / 100.0;
+    let leds = rgb_leds(dav2, &args[1 + numbers.len()..])?;
+
+    let mut engine = EffectEngine::new(Duration::from_millis(20));
+    for led in leds {
+        engine.set_effect(led, Blink::new(color, frequency, duty_cycle));
+    }
+    println!("blinking at {} Hz; press Ctrl+C to stop", frequency);
+    engine.run(dav2, &AtomicBool::new(false)).context("failed to blink")?;
+    Ok(())
+}
+
+/// `animate <file>`, until interrupted, or until it ends if it doesn't loop
+fn animate_command(dav2: &DeathAdderV2, args: &[String]) -> Result<()> {
+    let path = args.first().expect("usage: animate <file>");
+    let animation = Animation::load(path.as_ref())
+        .context(format!("failed to load {}", path))?;
+    let (looping, duration) = (animation.looping, animation.duration());
+
+    let mut engine = EffectEngine::new(Duration::from_millis(20));
+    engine.play(animation, dav2);
+    if engine.is_empty() {
+        return Err(Error::other(format!("{} sets no zone {} has", path, dav2.name())));
+    }
+
+    let stop = AtomicBool::new(false);
+    if looping {
+        println!("playing {}; press Ctrl+C to stop", path);
+        engine.run(dav2, &stop).context("failed to play animation")?;
+    } else {
+        thread::scope(|s| {
+            s.spawn(|| {
+                thread::sleep(duration);
+                stop.store(true, Ordering::Relaxed);
+            });
+            engine.run(dav2, &stop)
+        }).context("failed to play animation")?;
+        // the last frame may have been skipped
+        engine.render(dav2, duration).context("failed to play animation")?;
+    }
+    Ok(())
+}
+
+/// `snapshot`: the device settings as JSON on stdout, for `restore`. Colors
+/// can't be read from the device, so they're the last applied, from the config.
+fn snapshot_command(dav2: &DeathAdderV2) -> Result<()> {
+    let mut profile = Profile::snapshot(dav2).context("failed to read device settings")?;
+    if let Ok(cfg) = Config::load() {
+        let scroll_color = if cfg.same_color { cfg.logo_color } else { cfg.scroll_color };
+        for led in dav2.zones() {
+            let color = match led {
+                Led::Logo => Some(cfg.logo_color),
+                Led::ScrollWheel => Some(scroll_color),
+                _ => cfg.zone_color(led),
+            };
+            if let Some(color) = color {
+                profile.set_color(led, color);
+            }
+        }
+    }
+    println!("{}", profile.to_json()?);
+    Ok(())
+}
+
+/// `restore <file or saved profile>`: apply a `snapshot` or the saved profile,
+/// and remember its colors
+fn restore_command(dav2: &DeathAdderV2, args: &[String]) -> Result<()> {
+    let name = args.first().expect("usage: restore <file or saved profile name>");
+    let mut cfg = app_core::load_config()?;
+    let profile = app_core::find_profile(&cfg, name)?;
+    app_core::apply_profile(dav2, &profile, &mut cfg).context("failed to apply settings")?;
+    cfg.save().context("failed to save configuration")?;
+    Ok(())
+}
+
+/// `probe-tx-id`: find a transaction id the device answers to, and keep
+/// using it if it isn't the default
+fn probe_tx_id_command(dav2: &DeathAdderV2) -> Result<()> {
+    let tx_id = dav2.probe_tx_id().context("the device answers to none of the known ids")?;
+    println!("{} answers to transaction id {:#04x}", dav2, tx_id);
+
+    let mut cfg = Config::load().context("failed to load configuration")?;
+    let tx_id = Some(tx_id).filter(|&tx_id| tx_id != dav2.default_tx_id());
+    cfg.set_tx_id(dav2.vid(), dav2.pid(), tx_id);
+    cfg.save().context("failed to save configuration")?;
+    Ok(())
+}
+
+/// `raw <class> <id> [<arg>...]`: all in hex; getters take zeros where the
+/// response goes
+fn raw_command(dav2: &DeathAdderV2, args: &[String]) -> Result<()> {
+    let usage = "usage: raw <class> <id> [<arg>...] (hex bytes)";
+    if args.len() < 2 {
+        panic!("{}", usage);
+    }
+    let bytes = args.iter()
+        .map(|arg| u8::from_str_radix(arg.trim_start_matches("0x"), 16)
+            .map_err(|_| Error::other(format!("'{}' is not a hex byte", arg))))
+        .collect::<Result<Vec<u8>>>()?;
+    let command = RawCommand::new(bytes[0], bytes[1], &bytes[2..])?;
+    let response = dav2.send_raw(&command)
+        .context(format!("{} failed", command.command()))?;
+    let hex: Vec<String> = response.args().iter().map(|b| format!("{:02x}", b)).collect();
+    println!("{}", hex.join(" "));
+    Ok(())
+}
+
+/// `--idle-time [<secs>]`: show, or set, how long the first wireless mouse
+/// waits before going to sleep
+fn idle_time_command(value: Option<&String>) -> Result<()> {
+    let devices = enumerate().context("failed to list devices")?;
+    let mouse = devices.iter()
+        .find(|device| device.as_mouse().is_some() && device.capabilities().battery)
+        .ok_or_else(|| Error::other("no wireless mouse found"))?;
+    if let Some(value) = value {
+        let secs = value.parse::<u64>()
+            .map_err(|_| Error::other(format!("'{}' is not a number of seconds", value)))?;
+        let idle_time = Duration::from_secs(secs);
+        if !IDLE_TIMES.contains(&idle_time) {
+            return Err(Error::other(format!("the idle time must be {}-{} seconds",
+                IDLE_TIMES.start().as_secs(), IDLE_TIMES.end().as_secs())));
+        }
+        mouse.set_idle_time(idle_time).context("failed to set idle time")?;
+    }
+    let idle_time = mouse.get_idle_time().context("failed to get idle time")?;
+    println!("{} sleeps after {} s idle", mouse, idle_time.as_secs());
+    Ok(())
+}
+
+/// `pairing`: print the mouse each wireless dongle is paired with
+fn pairing_command() -> Result<()> {
+    let dongles = dongles().context("failed to list devices")?;
+    if dongles.is_empty() {
+        return Err(Error::other("no wireless dongle found"));
+    }
+    for dongle in dongles {
+        let pairing = dongle.get_pairing().context("failed to get pairing")?;
+        println!("{} is paired with 0x{:04x}, {}", dongle, pairing.pid,
+            if pairing.connected { "connected" } else { "disconnected" });
+    }
+    Ok(())
+}
+
+/// `argb <channel> <color> | size [<LEDs>]`: color the strip on an ARGB
+/// channel of the first device that has them, or show or set its length
+fn argb_command(args: &[String]) -> Result<()> {
+    let usage = || Error::other("usage: argb <channel> <color> | argb <channel> size [<LEDs>]");
+    let channel = args.first().and_then(|arg| arg.parse::<u8>().ok()).ok_or_else(usage)?;
+    let devices = enumerate().context("failed to list devices")?;
+    let device = devices.iter()
+        .find_map(|device| device.as_argb())
+        .ok_or_else(|| Error::other("no device with ARGB channels found"))?;
+    match (args.get(1).map(String::as_str), args.get(2)) {
+        (Some("size"), Some(size)) => {
+            let size = size.parse::<u8>()
+                .map_err(|_| Error::other(format!("'{}' is not a number of LEDs", size)))?;
+            device.set_channel_size(channel, size).context("failed to set channel size")?;
+        },
+        (Some("size"), None) => {},
+        (Some(color), None) => {
+            let color = rgb_from_str(color).context("bad color")?;
+            return device.set_channel_color(channel, color).context("failed to set color");
+        },
+        _ => return Err(usage()),
+    }
+    let size = device.get_channel_size(channel).context("failed to get channel size")?;
+    println!("channel {} of {} has {} LEDs", channel, device, size);
+    Ok(())
+}
+
+/// Write the current DPI stages and DPI back with `LedStorage::VarStore`
+fn persist_dpi(dav2: &DeathAdderV2) -> USBResult<()> {
+    let (stages, current) = dav2.get_dpi_stages()?;
+    if !stages.is_empty() {
+        dav2.set_dpi_stages_persistent(&stages, current)?;
+    }
+    let (dpi_x, dpi_y) = dav2.get_dpi()?;
+    dav2.set_dpi_persistent(dpi_x, dpi_y)
+}
+
+/// `monitor`: print Razer devices as they come and go, until interrupted
+fn monitor_command() -> Result<()> {
+    let _watcher = watch_devices(|event| match event {
+        DeviceEvent::Connected(info) if info.is_supported() => println!("connected {}", info),
+        DeviceEvent::Connected(info) => println!("connected {} (unsupported)", info),
+        DeviceEvent::Disconnected(info) => println!("disconnected {}", info),
+    }).context("failed to watch for devices")?;
+    println!("watching for Razer devices; press Ctrl+C to stop");
+    loop {
+        thread::park();
+    }
+}
+
+/// How often `dock` checks on the mouse battery
+const DOCK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// `dock [<charging color> [<charged color> [<idle color>]]]`, until
+/// interrupted: light the docks after the charging state of the first
+/// wireless mouse. Previews only, so the dock's flash isn't written to every
+/// time the state changes.
+fn dock_command(args: &[String]) -> Result<()> {
+    let color = |i: usize, default: RGB8| match args.get(i) {
+        Some(arg) => rgb_from_str(arg).context("bad color"),
+        None => Ok(default),
+    };
+    let charging = color(0, RGB8::new(0xFF, 0x80, 0x00))?;
+    let charged = color(1, RGB8::new(0x00, 0xFF, 0x00))?;
+    let idle = color(2, RGB8::new(0xFF, 0xFF, 0xFF))?;
+
+    let devices = enumerate().context("failed to list devices")?;
+    let (docks, others): (Vec<_>, Vec<_>) = devices.iter()
+        .partition(|device| device.capabilities().dock);
+    let mouse = others.into_iter()
+        .find(|device| device.capabilities().battery)
+        .ok_or_else(|| Error::other("no wireless mouse found"))?;
+    if docks.is_empty() {
+        return Err(Error::other("no dock found"));
+    }
+
+    println!("following {} on {} dock(s); press Ctrl+C to stop", mouse, docks.len());
+    let mut shown = None;
+    loop {
+        let color = match mouse.get_charging_state().context("failed to read battery")? {
+            Some(ChargingState::FullyCharged) => charged,
+            Some(_) => charging,
+            None => idle,
+        };
+        if shown != Some(color) {
+            for dock in &docks {
+                let caps = dock.capabilities();
+                for led in dock.zones().into_iter().filter(|&led| caps.has_rgb(led)) {
+                    dock.preview_led_color(led, color)
+                        .context(format!("failed to light {}", dock))?;
+                }
+            }
+            shown = Some(color);
+        }
+        thread::sleep(DOCK_INTERVAL);
+    }
+}
 
 fn main() {
-    let args: Vec<String> = std::env::args().collect();
+    let crash_device = Arc::new(Mutex::new(String::new()));
+    let transactions = Arc::new(RecentTransactions::new(32));
+    install_panic_hook(Arc::clone(&crash_device), Arc::clone(&transactions));
+
+    let mut args: Vec<String> = std::env::args().collect();
+
+    if let Some(i) = args.iter().position(|a| a == "--trace") {
+        args.remove(i);
+        TRACE.store(true, Ordering::Relaxed);
+    }
+
+    if args.get(1).map(String::as_str) == Some("config") {
+        return config_command(&args[2..]).unwrap_or_else(|e| panic!("{}", e));
+    }
+
+    // hammer the device with reads and report how it coped
+    if let Some(i) = args.iter().position(|a| a == "--stress") {
+        let secs = args.get(i + 1)
+            .and_then(|value| value.parse::<u64>().ok())
+            .filter(|&secs| secs > 0)
+            .unwrap_or_else(|| panic!("--stress needs a duration in seconds"));
+        let dav2 = open_device(&crash_device, &transactions);
+        println!("stressing {} for {} s", dav2, secs);
+        print!("{}", diagnostics::stress(&dav2, Duration::from_secs(secs)));
+        return;
+    }
+
+    if let Some(i) = args.iter().position(|a| a == "--idle-time") {
+        return idle_time_command(args.get(i + 1)).unwrap_or_else(|e| panic!("{}", e));
+    }
+
+    if args.get(1).map(String::as_str) == Some("breathe") {
+        let dav2 = open_device(&crash_device, &transactions);
+        return breathe_command(&dav2, &args[2..]).unwrap_or_else(|e| panic!("{}", e));
+    }
+
+    if args.get(1).map(String::as_str) == Some("bind") {
+        let dav2 = open_device(&crash_device, &transactions);
+        return bind_command(&dav2, &args[2..]).unwrap_or_else(|e| panic!("{}", e));
+    }
+
+    if args.get(1).map(String::as_str) == Some("raw") {
+        let dav2 = open_device(&crash_device, &transactions);
+        return raw_command(&dav2, &args[2..]).unwrap_or_else(|e| panic!("{}", e));
+    }
+
+    if args.get(1).map(String::as_str) == Some("macro") {
+        let dav2 = open_device(&crash_device, &transactions);
+        return macro_command(&dav2, &args[2..]).unwrap_or_else(|e| panic!("{}", e));
+    }
+
+    if args.get(1).map(String::as_str) == Some("spectrum") {
+        let dav2 = open_device(&crash_device, &transactions);
+        return spectrum_command(&dav2, &args[2..]).unwrap_or_else(|e| panic!("{}", e));
+    }
+
+    if args.get(1).map(String::as_str) == Some("reactive") {
+        let dav2 = open_device(&crash_device, &transactions);
+        return reactive_command(&dav2, &args[2..]).unwrap_or_else(|e| panic!("{}", e));
+    }
+
+    if args.get(1).map(String::as_str) == Some("blink") {
+        let dav2 = open_device(&crash_device, &transactions);
+        return blink_command(&dav2, &args[2..]).unwrap_or_else(|e| panic!("{}", e));
+    }
+
+    if args.get(1).map(String::as_str) == Some("animate") {
+        let dav2 = open_device(&crash_device, &transactions);
+        return animate_command(&dav2, &args[2..]).unwrap_or_else(|e| panic!("{}", e));
+    }
+
+    if args.get(1).map(String::as_str) == Some("snapshot") {
+        let dav2 = open_device(&crash_device, &transactions);
+        return snapshot_command(&dav2).unwrap_or_else(|e| panic!("{}", e));
+    }
+
+    if args.get(1).map(String::as_str) == Some("restore") {
+        let dav2 = open_device(&crash_device, &transactions);
+        return restore_command(&dav2, &args[2..]).unwrap_or_else(|e| panic!("{}", e));
+    }
+
+    if args.get(1).map(String::as_str) == Some("probe-tx-id") {
+        let dav2 = open_device(&crash_device, &transactions);
+        return probe_tx_id_command(&dav2).unwrap_or_else(|e| panic!("{}", e));
+    }
+
+    if args.get(1).map(String::as_str) == Some("monitor") {
+        return monitor_command().unwrap_or_else(|e| panic!("{}", e));
+    }
+
+    if args.get(1).map(String::as_str) == Some("pairing") {
+        return pairing_command().unwrap_or_else(|e| panic!("{}", e));
+    }
+
+    if args.get(1).map(String::as_str) == Some("argb") {
+        return argb_command(&args[2..]).unwrap_or_else(|e| panic!("{}", e));
+    }
+
+    if args.get(1).map(String::as_str) == Some("dock") {
+        return dock_command(&args[2..]).unwrap_or_else(|e| panic!("{}", e));
+    }
+
+    if args.get(1).map(String::as_str) == Some("stock-lighting") {
+        let dav2 = open_device(&crash_device, &transactions);
+        return dav2.restore_default_lighting()
+            .unwrap_or_else(|e| panic!("failed to restore lighting: {}", e));
+    }
+
+    let poll_rate = match args.iter().position(|a| a == "--poll") {
+        Some(i) => {
+            let value = args.get(i + 1)
+                .unwrap_or_else(|| panic!("--poll needs a rate (125, 250, 500 or 1000)"));
+            let rate = value.parse::<PollingRate>().unwrap_or_else(|e| panic!("{}", e));
+            args.drain(i..i + 2);
+            Some(rate)
+        },
+        None => None,
+    };
+    // switch to this (1-based) DPI stage
+    let stage = match args.iter().position(|a| a == "--stage") {
+        Some(i) => {
+            let stage = args.get(i + 1)
+                .and_then(|value| value.parse::<u8>().ok())
+                .filter(|&stage| stage > 0)
+                .unwrap_or_else(|| panic!("--stage needs a stage number (1, 2, ...)"));
+            args.drain(i..i + 2);
+            Some(stage - 1)
+        },
+        None => None,
+    };
+    // keep the settings in place after applying them, checking every so often
+    let watch = match args.iter().position(|a| a == "--watch") {
+        Some(i) => {
+            let secs = args.get(i + 1)
+                .and_then(|value| value.parse::<u64>().ok())
+                .filter(|&secs| secs > 0)
+                .unwrap_or_else(|| panic!("--watch needs an interval in seconds"));
+            args.drain(i..i + 2);
+            Some(Duration::from_secs(secs))
+        },
+        None => None,
+    };
+    // apply to every connected supported device instead of the first DAv2
+    let all = match args.iter().position(|a| a == "--all") {
+        Some(i) => { args.remove(i); true },
+        None => false,
+    };
+    // apply the saved profile (within its scope) instead of just the colors
+    let use_profile = match args.iter().position(|a| a == "--profile") {
+        Some(i) => { args.remove(i); true },
+        None => false,
+    };
+    // also write the DPI settings to the mouse's memory, so they survive a replug
+    let persist = match args.iter().position(|a| a == "--persist") {
+        Some(i) => { args.remove(i); true },
+        None => false,
+    };
+    // only the polling rate, stage or persistence was asked for; leave the
+    // colors alone
+    let poll_only = (poll_rate.is_some() || stage.is_some() || persist) && args.len() <= 1;
 
     let parse_arg = |input: &str| -> RGB8 {
-        match rgb_from_hex(input) {
+        match rgb_from_str(input) {
             Ok(rgb) => rgb,
             Err(e) => panic!("argument '{}' should be in the \
                 form [0x/#]RGB[h] or [0x/#]RRGGBB[h] where R, G, and B are hex \
-                digits: {}", input, e)
+                digits, a white (warm, neutral or cool) or a color temperature \
+                (e.g. 4000K): {}", input, e)
         }
     };
 
-    let cfgopt = Config::load();
+    let cfgopt = app_core::load_config().map_err(|e| eprintln!("{}", e)).ok();
 
     let (logo_color, scroll_color) = match args.len() {
         ..=1 => {
-            match cfgopt {
+            match &cfgopt {
                 Some(cfg) => (cfg.logo_color, cfg.scroll_color),
+                None if poll_only => Default::default(),
                 None => panic!("failed to load configuration; please specify \
                     arguments manually")
             }
@@ -33,19 +708,100 @@ fn main() {
                 color
             })
         },
-        _ => panic!("usage: {} [(body) color] [wheel color]", args[0])
+        _ => panic!("usage: {} [--trace] [(body) color] [wheel color] [--poll <Hz>] [--stage <n>] [--profile] [--all] [--watch <secs>] [--persist] | --idle-time [<secs>] | breathe ... | bind [--hypershift] <button> [<function>] | macro create|delete <id> ... | spectrum [<LED>...] | reactive ... | blink ... | animate <file> | dock ... | argb <channel> ... | snapshot | restore <file or profile> | raw <class> <id> [<arg>...] | probe-tx-id | pairing | stock-lighting | config ...", args[0])
+    };
+
+    // don't overwrite a config we couldn't read; the user may want to fix it
+    let save_cfg = cfgopt.is_some();
+    let mut cfg = cfgopt.unwrap_or(Default::default());
+
+    let profile = if use_profile {
+        let mut profile = cfg.profile.clone()
+            .unwrap_or_else(|| panic!("there is no saved profile"));
+        profile.poll_rate = poll_rate.or(profile.poll_rate);
+        profile
+    } else {
+        let mut profile = Profile {
+            poll_rate: poll_rate,
+            ..Default::default()
+        };
+        if !poll_only {
+            profile.set_color(Led::Logo, logo_color);
+            profile.set_color(Led::ScrollWheel, scroll_color);
+            // whole-device zones (backlights, strips) take the body color
+            profile.set_color(Led::Backlight, logo_color);
+            profile.set_color(Led::Zero, logo_color);
+        }
+        profile
     };
 
-    let dav2 = DeathAdderV2::new().expect("failed to open device");
+    if all {
+        apply_all(&profile);
+        cfg.logo_color = logo_color;
+        cfg.scroll_color = scroll_color;
+        if save_cfg {
+            _ = cfg.save().map_err(|e| panic!("failed to save config: {}", e));
+        }
+        return;
+    }
 
-    _= dav2.set_logo_color(logo_color)
-        .map_err(|e| panic!("failed to set logo color: {}", e))
-        .and_then(|_| dav2.set_scroll_color(scroll_color))
-        .map_err(|e| panic!("failed to set scroll color: {}", e));
+    let dav2 = open_device(&crash_device, &transactions);
 
-    _ = Config {
-        logo_color: logo_color,
-        scroll_color: scroll_color,
-        ..cfgopt.unwrap_or(Default::default())
-    }.save().map_err(|e| panic!("failed to save config: {}", e));
+    let serial = app_core::prepare(&dav2, &cfg);
+
+    // the colors were last set to what the config says, presumably
+    if !poll_only && !use_profile {
+        let caps = dav2.capabilities();
+        let fades: Vec<(Led, RGB8, RGB8)> = [
+            (Led::Logo, cfg.logo_color, logo_color),
+            (Led::ScrollWheel, cfg.scroll_color, scroll_color),
+        ].into_iter().filter(|&(led, _, _)| caps.has_rgb(led)).collect();
+        _ = effects::fade(&dav2, &fades, FADE_DURATION)
+            .map_err(|e| eprintln!("failed to fade colors: {}", e));
+    }
+
+    _ = app_core::with_retries(|| profile.apply(&dav2))
+        .map_err(|e| panic!("failed to apply settings: {}", e));
+    if let Some(stage) = stage {
+        _ = dav2.set_active_dpi_stage(stage)
+            .map_err(|e| panic!("failed to switch DPI stage: {}", e));
+    }
+    if persist {
+        _ = persist_dpi(&dav2).map_err(|e| panic!("failed to persist DPI: {}", e));
+    }
+
+    #[cfg(feature = "transport-stats")]
+    eprint!("{}", dav2.transport_stats());
+
+    cfg.logo_color = logo_color;
+    cfg.scroll_color = scroll_color;
+    if let Some(serial) = serial {
+        app_core::remember(&dav2, &serial, &mut cfg);
+    }
+    if save_cfg {
+        _ = cfg.save().map_err(|e| panic!("failed to save config: {}", e));
+    }
+
+    if let Some(interval) = watch {
+        let baseline = Profile::snapshot(&dav2)
+            .unwrap_or_else(|e| panic!("failed to read device settings: {}", e));
+        println!("watching {} every {}s; press Ctrl+C to stop", dav2, interval.as_secs());
+        Watchdog::new(&baseline, interval).run(&dav2, &AtomicBool::new(false), |res| {
+            match res {
+                Ok(changed) if changed.is_empty() => {},
+                Ok(changed) => {
+                    let fields: Vec<String> = changed.iter().map(|f| f.to_string()).collect();
+                    let culprits = running_suspects();
+                    println!("restored {} (changed by {})", fields.join(", "),
+                        if culprits.is_empty() { "an unknown program".into() }
+                        else { culprits.join(" or ") });
+                },
+                Err(e) => eprintln!("failed to check device settings: {}", e),
+            }
+            if let Some(path) = &cfg.status_path {
+                _ = Status::read(&dav2, profile.name.as_deref()).write(path).map_err(
+                    |e| eprintln!("failed to write status to {}: {}", path.display(), e));
+            }
+        });
+    }
 }
@@ -0,0 +1,59 @@
+//! Runs `diagnostics::soak()` for hours, logging every failure with a
+//! timestamp, to gather evidence of a flaky hub, cable or receiver.
+//!
+//! `deathadder-soak [--interval <secs>] [--hours <hours>] [--log <file>]`
+
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::time::Duration;
+use librazer::cfg::Config;
+use librazer::device::{DeathAdderV2, RazerDevice};
+use librazer::diagnostics::{self, StressReport};
+
+/// How often a summary is printed
+const SUMMARY_EVERY: Duration = Duration::from_secs(3600);
+
+fn value<T: std::str::FromStr>(args: &[String], flag: &str) -> Option<T> {
+    let i = args.iter().position(|a| a == flag)?;
+    let value = args.get(i + 1).and_then(|v| v.parse().ok());
+    Some(value.unwrap_or_else(|| panic!("bad or missing value for {}", flag)))
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.iter().any(|a| a == "--help" || a == "-h") {
+        println!("usage: {} [--interval <secs>] [--hours <hours>] [--log <file>]", args[0]);
+        return;
+    }
+    let interval = Duration::from_secs_f64(value(&args, "--interval").unwrap_or(10.0));
+    let total = value::<f64>(&args, "--hours").map(|h| Duration::from_secs_f64(h * 3600.0));
+    let mut log: Option<File> = value::<String>(&args, "--log").map(|path| {
+        OpenOptions::new().create(true).append(true).open(&path)
+            .unwrap_or_else(|e| panic!("failed to open {}: {}", path, e))
+    });
+
+    let dav2 = DeathAdderV2::new().unwrap_or_else(|e| panic!("failed to open device: {}", e));
+    dav2.set_tx_id(Config::load().ok().and_then(|cfg| cfg.tx_id(dav2.vid(), dav2.pid())));
+
+    let mut line = |text: String| {
+        println!("{}", text);
+        if let Some(file) = log.as_mut() {
+            // one line at a time, so nothing is lost when interrupted
+            _ = writeln!(file, "{}", text).and_then(|_| file.flush());
+        }
+    };
+    line(format!("soaking {} every {:.1} s {}; press Ctrl+C to stop", dav2,
+        interval.as_secs_f64(), total.map_or("until interrupted".into(),
+            |t| format!("for {:.1} h", t.as_secs_f64() / 3600.0))));
+
+    let done = |elapsed: Duration| total.is_some_and(|t| elapsed >= t);
+    let mut report = StressReport::default();
+    while !done(report.elapsed) {
+        let chunk = total.map_or(SUMMARY_EVERY, |t| SUMMARY_EVERY.min(t - report.elapsed));
+        let hour = diagnostics::soak(&dav2, interval, chunk,
+            |failure| line(failure.to_string()));
+        report.extend(hour);
+        line(format!("after {:.1} h: {}", report.elapsed.as_secs_f64() / 3600.0,
+            report.to_string().trim_end()));
+    }
+}
@@ -0,0 +1,249 @@
+//! Editor for `librazer::animation` files: keyframes on a timeline, a color
+//! per zone per keyframe, and a live preview on the device.
+
+use std::time::{Duration, Instant};
+use eframe::egui;
+use rgb::RGB8;
+use librazer::animation::{Animation, Easing, Keyframe};
+use librazer::common::Led;
+use librazer::device::{DeathAdderV2, RazerDevice};
+use librazer::effects::EffectEngine;
+
+use crate::{color_edit, from_array, to_array};
+
+/// Time between preview frames
+const FRAME_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Width of a second of animation on the timeline
+const PIXELS_PER_SEC: f32 = 120.0;
+
+pub struct AnimationEditor {
+    pub open: bool,
+    animation: Animation,
+    selected: usize,
+    path: String,
+    /// The engine playing the animation and when it started
+    preview: Option<(EffectEngine, Instant)>,
+    /// The animation changed since the preview engine got it
+    dirty: bool,
+    status: String,
+}
+
+impl Default for AnimationEditor {
+    fn default() -> Self {
+        let mut keyframe = Keyframe { duration_ms: 500, ..Default::default() };
+        keyframe.set_color(Led::Logo, RGB8::new(0xFF, 0xFF, 0xFF));
+        keyframe.set_color(Led::ScrollWheel, RGB8::new(0xFF, 0xFF, 0xFF));
+        Self {
+            open: false,
+            animation: Animation { keyframes: vec![keyframe], ..Default::default() },
+            selected: 0,
+            path: String::from("animation.json"),
+            preview: None,
+            dirty: false,
+            status: String::new(),
+        }
+    }
+}
+
+impl AnimationEditor {
+    /// Returns true when a preview stopped, so the caller can put back the
+    /// colors it covered
+    pub fn show(&mut self, ctx: &egui::Context, device: Option<&DeathAdderV2>) -> bool {
+        let mut open = self.open;
+        let mut stopped = false;
+        egui::Window::new("Animation editor").open(&mut open).show(ctx, |ui| {
+            self.file_ui(ui);
+            ui.separator();
+            self.timeline_ui(ui);
+            ui.separator();
+            self.keyframe_ui(ui, device);
+            ui.separator();
+            stopped = self.preview_ui(ui, device);
+            if !self.status.is_empty() {
+                ui.label(self.status.as_str());
+            }
+        });
+        self.open = open;
+
+        // closing the window ends the preview too
+        if !self.open && self.preview.is_some() {
+            self.preview = None;
+            stopped = true;
+        }
+        if let (Some(dav2), true) = (device, self.preview.is_some()) {
+            self.render(dav2);
+            if self.preview.is_some() {
+                ctx.request_repaint_after(FRAME_INTERVAL);
+            } else {
+                stopped = true;
+            }
+        }
+        stopped
+    }
+
+    fn file_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("File");
+            ui.text_edit_singleline(&mut self.path);
+            if ui.button("Load").clicked() {
+                match Animation::load(self.path.as_ref()) {
+                    Ok(animation) => {
+                        self.animation = animation;
+                        self.selected = 0;
+                        self.dirty = true;
+                        self.status = format!("Loaded {}", self.path);
+                    },
+                    Err(e) => self.status = format!("Failed to load {}: {}", self.path, e),
+                }
+            }
+            if ui.button("Save").clicked() {
+                self.status = match self.animation.save(self.path.as_ref()) {
+                    Ok(()) => format!("Saved {}", self.path),
+                    Err(e) => format!("Failed to save {}: {}", self.path, e),
+                };
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("Name");
+            let mut name = self.animation.name.clone().unwrap_or_default();
+            if ui.text_edit_singleline(&mut name).changed() {
+                self.animation.name = Some(name).filter(|name| !name.is_empty());
+            }
+            if ui.checkbox(&mut self.animation.looping, "Loop").changed() {
+                self.dirty = true;
+            }
+        });
+    }
+
+    /// One block per keyframe, as wide as it lasts, in its first zone's color
+    fn timeline_ui(&mut self, ui: &mut egui::Ui) {
+        egui::ScrollArea::horizontal().show(ui, |ui| {
+            ui.horizontal(|ui| {
+                ui.spacing_mut().item_spacing.x = 2.0;
+                for (i, kf) in self.animation.keyframes.iter().enumerate() {
+                    let width = (kf.duration().as_secs_f32() * PIXELS_PER_SEC).max(12.0);
+                    let fill = Led::iter().find_map(|led| kf.color(led)).unwrap_or_default();
+                    let stroke = if i == self.selected {
+                        egui::Stroke::new(2.0, ui.visuals().selection.stroke.color)
+                    } else {
+                        egui::Stroke::NONE
+                    };
+                    let button = egui::Button::new("")
+                        .fill(egui::Color32::from_rgb(fill.r, fill.g, fill.b))
+                        .stroke(stroke)
+                        .min_size(egui::vec2(width, 24.0));
+                    if ui.add(button).on_hover_text(format!("{} ms", kf.duration_ms)).clicked() {
+                        self.selected = i;
+                    }
+                }
+            });
+        });
+
+        ui.horizontal(|ui| {
+            if ui.button("Add keyframe").clicked() {
+                // a copy of the selected one, right after it
+                let copy = self.animation.keyframes.get(self.selected).cloned()
+                    .unwrap_or(Keyframe { duration_ms: 500, ..Default::default() });
+                let at = (self.selected + 1).min(self.animation.keyframes.len());
+                self.animation.keyframes.insert(at, copy);
+                self.selected = at;
+                self.dirty = true;
+            }
+            let can_remove = self.animation.keyframes.len() > 1;
+            if ui.add_enabled(can_remove, egui::Button::new("Remove keyframe")).clicked() {
+                self.animation.keyframes.remove(self.selected);
+                self.selected = self.selected.min(self.animation.keyframes.len() - 1);
+                self.dirty = true;
+            }
+            ui.label(format!("Total: {} ms", self.animation.duration().as_millis()));
+        });
+    }
+
+    fn keyframe_ui(&mut self, ui: &mut egui::Ui, device: Option<&DeathAdderV2>) {
+        // the device's zones, or those the animation uses if there's none
+        let zones = match device {
+            Some(dav2) => {
+                let caps = dav2.capabilities();
+                dav2.zones().into_iter().filter(|&led| caps.has_rgb(led)).collect()
+            },
+            None => self.animation.zones(),
+        };
+        let Some(kf) = self.animation.keyframes.get_mut(self.selected) else { return };
+
+        let mut changed = false;
+        egui::Grid::new("keyframe").num_columns(2).show(ui, |ui| {
+            ui.label("Duration (ms)");
+            changed |= ui.add(egui::DragValue::new(&mut kf.duration_ms)
+                .clamp_range(0..=60_000).speed(10)).changed();
+            ui.end_row();
+
+            ui.label("Easing");
+            egui::ComboBox::from_id_source("easing")
+                .selected_text(kf.easing.name())
+                .show_ui(ui, |ui| {
+                    for easing in Easing::iter() {
+                        changed |= ui.selectable_value(&mut kf.easing, easing, easing.name())
+                            .changed();
+                    }
+                });
+            ui.end_row();
+
+            for led in zones {
+                ui.label(led.name());
+                ui.horizontal(|ui| {
+                    let color = kf.color(led);
+                    let mut set = color.is_some();
+                    if ui.checkbox(&mut set, "").on_hover_text(
+                        "Unset zones keep the previous keyframe's color").changed() {
+                        if set {
+                            kf.set_color(led, RGB8::new(0xFF, 0xFF, 0xFF));
+                        } else {
+                            kf.remove_color(led);
+                        }
+                        changed = true;
+                    }
+                    if let Some(color) = kf.color(led) {
+                        let mut color = to_array(color);
+                        if color_edit(ui, &mut color) {
+                            kf.set_color(led, from_array(color));
+                            changed = true;
+                        }
+                    }
+                });
+                ui.end_row();
+            }
+        });
+        self.dirty |= changed;
+    }
+
+    fn preview_ui(&mut self, ui: &mut egui::Ui, device: Option<&DeathAdderV2>) -> bool {
+        let mut stopped = false;
+        ui.add_enabled_ui(device.is_some(), |ui| {
+            if self.preview.is_none() {
+                if ui.button("Preview on device").clicked() {
+                    self.preview = Some((EffectEngine::new(FRAME_INTERVAL), Instant::now()));
+                    self.dirty = true;
+                }
+            } else if ui.button("Stop preview").clicked() {
+                self.preview = None;
+                stopped = true;
+            }
+        });
+        stopped
+    }
+
+    fn render(&mut self, dav2: &DeathAdderV2) {
+        let Some((engine, start)) = &mut self.preview else { return };
+        if self.dirty {
+            // keeps playing from where it was
+            *engine = EffectEngine::new(FRAME_INTERVAL);
+            engine.play(self.animation.clone(), dav2);
+            self.dirty = false;
+        }
+        if let Err(e) = engine.render(dav2, start.elapsed()) {
+            self.status = format!("Preview failed: {}", e);
+            self.preview = None;
+        }
+    }
+}
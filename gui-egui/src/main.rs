@@ -0,0 +1,582 @@
+//! Cross-platform front-end built on egui. Offers the same settings as the
+//! Windows GUI, minus the things that need Win32 (DPI button events, the
+//! native color dialog).
+
+mod animation_editor;
+mod other_devices;
+
+use std::collections::HashMap;
+use std::time::Duration;
+use eframe::egui;
+use rgb::RGB8;
+use librazer::cfg::Config;
+use librazer::capabilities::Capabilities;
+use librazer::common::{rgb_from_kelvin, Led, LiftOff, PollingRate, ScrollMode, WhitePreset};
+use librazer::device::{DeathAdderV2, RazerDevice, RazerMouse};
+use librazer::effects::{Fader, FADE_DURATION};
+use librazer::error::USBResult;
+use librazer::transport::replay_from_env;
+
+use animation_editor::AnimationEditor;
+use other_devices::OtherDevices;
+
+struct DeathAdderv2App {
+    device: Option<DeathAdderV2>,
+    caps: Capabilities,
+    config: Config,
+    /// Don't overwrite a config we couldn't read; the user may want to fix it
+    save_config: bool,
+    dpi: u16,
+    stages: Vec<u16>,
+    current_stage: usize,
+    poll_rate: Option<PollingRate>,
+    logo_brightness: u8,
+    scroll_brightness: u8,
+    /// Brightness of the other zones
+    zone_brightness: HashMap<Led, u8>,
+    free_spin: bool,
+    smart_reel: bool,
+    liftoff: Option<LiftOff>,
+    battery: Option<String>,
+    status: String,
+    animation_editor: AnimationEditor,
+    other_devices: OtherDevices,
+    /// Color changes fading in; see `fade_color()`
+    fader: Fader,
+}
+
+fn to_array(color: RGB8) -> [u8; 3] {
+    [color.r, color.g, color.b]
+}
+
+fn from_array(color: [u8; 3]) -> RGB8 {
+    RGB8::new(color[0], color[1], color[2])
+}
+
+/// Saved on the device or just previewed, depending on `Config::onboard`
+fn write_color(dav2: &DeathAdderV2, cfg: &Config, led: Led, color: RGB8) -> USBResult<()> {
+    if cfg.onboard {
+        dav2.set_led_color(led, color)
+    } else {
+        dav2.preview_led_color(led, color)
+    }
+}
+
+/// Saved on the device or just previewed, depending on `Config::onboard`
+fn write_brightness(dav2: &DeathAdderV2, cfg: &Config, led: Led, b: u8) -> USBResult<()> {
+    if cfg.onboard {
+        dav2.set_led_brightness(led, b)
+    } else {
+        dav2.preview_led_brightness(led, b)
+    }
+}
+
+/// The color button, plus a menu of whites that don't come out blue-tinted
+/// on the LEDs; returns whether the color changed
+fn color_edit(ui: &mut egui::Ui, color: &mut [u8; 3]) -> bool {
+    let mut changed = ui.color_edit_button_srgb(color).changed();
+    ui.menu_button("White", |ui| {
+        for preset in WhitePreset::iter() {
+            if ui.button(preset.name()).clicked() {
+                *color = to_array(preset.rgb());
+                changed = true;
+                ui.close_menu();
+            }
+        }
+        let id = ui.id().with("kelvin");
+        let mut kelvin = ui.data_mut(|d| *d.get_temp_mut_or(id, 4000u16));
+        let slider = egui::Slider::new(&mut kelvin, 1000..=10000).step_by(100.0).suffix(" K");
+        if ui.add(slider).changed() {
+            ui.data_mut(|d| d.insert_temp(id, kelvin));
+            *color = to_array(rgb_from_kelvin(kelvin));
+            changed = true;
+        }
+    });
+    changed
+}
+
+impl DeathAdderv2App {
+    fn new() -> Self {
+        let (config, mut status) = match app_core::load_config() {
+            Ok(cfg) => (Some(cfg), String::new()),
+            Err(e) => (None, e.to_string()),
+        };
+
+        let device = match replay_from_env() {
+            Some(replay) => replay
+                .map(|t| DeathAdderV2::with_transport(Box::new(t))),
+            None => DeathAdderV2::new(),
+        };
+        let device = device.map_err(|e| {
+            status = format!("Failed to open device: {}", e);
+        }).ok();
+
+        let mut app = Self {
+            caps: device.as_ref().map(|d| d.capabilities()).unwrap_or_default(),
+            device: device,
+            save_config: config.is_some(),
+            config: config.unwrap_or_default(),
+            dpi: 0,
+            stages: Vec::new(),
+            current_stage: 0,
+            poll_rate: None,
+            logo_brightness: 0,
+            scroll_brightness: 0,
+            zone_brightness: HashMap::new(),
+            free_spin: false,
+            smart_reel: false,
+            liftoff: None,
+            battery: None,
+            status: status,
+            animation_editor: AnimationEditor::default(),
+            other_devices: OtherDevices::default(),
+            fader: Fader::default(),
+        };
+
+        if let Some(dav2) = &app.device {
+            app_core::prepare(dav2, &app.config);
+        }
+
+        app.refresh();
+        app.apply_colors();
+        app
+    }
+
+    /// Set a color by fading to it from `from`; see `tick_fades()`
+    fn fade_color(&mut self, led: Led, from: RGB8, to: RGB8) {
+        self.fader.start(led, from, to, FADE_DURATION);
+    }
+
+    /// Send the fade frames that are due, and set the colors of the fades
+    /// that are done
+    fn tick_fades(&mut self, ctx: &egui::Context) {
+        if self.fader.is_idle() {
+            return;
+        }
+        let Some(dav2) = &self.device else { return };
+        let res = self.fader.tick(dav2).and_then(|done| {
+            done.into_iter()
+                .try_for_each(|(led, color)| write_color(dav2, &self.config, led, color))
+        });
+        if let Err(e) = res {
+            self.status = format!("Failed to apply setting: {}", e);
+            self.fader = Fader::default();
+        }
+        ctx.request_repaint_after(Duration::from_millis(20));
+    }
+
+    /// Can't read colors from the device; assume they're what the config says
+    fn apply_colors(&mut self) {
+        self.apply(|dav2, cfg| {
+            write_color(dav2, cfg, Led::Logo, cfg.logo_color)?;
+            write_color(dav2, cfg, Led::ScrollWheel, cfg.scroll_color)?;
+            for led in dav2.zones() {
+                if let Some(color) = cfg.zone_color(led) {
+                    write_color(dav2, cfg, led, color)?;
+                }
+            }
+            Ok(())
+        });
+    }
+
+    /// Read the current values back from the device
+    /// The model the main window drives, left out of the other devices
+    fn own_pid(&self) -> u16 {
+        self.device.as_ref().map_or(0, |dav2| dav2.pid())
+    }
+
+    fn refresh(&mut self) {
+        let Some(dav2) = &self.device else { return };
+        let mut errors = Vec::new();
+
+        match dav2.get_dpi() {
+            Ok((dpi, _)) => self.dpi = dpi,
+            Err(e) => errors.push(format!("DPI: {}", e)),
+        }
+        match dav2.get_dpi_stages() {
+            Ok((stages, current)) => {
+                self.stages = stages.iter().map(|&(x, _)| x).collect();
+                self.current_stage = current as usize;
+            },
+            Err(e) => errors.push(format!("DPI stages: {}", e)),
+        }
+        match dav2.get_poll_rate() {
+            Ok(rate) => self.poll_rate = Some(rate),
+            Err(e) => errors.push(format!("polling rate: {}", e)),
+        }
+        if self.caps.has_led(Led::Logo) {
+            match dav2.get_logo_brightness() {
+                Ok(b) => self.logo_brightness = b,
+                Err(e) => errors.push(format!("logo brightness: {}", e)),
+            }
+        }
+        if self.caps.has_led(Led::ScrollWheel) {
+            match dav2.get_scroll_brightness() {
+                Ok(b) => self.scroll_brightness = b,
+                Err(e) => errors.push(format!("scroll wheel brightness: {}", e)),
+            }
+        }
+        for led in dav2.zones() {
+            if led == Led::Logo || led == Led::ScrollWheel {
+                continue;
+            }
+            match dav2.get_led_brightness(led) {
+                Ok(b) => {
+                    self.zone_brightness.insert(led, b);
+                },
+                Err(e) => errors.push(format!("{} brightness: {}", led.name(), e)),
+            }
+        }
+        if self.caps.scroll_mode {
+            match dav2.get_scroll_mode() {
+                Ok(mode) => self.free_spin = mode == ScrollMode::FreeSpin,
+                Err(e) => errors.push(format!("scroll mode: {}", e)),
+            }
+            match dav2.get_smart_reel() {
+                Ok(on) => self.smart_reel = on,
+                Err(e) => errors.push(format!("smart reel: {}", e)),
+            }
+        }
+        if self.caps.liftoff {
+            match dav2.get_liftoff() {
+                Ok(liftoff) => self.liftoff = Some(liftoff),
+                Err(e) => errors.push(format!("lift-off distance: {}", e)),
+            }
+        }
+        if self.caps.battery {
+            self.battery = Some(match dav2.get_battery_level() {
+                Ok(level) => format!("{}%", level),
+                Err(e) => e.to_string(),
+            });
+        }
+
+        if !errors.is_empty() {
+            self.status = format!("Failed to get {}", errors.join(", "));
+        }
+    }
+
+    /// Run a device command, reporting any error in the status line
+    fn apply<F>(&mut self, f: F)
+    where
+        F: FnOnce(&DeathAdderV2, &Config) -> USBResult<()>,
+    {
+        if let Some(dav2) = &self.device {
+            if let Err(e) = f(dav2, &self.config) {
+                self.status = format!("Failed to apply setting: {}", e);
+            }
+        }
+    }
+
+    fn dpi_ui(&mut self, ui: &mut egui::Ui) {
+        let Some(range) = self.caps.dpi else { return };
+
+        ui.label("DPI");
+        let slider = egui::Slider::new(&mut self.dpi, range.min..=range.max)
+            .step_by(range.step as f64);
+        if ui.add(slider).changed() {
+            let dpi = self.dpi;
+            self.apply(|dav2, _| dav2.set_dpi(dpi, dpi));
+        }
+        ui.end_row();
+
+        ui.label("DPI stages");
+        let mut changed = false;
+        ui.horizontal(|ui| {
+            for (i, dpi) in self.stages.iter_mut().enumerate() {
+                changed |= ui.radio_value(&mut self.current_stage, i, "").changed();
+                changed |= ui.add(egui::DragValue::new(dpi)
+                    .clamp_range(range.min..=range.max)
+                    .speed(range.step)).changed();
+            }
+        });
+        if changed {
+            let stages = self.stages.iter()
+                .map(|&dpi| (range.fit(dpi), range.fit(dpi)))
+                .collect::<Vec<(u16, u16)>>();
+            let current = self.current_stage as u8;
+            self.apply(|dav2, _| dav2.set_dpi_stages(&stages, current));
+        }
+        ui.end_row();
+    }
+
+    fn poll_rate_ui(&mut self, ui: &mut egui::Ui) {
+        ui.label("Polling rate");
+        let mut selected = self.poll_rate;
+        egui::ComboBox::from_id_source("poll_rate")
+            .selected_text(selected.map_or(String::new(), |rate| rate.to_string()))
+            .show_ui(ui, |ui| {
+                for &rate in &self.caps.polling_rates {
+                    ui.selectable_value(&mut selected, Some(rate), rate.to_string());
+                }
+            });
+        if selected != self.poll_rate {
+            self.poll_rate = selected;
+            if let Some(rate) = selected {
+                self.apply(|dav2, _| dav2.set_poll_rate(rate));
+            }
+        }
+        ui.end_row();
+    }
+
+    fn scroll_mode_ui(&mut self, ui: &mut egui::Ui) {
+        if !self.caps.scroll_mode {
+            return;
+        }
+        ui.label("Scroll wheel");
+        ui.horizontal(|ui| {
+            // smart reel switches the mode by itself
+            let free_spin = egui::Checkbox::new(&mut self.free_spin, "Free spin");
+            if ui.add_enabled(!self.smart_reel, free_spin).changed() {
+                let mode = if self.free_spin { ScrollMode::FreeSpin } else { ScrollMode::Tactile };
+                self.apply(|dav2, _| dav2.set_scroll_mode(mode));
+            }
+            if ui.checkbox(&mut self.smart_reel, "Smart reel").changed() {
+                let on = self.smart_reel;
+                self.apply(|dav2, _| dav2.set_smart_reel(on));
+            }
+        });
+        ui.end_row();
+    }
+
+    fn liftoff_ui(&mut self, ui: &mut egui::Ui) {
+        if !self.caps.liftoff {
+            return;
+        }
+        ui.label("Lift-off distance");
+        let mut selected = self.liftoff;
+        egui::ComboBox::from_id_source("liftoff")
+            .selected_text(selected.map_or(String::new(), |liftoff| liftoff.to_string()))
+            .show_ui(ui, |ui| {
+                for liftoff in LiftOff::all() {
+                    ui.selectable_value(&mut selected, Some(liftoff), liftoff.to_string());
+                }
+            });
+        if selected != self.liftoff {
+            self.liftoff = selected;
+            if let Some(liftoff) = selected {
+                self.apply(|dav2, _| dav2.set_liftoff(liftoff));
+            }
+        }
+        ui.end_row();
+    }
+
+    fn lighting_ui(&mut self, ui: &mut egui::Ui) {
+        let logo = self.caps.has_led(Led::Logo);
+        let scroll = self.caps.has_led(Led::ScrollWheel);
+
+        ui.label("Lighting");
+        if ui.checkbox(&mut self.config.onboard, "Apply to onboard memory").changed() &&
+            self.config.onboard {
+            // saving is deliberate; write what is previewed now
+            let (logo_b, scroll_b) = (self.logo_brightness, self.scroll_brightness);
+            let zone_b = self.zone_brightness.clone();
+            self.apply(|dav2, cfg| {
+                let caps = dav2.capabilities();
+                for led in dav2.zones() {
+                    let (color, b) = match led {
+                        Led::Logo => (Some(cfg.logo_color), Some(logo_b)),
+                        Led::ScrollWheel => (Some(cfg.scroll_color), Some(scroll_b)),
+                        _ => (cfg.zone_color(led), zone_b.get(&led).copied()),
+                    };
+                    if let Some(color) = color.filter(|_| caps.has_rgb(led)) {
+                        write_color(dav2, cfg, led, color)?;
+                    }
+                    if let Some(b) = b {
+                        write_brightness(dav2, cfg, led, b)?;
+                    }
+                }
+                Ok(())
+            });
+        }
+        ui.end_row();
+
+        if self.caps.has_rgb(Led::Logo) {
+            ui.label("Logo color");
+            let mut color = to_array(self.config.logo_color);
+            if ui.horizontal(|ui| color_edit(ui, &mut color)).inner {
+                let (logo, scroll) = (self.config.logo_color, self.config.scroll_color);
+                self.config.logo_color = from_array(color);
+                self.fade_color(Led::Logo, logo, self.config.logo_color);
+                if self.config.same_color {
+                    self.config.scroll_color = self.config.logo_color;
+                    self.fade_color(Led::ScrollWheel, scroll, self.config.scroll_color);
+                }
+            }
+            ui.end_row();
+        }
+
+        if self.caps.has_rgb(Led::ScrollWheel) {
+            ui.label("Scroll wheel color");
+            ui.horizontal(|ui| {
+                let mut color = to_array(self.config.scroll_color);
+                let changed = ui.add_enabled_ui(!self.config.same_color,
+                    |ui| color_edit(ui, &mut color)).inner;
+                if changed {
+                    let scroll = self.config.scroll_color;
+                    self.config.scroll_color = from_array(color);
+                    self.fade_color(Led::ScrollWheel, scroll, self.config.scroll_color);
+                }
+                if self.caps.has_rgb(Led::Logo) &&
+                    ui.checkbox(&mut self.config.same_color, "Same as logo").changed() &&
+                    self.config.same_color {
+                    let scroll = self.config.scroll_color;
+                    self.config.scroll_color = self.config.logo_color;
+                    self.fade_color(Led::ScrollWheel, scroll, self.config.scroll_color);
+                }
+            });
+            ui.end_row();
+        }
+
+        if logo {
+            ui.label("Logo brightness");
+            if ui.add(egui::Slider::new(&mut self.logo_brightness, 0..=100)).changed() {
+                if self.config.same_brightness {
+                    self.scroll_brightness = self.logo_brightness;
+                }
+                let (b, same) = (self.logo_brightness, self.config.same_brightness);
+                self.apply(|dav2, cfg| {
+                    write_brightness(dav2, cfg, Led::Logo, b)?;
+                    if same && scroll {
+                        write_brightness(dav2, cfg, Led::ScrollWheel, b)?;
+                    }
+                    Ok(())
+                });
+            }
+            ui.end_row();
+        }
+
+        if scroll {
+            ui.label("Scroll wheel brightness");
+            ui.horizontal(|ui| {
+                let slider = egui::Slider::new(&mut self.scroll_brightness, 0..=100);
+                let response = ui.add_enabled(!(logo && self.config.same_brightness), slider);
+                if response.changed() {
+                    let b = self.scroll_brightness;
+                    self.apply(|dav2, cfg| write_brightness(dav2, cfg, Led::ScrollWheel, b));
+                }
+                if logo &&
+                    ui.checkbox(&mut self.config.same_brightness, "Same as logo").changed() &&
+                    self.config.same_brightness {
+                    self.scroll_brightness = self.logo_brightness;
+                    let b = self.scroll_brightness;
+                    self.apply(|dav2, cfg| write_brightness(dav2, cfg, Led::ScrollWheel, b));
+                }
+            });
+            ui.end_row();
+        }
+
+        self.zones_ui(ui);
+    }
+
+    /// A color and brightness row for each zone other than logo and scroll wheel
+    fn zones_ui(&mut self, ui: &mut egui::Ui) {
+        let zones = self.caps.leds.iter()
+            .map(|cap| cap.led)
+            .filter(|&led| led != Led::Logo && led != Led::ScrollWheel)
+            .collect::<Vec<Led>>();
+
+        for led in zones {
+            ui.label(led.name());
+            ui.horizontal(|ui| {
+                if self.caps.has_rgb(led) {
+                    let initial = self.config.zone_color(led)
+                        .unwrap_or(RGB8::new(0xFF, 0xFF, 0xFF));
+                    let mut color = to_array(initial);
+                    if color_edit(ui, &mut color) {
+                        let color = from_array(color);
+                        self.config.set_zone_color(led, color);
+                        self.fade_color(led, initial, color);
+                    }
+                }
+
+                let brightness = self.zone_brightness.entry(led).or_insert(100);
+                if ui.add(egui::Slider::new(brightness, 0..=100)).changed() {
+                    let b = *brightness;
+                    self.apply(|dav2, cfg| write_brightness(dav2, cfg, led, b));
+                }
+            });
+            ui.end_row();
+        }
+    }
+}
+
+impl eframe::App for DeathAdderv2App {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        egui::TopBottomPanel::bottom("status").show(ctx, |ui| {
+            ui.label(self.status.as_str());
+        });
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            let name = self.device.as_ref()
+                .map_or(String::from("No device"), |dav2| dav2.name());
+            ui.heading(name);
+            if let Some(battery) = &self.battery {
+                ui.label(format!("Battery: {}", battery));
+            }
+
+            ui.add_enabled_ui(self.device.is_some(), |ui| {
+                egui::Grid::new("settings").num_columns(2).show(ui, |ui| {
+                    self.dpi_ui(ui);
+                    self.poll_rate_ui(ui);
+                    self.scroll_mode_ui(ui);
+                    self.liftoff_ui(ui);
+                    self.lighting_ui(ui);
+                });
+
+                ui.separator();
+                if ui.button("Reset to defaults").clicked() {
+                    self.config = Config { receiver_waits: self.config.receiver_waits.clone(),
+                        tx_ids: self.config.tx_ids.clone(),
+                        ..Default::default() };
+                    self.apply(|dav2, _| dav2.reset_to_defaults());
+                    self.refresh();
+                }
+                if ui.button("Restore stock lighting").clicked() {
+                    self.apply(|dav2, _| dav2.restore_default_lighting());
+                    self.refresh();
+                }
+            });
+            if ui.button("Animation editor").clicked() {
+                self.animation_editor.open = true;
+            }
+            if ui.button("Other devices").clicked() {
+                self.other_devices.open = true;
+                self.other_devices.refresh(self.own_pid());
+            }
+        });
+
+        if self.animation_editor.show(ctx, self.device.as_ref()) {
+            self.apply_colors();
+        }
+        let own_pid = self.own_pid();
+        self.other_devices.show(ctx, own_pid);
+        self.tick_fades(ctx);
+    }
+}
+
+impl Drop for DeathAdderv2App {
+    fn drop(&mut self) {
+        if let Some(dav2) = &self.device {
+            #[cfg(feature = "transport-stats")]
+            eprint!("{}", dav2.transport_stats());
+
+            if let Ok(serial) = dav2.get_serial() {
+                app_core::remember(dav2, &serial, &mut self.config);
+            }
+        }
+        if self.save_config {
+            if let Err(e) = self.config.save() {
+                eprintln!("failed to save config: {}", e);
+            }
+        }
+    }
+}
+
+fn main() -> Result<(), eframe::Error> {
+    let options = eframe::NativeOptions {
+        viewport: egui::ViewportBuilder::default().with_inner_size([520.0, 420.0]),
+        ..Default::default()
+    };
+    eframe::run_native("DeathAdder v2", options,
+        Box::new(|_cc| Box::new(DeathAdderv2App::new())))
+}
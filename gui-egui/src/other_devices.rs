@@ -0,0 +1,105 @@
+//! Lighting for the supported devices other than the DeathAdder v2 (stands,
+//! keypads, other mice), one color and brightness per zone.
+
+use std::collections::HashMap;
+use eframe::egui;
+use rgb::RGB8;
+use librazer::common::Led;
+use librazer::device::{enumerate, RazerDevice};
+use librazer::error::USBResult;
+
+use crate::{color_edit, from_array, to_array};
+
+struct Entry {
+    device: Box<dyn RazerDevice>,
+    name: String,
+    /// Colors aren't read back from devices; these are the last ones set
+    colors: HashMap<Led, RGB8>,
+    brightness: HashMap<Led, u8>,
+}
+
+#[derive(Default)]
+pub struct OtherDevices {
+    pub open: bool,
+    entries: Vec<Entry>,
+    status: String,
+}
+
+impl OtherDevices {
+    /// Look for devices again, leaving out those of model `skip_pid` (the one
+    /// the main window drives)
+    pub fn refresh(&mut self, skip_pid: u16) {
+        self.entries.clear();
+        let devices = match enumerate() {
+            Ok(devices) => devices,
+            Err(e) => {
+                self.status = format!("Failed to list devices: {}", e);
+                return;
+            },
+        };
+        for device in devices.into_iter().filter(|d| d.pid() != skip_pid) {
+            let brightness = device.zones().into_iter()
+                .filter_map(|led| device.get_led_brightness(led).ok().map(|b| (led, b)))
+                .collect();
+            self.entries.push(Entry {
+                name: device.to_string(),
+                device: device,
+                colors: HashMap::new(),
+                brightness: brightness,
+            });
+        }
+        self.status = format!("Found {} other device(s)", self.entries.len());
+    }
+
+    pub fn show(&mut self, ctx: &egui::Context, skip_pid: u16) {
+        let mut open = self.open;
+        egui::Window::new("Other devices").open(&mut open).show(ctx, |ui| {
+            if ui.button("Refresh").clicked() {
+                self.refresh(skip_pid);
+            }
+            for (i, entry) in self.entries.iter_mut().enumerate() {
+                ui.separator();
+                ui.label(entry.name.as_str());
+                if let Err(e) = Self::zones_ui(ui, i, entry) {
+                    self.status = format!("{}: {}", entry.name, e);
+                }
+            }
+            if !self.status.is_empty() {
+                ui.separator();
+                ui.label(self.status.as_str());
+            }
+        });
+        self.open = open;
+    }
+
+    fn zones_ui(ui: &mut egui::Ui, i: usize, entry: &mut Entry) -> USBResult<()> {
+        let caps = entry.device.capabilities();
+        let mut res = Ok(());
+        egui::Grid::new(("zones", i)).num_columns(2).show(ui, |ui| {
+            for led in entry.device.zones() {
+                ui.label(led.name());
+                ui.horizontal(|ui| {
+                    if caps.has_rgb(led) {
+                        let color = entry.colors.entry(led)
+                            .or_insert(RGB8::new(0xFF, 0xFF, 0xFF));
+                        let mut rgb = to_array(*color);
+                        if color_edit(ui, &mut rgb) {
+                            *color = from_array(rgb);
+                            if let Err(e) = entry.device.set_led_color(led, *color) {
+                                res = Err(e);
+                            }
+                        }
+                    }
+                    let brightness = entry.brightness.entry(led).or_insert(100);
+                    if ui.add(egui::Slider::new(brightness, 0..=100)).changed() {
+                        if let Err(e) = entry.device.set_led_brightness(led, *brightness) {
+                            res = Err(e);
+                        }
+                    }
+                });
+                ui.end_row();
+            }
+        });
+        res
+    }
+}
@@ -0,0 +1,126 @@
+//! Switching profiles from outside the app: `razerda://profile/<name>` links
+//! (from a browser, a Stream Deck, a script) and `--activate-profile <name>`.
+//! When the app is already running, the new instance hands the profile over
+//! to it (WM_COPYDATA to its window) and exits.
+
+use std::ffi::c_void;
+use std::sync::Mutex;
+use windows::{
+    core::{s, HSTRING, PCSTR},
+    Win32::{
+        Foundation::{LPARAM, WPARAM, ERROR_SUCCESS},
+        System::{
+            DataExchange::COPYDATASTRUCT,
+            Registry::{RegSetKeyValueW, HKEY_CURRENT_USER, REG_SZ},
+        },
+        UI::WindowsAndMessaging::{FindWindowA, SendMessageA, WM_COPYDATA},
+    },
+};
+use native_windows_gui as nwg;
+
+pub const SCHEME: &str = "razerda";
+/// Tells our WM_COPYDATA messages apart from anyone else's
+const COPYDATA_ACTIVATE: usize = 0x52_41_5A_44; // "RAZD"
+
+static PENDING: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// The profile name in `razerda://profile/<name>`, or `arg` itself if it's
+/// not a link
+pub fn profile_name(arg: &str) -> Result<String, String> {
+    let prefix = format!("{}:", SCHEME);
+    let rest = match arg.get(..prefix.len()) {
+        Some(p) if p.eq_ignore_ascii_case(&prefix) => &arg[prefix.len()..],
+        _ => return Ok(arg.into()),
+    };
+    // browsers may add a trailing slash
+    let rest = rest.trim_start_matches('/').trim_end_matches('/');
+    match rest.split_once('/') {
+        Some((action, name)) if action.eq_ignore_ascii_case("profile") && !name.is_empty() =>
+            percent_decode(name),
+        _ => Err(format!("unsupported link '{}'; expected {}://profile/<name>", arg, SCHEME)),
+    }
+}
+
+fn percent_decode(s: &str) -> Result<String, String> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut iter = s.bytes();
+    while let Some(b) = iter.next() {
+        if b != b'%' {
+            bytes.push(b);
+            continue;
+        }
+        let hex: Vec<u8> = iter.by_ref().take(2).collect();
+        let byte = std::str::from_utf8(&hex).ok()
+            .filter(|hex| hex.len() == 2)
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            .ok_or(format!("bad escape in '{}'", s))?;
+        bytes.push(byte);
+    }
+    String::from_utf8(bytes).map_err(|_| format!("'{}' is not UTF-8", s))
+}
+
+/// Have the running instance, if any, activate `profile`; returns false if
+/// there is none. Call before building our own window, or it'll find that.
+pub fn forward(profile: &str) -> bool {
+    // the title of the main window
+    let hwnd = unsafe { FindWindowA(PCSTR::null(), s!("Razer DeathAdder v2 configuration")) };
+    if hwnd.0 == 0 {
+        return false;
+    }
+    let data = COPYDATASTRUCT {
+        dwData: COPYDATA_ACTIVATE,
+        cbData: profile.len() as u32,
+        lpData: profile.as_ptr() as *mut c_void,
+    };
+    unsafe {
+        SendMessageA(hwnd, WM_COPYDATA, WPARAM(0), LPARAM(&data as *const _ as isize));
+    }
+    true
+}
+
+/// `notice` fires on the GUI thread whenever another instance forwards a
+/// profile; see `take_pending()`
+pub fn install_receiver(window: &nwg::Window, notice: nwg::NoticeSender) {
+    _ = nwg::bind_raw_event_handler(&window.handle, 0x10001, move |_hwnd, msg, _w, l| {
+        if msg != WM_COPYDATA {
+            return None;
+        }
+        let data = unsafe { &*(l as *const COPYDATASTRUCT) };
+        if data.dwData != COPYDATA_ACTIVATE || data.lpData.is_null() {
+            return None;
+        }
+        let bytes = unsafe {
+            std::slice::from_raw_parts(data.lpData as *const u8, data.cbData as usize)
+        };
+        PENDING.lock().unwrap().push(String::from_utf8_lossy(bytes).into_owned());
+        notice.notice();
+        Some(1)
+    });
+}
+
+/// Profiles forwarded since last called, oldest first
+pub fn take_pending() -> Vec<String> {
+    std::mem::take(&mut *PENDING.lock().unwrap())
+}
+
+/// Have `razerda://` links start this executable (for the current user)
+pub fn register_uri_handler() -> Result<(), String> {
+    let exe = std::env::current_exe().map_err(|e| e.to_string())?;
+    let key = format!("Software\\Classes\\{}", SCHEME);
+    let command = format!("\"{}\" --activate-profile \"%1\"", exe.display());
+    set_value(&key, "", &format!("URL:{} Protocol", SCHEME))?;
+    set_value(&key, "URL Protocol", "")?;
+    set_value(&format!("{}\\shell\\open\\command", key), "", &command)
+}
+
+fn set_value(key: &str, name: &str, value: &str) -> Result<(), String> {
+    let data: Vec<u16> = value.encode_utf16().chain(Some(0)).collect();
+    let err = unsafe {
+        RegSetKeyValueW(HKEY_CURRENT_USER, &HSTRING::from(key), &HSTRING::from(name),
+            REG_SZ.0, Some(data.as_ptr() as *const c_void), (data.len() * 2) as u32)
+    };
+    if err != ERROR_SUCCESS {
+        return Err(format!("failed to write HKCU\\{}: error {}", key, err.0));
+    }
+    Ok(())
+}
@@ -15,6 +15,7 @@ use windows::{
     },
 };
 use rgb::RGB8;
+use librazer::common::{rgb_from_kelvin, WhitePreset};
 
 /*
  * trying hard to follow:
@@ -91,17 +92,28 @@ impl<'a> ColorDialog<'a> {
             // will set lCustData to self so we can access it in the hook proc
             let this_lp = LPARAM((self as *mut Self) as isize);
 
-            // this will be both the initial and custom colors for now
-            let mut initial_cr = COLORREF(
-                initial.r as u32 |
-                (initial.g as u32) << 8 |
-                (initial.b as u32) << 16);
+            let colorref = |c: RGB8| COLORREF(
+                c.r as u32 |
+                (c.g as u32) << 8 |
+                (c.b as u32) << 16);
+            let initial_cr = colorref(initial);
+
+            // the 16 custom colors: the initial one, then whites that don't
+            // come out blue-tinted on the LEDs, then warm to cool by 500K
+            let mut custom_crs = [COLORREF(0xffffff); 16];
+            custom_crs[0] = initial_cr;
+            for (i, preset) in WhitePreset::iter().enumerate() {
+                custom_crs[1 + i] = colorref(preset.rgb());
+            }
+            for (i, cr) in custom_crs[8..].iter_mut().enumerate() {
+                *cr = colorref(rgb_from_kelvin(2500 + 500 * i as u16));
+            }
 
             let mut cc = CHOOSECOLORA {
                 lStructSize: size_of::<CHOOSECOLORA>() as u32,
                 hwndOwner: parent,
                 rgbResult: initial_cr,
-                lpCustColors: &mut initial_cr,
+                lpCustColors: custom_crs.as_mut_ptr(),
                 Flags: CC_FULLOPEN | CC_ANYCOLOR | CC_RGBINIT | CC_ENABLEHOOK | CC_PREVENTFULLOPEN,
                 lpfnHook: Some(cc_hook_proc),
                 lpTemplateName: PCSTR::null(),
@@ -0,0 +1,77 @@
+//! Writing a crash log and telling the user where it is when the app panics
+
+use std::backtrace::Backtrace;
+use std::fs;
+use std::io;
+use std::panic;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+use windows::{
+    core::{s, PCSTR},
+    Win32::{
+        Foundation::HWND,
+        UI::WindowsAndMessaging::{MessageBoxA, MB_OK, MB_ICONERROR},
+    },
+};
+
+use librazer::cfg::Config;
+use librazer::device::RazerDevice;
+use librazer::hooks::RecentTransactions;
+
+/// How many of the last transactions go in the log
+const LOGGED_TRANSACTIONS: usize = 32;
+
+static TRANSACTIONS: OnceLock<Arc<RecentTransactions>> = OnceLock::new();
+static DEVICE: Mutex<String> = Mutex::new(String::new());
+
+fn transactions() -> &'static Arc<RecentTransactions> {
+    TRANSACTIONS.get_or_init(|| Arc::new(RecentTransactions::new(LOGGED_TRANSACTIONS)))
+}
+
+/// Include `dav2` and its transactions in the crash log
+pub fn watch(dav2: &dyn RazerDevice) {
+    dav2.hooks().register(Arc::clone(transactions()));
+    *DEVICE.lock().unwrap_or_else(|e| e.into_inner()) =
+        format!("{:04x}:{:04x} {}", dav2.vid(), dav2.pid(), dav2.name());
+}
+
+fn write_log(message: &str) -> io::Result<PathBuf> {
+    let path = Config::crash_log_path()
+        .unwrap_or_else(|_| std::env::temp_dir().join("deathadder_v2-crash.log"));
+    let time = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let device = DEVICE.lock().unwrap_or_else(|e| e.into_inner()).clone();
+
+    let log = format!("{} {}\ntime: {}\n{}\ndevice: {}\n\nlast transactions:\n{}\n\n\
+        backtrace:\n{}\n",
+        env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"), time, message,
+        if device.is_empty() { "none" } else { &device },
+        transactions().lines().join("\n"),
+        Backtrace::force_capture());
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&path, log)?;
+    Ok(path)
+}
+
+/// Keep the default panic output (for the debugger) and add the log + dialog
+pub fn install_panic_hook() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |panic_info| {
+        default_hook(panic_info);
+
+        let panic_info = panic_info.to_string();
+        let msg = match write_log(&panic_info) {
+            Ok(path) => format!("The application crashed:\n{}\n\nDetails were saved \
+                to\n{}\nPlease attach that file when reporting the problem.\0",
+                panic_info, path.display()),
+            Err(e) => format!("The application crashed:\n{}\n\n(Failed to write \
+                the crash log: {})\0", panic_info, e),
+        };
+        unsafe {
+            MessageBoxA(HWND(0), PCSTR::from_raw(msg.as_ptr()), s!("Crash"),
+                MB_OK | MB_ICONERROR);
+        }
+    }));
+}
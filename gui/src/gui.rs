@@ -1,38 +1,55 @@
 #![windows_subsystem = "windows"]
 
 use std::sync::Arc;
-use std::ptr;
-use std::{cell::RefCell, sync::Mutex};
+use std::time::{Duration, Instant};
+use std::{cell::{Cell, Ref, RefCell}, rc::Rc, sync::Mutex};
 use std::thread;
-use hidapi_rusb::{HidError, HidApi, HidDevice};
 use windows::{
     core::{s, PCSTR},
     Win32::{
         System::Diagnostics::Debug::OutputDebugStringA,
-        Foundation::{HWND, WPARAM, LPARAM, HINSTANCE},
+        Foundation::{HWND, WPARAM, LPARAM, HINSTANCE, RECT},
+        Graphics::Gdi::{MonitorFromRect, MONITOR_DEFAULTTONULL},
         UI::{
+            Shell::ShellExecuteA,
             Controls::{TBS_TOOLTIPS, TBS_BOTTOM, TBS_DOWNISLEFT, TBM_SETLINESIZE,
                 TBM_SETPAGESIZE, TBM_SETTICFREQ, TBS_NOTIFYBEFOREMOVE,
             },
             WindowsAndMessaging::{SendMessageA, GetWindowLongA, SetWindowLongA,
                 GWL_STYLE, MessageBoxA, MB_OK, MB_ICONERROR, BS_TOP,
                 SetCursor, LoadCursorW, IDC_HAND, IDC_ARROW,
-                WM_GETMINMAXINFO, MINMAXINFO,
+                WM_GETMINMAXINFO, MINMAXINFO, MB_YESNO, MB_ICONWARNING, IDYES,
+                IsIconic, IsZoomed, SW_SHOWNORMAL,
             },
         },
     },
 };
+#[cfg(feature = "hidapi")]
+use windows::Win32::UI::WindowsAndMessaging::{MB_YESNOCANCEL, IDNO};
 use native_windows_gui as nwg;
 use native_windows_derive as nwd;
-use nwd::{NwgUi, NwgPartial};
+use nwd::NwgUi;
 use nwg::{NativeUi, RadioButtonState};
 
 use rgb::RGB8;
-use librazer::{cfg::Config, device::UsbDevice, common::PollingRate};
-use librazer::device::{DeathAdderV2, RazerDevice, RazerMouse};
+use librazer::{cfg::{ButtonAction, Config, WindowPlacement}, device::UsbDevice,
+    common::{Led, LiftOff, PollingRate, ScrollMode}};
+use librazer::device::{self, BasiliskV2, BasiliskV3, DeathAdderElite, DeathAdderV2, OrochiV2,
+    RazerDevice, RazerMouse};
+use librazer::capabilities::{Capabilities, LedKind};
+use librazer::effects::{Fader, FADE_DURATION};
+use librazer::error::{Context, DriverProblem, USBError, USBResult};
+use librazer::hid::{self, EventListener};
+use librazer::profile::{Profile, SyncStatus};
+use librazer::transport::replay_from_env;
 
 pub mod color_chooser;
 use color_chooser::ColorDialog;
+mod update;
+mod crash;
+mod tray;
+mod hidpath;
+mod activation;
 
 /*
  * Log messages to the debugger using OutputDebugString (only for command line
@@ -60,15 +77,10 @@ macro_rules! dbglog {
 //     }}
 // }
 
+// the panic hook (see crash.rs) shows the message box
 macro_rules! msgboxpanic {
     ($($args: tt)*) => {{
-        let msg = format!($($args)*);
-        let msg_sz = format!("{}{}", msg, "\0");
-        unsafe {
-            let msg_ptr = PCSTR::from_raw(msg_sz.as_ptr());
-            MessageBoxA(HWND(0), msg_ptr, s!("Error"), MB_OK | MB_ICONERROR);
-        }
-        panic!("{}", msg);
+        panic!($($args)*);
     }}
 }
 
@@ -101,6 +113,26 @@ macro_rules! from_check_state {
     };
 }
 
+/// How long the device stays open after the last command, when the config
+/// says to release it
+const IDLE_RELEASE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long DPI changes are ignored after undoing one the DPI button made,
+/// since the undo is reported as a change too
+const DPI_UNDO_GRACE: Duration = Duration::from_millis(500);
+
+/// How often the device is compared with the saved profile
+const SYNC_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The mice offered in the device list, by how to find them
+const MICE: &[fn() -> USBResult<Vec<UsbDevice>>] = &[
+    DeathAdderV2::list,
+    DeathAdderElite::list,
+    BasiliskV2::list,
+    BasiliskV3::list,
+    OrochiV2::list,
+];
+
 fn configure_trackbar(bar: &nwg::TrackBar, line: isize, page: isize, tick: usize) {
     unsafe {
         let hbar = HWND(bar.handle.hwnd().unwrap() as isize);
@@ -120,37 +152,53 @@ fn add_style(handle: &nwg::ControlHandle, style: i32) {
     }
 }
 
-#[derive(Default, NwgPartial)]
-pub struct DpiStagesUI {
-    #[nwg_layout(margin: [0, 0, 0, 0], max_column: Some(5)/* , max_size: [1000, 150]*/)]
-    grid: nwg::GridLayout,
+#[derive(Default, NwgUi)]
+pub struct DeathAdderv2App {
+    #[nwg_control(size: (700, 460), center: true, title: "Razer DeathAdder v2 configuration")]
+    #[nwg_events( OnWindowClose: [DeathAdderv2App::window_close(SELF)])]
+    window: nwg::Window,
 
-    #[nwg_control(text: "2000", flags: "VISIBLE | GROUP")]
-    #[nwg_layout_item(layout: grid, col: 0)]
-    rad_dpi_1: nwg::RadioButton,
+    #[nwg_control(parent: window, text: "&Configuration")]
+    mnu_config: nwg::Menu,
 
-    #[nwg_control(text: "5000")]
-    #[nwg_layout_item(layout: grid, col: 1)]
-    rad_dpi_2: nwg::RadioButton,
+    #[nwg_control(parent: mnu_config, text: "&Restore previous configuration")]
+    #[nwg_events(OnMenuItemSelected: [DeathAdderv2App::restore_config_clicked(SELF)])]
+    mni_restore_config: nwg::MenuItem,
 
-    #[nwg_control(text: "10000")]
-    #[nwg_layout_item(layout: grid, col: 2)]
-    rad_dpi_3: nwg::RadioButton,
+    #[nwg_control(parent: mnu_config, text: "&Save device settings as profile")]
+    #[nwg_events(OnMenuItemSelected: [DeathAdderv2App::save_profile_clicked(SELF)])]
+    mni_save_profile: nwg::MenuItem,
 
-    #[nwg_control(text: "15000")]
-    #[nwg_layout_item(layout: grid, col: 3)]
-    rad_dpi_4: nwg::RadioButton,
+    #[nwg_control(parent: mnu_config, text: "&Compare device with profile")]
+    #[nwg_events(OnMenuItemSelected: [DeathAdderv2App::compare_profile_clicked(SELF)])]
+    mni_compare_profile: nwg::MenuItem,
 
-    #[nwg_control(text: "20000")]
-    #[nwg_layout_item(layout: grid, col: 4)]
-    rad_dpi_5: nwg::RadioButton,
-}
+    #[nwg_control(parent: mnu_config, text: "&Live color preview", check: true)]
+    #[nwg_events(OnMenuItemSelected: [DeathAdderv2App::live_preview_clicked(SELF)])]
+    mni_live_preview: nwg::MenuItem,
 
-#[derive(Default, NwgUi)]
-pub struct DeathAdderv2App {
-    #[nwg_control(size: (700, 400), center: true, title: "Razer DeathAdder v2 configuration")]
-    #[nwg_events( OnWindowClose: [DeathAdderv2App::window_close(SELF)])]
-    window: nwg::Window,
+    #[nwg_control(parent: mnu_config, text: "Show &DPI stage on scroll wheel", check: true)]
+    #[nwg_events(OnMenuItemSelected: [DeathAdderv2App::stage_indicator_clicked(SELF)])]
+    mni_stage_indicator: nwg::MenuItem,
+
+    #[nwg_control(parent: mnu_config, text: "Color of current DPI st&age...")]
+    #[nwg_events(OnMenuItemSelected: [DeathAdderv2App::stage_color_clicked(SELF)])]
+    mni_stage_color: nwg::MenuItem,
+
+    #[nwg_control(parent: mnu_config, text: "Restore s&tock lighting")]
+    #[nwg_events(OnMenuItemSelected: [DeathAdderv2App::stock_lighting_clicked(SELF)])]
+    mni_stock_lighting: nwg::MenuItem,
+
+    #[nwg_control(parent: mnu_config, text: "Open razerda:// &links with this app")]
+    #[nwg_events(OnMenuItemSelected: [DeathAdderv2App::register_links_clicked(SELF)])]
+    mni_register_links: nwg::MenuItem,
+
+    #[nwg_control(parent: window, text: "&Help")]
+    mnu_help: nwg::Menu,
+
+    #[nwg_control(parent: mnu_help, text: "Check for &updates")]
+    #[nwg_events(OnMenuItemSelected: [DeathAdderv2App::check_updates_clicked(SELF)])]
+    mni_check_updates: nwg::MenuItem,
 
     #[nwg_layout(parent: window, min_size: [400, 200], max_column: Some(11))]
     grid: nwg::GridLayout,
@@ -164,15 +212,20 @@ pub struct DeathAdderv2App {
     #[nwg_events( OnComboxBoxSelection: [DeathAdderv2App::device_selected(SELF)])]
     cmb_device: nwg::ComboBox<UsbDevice>,
 
+    // shown while there is no device to drive; lists them and selects again
+    #[nwg_control(text: "Retry", flags: "NONE")]
+    #[nwg_layout_item(layout: grid, col: 10)]
+    #[nwg_events(OnButtonClick: [DeathAdderv2App::retry_clicked(SELF)])]
+    btn_retry: nwg::Button,
+
     /*
      * DPI stages
      */
     #[nwg_control(v_align: nwg::VTextAlign::Top, // has trouble aligning vertically
-        collection: vec!["1 DPI stage", "2 DPI stages", "3 DPI stages", "4 DPI stages", "5 DPI stages"],
-        selected_index: Some(0))]
+        collection: vec![String::from("1 DPI stage")], selected_index: Some(0))]
     #[nwg_layout_item(layout: grid, row: 1, col: 1, col_span: 2)]
     #[nwg_events( OnComboxBoxSelection: [DeathAdderv2App::numstages_selected(SELF)])]
-    cmb_numstages: nwg::ComboBox<&'static str>,
+    cmb_numstages: nwg::ComboBox<String>,
 
     #[nwg_control(text: "Stage DPI:", h_align: nwg::HTextAlign::Right, v_align: nwg::VTextAlign::Top)]
     #[nwg_layout_item(layout: grid, row: 2, col_span: 3)]
@@ -182,15 +235,15 @@ pub struct DeathAdderv2App {
     #[nwg_layout_item(layout: grid, row: 1, col: 3, col_span: 6)]
     frm_stages: nwg::Frame,
 
-    #[nwg_partial(parent: frm_stages)]
-    #[nwg_events(
-        (rad_dpi_1, OnButtonClick): [DeathAdderv2App::stage_selected(SELF)],
-        (rad_dpi_2, OnButtonClick): [DeathAdderv2App::stage_selected(SELF)],
-        (rad_dpi_3, OnButtonClick): [DeathAdderv2App::stage_selected(SELF)],
-        (rad_dpi_4, OnButtonClick): [DeathAdderv2App::stage_selected(SELF)],
-        (rad_dpi_5, OnButtonClick): [DeathAdderv2App::stage_selected(SELF)],
-    )]
-    par_stages: DpiStagesUI,
+    // one radio button per stage the device takes; see build_dpi_stage_controls()
+    grd_stages: nwg::GridLayout,
+    rad_stages: RefCell<Vec<nwg::RadioButton>>,
+
+    // the stage radio buttons are created at runtime, so nwd can't route their
+    // clicks; a handler bound in main() signals this instead
+    #[nwg_control]
+    #[nwg_events(OnNotice: [DeathAdderv2App::stage_selected])]
+    stage_notice: nwg::Notice,
 
     #[nwg_control(range: Some(100..20000), pos: Some(20000))]
     #[nwg_layout_item(layout: grid, row: 2, col: 3, col_span: 5)]
@@ -202,6 +255,11 @@ pub struct DeathAdderv2App {
     )]
     bar_stagedpi: nwg::TrackBar,
 
+    // why the last set of stages was not applied, if it wasn't
+    #[nwg_control(text: "", h_align: nwg::HTextAlign::Left, v_align: nwg::VTextAlign::Top)]
+    #[nwg_layout_item(layout: grid, row: 2, col: 8, col_span: 3)]
+    txt_stages_error: nwg::Label,
+
     /*
      * Current DPI
      */
@@ -235,6 +293,22 @@ pub struct DeathAdderv2App {
     #[nwg_events( OnComboxBoxSelection: [DeathAdderv2App::pollrate_selected(SELF)])]
     cmb_pollrate: nwg::ComboBox<PollingRate>,
 
+    #[nwg_control(text: "", h_align: nwg::HTextAlign::Left, v_align: nwg::VTextAlign::Top)]
+    #[nwg_layout_item(layout: grid, row: 4, col: 6, col_span: 4)]
+    txt_battery: nwg::Label,
+
+    /*
+     * Whether lighting changes are saved on the device; unchecked, they are
+     * only previews that last until the device is unplugged
+     */
+    #[nwg_control(text: "Apply to onboard memory")]
+    #[nwg_layout_item(layout: grid, row: 5, col: 6, col_span: 5)]
+    #[nwg_events(
+        MousePressLeftUp: [DeathAdderv2App::onboard_changed(SELF, EVT, EVT_DATA)],
+        OnKeyRelease: [DeathAdderv2App::onboard_changed(SELF, EVT, EVT_DATA)]
+    )]
+    chk_onboard: nwg::CheckBox,
+
     /*
      * Logo color
      */
@@ -273,6 +347,25 @@ pub struct DeathAdderv2App {
     )]
     chk_samecolor: nwg::CheckBox,
 
+    /*
+     * Scroll wheel mode, on mice that have it
+     */
+    #[nwg_control(text: "Free spin")]
+    #[nwg_layout_item(layout: grid, row: 6, col: 8, col_span: 3)]
+    #[nwg_events(
+        MousePressLeftUp: [DeathAdderv2App::free_spin_changed(SELF, EVT, EVT_DATA)],
+        OnKeyRelease: [DeathAdderv2App::free_spin_changed(SELF, EVT, EVT_DATA)]
+    )]
+    chk_freespin: nwg::CheckBox,
+
+    #[nwg_control(text: "Smart reel")]
+    #[nwg_layout_item(layout: grid, row: 7, col: 8, col_span: 3)]
+    #[nwg_events(
+        MousePressLeftUp: [DeathAdderv2App::smart_reel_changed(SELF, EVT, EVT_DATA)],
+        OnKeyRelease: [DeathAdderv2App::smart_reel_changed(SELF, EVT, EVT_DATA)]
+    )]
+    chk_smartreel: nwg::CheckBox,
+
     /*
      * Logo brightness
      */
@@ -326,21 +419,165 @@ pub struct DeathAdderv2App {
     )]
     chk_samebright: nwg::CheckBox,
 
+    /*
+     * LED zones other than the logo and scroll wheel; one swatch per zone,
+     * created for the selected device
+     */
+    #[nwg_control(text: "Other zones:", h_align: nwg::HTextAlign::Right)]
+    #[nwg_layout_item(layout: grid, row: 9, col_span: 3)]
+    lbl_zones: nwg::Label,
+
+    #[nwg_control(flags: "VISIBLE")]
+    #[nwg_layout_item(layout: grid, row: 9, col: 3, col_span: 5)]
+    frm_zones: nwg::Frame,
+    grd_zones: nwg::GridLayout,
+    btn_zones: RefCell<Vec<(Led, nwg::RichLabel)>>,
+    zone_handlers: RefCell<Vec<nwg::EventHandler>>,
+    zone_clicked: Rc<Cell<Option<Led>>>,
+
+    #[nwg_control]
+    #[nwg_events(OnNotice: [DeathAdderv2App::zone_color_clicked])]
+    zone_notice: nwg::Notice,
+
+    /*
+     * Reset
+     */
+    #[nwg_control(text: "Reset to defaults")]
+    #[nwg_layout_item(layout: grid, row: 9, col: 8, col_span: 3)]
+    #[nwg_events(OnButtonClick: [DeathAdderv2App::reset_clicked(SELF)])]
+    btn_reset: nwg::Button,
+
+    /*
+     * Quick DPI presets, set the current DPI apart from the stages; one
+     * button per preset in the config. Shares the row with the device
+     * error, which shows only when they're of no use.
+     */
+    #[nwg_control(text: "Quick DPI:", h_align: nwg::HTextAlign::Right)]
+    #[nwg_layout_item(layout: grid, row: 10, col_span: 3)]
+    lbl_presets: nwg::Label,
+
+    #[nwg_control(flags: "VISIBLE")]
+    #[nwg_layout_item(layout: grid, row: 10, col: 3, col_span: 8)]
+    frm_presets: nwg::Frame,
+    grd_presets: nwg::GridLayout,
+    btn_presets: RefCell<Vec<nwg::Button>>,
+    preset_handlers: RefCell<Vec<nwg::EventHandler>>,
+    preset_clicked: Rc<Cell<Option<u16>>>,
+
+    #[nwg_control]
+    #[nwg_events(OnNotice: [DeathAdderv2App::dpi_preset_selected])]
+    preset_notice: nwg::Notice,
+
+    // why there is no device and what to do about it, if there isn't
+    #[nwg_control(text: "", flags: "NONE", h_align: nwg::HTextAlign::Left,
+        v_align: nwg::VTextAlign::Top)]
+    #[nwg_layout_item(layout: grid, row: 10, col_span: 11)]
+    txt_device_error: nwg::Label,
+
+    /*
+     * Whether the device is at the saved profile; checked after applying
+     * one and every few seconds, to catch changes made elsewhere
+     */
+    #[nwg_control(text: "Status:", h_align: nwg::HTextAlign::Right)]
+    #[nwg_layout_item(layout: grid, row: 11, col_span: 3)]
+    lbl_sync: nwg::Label,
+
+    #[nwg_control(text: "", h_align: nwg::HTextAlign::Left)]
+    #[nwg_layout_item(layout: grid, row: 11, col: 3, col_span: 8)]
+    txt_sync: nwg::Label,
+
+    /*
+     * Lift-off distance, on mice whose sensor has it
+     */
+    #[nwg_control(text: "Lift-off distance:", h_align: nwg::HTextAlign::Right, v_align: nwg::VTextAlign::Top)]
+    #[nwg_layout_item(layout: grid, row: 12, col_span: 3)]
+    lbl_liftoff: nwg::Label,
+
+    #[nwg_control(collection: LiftOff::all(), v_align: nwg::VTextAlign::Top)]
+    #[nwg_layout_item(layout: grid, row: 12, col: 3, col_span: 2)]
+    #[nwg_events( OnComboxBoxSelection: [DeathAdderv2App::liftoff_selected(SELF)])]
+    cmb_liftoff: nwg::ComboBox<LiftOff>,
+
+    #[nwg_control(interval: SYNC_CHECK_INTERVAL, active: false)]
+    #[nwg_events(OnTimerTick: [DeathAdderv2App::update_sync_status])]
+    sync_timer: nwg::AnimationTimer,
+
+    /*
+     * Tray icon
+     */
+    #[nwg_resource(source_system: Some(nwg::OemIcon::WinLogo))]
+    tray_icon: nwg::Icon,
+
+    #[nwg_control(icon: Some(&data.tray_icon), tip: Some("Razer DeathAdder v2"))]
+    #[nwg_events(MousePressLeftUp: [DeathAdderv2App::show_window],
+        OnContextMenu: [DeathAdderv2App::show_tray_menu],
+        OnMouseMove: [DeathAdderv2App::tray_hovered])]
+    tray: nwg::TrayNotification,
+
+    #[nwg_control(parent: window, popup: true)]
+    tray_menu: nwg::Menu,
+
+    #[nwg_control(parent: tray_menu, text: "&Show")]
+    #[nwg_events(OnMenuItemSelected: [DeathAdderv2App::show_window])]
+    tray_mni_show: nwg::MenuItem,
+
+    #[nwg_control(parent: tray_menu, text: "&Quick DPI")]
+    tray_mnu_presets: nwg::Menu,
+    tray_mni_presets: RefCell<Vec<nwg::MenuItem>>,
+
+    #[nwg_control(parent: tray_menu, text: "E&xit")]
+    #[nwg_events(OnMenuItemSelected: [DeathAdderv2App::window_close])]
+    tray_mni_exit: nwg::MenuItem,
+
+    /// Wheel scrolled over the tray icon; see `tray`
+    #[nwg_control]
+    #[nwg_events(OnNotice: [DeathAdderv2App::tray_scrolled])]
+    tray_notice: nwg::Notice,
+
     /*
      * Events coming from the device
      */
     #[nwg_control]
-    #[nwg_events(OnNotice: [DeathAdderv2App::update_dpi_selection])]
+    #[nwg_events(OnNotice: [DeathAdderv2App::dpi_button_pressed])]
     dev_dpi_notice: nwg::Notice,
-    dev_dpi_thread: RefCell<Option<thread::JoinHandle<Result<(), HidError>>>>,
+    dpi_changes_ignored_until: Cell<Option<Instant>>,
+    /// Brightness of each zone before the DPI button turned the lighting
+    /// off; empty while it's on
+    lighting_off: RefCell<Vec<(Led, u8)>>,
+    /// Next of `ButtonAction::CycleProfiles` to activate
+    profile_cycle: Cell<usize>,
+
+    // another instance was asked to activate a profile; see activation.rs
+    #[nwg_control]
+    #[nwg_events(OnNotice: [DeathAdderv2App::profile_forwarded])]
+    activation_notice: nwg::Notice,
+    dev_dpi_thread: RefCell<Option<thread::JoinHandle<librazer::error::Result<()>>>>,
     dev_dpi_keepalive: RefCell<Arc<Mutex<bool>>>,
 
+    /*
+     * Color dialog previews fade to the picked color; the dialog is modal,
+     * so this ticks them from its message loop
+     */
+    #[nwg_control(interval: Duration::from_millis(20), active: false)]
+    #[nwg_events(OnTimerTick: [DeathAdderv2App::fade_tick])]
+    fade_timer: nwg::AnimationTimer,
+    fader: Mutex<Fader>,
+
     /*
      * Other members
      */
-    device: RefCell<Option<DeathAdderV2>>,
+    /// One of `MICE`
+    device: RefCell<Option<Box<dyn RazerDevice>>>,
     config: RefCell<Config>,
+    save_config: RefCell<bool>,
     ui_events_enabled: RefCell<bool>,
+    /// Mirrors `Config::onboard`, readable while the config is borrowed
+    onboard: Cell<bool>,
+    /// `Config::stage_colors` while the stage indicator is on, empty while
+    /// it's off; readable while the config is borrowed
+    stage_colors: RefCell<Vec<RGB8>>,
+    /// The device asked for on the command line, selected again on retry
+    wanted_serial: RefCell<Option<String>>,
 }
 
 impl DeathAdderv2App {
@@ -348,9 +585,9 @@ impl DeathAdderv2App {
     /// Note: will not execute if device is None
     fn with_device<U, F>(&self, dav2: F) -> Option<U>
     where
-        F: FnOnce(&DeathAdderV2) -> U,
+        F: FnOnce(&dyn RazerMouse) -> U,
     {
-        self.device.borrow().as_ref().map(dav2)
+        self.device.borrow().as_ref().and_then(|device| device.as_mouse()).map(dav2)
     }
 
     /// Borrow config and apply closure
@@ -371,12 +608,56 @@ impl DeathAdderv2App {
         cfg_cb(&mut (*cfg))
     }
 
-    fn rad_dpistages(&self) -> Vec<&nwg::RadioButton> {
-        vec![&self.par_stages.rad_dpi_1,
-            &self.par_stages.rad_dpi_2,
-            &self.par_stages.rad_dpi_3,
-            &self.par_stages.rad_dpi_4,
-            &self.par_stages.rad_dpi_5]
+    fn rad_dpistages(&self) -> Ref<'_, Vec<nwg::RadioButton>> {
+        self.rad_stages.borrow()
+    }
+
+    /// (Re)create the stage radio buttons for a device with these capabilities
+    fn build_dpi_stage_controls(&self, caps: &Capabilities) {
+        let max_stages = caps.dpi_stages;
+        if self.rad_stages.borrow().len() == max_stages {
+            return;
+        }
+
+        let mut rad_stages = Vec::with_capacity(max_stages);
+        let mut layout = nwg::GridLayout::builder()
+            .parent(&self.frm_stages)
+            .margin([0, 0, 0, 0])
+            .max_column(Some(max_stages.max(1) as u32));
+        for i in 0..max_stages {
+            let flags = if i == 0 {
+                nwg::RadioButtonFlags::VISIBLE | nwg::RadioButtonFlags::GROUP
+            } else {
+                nwg::RadioButtonFlags::VISIBLE
+            };
+            // placeholder until read from the device; spread over the range
+            let dpi = caps.dpi.map_or(0, |range|
+                range.fit((range.max as usize * (i + 1) / max_stages) as u16));
+            let mut rad = nwg::RadioButton::default();
+            _ = nwg::RadioButton::builder()
+                .text(&dpi.to_string())
+                .flags(flags)
+                .parent(&self.frm_stages)
+                .build(&mut rad)
+                .map_err(|e| dbglog!("Failed to create DPI stage control: {}", e));
+            // v_align; nwg doesn't provide the option
+            add_style(&rad.handle, BS_TOP);
+            rad_stages.push(rad);
+        }
+        for (i, rad) in rad_stages.iter().enumerate() {
+            layout = layout.child(i as u32, 0, rad);
+        }
+        _ = layout.build(&self.grd_stages)
+            .map_err(|e| dbglog!("Failed to lay out DPI stage controls: {}", e));
+
+        // the old ones get destroyed when dropped
+        self.rad_stages.replace(rad_stages);
+
+        let ui_events_enabled = self.ui_events_enabled.replace(false);
+        self.cmb_numstages.set_collection((1..=max_stages)
+            .map(|n| format!("{} DPI stage{}", n, if n == 1 { "" } else { "s" }))
+            .collect());
+        self.ui_events_enabled.replace(ui_events_enabled);
     }
 
     fn set_device_controls_enabled(&self, enabled: bool) {
@@ -385,10 +666,310 @@ impl DeathAdderv2App {
         self.bar_stagedpi.set_enabled(enabled);
         self.bar_currdpi.set_enabled(enabled);
         self.cmb_pollrate.set_enabled(enabled);
+        self.cmb_liftoff.set_enabled(enabled);
         self.chk_samecolor.set_enabled(enabled);
+        self.chk_freespin.set_enabled(enabled);
+        self.chk_smartreel.set_enabled(enabled);
+        self.chk_onboard.set_enabled(enabled);
         self.bar_logobright.set_enabled(enabled);
         self.bar_scrollbright.set_enabled(enabled);
         self.chk_samebright.set_enabled(enabled);
+        self.frm_presets.set_enabled(enabled);
+        self.tray_mnu_presets.set_enabled(enabled);
+    }
+
+    /// Show only the controls that apply to a device with these capabilities
+    fn show_capabilities(&self, caps: &Capabilities) {
+        let logo = caps.has_led(Led::Logo);
+        let scroll = caps.has_led(Led::ScrollWheel);
+
+        // no color swatches for single color LEDs
+        let logo_rgb = caps.has_rgb(Led::Logo);
+        let scroll_rgb = caps.has_rgb(Led::ScrollWheel);
+        self.lbl_logocolor.set_visible(logo_rgb);
+        self.btn_logocolor.set_visible(logo_rgb);
+        self.lbl_scrollcolor.set_visible(scroll_rgb);
+        self.btn_scrollcolor.set_visible(scroll_rgb);
+        self.chk_samecolor.set_visible(logo_rgb && scroll_rgb);
+
+        self.lbl_logobright.set_visible(logo);
+        self.bar_logobright.set_visible(logo);
+        self.txt_logobright.set_visible(logo);
+        self.lbl_scrollbright.set_visible(scroll);
+        self.bar_scrollbright.set_visible(scroll);
+        self.txt_scrollbright.set_visible(scroll);
+        self.chk_samebright.set_visible(logo && scroll);
+
+        if let Some(dpi) = caps.dpi {
+            for bar in [&self.bar_stagedpi, &self.bar_currdpi] {
+                bar.set_range_min(dpi.min as usize);
+                bar.set_range_max(dpi.max as usize);
+                configure_trackbar(bar, dpi.step as isize, 1000, 1000);
+            }
+        }
+
+        self.cmb_pollrate.set_collection(caps.polling_rates.clone());
+        self.txt_battery.set_visible(caps.battery);
+        self.chk_freespin.set_visible(caps.scroll_mode);
+        self.chk_smartreel.set_visible(caps.scroll_mode);
+        self.lbl_liftoff.set_visible(caps.liftoff);
+        self.cmb_liftoff.set_visible(caps.liftoff);
+
+        // nothing of the lighting applies to mice without LEDs, e.g. the Orochi V2
+        let lit = !caps.leds.is_empty();
+        self.chk_onboard.set_visible(lit);
+        self.mni_live_preview.set_enabled(lit);
+        self.mni_stock_lighting.set_enabled(lit);
+        self.mni_stage_indicator.set_enabled(scroll_rgb);
+        self.mni_stage_color.set_enabled(scroll_rgb);
+    }
+
+    /// (Re)create a swatch for each zone other than the logo and scroll wheel
+    fn build_zone_controls(&self, caps: &Capabilities) {
+        let zones = caps.leds.iter()
+            .filter(|cap| cap.kind == LedKind::Rgb)
+            .map(|cap| cap.led)
+            .filter(|&led| led != Led::Logo && led != Led::ScrollWheel)
+            .collect::<Vec<Led>>();
+        let current = self.btn_zones.borrow().iter()
+            .map(|&(led, _)| led)
+            .collect::<Vec<Led>>();
+        if zones == current {
+            return;
+        }
+
+        for handler in self.zone_handlers.take() {
+            nwg::unbind_event_handler(&handler);
+        }
+
+        let mut layout = nwg::GridLayout::builder()
+            .parent(&self.frm_zones)
+            .margin([0, 0, 0, 0])
+            .max_column(Some(zones.len().max(1) as u32));
+        let mut btn_zones = Vec::with_capacity(zones.len());
+        let mut handlers = Vec::with_capacity(zones.len());
+        for led in zones {
+            let mut btn = nwg::RichLabel::default();
+            _ = nwg::RichLabel::builder()
+                .text(led.name())
+                .line_height(Some(20))
+                .parent(&self.frm_zones)
+                .build(&mut btn)
+                .map_err(|e| dbglog!("Failed to create {} zone control: {}", led.name(), e));
+
+            // created at runtime, so nwd can't route their clicks
+            let clicked = Rc::clone(&self.zone_clicked);
+            let notice = self.zone_notice.sender();
+            handlers.push(nwg::bind_event_handler(&btn.handle, &self.window.handle,
+                move |evt, _evt_data, _handle| {
+                    if let nwg::Event::OnMousePress(nwg::MousePressEvent::MousePressLeftUp) = evt {
+                        clicked.set(Some(led));
+                        notice.notice();
+                    }
+                }));
+            btn_zones.push((led, btn));
+        }
+        for (i, (_, btn)) in btn_zones.iter().enumerate() {
+            layout = layout.child(i as u32, 0, btn);
+        }
+        _ = layout.build(&self.grd_zones)
+            .map_err(|e| dbglog!("Failed to lay out zone controls: {}", e));
+
+        let any = !btn_zones.is_empty();
+        self.btn_zones.replace(btn_zones);
+        self.zone_handlers.replace(handlers);
+        self.lbl_zones.set_visible(any);
+        self.frm_zones.set_visible(any);
+    }
+
+    /// A button and a tray menu item for each of the configured DPI presets
+    fn build_dpi_preset_controls(&self) {
+        let presets = self.with_config(|cfg| cfg.dpi_presets.clone());
+
+        for handler in self.preset_handlers.take() {
+            nwg::unbind_event_handler(&handler);
+        }
+
+        let mut layout = nwg::GridLayout::builder()
+            .parent(&self.frm_presets)
+            .margin([0, 0, 0, 0])
+            .max_column(Some(presets.len().max(1) as u32));
+        let mut buttons = Vec::with_capacity(presets.len());
+        let mut items = Vec::with_capacity(presets.len());
+        let mut handles = Vec::with_capacity(presets.len() * 2);
+        for &dpi in &presets {
+            let mut btn = nwg::Button::default();
+            _ = nwg::Button::builder()
+                .text(&dpi.to_string())
+                .parent(&self.frm_presets)
+                .build(&mut btn)
+                .map_err(|e| dbglog!("Failed to create DPI preset control: {}", e));
+            let mut item = nwg::MenuItem::default();
+            _ = nwg::MenuItem::builder()
+                .text(&dpi.to_string())
+                .parent(&self.tray_mnu_presets)
+                .build(&mut item)
+                .map_err(|e| dbglog!("Failed to create DPI preset menu item: {}", e));
+            handles.push((btn.handle, dpi));
+            handles.push((item.handle, dpi));
+            buttons.push(btn);
+            items.push(item);
+        }
+        for (i, btn) in buttons.iter().enumerate() {
+            layout = layout.child(i as u32, 0, btn);
+        }
+        _ = layout.build(&self.grd_presets)
+            .map_err(|e| dbglog!("Failed to lay out DPI preset controls: {}", e));
+
+        // created at runtime, so nwd can't route their clicks; the buttons
+        // report to the frame, the menu items to the window
+        let mut handlers = Vec::with_capacity(2);
+        for parent in [&self.frm_presets.handle, &self.window.handle] {
+            let clicked = Rc::clone(&self.preset_clicked);
+            let notice = self.preset_notice.sender();
+            let handles = handles.clone();
+            handlers.push(nwg::bind_event_handler(parent, &self.window.handle,
+                move |evt, _evt_data, handle| {
+                    let dpi = handles.iter().find(|(h, _)| *h == handle).map(|&(_, dpi)| dpi);
+                    match (evt, dpi) {
+                        (nwg::Event::OnButtonClick, Some(dpi)) |
+                        (nwg::Event::OnMenuItemSelected, Some(dpi)) => {
+                            clicked.set(Some(dpi));
+                            notice.notice();
+                        },
+                        _ => {},
+                    }
+                }));
+        }
+
+        // the old ones get destroyed when dropped
+        self.btn_presets.replace(buttons);
+        self.tray_mni_presets.replace(items);
+        self.preset_handlers.replace(handlers);
+        self.lbl_presets.set_visible(!presets.is_empty());
+        self.frm_presets.set_visible(!presets.is_empty());
+        self.tray_mnu_presets.set_enabled(!presets.is_empty());
+    }
+
+    fn dpi_preset_selected(&self) {
+        let dpi = match self.preset_clicked.take() {
+            Some(dpi) => self.fit_dpi(dpi as usize),
+            None => return,
+        };
+        match self.with_device(|dav2| dav2.set_dpi(dpi, dpi)) {
+            Some(Ok(())) => self.set_current_dpi_ui(dpi as usize),
+            Some(Err(e)) => msgboxerror!("Failed to set DPI: {}", e),
+            None => {},
+        }
+    }
+
+    fn zone_color_clicked(&self) {
+        let led = match self.zone_clicked.take() {
+            Some(led) => led,
+            None => return,
+        };
+        if !*self.ui_events_enabled.borrow() {
+            return;
+        }
+
+        self.with_mut_config(|cfg| {
+            self.with_device(|dav2| {
+                let mut dialog = ColorDialog::new();
+                let parent = HWND(self.window.handle.hwnd().unwrap() as isize);
+                let initial = cfg.zone_color(led);
+                let fader = &self.fader;
+                let live_preview = cfg.live_preview;
+                let last = Mutex::new(initial.unwrap_or_default());
+                let change_cb = Some(move |_: &ColorDialog, &color: &RGB8| {
+                    if !live_preview {
+                        return;
+                    }
+                    let mut last = last.lock().unwrap();
+                    fader.lock().unwrap().start(led, *last, color, FADE_DURATION);
+                    *last = color;
+                });
+
+                let chosen = self.show_fading(|| dialog.show(parent, initial, change_cb));
+                match chosen {
+                    Some(color) => {
+                        cfg.set_zone_color(led, color);
+                        self.set_zone_color(dav2, led, color);
+                    },
+                    // put back what it was, if we know
+                    None => {
+                        _ = initial.map(|color| dav2.preview_led_color(led, color));
+                    },
+                }
+            });
+        });
+    }
+
+    /// Run `show_dialog` with the fade timer going; fades still in progress
+    /// after it are dropped, as the colors are then set for good
+    fn show_fading<F: FnOnce() -> Option<RGB8>>(&self, show_dialog: F) -> Option<RGB8> {
+        self.fade_timer.start();
+        let chosen = show_dialog();
+        self.fade_timer.stop();
+        *self.fader.lock().unwrap() = Fader::default();
+        // the scroll wheel may have faded away from the stage color
+        self.show_stage_color();
+        chosen
+    }
+
+    fn fade_tick(&self) {
+        let mut fader = self.fader.lock().unwrap();
+        if fader.is_idle() {
+            return;
+        }
+        // done fades are left previewed; the dialog sets the color on OK
+        self.with_device(|dav2| {
+            let done = fader.tick(dav2)?;
+            done.into_iter().try_for_each(|(led, color)| dav2.preview_led_color(led, color))
+        }).map(|res| res.map_err(|e| dbglog!("Failed to fade color: {}", e)));
+    }
+
+    fn set_zone_color(&self, dav2: &dyn RazerMouse, led: Led, color: RGB8) {
+        if let Some((_, btn)) = self.btn_zones.borrow().iter().find(|(l, _)| *l == led) {
+            btn.set_background_color(color.into());
+        }
+        _ = self.write_led_color(dav2, led, color).map_err(|e| {
+            msgboxerror!("Failed to set {} color: {}", led.name(), e)
+        });
+    }
+
+    /// Saved on the device or just previewed, depending on `chk_onboard`
+    fn write_led_color(&self, dav2: &dyn RazerMouse, led: Led, color: RGB8) -> USBResult<()> {
+        // the stage indicator has the scroll wheel
+        if led == Led::ScrollWheel && !self.stage_colors.borrow().is_empty() {
+            return Ok(());
+        }
+        if self.onboard.get() {
+            dav2.set_led_color(led, color)
+        } else {
+            dav2.preview_led_color(led, color)
+        }
+    }
+
+    /// Saved on the device or just previewed, depending on `chk_onboard`
+    fn write_led_brightness(
+        &self,
+        dav2: &dyn RazerMouse,
+        led: Led,
+        brightness: u8
+    ) -> USBResult<()> {
+        if self.onboard.get() {
+            dav2.set_led_brightness(led, brightness)
+        } else {
+            dav2.preview_led_brightness(led, brightness)
+        }
+    }
+
+    /// Round a DPI value to what the current device accepts
+    fn fit_dpi(&self, dpi: usize) -> u16 {
+        let dpi = dpi.min(u16::MAX as usize) as u16;
+        self.with_device(|dav2| dav2.capabilities().dpi)
+            .flatten()
+            .map_or(dpi, |range| range.fit(dpi))
     }
 
     // mainly called by the device DPI listener
@@ -405,7 +986,7 @@ impl DeathAdderv2App {
             match dav2.get_dpi_stages() {
                 Ok((dpi_stages, current)) => {
                     let rad_stages = self.rad_dpistages();
-                    let ui_current = rad_stages.iter().position(|&rad|
+                    let ui_current = rad_stages.iter().position(|rad|
                         rad.check_state() == RadioButtonState::Checked
                     ).unwrap_or(100);
 
@@ -419,7 +1000,7 @@ impl DeathAdderv2App {
                     self.cmb_numstages.set_selection(Some(dpi_stages.len()-1));
                     let mut i = 0;
                     let mut stages = dpi_stages.iter();
-                    for rad in rad_stages {
+                    for rad in rad_stages.iter() {
                         match stages.next() {
                             Some(&(dpi, _)) => {
                                 rad.set_visible(true);
@@ -452,9 +1033,162 @@ impl DeathAdderv2App {
 
         // re-enable events
         self.ui_events_enabled.replace(true);
+        self.update_tray_tip();
+        self.show_stage_color();
+    }
+
+    /// The device reported a DPI change; unless the DPI button is bound to
+    /// something else, that's the button stepping through the stages
+    fn dpi_button_pressed(&self) {
+        // the config is borrowed while a dialog is open; stick to the stages then
+        let action = self.config.try_borrow().map_or(ButtonAction::Stages,
+            |cfg| cfg.dpi_button.clone());
+        if action == ButtonAction::Stages {
+            self.update_dpi_selection();
+            return;
+        }
+        if self.dpi_changes_ignored_until.get().map_or(false, |until| Instant::now() < until) {
+            return;
+        }
+
+        // put back the stage the button stepped away from
+        let stage = self.rad_dpistages().iter()
+            .position(|rad| rad.check_state() == RadioButtonState::Checked);
+        if let Some(stage) = stage {
+            self.with_device(|dav2| dav2.set_active_dpi_stage(stage as u8))
+                .map(|res| res.map_err(|e| dbglog!("Failed to restore DPI stage: {}", e)));
+            self.dpi_changes_ignored_until.set(Some(Instant::now() + DPI_UNDO_GRACE));
+        }
+
+        match action {
+            ButtonAction::Stages => {},
+            ButtonAction::CycleProfiles(profiles) => {
+                if profiles.is_empty() {
+                    return;
+                }
+                let next = self.profile_cycle.get() % profiles.len();
+                self.profile_cycle.set(next + 1);
+                self.activate_profile(&profiles[next]);
+            },
+            ButtonAction::ToggleLighting => self.toggle_lighting(),
+            ButtonAction::Command(command) => {
+                use std::os::windows::process::CommandExt;
+                const CREATE_NO_WINDOW: u32 = 0x08000000;
+                let res = std::process::Command::new("cmd")
+                    .args(["/C", &command])
+                    .creation_flags(CREATE_NO_WINDOW)
+                    .spawn();
+                if let Err(e) = res {
+                    msgboxerror!("Failed to run '{}': {}", command, e);
+                }
+            },
+        }
+    }
+
+    /// Turn all zones off, or back to the brightness they had before;
+    /// previewed, so the lighting saved on the device stays as it was
+    fn toggle_lighting(&self) {
+        let saved = self.lighting_off.take();
+        let res = self.with_device(|dav2| -> USBResult<()> {
+            if !saved.is_empty() {
+                return saved.iter()
+                    .try_for_each(|&(led, b)| dav2.preview_led_brightness(led, b));
+            }
+            let mut off = Vec::new();
+            for led in dav2.zones() {
+                off.push((led, dav2.get_led_brightness(led)?));
+                dav2.preview_led_brightness(led, 0)?;
+            }
+            self.lighting_off.replace(off);
+            Ok(())
+        });
+        if let Some(Err(e)) = res {
+            msgboxerror!("Failed to toggle the lighting: {}", e);
+        }
+    }
+
+    /// Preview the color of the active DPI stage on the scroll wheel, if the
+    /// stage indicator is on; previewed so stage changes don't wear the flash
+    fn show_stage_color(&self) {
+        let colors = self.stage_colors.borrow();
+        if colors.is_empty() {
+            return;
+        }
+        self.with_device(|dav2| {
+            let (_, current) = dav2.get_dpi_stages()?;
+            match colors.get(current as usize) {
+                Some(&color) => dav2.preview_led_color(Led::ScrollWheel, color),
+                None => Ok(()),
+            }
+        }).map(|res| res.map_err(|e| dbglog!("Failed to show DPI stage color: {}", e)));
+    }
+
+    fn stage_indicator_clicked(&self) {
+        let on = !self.mni_stage_indicator.checked();
+        self.mni_stage_indicator.set_checked(on);
+        let (colors, scroll_color) = self.with_mut_config(|cfg| {
+            cfg.stage_indicator = on;
+            let scroll_color = if cfg.same_color { cfg.logo_color } else { cfg.scroll_color };
+            (cfg.stage_colors.clone(), scroll_color)
+        });
+        self.save_config.replace(true);
+        self.stage_colors.replace(if on { colors } else { Vec::new() });
+        if on {
+            self.show_stage_color();
+        } else {
+            // give the scroll wheel its own color back
+            self.set_scroll_color(scroll_color);
+        }
+    }
+
+    fn stage_color_clicked(&self) {
+        let current = match self.with_device(|dav2| dav2.get_dpi_stages()) {
+            Some(Ok((_, current))) => current,
+            Some(Err(e)) => {
+                msgboxerror!("Failed to get DPI stages: {}", e);
+                return;
+            },
+            None => return,
+        };
+
+        let mut dialog = ColorDialog::new();
+        let parent = HWND(self.window.handle.hwnd().unwrap() as isize);
+        let (initial, live_preview) = self.with_config(|cfg|
+            (cfg.stage_colors.get(current as usize).copied(), cfg.live_preview));
+        let fader = &self.fader;
+        let last = Mutex::new(initial.unwrap_or_default());
+        let change_cb = Some(move |_: &ColorDialog, &color: &RGB8| {
+            if !live_preview {
+                return;
+            }
+            let mut last = last.lock().unwrap();
+            fader.lock().unwrap().start(Led::ScrollWheel, *last, color, FADE_DURATION);
+            *last = color;
+        });
+        let color = match self.show_fading(|| dialog.show(parent, initial, change_cb)) {
+            Some(color) => color,
+            None => return,
+        };
+
+        let colors = self.with_mut_config(|cfg| {
+            cfg.set_stage_color(current, color);
+            cfg.stage_colors.clone()
+        });
+        self.save_config.replace(true);
+        if !self.stage_colors.borrow().is_empty() {
+            self.stage_colors.replace(colors);
+        }
+        self.show_stage_color();
     }
 
-    fn update_ui_values(&self) {
+    /// Refresh the controls from the device; colors can't be read back so
+    /// they're taken from the config and, if `apply_colors`, sent to the device
+    fn update_ui_values(&self, apply_colors: bool) {
+        self.with_device(|dav2| {
+            self.build_dpi_stage_controls(&dav2.capabilities());
+            self.build_zone_controls(&dav2.capabilities());
+        });
+        self.txt_stages_error.set_text("");
         self.update_dpi_selection();
 
         // we will be modifying controls here; some of them fire 'change'
@@ -463,9 +1197,41 @@ impl DeathAdderv2App {
 
         self.set_device_controls_enabled(self.device.borrow().is_some());
 
-        match self.device.borrow().as_ref() {
+        match self.device.borrow().as_ref().and_then(|device| device.as_mouse()) {
             Some(dav2) => {
 
+                self.show_capabilities(&dav2.capabilities());
+
+                if dav2.capabilities().scroll_mode {
+                    match dav2.get_scroll_mode() {
+                        Ok(mode) => self.chk_freespin.set_check_state(
+                            to_check_state!(mode == ScrollMode::FreeSpin)),
+                        Err(e) => dbglog!("Failed to get scroll mode: {}", e),
+                    }
+                    let smart_reel = dav2.get_smart_reel().unwrap_or(false);
+                    self.chk_smartreel.set_check_state(to_check_state!(smart_reel));
+                    // smart reel switches the mode by itself
+                    self.chk_freespin.set_enabled(!smart_reel);
+                }
+
+                if dav2.capabilities().liftoff {
+                    match dav2.get_liftoff() {
+                        Ok(liftoff) => {
+                            let collection = self.cmb_liftoff.collection();
+                            let index = collection.iter().position(|&l| l == liftoff);
+                            self.cmb_liftoff.set_selection(index);
+                        },
+                        Err(e) => dbglog!("Failed to get lift-off distance: {}", e),
+                    }
+                }
+
+                if dav2.capabilities().battery {
+                    match dav2.get_battery_level() {
+                        Ok(level) => self.txt_battery.set_text(&format!("Battery: {}%", level)),
+                        Err(e) => self.txt_battery.set_text(&format!("Battery: {}", e)),
+                    }
+                }
+
                 match dav2.get_dpi() {
                     Ok((dpi, _)) => self.bar_currdpi.set_pos(dpi as usize),
                     Err(e) => {
@@ -486,26 +1252,31 @@ impl DeathAdderv2App {
                     }
                 };
 
-                match dav2.get_logo_brightness() {
-                    Ok(b) => self.bar_logobright.set_pos(b as usize),
-                    Err(e) => {
-                        msgboxerror!("Failed to get logo brightness: {}", e);
-                        self.bar_logobright.set_enabled(false);
-                    }
-                };
+                if dav2.capabilities().has_led(Led::Logo) {
+                    match dav2.get_logo_brightness() {
+                        Ok(b) => self.bar_logobright.set_pos(b as usize),
+                        Err(e) => {
+                            msgboxerror!("Failed to get logo brightness: {}", e);
+                            self.bar_logobright.set_enabled(false);
+                        }
+                    };
+                }
 
-                match dav2.get_scroll_brightness() {
-                    Ok(b) => self.bar_scrollbright.set_pos(b as usize),
-                    Err(e) => {
-                        msgboxerror!("Failed to get scroll whell brightness: {}", e);
-                        self.bar_scrollbright.set_enabled(false);
-                    }
-                };
+                if dav2.capabilities().has_led(Led::ScrollWheel) {
+                    match dav2.get_scroll_brightness() {
+                        Ok(b) => self.bar_scrollbright.set_pos(b as usize),
+                        Err(e) => {
+                            msgboxerror!("Failed to get scroll whell brightness: {}", e);
+                            self.bar_scrollbright.set_enabled(false);
+                        }
+                    };
+                }
             },
 
             None => { // no device; set some defaults
                 self.set_stage_dpi_ui(self.bar_stagedpi.range_min());
                 self.cmb_pollrate.set_selection(None);
+                self.cmb_liftoff.set_selection(None);
                 self.bar_logobright.set_pos(self.bar_logobright.range_min());
                 self.bar_scrollbright.set_pos(self.bar_scrollbright.range_min());
             },
@@ -517,80 +1288,168 @@ impl DeathAdderv2App {
         self.txt_scrollbright.set_text(&self.bar_scrollbright.pos().to_string());
 
         self.with_config(|cfg| {
-            // can't take these from the device; assume they're what the config says
-            self.set_logo_color(cfg.logo_color);
-            self.set_scroll_color(cfg.scroll_color);
-            self.set_same_color(cfg.same_color, true);
-            self.set_same_brightness(cfg.same_brightness, true);
+            self.onboard.set(cfg.onboard);
+            self.mni_stage_indicator.set_checked(cfg.stage_indicator);
+            self.stage_colors.replace(if cfg.stage_indicator {
+                cfg.stage_colors.clone()
+            } else {
+                Vec::new()
+            });
+            self.chk_onboard.set_check_state(to_check_state!(cfg.onboard));
+            self.mni_live_preview.set_checked(cfg.live_preview);
+
+            for (led, btn) in self.btn_zones.borrow().iter() {
+                if let Some(color) = cfg.zone_color(*led) {
+                    btn.set_background_color(color.into());
+                    if apply_colors {
+                        self.with_device(|dav2| self.set_zone_color(dav2, *led, color));
+                    }
+                }
+            }
+
+            if apply_colors {
+                // can't take these from the device; assume they're what the config says
+                self.set_logo_color(cfg.logo_color);
+                self.set_scroll_color(cfg.scroll_color);
+                self.set_same_color(cfg.same_color, true);
+                self.set_same_brightness(cfg.same_brightness, true);
+            } else {
+                self.btn_logocolor.set_background_color(cfg.logo_color.into());
+                self.btn_scrollcolor.set_background_color(cfg.scroll_color.into());
+                self.chk_samecolor.set_check_state(to_check_state!(cfg.same_color));
+                self.chk_samebright.set_check_state(to_check_state!(cfg.same_brightness));
+                self.bar_scrollbright.set_enabled(!cfg.same_brightness);
+            }
         });
 
         // re-enable events
         self.ui_events_enabled.replace(ui_events_enabled);
+        self.update_tray_tip();
+        self.show_stage_color();
+        self.update_sync_status();
     }
 
-    fn spawn_dev_dpi_listener_thread(&self, dav2: &DeathAdderV2) {
+    /// Current DPI, stage and battery in the tray icon's tooltip
+    fn update_tray_tip(&self) {
+        let mut tip = String::from("Razer DeathAdder v2");
+        self.with_device(|dav2| {
+            if let Ok((dpi, _)) = dav2.get_dpi() {
+                tip += &format!("\nDPI: {}", dpi);
+            }
+            if let Ok((stages, current)) = dav2.get_dpi_stages() {
+                tip += &format!(" (stage {}/{})", current + 1, stages.len());
+            }
+            if dav2.capabilities().battery {
+                if let Ok(level) = dav2.get_battery_level() {
+                    tip += &format!("\nBattery: {}%", level);
+                }
+            }
+        });
+        self.tray.set_tip(&tip);
+    }
+
+    fn show_tray_menu(&self) {
+        let (x, y) = nwg::GlobalCursor::position();
+        self.tray_menu.popup(x, y);
+    }
+
+    fn show_window(&self) {
+        self.window.set_visible(true);
+        self.window.restore();
+        self.window.set_focus();
+        self.update_sync_status();
+    }
+
+    /// Compare the device with the saved profile. Skipped while the window
+    /// is hidden; there's no one to see it.
+    fn update_sync_status(&self) {
+        if !self.window.visible() {
+            return;
+        }
+        // the timer also ticks while a dialog has the config borrowed
+        let cfg = match self.config.try_borrow() {
+            Ok(cfg) => cfg,
+            Err(_) => return,
+        };
+        let status = self.with_device(|dav2| {
+            // the device can't report its colors; take them from the config
+            let snapshot = Profile::snapshot(dav2).map(|mut snapshot| {
+                snapshot.colors = cfg.profile().colors;
+                snapshot
+            });
+            SyncStatus::compare(cfg.profile.as_ref(), &snapshot)
+        });
+        let text = match status {
+            Some(SyncStatus::Modified(fields)) if !fields.is_empty() => {
+                let fields: Vec<String> = fields.iter().map(ToString::to_string).collect();
+                format!("modified (unsaved): {}", fields.join(", "))
+            },
+            Some(status) => status.to_string(),
+            None => String::new(),
+        };
+        self.txt_sync.set_text(&text);
+    }
+
+    fn tray_hovered(&self) {
+        tray::hovered();
+    }
+
+    /// Step through the DPI stages, up for scrolling up
+    fn tray_scrolled(&self) {
+        let steps = tray::take_steps();
+        if steps == 0 {
+            return;
+        }
+        let res: Option<USBResult<()>> = self.with_device(|dav2| {
+            let (stages, current) = dav2.get_dpi_stages()?;
+            let last = stages.len().saturating_sub(1) as i32;
+            let next = (current as i32 + steps).clamp(0, last) as u8;
+            if next != current {
+                dav2.set_active_dpi_stage(next)?;
+            }
+            Ok(())
+        });
+        if let Some(Err(e)) = res {
+            self.tray.set_tip(&format!("Failed to change DPI stage: {}", e));
+            return;
+        }
+        self.update_dpi_selection();
+    }
+
+    /// `ports` is where the device is on the USB tree, to only listen to its
+    /// own HID interfaces; without it, or if that doesn't pan out, we listen
+    /// to those of all devices of the model, which only costs an extra UI
+    /// update here and there
+    fn spawn_dev_dpi_listener_thread(&self, dav2: &dyn RazerMouse, ports: Option<Vec<u8>>) {
         let vid = dav2.vid();
         let pid = dav2.pid();
-        // wish we could use the serial to pick the specific device
-        // but hidapi (or windows?) won't report the serial so i
-        // don't have a way to match it; In any case, even if more than
-        // one DeathAdderV2s are connected, it doesn't harm to get an
-        // extra event here and there and make an extra update in the UI
 
         self.dev_dpi_keepalive.replace(Arc::new(Mutex::new(true)));
         let keepalive = Arc::clone(&self.dev_dpi_keepalive.borrow());
         let sender = self.dev_dpi_notice.sender();
-        *self.dev_dpi_thread.borrow_mut() = Some(thread::spawn(move || {
-
-            const REPORT_SIZE: usize = 16;
-
-            // we will be filtering mutli-reporting of the same event
-            let mut last_dev_noticed: Option<&HidDevice> = None;
-            let mut last_buf_noticed = [0; REPORT_SIZE];
-
-            let api = HidApi::new()?;
-
-            // and here we have another problem: DeathAdderV2 has 2 HID
-            // devices with the exact same i/f num, usage and usage page
-            // and i don't know how to distinguish between the 2 without
-            // looking in the path, which is supposed to be opaque anyways;
-            // the solution i chose is to open and listen on both of them
-            // and split the reads and their timeout evenly among them;
-            // if any of them reports a DPI change, we update the UI. In
-            // theory, if there's many of them, it could add delay-to-read
-            // but in practise it isn't noticeable
-            let devinfos = api.device_list().filter(|d| {
-                d.vendor_id() == vid && d.product_id() == pid &&
-                d.interface_number() == 1 && d.usage() == 0 &&
-                d.usage_page() == 1
-            });
+        *self.dev_dpi_thread.borrow_mut() = Some(thread::spawn(move || {
 
-            let devs = devinfos.filter_map(|devinfo| {
-                devinfo.open_device(&api).ok()
-            }).collect::<Vec<HidDevice>>();
+            // DeathAdderV2 has 2 HID interfaces with the exact same i/f num,
+            // usage and usage page; we listen on both and update the UI if
+            // any of them reports a DPI change
+            let infos = hid::device_infos(vid, pid)
+                .context("failed to list HID interfaces")?
+                .into_iter()
+                .filter(|d| d.interface_number() == 1 && d.usage() == 0 &&
+                    d.usage_page() == 1)
+                .collect::<Vec<_>>();
+
+            // narrow it down to the selected unit, if we can tell which it is
+            let ours = infos.iter().filter(|d| {
+                ports.is_some() &&
+                d.path().to_str().ok().and_then(hidpath::usb_ports) == ports
+            }).cloned().collect::<Vec<_>>();
+
+            let mut listener = EventListener::open(if ours.is_empty() { &infos } else { &ours })
+                .context("failed to open HID interfaces")?;
 
-            let timeout = (300 / devs.len()) as i32;
             loop {
-
-                // find a device that reports a (new) DPI event
-                let dpi_reporting_dev = devs.iter().find(|&dev| {
-                    let mut buf = [0; REPORT_SIZE];
-                    match dev.read_timeout(&mut buf[..], timeout) {
-                        Ok(REPORT_SIZE) => {
-                            if buf[0] == 0x05 && buf[1] == 0x02 && (
-                                last_dev_noticed.is_none() ||
-                                !ptr::eq(last_dev_noticed.unwrap(), dev) ||
-                                buf != last_buf_noticed
-                            ) {
-                                last_dev_noticed = Some(dev);
-                                last_buf_noticed = buf;
-                                return true;
-                            }
-                            false
-                        },
-                        _ => false,
-                    }
-                });
+                let dpi_changed = listener.wait_dpi_change(Duration::from_millis(300));
 
                 let keepalive_lock = keepalive.lock();
                 if !*keepalive_lock.unwrap() {
@@ -598,7 +1457,7 @@ impl DeathAdderv2App {
                     return Ok(());
                 }
 
-                if dpi_reporting_dev.is_some() {
+                if dpi_changed {
                     sender.notice();
                 }
             } // end of main thread loop
@@ -611,22 +1470,43 @@ impl DeathAdderv2App {
         let prev_keepalive_mutex = prev_keepalive_ref.as_ref();
         let prev_keepalive_lock = prev_keepalive_mutex.lock();
 
-        // attempt to open the newly selected device (using DeathAdderV2::from(..))
+        // attempt to open the newly selected device
         let collection = self.cmb_device.collection();
         let dev = self.cmb_device.selection().and_then(|i| collection.get(i));
+        let ports = dev.and_then(|d| d.as_ref()).and_then(|d| d.port_numbers().ok());
+        let release_when_idle = self.with_config(|cfg| cfg.release_when_idle);
         let dav2 = dev.and_then(|d| {
-            match DeathAdderV2::from(d) {
+            let dav2 = if release_when_idle {
+                device::open_on_demand(d, IDLE_RELEASE_TIMEOUT)
+            } else {
+                device::open(d)
+            };
+            match dav2 {
                 Ok(d) => Some(d),
-                Err(e) => {
-                    msgboxerror!("Error opening device: {}", e);
-                    None
+                Err(e) => match e.driver_problem() {
+                    Some(problem) => self.troubleshoot_driver(problem),
+                    None => {
+                        self.show_device_error(Some(format!("Error opening device: {}. \
+                            Check it is plugged in and not in use, then press Retry.", e)));
+                        None
+                    }
                 }
             }
         });
 
+        dav2.as_deref().map(crash::watch);
+
+        // talk to it as configured, with what we learned about it last time
+        dav2.as_deref().map(|dav2| self.with_config(|cfg| app_core::prepare(dav2, cfg)));
+
+        if dav2.is_some() {
+            self.show_device_error(None);
+        }
+
         // update the UI accordingly
+        self.remember_receiver_wait();
         self.device.replace(dav2);
-        self.update_ui_values();
+        self.update_ui_values(true);
 
         // join the previous thread
         let prev_thread = self.dev_dpi_thread.take();
@@ -641,7 +1521,119 @@ impl DeathAdderv2App {
 
         // if we opened a new device, start a new listener thread
         self.with_device(|dav2| {
-            self.spawn_dev_dpi_listener_thread(dav2);
+            self.spawn_dev_dpi_listener_thread(dav2, ports);
+        });
+    }
+
+    /// Show why there is no device (and the Retry button), or hide both
+    fn show_device_error(&self, msg: Option<String>) {
+        self.txt_device_error.set_text(msg.as_deref().unwrap_or(""));
+        self.txt_device_error.set_visible(msg.is_some());
+        self.btn_retry.set_visible(msg.is_some());
+        // they share the row
+        let presets = !self.btn_presets.borrow().is_empty();
+        self.lbl_presets.set_visible(msg.is_none() && presets);
+        self.frm_presets.set_visible(msg.is_none() && presets);
+    }
+
+    /// List the devices and select the one asked for on the command line, or
+    /// the only one there is. Failures are shown in the window, not fatal, so
+    /// the user can fix them and retry.
+    fn refresh_devices(&self) {
+        let available_devices = MICE.iter().try_fold(Vec::new(), |mut devices, list| {
+            devices.extend(list()?);
+            Ok::<_, USBError>(devices)
+        });
+        let available_devices = match available_devices {
+            Ok(devices) => devices,
+            Err(e) => {
+                self.cmb_device.set_collection(Vec::new());
+                self.show_device_error(Some(format!("Error querying devices: {}. \
+                    Check the driver and permissions, then press Retry.", e)));
+                return;
+            }
+        };
+
+        self.cmb_device.set_collection(available_devices);
+        if self.cmb_device.len() == 0 {
+            self.show_device_error(Some(String::from("No supported mouse found. \
+                Plug it in and press Retry.")));
+            return;
+        }
+        self.show_device_error(None);
+
+        let serial = self.wanted_serial.borrow().clone();
+        if let Some(serial) = serial {
+            let index = app_core::find_by_serial(&self.cmb_device.collection(), &serial);
+            match index {
+                Some(index) => {
+                    self.cmb_device.set_selection(Some(index));
+                    self.device_selected();
+                },
+                None => self.show_device_error(Some(format!("No device with serial {} \
+                    found. Plug it in and press Retry.", serial))),
+            }
+        // if only 1, select it by default and show appropriate error if failed to open
+        } else if self.cmb_device.len() == 1 {
+            self.cmb_device.set_selection(Some(0));
+            self.device_selected();
+        }
+    }
+
+    fn retry_clicked(&self) {
+        // let go of the current device (if any) so it can be opened again
+        if self.device.borrow().is_some() {
+            self.cmb_device.set_selection(None);
+            self.device_selected();
+        }
+        self.refresh_devices();
+    }
+
+    /// Explain why the device can't be opened and offer a way out. Returns the
+    /// device if the user chose to fall back to hidapi and that worked.
+    fn troubleshoot_driver(&self, problem: DriverProblem) -> Option<Box<dyn RazerDevice>> {
+        if problem == DriverProblem::Access {
+            msgboxerror!("Error opening device: {}.\n\nClose Synapse or any other \
+                program using the mouse and select the device again.", problem);
+            return None;
+        }
+
+        #[cfg(feature = "hidapi")]
+        let (msg, buttons) = (format!("Error opening device: {}.\n\n\
+            Yes: install the driver (Zadig); select \"Razer DeathAdder V2 \
+            (Interface 3)\", then \"WinUSB\" and \"Replace driver\".\n\
+            No: use the stock HID driver instead.\0", problem), MB_YESNOCANCEL);
+        #[cfg(not(feature = "hidapi"))]
+        let (msg, buttons) = (format!("Error opening device: {}.\n\n\
+            Install the driver now? In Zadig, select \"Razer DeathAdder V2 \
+            (Interface 3)\", then \"WinUSB\" and \"Replace driver\", and \
+            select the device here again.\0", problem), MB_YESNO);
+
+        let answer = unsafe {
+            MessageBoxA(HWND(self.window.handle.hwnd().unwrap() as isize),
+                PCSTR::from_raw(msg.as_ptr()), s!("Driver problem"),
+                buttons | MB_ICONWARNING)
+        };
+        match answer {
+            IDYES => {
+                launch_driver_installer();
+                None
+            },
+            #[cfg(feature = "hidapi")]
+            IDNO => DeathAdderV2::open_hidapi()
+                .map(|dav2| Box::new(dav2) as Box<dyn RazerDevice>)
+                .map_err(|e| msgboxerror!("Error opening device through hidapi: {}", e))
+                .ok(),
+            _ => None,
+        }
+    }
+
+    /// Store the receiver wait learned for the current device in the config
+    fn remember_receiver_wait(&self) {
+        self.with_device(|dav2| {
+            dav2.get_serial().map(|serial| self.with_mut_config(|cfg| {
+                app_core::remember(dav2, &serial, cfg);
+            }))
         });
     }
 
@@ -656,7 +1648,7 @@ impl DeathAdderv2App {
             let mut stages: Vec<(u16, u16)> = Vec::new();
             let mut i = 0;
             let mut current = 0;
-            for &rad_stage in rad_stages.iter() {
+            for rad_stage in rad_stages.iter() {
                 if i < num_stages {
                     rad_stage.set_visible(true);
                     let dpi = rad_stage.text().parse::<u16>().unwrap();
@@ -677,7 +1669,8 @@ impl DeathAdderv2App {
 
             rad_stages[current].set_check_state(RadioButtonState::Checked);
             self.set_stage_dpi_ui(stages.get(current).unwrap().0 as usize);
-            self.with_device(|dav2| dav2.set_dpi_stages(&stages, current as u8))
+            self.apply_dpi_stages(&stages, current as u8);
+            Some(())
         });
     }
 
@@ -690,7 +1683,7 @@ impl DeathAdderv2App {
         let mut stages: Vec<(u16, u16)> = Vec::new();
         let mut current: u8 = 0;
         let mut i = 0;
-        for rad_stage in rad_stages {
+        for rad_stage in rad_stages.iter() {
             if !rad_stage.visible() {
                 break;
             }
@@ -705,7 +1698,7 @@ impl DeathAdderv2App {
         }
 
         self.set_stage_dpi_ui(stages.get(current as usize).unwrap().0 as usize);
-        self.with_device(|dav2| dav2.set_dpi_stages(&stages, current));
+        self.apply_dpi_stages(&stages, current);
     }
 
     fn stage_dpi_selected(&self) {
@@ -717,14 +1710,14 @@ impl DeathAdderv2App {
         let mut stages: Vec<(u16, u16)> = Vec::new();
         let mut current = 0;
         let mut i = 0;
-        for rad_stage in rad_stages {
+        for rad_stage in rad_stages.iter() {
             if !rad_stage.visible() {
                 break;
             }
 
             if rad_stage.check_state() == RadioButtonState::Checked {
                 current = i;
-                let dpi = self.bar_stagedpi.pos() as u16;
+                let dpi = self.fit_dpi(self.bar_stagedpi.pos());
                 rad_stage.set_text(&dpi.to_string());
                 stages.push((dpi, dpi));
             } else {
@@ -735,8 +1728,16 @@ impl DeathAdderv2App {
             i += 1;
         }
 
-        self.set_current_dpi_ui(self.bar_stagedpi.pos());
-        self.with_device(|dav2| dav2.set_dpi_stages(&stages, current));
+        self.set_current_dpi_ui(stages[current as usize].0 as usize);
+        self.apply_dpi_stages(&stages, current);
+    }
+
+    /// Send the stages to the device, or show next to them why they can't be
+    fn apply_dpi_stages(&self, stages: &[(u16, u16)], current: u8) {
+        self.with_device(|dav2| {
+            let error = dav2.set_dpi_stages(stages, current).err();
+            self.txt_stages_error.set_text(&error.map_or(String::new(), |e| e.to_string()));
+        });
     }
 
     fn set_stage_dpi_ui(&self, dpi: usize) {
@@ -754,7 +1755,7 @@ impl DeathAdderv2App {
             return;
         }
 
-        let dpi = self.bar_currdpi.pos() as u16;
+        let dpi = self.fit_dpi(self.bar_currdpi.pos());
         self.txt_currdpi.set_text(&self.bar_currdpi.pos().to_string());
         self.with_device(|dav2| dav2.set_dpi(dpi, dpi));
     }
@@ -779,6 +1780,20 @@ impl DeathAdderv2App {
             });
     }
 
+    fn liftoff_selected(&self) {
+        if !*self.ui_events_enabled.borrow() {
+            return;
+        }
+
+        let collection = self.cmb_liftoff.collection();
+        self.cmb_liftoff.selection()
+            .and_then(|i| collection.get(i))
+            .map(|&liftoff| {
+                self.with_device(|dav2| dav2.set_liftoff(liftoff))
+                    .map(|res| res.map_err(|e| msgboxerror!("Failed to set lift-off distance: {}", e)));
+            });
+    }
+
     fn set_cursor_hand(&self) {
         let lpcursorname = match self.device.borrow().as_ref() {
             Some(_) => IDC_HAND,
@@ -797,23 +1812,34 @@ impl DeathAdderv2App {
         }
 
         self.with_mut_config(|cfg| {
-            self.with_device(|dav2| {
+            // nothing to pick a color for without a device
+            self.with_device(|_| {
 
-                // dav2 here must outlive dialog and therefore change_cb
                 let mut dialog = ColorDialog::new();
 
                 // ColorDialog arguments
                 let parent = HWND(self.window.handle.hwnd().unwrap() as isize);
                 let init_logo = Some(cfg.logo_color);
-                let init_scroll = cfg.scroll_color;
                 let same_color = cfg.same_color;
+                let fader = &self.fader;
+                let live_preview = cfg.live_preview;
+                let last = Mutex::new(cfg.logo_color);
                 let change_cb = Some(move |_: &ColorDialog, &color: &RGB8| {
-                    _ = dav2.preview_static(
-                        color, if same_color { color } else { init_scroll });
+                    if !live_preview {
+                        return;
+                    }
+                    let mut last = last.lock().unwrap();
+                    let mut fader = fader.lock().unwrap();
+                    fader.start(Led::Logo, *last, color, FADE_DURATION);
+                    if same_color {
+                        fader.start(Led::ScrollWheel, *last, color, FADE_DURATION);
+                    }
+                    *last = color;
                 });
 
                 // show the dialog and choose what to apply (either initial or new)
-                let color = match dialog.show(parent, init_logo, change_cb) {
+                let chosen = self.show_fading(|| dialog.show(parent, init_logo, change_cb));
+                let color = match chosen {
                     Some(chosen_color) => chosen_color,
                     None => cfg.logo_color,
                 };
@@ -835,7 +1861,7 @@ impl DeathAdderv2App {
 
     /// Does not update the config
     fn set_logo_color(&self, color: RGB8) {
-        self.with_device(|dav2| dav2.set_logo_color(color));
+        self.with_device(|dav2| self.write_led_color(dav2, Led::Logo, color));
         self.btn_logocolor.set_background_color(color.into());
     }
 
@@ -845,9 +1871,9 @@ impl DeathAdderv2App {
         }
 
         self.with_mut_config(|cfg| {
-            self.with_device(|dav2| {
+            // nothing to pick a color for without a device
+            self.with_device(|_| {
 
-                // dav2 here must outlive dialog and therefore change_cb
                 let mut dialog = ColorDialog::new();
 
                 // ColorDialog arguments
@@ -858,12 +1884,21 @@ impl DeathAdderv2App {
                 } else {
                     cfg.scroll_color
                 });
+                let fader = &self.fader;
+                let live_preview = cfg.live_preview;
+                let last = Mutex::new(init_scroll.unwrap_or_default());
                 let change_cb = Some(move |_: &ColorDialog, &color: &RGB8| {
-                    _ = dav2.preview_static(logo_color, color);
+                    if !live_preview {
+                        return;
+                    }
+                    let mut last = last.lock().unwrap();
+                    fader.lock().unwrap().start(Led::ScrollWheel, *last, color, FADE_DURATION);
+                    *last = color;
                 });
 
                 // show the dialog and choose what to apply (either initial or new)
-                let color = match dialog.show(parent, init_scroll, change_cb) {
+                let chosen = self.show_fading(|| dialog.show(parent, init_scroll, change_cb));
+                let color = match chosen {
                     Some(chosen_color) => {
                         // if the user pressed ok, we no longer use same colors
                         cfg.same_color = false;
@@ -894,7 +1929,7 @@ impl DeathAdderv2App {
 
     /// Does not update the config
     fn set_scroll_color(&self, color: RGB8) {
-        self.with_device(|dav2| dav2.set_scroll_color(color));
+        self.with_device(|dav2| self.write_led_color(dav2, Led::ScrollWheel, color));
         self.btn_scrollcolor.set_background_color(color.into());
     }
 
@@ -936,7 +1971,7 @@ impl DeathAdderv2App {
 
         let brightness = self.bar_logobright.pos() as u8;
         self.txt_logobright.set_text(&brightness.to_string());
-        self.with_device(|dav2| dav2.set_logo_brightness(brightness));
+        self.with_device(|dav2| self.write_led_brightness(dav2, Led::Logo, brightness));
         self.with_config(|cfg| if cfg.same_brightness {
             self.set_scroll_brightness(brightness as usize);
         });
@@ -949,7 +1984,9 @@ impl DeathAdderv2App {
 
         let brightness = self.bar_scrollbright.pos();
         self.txt_scrollbright.set_text(&brightness.to_string());
-        self.with_device(|dav2| dav2.set_scroll_brightness(brightness as u8));
+        self.with_device(|dav2| {
+            self.write_led_brightness(dav2, Led::ScrollWheel, brightness as u8)
+        });
     }
 
     /// Does not update the config
@@ -957,10 +1994,97 @@ impl DeathAdderv2App {
         self.ui_events_enabled.replace(false);
         self.txt_scrollbright.set_text(&brightness.to_string());
         self.bar_scrollbright.set_pos(brightness);
-        self.with_device(|dav2| dav2.set_scroll_brightness(brightness as u8));
+        self.with_device(|dav2| {
+            self.write_led_brightness(dav2, Led::ScrollWheel, brightness as u8)
+        });
         self.ui_events_enabled.replace(true);
     }
 
+    fn free_spin_changed(&self, evt: nwg::Event, evtdata: &nwg::EventData) {
+        if !*self.ui_events_enabled.borrow() {
+            return;
+        }
+
+        // only interested in space key
+        if evt == nwg::Event::OnKeyRelease && evtdata.on_key() != 32u32 {
+            return
+        }
+
+        // see same_color_changed() for why this is negated
+        let free_spin = !from_check_state!(self.chk_freespin.check_state());
+        let mode = if free_spin { ScrollMode::FreeSpin } else { ScrollMode::Tactile };
+        self.with_device(|dav2| dav2.set_scroll_mode(mode))
+            .map(|res| res.map_err(|e| msgboxerror!("Failed to set scroll mode: {}", e)));
+    }
+
+    fn smart_reel_changed(&self, evt: nwg::Event, evtdata: &nwg::EventData) {
+        if !*self.ui_events_enabled.borrow() {
+            return;
+        }
+
+        // only interested in space key
+        if evt == nwg::Event::OnKeyRelease && evtdata.on_key() != 32u32 {
+            return
+        }
+
+        // see same_color_changed() for why this is negated
+        let on = !from_check_state!(self.chk_smartreel.check_state());
+        self.with_device(|dav2| dav2.set_smart_reel(on))
+            .map(|res| res.map_err(|e| msgboxerror!("Failed to set smart reel: {}", e)));
+        self.chk_freespin.set_enabled(!on);
+    }
+
+    fn onboard_changed(&self, evt: nwg::Event, evtdata: &nwg::EventData) {
+        if !*self.ui_events_enabled.borrow() {
+            return;
+        }
+
+        // only interested in space key
+        if evt == nwg::Event::OnKeyRelease && evtdata.on_key() != 32u32 {
+            return
+        }
+
+        // see same_color_changed() for why this is negated
+        let onboard = !from_check_state!(self.chk_onboard.check_state());
+        self.onboard.set(onboard);
+        self.with_mut_config(|cfg| cfg.onboard = onboard);
+
+        // saving is deliberate; write what is previewed now
+        if onboard {
+            self.save_lighting();
+        }
+    }
+
+    /// Write the lighting shown in the UI to the device's onboard memory
+    fn save_lighting(&self) {
+        let res = self.with_device(|dav2| -> USBResult<()> {
+            let caps = dav2.capabilities();
+            let (logo, scroll) = (self.logo_color(), self.scroll_color());
+            let same_color = self.with_config(|cfg| cfg.same_color);
+            if caps.has_rgb(Led::Logo) {
+                dav2.set_led_color(Led::Logo, logo)?;
+            }
+            if caps.has_rgb(Led::ScrollWheel) {
+                dav2.set_led_color(Led::ScrollWheel, if same_color { logo } else { scroll })?;
+            }
+            if caps.has_led(Led::Logo) {
+                dav2.set_led_brightness(Led::Logo, self.bar_logobright.pos() as u8)?;
+            }
+            if caps.has_led(Led::ScrollWheel) {
+                dav2.set_led_brightness(Led::ScrollWheel, self.bar_scrollbright.pos() as u8)?;
+            }
+            for (led, _) in self.btn_zones.borrow().iter() {
+                if let Some(color) = self.with_config(|cfg| cfg.zone_color(*led)) {
+                    dav2.set_led_color(*led, color)?;
+                }
+            }
+            Ok(())
+        });
+        if let Some(Err(e)) = res {
+            msgboxerror!("Failed to save lighting on the device: {}", e);
+        }
+    }
+
     fn same_brightness_changed(&self, evt: nwg::Event, evtdata: &nwg::EventData) {
         if !*self.ui_events_enabled.borrow() {
             return;
@@ -998,15 +2122,308 @@ impl DeathAdderv2App {
         }
     }
 
+    fn reset_clicked(&self) {
+        let answer = unsafe {
+            MessageBoxA(HWND(self.window.handle.hwnd().unwrap() as isize),
+                s!("This will reset the saved configuration, as well as the DPI, \
+                    polling rate and lighting of the device to their defaults.\n\n\
+                    Continue?"),
+                s!("Reset to defaults"), MB_YESNO | MB_ICONWARNING)
+        };
+        if answer != IDYES {
+            return;
+        }
+
+        self.with_mut_config(|cfg| *cfg = Config::default());
+        self.save_config.replace(true);
+        _ = self.with_config(|cfg| cfg.save()).map_err(|e| {
+            msgboxerror!("Failed to save config: {}", e);
+        });
+
+        self.with_device(|dav2| dav2.reset_to_defaults())
+            .map(|res| res.map_err(|e| msgboxerror!("Failed to reset device: {}", e)));
+
+        // the device is now showing its stock lighting; don't override it
+        self.update_ui_values(false);
+    }
+
+    /// Unlike `reset_clicked()`, leaves DPI, polling rate and the saved
+    /// configuration alone
+    fn live_preview_clicked(&self) {
+        let on = !self.mni_live_preview.checked();
+        self.mni_live_preview.set_checked(on);
+        self.with_mut_config(|cfg| cfg.live_preview = on);
+    }
+
+    fn register_links_clicked(&self) {
+        match activation::register_uri_handler() {
+            Ok(()) => {
+                nwg::modal_info_message(&self.window, "Links",
+                    &format!("{}://profile/<name> links now switch to that profile.", activation::SCHEME));
+            },
+            Err(e) => msgboxerror!("Failed to register links: {}", e),
+        }
+    }
+
+    fn profile_forwarded(&self) {
+        for arg in activation::take_pending() {
+            self.activate_profile(&arg);
+        }
+    }
+
+    /// Apply the saved profile by that name, or the profile file at that
+    /// path; `arg` may also be a razerda:// link to either
+    /// Put the keyboard focus on the first control of `section`, e.g. for
+    /// shortcuts to it
+    fn focus_section(&self, section: Section) {
+        match section {
+            Section::Dpi => self.cmb_numstages.set_focus(),
+            Section::Lighting => self.bar_logobright.set_focus(),
+            Section::Presets => match self.btn_presets.borrow().first() {
+                Some(button) => button.set_focus(),
+                None => self.bar_currdpi.set_focus(),
+            },
+        }
+    }
+
+    fn activate_profile(&self, arg: &str) {
+        let name = match activation::profile_name(arg) {
+            Ok(name) => name,
+            Err(e) => {
+                msgboxerror!("{}", e);
+                return;
+            },
+        };
+        let profile = match self.with_config(|cfg| app_core::find_profile(cfg, &name)) {
+            Ok(profile) => profile,
+            Err(e) => {
+                msgboxerror!("{}", e);
+                return;
+            },
+        };
+
+        // takes the colors into the config too; the swatches show what it says
+        let res = self.with_device(|dav2| {
+            self.with_mut_config(|cfg| app_core::apply_profile(dav2, &profile, cfg))
+        });
+        match res {
+            Some(Ok(())) => {},
+            Some(Err(e)) => {
+                msgboxerror!("Failed to apply profile '{}': {}", name, e);
+                return;
+            },
+            None => {
+                msgboxerror!("No device to apply profile '{}' to", name);
+                return;
+            },
+        }
+
+        self.save_config.replace(true);
+        self.update_ui_values(false);
+    }
+
+    fn stock_lighting_clicked(&self) {
+        self.with_device(|dav2| dav2.restore_default_lighting())
+            .map(|res| res.map_err(|e| msgboxerror!("Failed to restore lighting: {}", e)));
+
+        // the device is now showing its stock lighting; don't override it
+        self.update_ui_values(false);
+    }
+
+    fn restore_config_clicked(&self) {
+        let backup = match Config::backups() {
+            Ok(backups) => match backups.into_iter().next() {
+                Some(backup) => backup,
+                None => {
+                    nwg::modal_info_message(&self.window, "Restore configuration",
+                        "There is no previous configuration to restore.");
+                    return;
+                },
+            },
+            Err(e) => {
+                msgboxerror!("Failed to list configuration backups: {}", e);
+                return;
+            },
+        };
+
+        let msg = format!("Restore the configuration saved on {}? \
+            Any changes since then will be lost.\0", backup.time_string());
+        let answer = unsafe {
+            MessageBoxA(HWND(self.window.handle.hwnd().unwrap() as isize),
+                PCSTR::from_raw(msg.as_ptr()), s!("Restore configuration"),
+                MB_YESNO | MB_ICONWARNING)
+        };
+        if answer != IDYES {
+            return;
+        }
+
+        match Config::restore(&backup) {
+            Ok(cfg) => {
+                self.config.replace(cfg);
+                self.save_config.replace(true);
+                self.update_ui_values(true);
+            },
+            Err(e) => msgboxerror!("Failed to restore configuration: {}", e),
+        }
+    }
+
+    fn save_profile_clicked(&self) {
+        let snapshot = match self.with_device(|dav2| Profile::snapshot(dav2)) {
+            Some(Ok(snapshot)) => snapshot,
+            Some(Err(e)) => {
+                msgboxerror!("Failed to read device settings: {}", e);
+                return;
+            },
+            None => return,
+        };
+
+        // the device can't report its colors; take them from the config
+        self.with_mut_config(|cfg| {
+            let mut profile = cfg.profile();
+            if let Some(saved) = &cfg.profile {
+                profile.name = saved.name.clone();
+                profile.scope = saved.scope;
+            }
+            profile.dpi = snapshot.dpi;
+            profile.dpi_stages = snapshot.dpi_stages;
+            profile.poll_rate = snapshot.poll_rate;
+            profile.brightness = snapshot.brightness;
+            cfg.profile = Some(profile);
+        });
+        self.save_config.replace(true);
+        self.update_sync_status();
+        nwg::modal_info_message(&self.window, "Save profile",
+            "The current device settings were saved as the profile.");
+    }
+
+    fn compare_profile_clicked(&self) {
+        let profile = match self.with_config(|cfg| cfg.profile.clone()) {
+            Some(profile) => profile,
+            None => {
+                nwg::modal_info_message(&self.window, "Compare with profile",
+                    "There is no saved profile to compare with.");
+                return;
+            },
+        };
+        let snapshot = match self.with_device(|dav2| Profile::snapshot(dav2)) {
+            Some(Ok(snapshot)) => snapshot,
+            Some(Err(e)) => {
+                msgboxerror!("Failed to read device settings: {}", e);
+                return;
+            },
+            None => return,
+        };
+
+        let diff = profile.diff(&snapshot);
+        if diff.is_empty() {
+            nwg::modal_info_message(&self.window, "Compare with profile",
+                "The device matches the saved profile.");
+            return;
+        }
+
+        let fields: Vec<String> = diff.iter().map(|field| format!("  - {}", field)).collect();
+        let msg = format!("The device differs from the saved profile in:\n\n{}\n\n\
+            Apply the differences?\0", fields.join("\n"));
+        let answer = unsafe {
+            MessageBoxA(HWND(self.window.handle.hwnd().unwrap() as isize),
+                PCSTR::from_raw(msg.as_ptr()), s!("Compare with profile"), MB_YESNO)
+        };
+        if answer != IDYES {
+            return;
+        }
+
+        self.with_device(|dav2| profile.only(&diff).apply(dav2))
+            .map(|res| res.map_err(|e| msgboxerror!("Failed to apply profile: {}", e)));
+        self.update_ui_values(false);
+    }
+
+    fn check_updates_clicked(&self) {
+        let hwnd = HWND(self.window.handle.hwnd().unwrap() as isize);
+        let release = match update::check() {
+            Ok(Some(release)) => release,
+            Ok(None) => {
+                let msg = format!("You have the latest version ({}).\0",
+                    env!("CARGO_PKG_VERSION"));
+                unsafe {
+                    MessageBoxA(hwnd, PCSTR::from_raw(msg.as_ptr()),
+                        s!("Check for updates"), MB_OK);
+                }
+                return;
+            },
+            Err(e) => {
+                msgboxerror!("Failed to check for updates: {}", e);
+                return;
+            },
+        };
+
+        let msg = format!("Version {} is available (you have {}).\n\n\
+            Download it now?\0", release.version(), env!("CARGO_PKG_VERSION"));
+        let answer = unsafe {
+            MessageBoxA(hwnd, PCSTR::from_raw(msg.as_ptr()),
+                s!("Check for updates"), MB_YESNO)
+        };
+        if answer == IDYES {
+            let url_sz = format!("{}\0", release.download_url());
+            unsafe {
+                ShellExecuteA(HWND(0), s!("open"), PCSTR::from_raw(url_sz.as_ptr()),
+                    PCSTR::null(), PCSTR::null(), SW_SHOWNORMAL);
+            }
+        }
+    }
+
+    /// Save the window position and size, unless minimized or maximized
+    fn remember_window_placement(&self) {
+        let hwnd = HWND(self.window.handle.hwnd().unwrap() as isize);
+        if unsafe { IsIconic(hwnd).as_bool() || IsZoomed(hwnd).as_bool() } {
+            return;
+        }
+
+        let (x, y) = self.window.position();
+        let (width, height) = self.window.size();
+        self.with_mut_config(|cfg| {
+            cfg.window = Some(WindowPlacement { x, y, width, height });
+        });
+    }
+
+    /// Put the window where it was last time, if that's still on a monitor
+    fn restore_window_placement(&self) {
+        let placement = match self.with_config(|cfg| cfg.window) {
+            Some(placement) => placement,
+            None => return,
+        };
+
+        // monitors may have been unplugged or rearranged since
+        let rect = RECT {
+            left: placement.x,
+            top: placement.y,
+            right: placement.x + placement.width as i32,
+            bottom: placement.y + placement.height as i32,
+        };
+        let monitor = unsafe { MonitorFromRect(&rect, MONITOR_DEFAULTTONULL) };
+        if monitor.0 == 0 {
+            return;
+        }
+
+        self.window.set_position(placement.x, placement.y);
+        self.window.set_size(placement.width, placement.height);
+    }
+
     fn window_close(&self) {
+        #[cfg(feature = "transport-stats")]
+        self.with_device(|dav2| dbglog!("{}", dav2.transport_stats()));
+
         // signal the thread to stop, if any
         let prev_keepalive_ref = self.dev_dpi_keepalive.borrow();
         let prev_keepalive_mutex = prev_keepalive_ref.as_ref();
         *prev_keepalive_mutex.lock().unwrap() = false;
 
-        _ = self.with_config(|cfg| cfg.save()).map_err(|e|{
-            msgboxerror!("Failed to save config: {}", e);
-        });
+        self.remember_receiver_wait();
+        self.remember_window_placement();
+        if *self.save_config.borrow() {
+            _ = self.with_config(|cfg| cfg.save()).map_err(|e|{
+                msgboxerror!("Failed to save config: {}", e);
+            });
+        }
 
         // join the previous thread
         self.dev_dpi_thread.take().map(|thread| {
@@ -1017,7 +2434,130 @@ impl DeathAdderv2App {
     }
 }
 
+/// Run Zadig if it's next to the executable, otherwise open its web page
+fn launch_driver_installer() {
+    let zadig = std::env::current_exe().ok()
+        .and_then(|exe| std::fs::read_dir(exe.parent()?).ok())
+        .and_then(|entries| entries
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .find(|path| path.file_name()
+                .and_then(|name| name.to_str())
+                .map(|name| name.to_lowercase())
+                .map_or(false, |name| name.starts_with("zadig") && name.ends_with(".exe"))));
+    let target = zadig.map_or(String::from("https://zadig.akeo.ie/"),
+        |path| path.display().to_string());
+
+    let target_sz = format!("{}\0", target);
+    unsafe {
+        ShellExecuteA(HWND(0), s!("open"), PCSTR::from_raw(target_sz.as_ptr()),
+            PCSTR::null(), PCSTR::null(), SW_SHOWNORMAL);
+    }
+}
+
+/// Load the config, asking the user what to do if the file is broken. Returns
+/// the config and whether it's ok to overwrite the file on exit.
+fn load_config() -> (Config, bool) {
+    let err = match app_core::load_config() {
+        Ok(cfg) => return (cfg, true),
+        Err(e) => e,
+    };
+    dbglog!("{}", err);
+
+    let msg = format!("{}\n\n\
+        Reset it to the defaults? Choose 'No' to keep the file as is (e.g. to \
+        fix it by hand); defaults will be used until then and nothing will \
+        be saved.\0", err);
+    let answer = unsafe {
+        MessageBoxA(HWND(0), PCSTR::from_raw(msg.as_ptr()),
+            s!("Configuration error"), MB_YESNO | MB_ICONWARNING)
+    };
+
+    if answer == IDYES {
+        let cfg = Config::default();
+        _ = cfg.save().map_err(|e| msgboxerror!("Failed to reset config: {}", e));
+        (cfg, true)
+    } else {
+        (Config::default(), false)
+    }
+}
+
+/// Command line options, e.g. for shortcuts to a specific device
+#[derive(Default)]
+struct Args {
+    /// Select the device with this serial at startup
+    serial: Option<String>,
+    /// Saved profile name, profile file or razerda:// link to apply
+    activate_profile: Option<String>,
+    /// Where the keyboard focus starts
+    tab: Option<Section>,
+}
+
+impl Args {
+    fn parse() -> Result<Self, String> {
+        let mut parsed = Args::default();
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--serial" => parsed.serial = Some(args.next()
+                    .ok_or("--serial needs a value")?),
+                "--activate-profile" | "--profile" => parsed.activate_profile = Some(args.next()
+                    .ok_or(format!("{} needs a value", arg))?),
+                "--tab" => parsed.tab = Some(args.next()
+                    .ok_or("--tab needs a value")?
+                    .parse()?),
+                _ => return Err(format!("unknown argument '{}'\n\n\
+                    usage: {} [--serial <serial>] [--activate-profile <name|file|link>] \
+                    [--tab <{}>]",
+                    arg, env!("CARGO_BIN_NAME"), Section::NAMES.join("|"))),
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+/// The parts of the window, which `--tab` picks from as there are no tabs
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Section {
+    /// DPI stages, current DPI and polling rate
+    Dpi,
+    Lighting,
+    /// Quick DPI presets
+    Presets,
+}
+
+impl Section {
+    const NAMES: [&'static str; 3] = ["dpi", "lighting", "presets"];
+}
+
+impl std::str::FromStr for Section {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "dpi" => Ok(Section::Dpi),
+            "lighting" => Ok(Section::Lighting),
+            "presets" => Ok(Section::Presets),
+            _ => Err(format!("unknown tab '{}', expected one of: {}",
+                s, Section::NAMES.join(", "))),
+        }
+    }
+}
+
 fn main() {
+    crash::install_panic_hook();
+
+    let args = Args::parse().unwrap_or_else(|e| {
+        msgboxerror!("{}", e);
+        Args::default()
+    });
+
+    // let the running instance, if any, switch profiles instead
+    if let Some(profile) = &args.activate_profile {
+        if activation::forward(profile) {
+            return;
+        }
+    }
+
     _ = nwg::init().map_err(
         |e| msgboxpanic!("Failed to init Native Windows GUI: {}", e));
     _ = nwg::Font::set_global_family("Segoe UI").map_err(
@@ -1027,10 +2567,18 @@ fn main() {
         .unwrap_or_else(|e| msgboxpanic!("Failed to build UI: {}", e));
 
     app.ui_events_enabled.replace(true);
-    app.config.replace(Config::load().unwrap_or(Config::default()));
+    let (config, save_config) = load_config();
+    app.onboard.set(config.onboard);
+    app.config.replace(config);
+    app.save_config.replace(save_config);
+    app.restore_window_placement();
+    app.build_dpi_preset_controls();
 
     // default to false and if a valid device is selected they will be enabled
     app.set_device_controls_enabled(false);
+    // until a device with such zones is selected
+    app.lbl_zones.set_visible(false);
+    app.frm_zones.set_visible(false);
 
     // configure a few things on the trackbars
     configure_trackbar(&app.bar_stagedpi, 1, 1000, 1000);
@@ -1040,9 +2588,18 @@ fn main() {
 
     // v_align some controls that nwg does provide the option
     add_style(&app.chk_samebright.handle, BS_TOP);
-    for rad_stage in app.rad_dpistages() {
-        add_style(&rad_stage.handle, BS_TOP);
-    }
+
+    tray::install_wheel_hook(app.tray_notice.sender());
+    activation::install_receiver(&app.window, app.activation_notice.sender());
+
+    // route clicks on the (runtime-created) stage radio buttons
+    let stage_notice = app.stage_notice.sender();
+    nwg::bind_event_handler(&app.frm_stages.handle, &app.window.handle,
+        move |evt, _evt_data, _handle| {
+            if let nwg::Event::OnButtonClick = evt {
+                stage_notice.notice();
+            }
+        });
 
     // set the minimum window size
     _ = nwg::bind_raw_event_handler(&app.window.handle, 0x10000, |_hwnd, msg, _w, l| {
@@ -1061,15 +2618,29 @@ fn main() {
         None
     });
 
-    let available_devices = DeathAdderV2::list().unwrap_or_else(
-        |e| msgboxpanic!("Error querying DeathAdder v2 devices: {}", e)
-    );
+    // run against a recorded session instead of the hardware, if asked to
+    if let Some(replay) = replay_from_env() {
+        match replay {
+            Ok(replay) => {
+                let dav2 = DeathAdderV2::with_transport(Box::new(replay));
+                crash::watch(&dav2);
+                app.device.replace(Some(Box::new(dav2)));
+                app.update_ui_values(true);
+            },
+            Err(e) => msgboxerror!("Failed to load session to replay: {}", e),
+        }
+        nwg::dispatch_thread_events();
+        return;
+    }
 
-    app.cmb_device.set_collection(available_devices);
-    // if only 1, select it by default and show appropriate error if failed to open
-    if app.cmb_device.len() == 1 {
-        app.cmb_device.set_selection(Some(0));
-        app.device_selected();
+    app.wanted_serial.replace(args.serial.clone());
+    app.refresh_devices();
+    if let Some(profile) = &args.activate_profile {
+        app.activate_profile(profile);
+    }
+    if let Some(section) = args.tab {
+        app.focus_section(section);
     }
+    app.sync_timer.start();
     nwg::dispatch_thread_events();
 }
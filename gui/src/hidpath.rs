@@ -0,0 +1,89 @@
+//! Telling apart the HID interfaces of identical mice. hidapi paths are
+//! opaque, but Windows knows where on the USB tree the device behind them
+//! is, and that's what libusb reports as port numbers too.
+
+use windows::{
+    core::PCWSTR,
+    Win32::Devices::{
+        DeviceAndDriverInstallation::{CM_Get_DevNode_PropertyW, CM_Get_Device_Interface_PropertyW,
+            CM_Get_Parent, CM_Locate_DevNodeW, CM_LOCATE_DEVNODE_NORMAL, CONFIGRET,
+            CR_BUFFER_SMALL, CR_SUCCESS,
+        },
+        Properties::{DEVPKEY_Device_InstanceId, DEVPKEY_Device_LocationPaths, DEVPROPKEY,
+            DEVPROPTYPE,
+        },
+    },
+};
+
+/// How far up from the HID collection to look for the USB device
+const MAX_DEPTH: usize = 6;
+
+/// The hub ports (as in `rusb::Device::port_numbers()`) of the USB device
+/// the HID interface at `path` belongs to
+pub fn usb_ports(path: &str) -> Option<Vec<u8>> {
+    let path: Vec<u16> = path.encode_utf16().chain(Some(0)).collect();
+    let instance_id = property(|ty, buf, size| unsafe {
+        CM_Get_Device_Interface_PropertyW(PCWSTR(path.as_ptr()),
+            &DEVPKEY_Device_InstanceId, ty, buf, size, 0)
+    })?;
+
+    let mut devinst = 0u32;
+    let res = unsafe {
+        CM_Locate_DevNodeW(&mut devinst, PCWSTR(instance_id.as_ptr()), CM_LOCATE_DEVNODE_NORMAL)
+    };
+    if res != CR_SUCCESS {
+        return None;
+    }
+
+    // the collection itself has no location; its interface or device does
+    for _ in 0..MAX_DEPTH {
+        let paths = devnode_property(devinst, &DEVPKEY_Device_LocationPaths);
+        if let Some(ports) = paths.and_then(|paths| parse_location_path(&first_string(&paths))) {
+            return Some(ports);
+        }
+        let mut parent = 0u32;
+        if unsafe { CM_Get_Parent(&mut parent, devinst, 0) } != CR_SUCCESS {
+            return None;
+        }
+        devinst = parent;
+    }
+    None
+}
+
+fn devnode_property(devinst: u32, key: &DEVPROPKEY) -> Option<Vec<u16>> {
+    property(|ty, buf, size| unsafe {
+        CM_Get_DevNode_PropertyW(devinst, key, ty, buf, size, 0)
+    })
+}
+
+/// Call `get` once for the size and once for the (UTF-16) value
+fn property<F>(get: F) -> Option<Vec<u16>>
+where
+    F: Fn(*mut DEVPROPTYPE, Option<*mut u8>, *mut u32) -> CONFIGRET
+{
+    let mut ty = DEVPROPTYPE::default();
+    let mut size = 0u32;
+    if get(&mut ty, None, &mut size) != CR_BUFFER_SMALL || size == 0 {
+        return None;
+    }
+    let mut buf = vec![0u16; (size as usize + 1) / 2];
+    if get(&mut ty, Some(buf.as_mut_ptr() as *mut u8), &mut size) != CR_SUCCESS {
+        return None;
+    }
+    Some(buf)
+}
+
+/// Up to the first nul; location paths are a list
+fn first_string(wide: &[u16]) -> String {
+    let len = wide.iter().position(|&c| c == 0).unwrap_or(wide.len());
+    String::from_utf16_lossy(&wide[..len])
+}
+
+/// "PCIROOT(0)#PCI(1400)#USBROOT(0)#USB(3)#USB(1)#USBMI(1)" -> [3, 1]
+fn parse_location_path(path: &str) -> Option<Vec<u8>> {
+    let (_, devices) = path.split_once("USBROOT(")?;
+    let ports: Vec<u8> = devices.split('#')
+        .filter_map(|node| node.strip_prefix("USB(")?.strip_suffix(')')?.parse().ok())
+        .collect();
+    if ports.is_empty() { None } else { Some(ports) }
+}
@@ -0,0 +1,68 @@
+//! Scrolling the wheel over the tray icon. Notification icons don't get wheel
+//! messages, so a low-level mouse hook picks up the ones that happen where
+//! the mouse last moved over the icon.
+
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::time::{Duration, Instant};
+use windows::Win32::{
+    Foundation::{HINSTANCE, LPARAM, LRESULT, POINT, WPARAM},
+    UI::WindowsAndMessaging::{CallNextHookEx, GetCursorPos, SetWindowsHookExW,
+        HHOOK, MSLLHOOKSTRUCT, WH_MOUSE_LL, WM_MOUSEWHEEL,
+    },
+};
+use native_windows_gui as nwg;
+
+/// How long after the last move over the icon a wheel event still counts
+const HOVER_TIMEOUT: Duration = Duration::from_millis(1500);
+/// How far (in px) from where it was hovered the cursor may be
+const HOVER_SLOP: i32 = 12;
+
+static HOVER: Mutex<Option<(Instant, POINT)>> = Mutex::new(None);
+static STEPS: AtomicI32 = AtomicI32::new(0);
+static NOTICE: Mutex<Option<nwg::NoticeSender>> = Mutex::new(None);
+
+/// Call when the mouse moves over the icon
+pub fn hovered() {
+    let mut pt = POINT::default();
+    if unsafe { GetCursorPos(&mut pt).as_bool() } {
+        *HOVER.lock().unwrap() = Some((Instant::now(), pt));
+    }
+}
+
+/// `notice` fires on the GUI thread whenever there are steps to take
+pub fn install_wheel_hook(notice: nwg::NoticeSender) {
+    *NOTICE.lock().unwrap() = Some(notice);
+    unsafe {
+        _ = SetWindowsHookExW(WH_MOUSE_LL, Some(hook_proc), HINSTANCE(0), 0);
+    }
+}
+
+/// Wheel notches scrolled over the icon since last called; up is positive
+pub fn take_steps() -> i32 {
+    STEPS.swap(0, Ordering::Relaxed)
+}
+
+fn over_icon(pt: POINT) -> bool {
+    match *HOVER.lock().unwrap() {
+        Some((at, hover)) => at.elapsed() < HOVER_TIMEOUT &&
+            (hover.x - pt.x).abs() <= HOVER_SLOP && (hover.y - pt.y).abs() <= HOVER_SLOP,
+        None => false,
+    }
+}
+
+unsafe extern "system" fn hook_proc(code: i32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    if code >= 0 && wparam.0 as u32 == WM_MOUSEWHEEL {
+        let info = &*(lparam.0 as *const MSLLHOOKSTRUCT);
+        if over_icon(info.pt) {
+            let delta = (info.mouseData >> 16) as i16;
+            STEPS.fetch_add(delta.signum() as i32, Ordering::Relaxed);
+            if let Some(notice) = *NOTICE.lock().unwrap() {
+                notice.notice();
+            }
+            // don't also scroll whatever is under the taskbar
+            return LRESULT(1);
+        }
+    }
+    CallNextHookEx(HHOOK(0), code, wparam, lparam)
+}
@@ -0,0 +1,77 @@
+//! Checking GitHub for newer releases
+
+use std::{fmt, io, time::Duration};
+use serde::Deserialize;
+
+const LATEST_RELEASE_URL: &str =
+    "https://api.github.com/repos/gpoulios/deathadderv2/releases/latest";
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Deserialize)]
+pub struct Asset {
+    pub name: String,
+    pub browser_download_url: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Release {
+    pub tag_name: String,
+    pub html_url: String,
+    #[serde(default)]
+    pub assets: Vec<Asset>,
+}
+
+impl Release {
+    /// The version without the leading 'v' of the tag
+    pub fn version(&self) -> &str {
+        self.tag_name.trim_start_matches('v')
+    }
+
+    /// Where to get it: the installer if one is attached, else the release page
+    pub fn download_url(&self) -> &str {
+        self.assets.iter()
+            .find(|a| a.name.ends_with(".msi") || a.name.ends_with(".exe"))
+            .map_or(&self.html_url, |a| &a.browser_download_url)
+    }
+}
+
+#[derive(Debug)]
+pub enum UpdateError {
+    Request(Box<ureq::Error>),
+    Response(io::Error),
+}
+
+impl fmt::Display for UpdateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UpdateError::Request(e) => write!(f, "{}", e),
+            UpdateError::Response(e) => write!(f, "unexpected response: {}", e),
+        }
+    }
+}
+
+/// "1.2.3" -> [1, 2, 3]; anything after a '-' (pre-release) is ignored
+fn parse_version(version: &str) -> Vec<u32> {
+    version.split('-').next().unwrap_or_default()
+        .split('.')
+        .map(|n| n.parse().unwrap_or(0))
+        .collect()
+}
+
+/// The latest release if it's newer than this build
+pub fn check() -> Result<Option<Release>, UpdateError> {
+    let release: Release = ureq::get(LATEST_RELEASE_URL)
+        .set("User-Agent", concat!("deathadderv2/", env!("CARGO_PKG_VERSION")))
+        .set("Accept", "application/vnd.github+json")
+        .timeout(TIMEOUT)
+        .call()
+        .map_err(|e| UpdateError::Request(Box::new(e)))?
+        .into_json()
+        .map_err(UpdateError::Response)?;
+
+    if parse_version(release.version()) > parse_version(env!("CARGO_PKG_VERSION")) {
+        Ok(Some(release))
+    } else {
+        Ok(None)
+    }
+}
@@ -0,0 +1,28 @@
+//! Commands through the public API: checking a raw command, and a whole
+//! transaction with a device answering at once. The latter includes the first
+//! poll interval (1 ms), so compare runs with each other rather than read the
+//! absolute time. Run with `cargo bench -p librazer`.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use librazer::commands::{self, RawCommand};
+use librazer::device::{DeathAdderV2, RazerDevice};
+use librazer::mock::{MockReply, MockTransport};
+
+fn report(c: &mut Criterion) {
+    let cmd = commands::SET_EXTENDED_MATRIX_EFFECT;
+    let args = [0x01, 0x05, 0x01, 0x00, 0x00, 0x01, 0xFF, 0x00, 0x00];
+
+    c.bench_function("raw_command", |b| {
+        b.iter(|| RawCommand::new(black_box(cmd.class), black_box(cmd.id), &args))
+    });
+
+    let device = DeathAdderV2::with_transport(
+        Box::new(MockTransport::new().otherwise(MockReply::Echo)));
+    let command = RawCommand::new(cmd.class, cmd.id, &args).unwrap();
+    c.bench_function("send_raw", |b| {
+        b.iter(|| device.send_raw(black_box(&command)))
+    });
+}
+
+criterion_group!(benches, report);
+criterion_main!(benches);
@@ -0,0 +1,201 @@
+//! Lighting animations as files: keyframes of per-zone colors, each shown for
+//! a while, optionally looping. Played by the `effects::EffectEngine`, so
+//! they run on any device with RGB zones and nothing is saved on it.
+//!
+//! ```json
+//! {
+//!   "name": "Police",
+//!   "loop": true,
+//!   "keyframes": [
+//!     { "duration_ms": 250, "colors": { "Logo": "#FF0000", "ScrollWheel": "#0000FF" } },
+//!     { "duration_ms": 250, "colors": { "Logo": "#0000FF", "ScrollWheel": "#FF0000" } }
+//!   ]
+//! }
+//! ```
+//!
+//! Zones are `Led` names. A zone left out of a keyframe keeps the color it
+//! had in the previous one.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use serde::{Serialize, Deserialize};
+use rgb::RGB8;
+
+use crate::common::Led;
+use crate::effects::{mix, SoftwareEffect};
+use crate::error::{Error, Result};
+
+/// How a keyframe turns into the next one
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Easing {
+    /// Jump at the end of the keyframe
+    #[default]
+    Step,
+    /// Fade evenly over the keyframe
+    Linear,
+    /// Fade slowly at first and at the end
+    EaseInOut,
+}
+
+impl Easing {
+    pub fn iter() -> impl Iterator<Item = Self> {
+        [Easing::Step, Easing::Linear, Easing::EaseInOut].into_iter()
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Easing::Step => "Step",
+            Easing::Linear => "Linear",
+            Easing::EaseInOut => "Ease in/out",
+        }
+    }
+
+    /// Map `progress` through the keyframe (in [0-1]) to how far the colors
+    /// are towards the next one
+    pub fn apply(&self, progress: f32) -> f32 {
+        match self {
+            Easing::Step => 0.0,
+            Easing::Linear => progress,
+            Easing::EaseInOut => progress * progress * (3.0 - 2.0 * progress),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Keyframe {
+    pub duration_ms: u64,
+    /// By `Led` name
+    #[serde(with = "crate::hex_color::map")]
+    pub colors: HashMap<String, RGB8>,
+    pub easing: Easing,
+}
+
+impl Keyframe {
+    pub fn duration(&self) -> Duration {
+        Duration::from_millis(self.duration_ms)
+    }
+
+    pub fn color(&self, led: Led) -> Option<RGB8> {
+        self.colors.iter()
+            .find(|(name, _)| name.parse::<Led>().ok() == Some(led))
+            .map(|(_, &color)| color)
+    }
+
+    pub fn set_color(&mut self, led: Led, color: RGB8) {
+        self.remove_color(led);
+        self.colors.insert(format!("{:?}", led), color);
+    }
+
+    /// Leave `led` as the previous keyframe had it
+    pub fn remove_color(&mut self, led: Led) {
+        self.colors.retain(|name, _| name.parse::<Led>().ok() != Some(led));
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Animation {
+    pub name: Option<String>,
+    #[serde(rename = "loop")]
+    pub looping: bool,
+    pub keyframes: Vec<Keyframe>,
+}
+
+impl Default for Animation {
+    fn default() -> Self {
+        Self {
+            name: None,
+            looping: true,
+            keyframes: Vec::new(),
+        }
+    }
+}
+
+impl Animation {
+    pub fn from_json(json: &str) -> Result<Self> {
+        serde_json::from_str(json).map_err(Error::other)
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(self).map_err(Error::other)
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        Ok(fs::write(path, self.to_json()?)?)
+    }
+
+    /// Of one pass through the keyframes
+    pub fn duration(&self) -> Duration {
+        self.keyframes.iter().map(Keyframe::duration).sum()
+    }
+
+    /// The zones any keyframe sets, in `Led` order
+    pub fn zones(&self) -> Vec<Led> {
+        Led::iter()
+            .filter(|&led| self.keyframes.iter().any(|kf| kf.color(led).is_some()))
+            .collect()
+    }
+
+    /// The color of `led` in keyframe `index`, carried over from earlier
+    /// keyframes (or, when looping, from the end) if that one doesn't set it
+    fn keyframe_color(&self, index: usize, led: Led) -> Option<RGB8> {
+        let earlier = self.keyframes[..=index].iter().rev();
+        let wrapped = self.keyframes[index + 1..].iter().rev().filter(|_| self.looping);
+        earlier.chain(wrapped).find_map(|kf| kf.color(led))
+    }
+
+    /// The color of `led` at `elapsed` since the animation started, or
+    /// `None` if no keyframe sets it
+    pub fn color(&self, led: Led, elapsed: Duration) -> Option<RGB8> {
+        let last = self.keyframes.len().checked_sub(1)?;
+        let total = self.duration();
+        if total.is_zero() || (!self.looping && elapsed >= total) {
+            return self.keyframe_color(last, led);
+        }
+
+        let mut t = Duration::from_nanos((elapsed.as_nanos() % total.as_nanos()) as u64);
+        for (i, kf) in self.keyframes.iter().enumerate() {
+            if t >= kf.duration() {
+                t -= kf.duration();
+                continue;
+            }
+
+            let from = self.keyframe_color(i, led)?;
+            let next = if i < last { i + 1 } else if self.looping { 0 } else { i };
+            let to = self.keyframe_color(next, led).unwrap_or(from);
+            let amount = kf.easing.apply(t.as_secs_f32() / kf.duration().as_secs_f32());
+            return Some(mix(from, to, amount));
+        }
+        self.keyframe_color(last, led)
+    }
+}
+
+/// One zone of an animation, to run it as a `SoftwareEffect`
+pub struct AnimationTrack {
+    animation: Arc<Animation>,
+    led: Led,
+}
+
+impl AnimationTrack {
+    pub fn new(animation: Arc<Animation>, led: Led) -> Self {
+        Self {
+            animation: animation,
+            led: led,
+        }
+    }
+}
+
+impl SoftwareEffect for AnimationTrack {
+    fn color(&self, elapsed: Duration) -> RGB8 {
+        self.animation.color(self.led, elapsed).unwrap_or_default()
+    }
+}
@@ -0,0 +1,98 @@
+//! Async variants of the device calls (`tokio` feature), for services that
+//! shouldn't hold up their runtime while a transaction waits on the device:
+//!
+//! ```ignore
+//! let device = SharedDevice::new(DeathAdderV2::new()?);
+//! device.set_dpi_async(1600, 1600).await?;
+//! ```
+//!
+//! Each call runs on tokio's blocking pool, so it must be made from within a
+//! tokio runtime.
+
+use std::{io, panic};
+use rgb::RGB8;
+
+use crate::common::{Led, PollingRate};
+use crate::device::{RazerDevice, RazerMouse};
+use crate::error::{USBError, USBResult};
+use crate::lighting::Effect;
+use crate::shared::SharedDevice;
+
+impl<D: RazerDevice + Send + 'static> SharedDevice<D> {
+    /// Run `f` with the device on the blocking pool, like `with()`
+    pub async fn with_async<R, F>(&self, f: F) -> USBResult<R>
+    where
+        F: FnOnce(&D) -> USBResult<R> + Send + 'static,
+        R: Send + 'static,
+    {
+        let device = self.clone();
+        match tokio::task::spawn_blocking(move || device.with(f)).await {
+            Ok(res) => res,
+            Err(e) if e.is_panic() => panic::resume_unwind(e.into_panic()),
+            // the runtime is shutting down
+            Err(e) => Err(USBError::IOError(io::Error::other(e))),
+        }
+    }
+
+    pub async fn get_serial_async(&self) -> USBResult<String> {
+        self.with_async(|device| device.get_serial()).await
+    }
+
+    pub async fn get_battery_level_async(&self) -> USBResult<u8> {
+        self.with_async(|device| device.get_battery_level()).await
+    }
+
+    pub async fn set_led_color_async(&self, led: Led, color: RGB8) -> USBResult<()> {
+        self.with_async(move |device| device.set_led_color(led, color)).await
+    }
+
+    pub async fn preview_led_color_async(&self, led: Led, color: RGB8) -> USBResult<()> {
+        self.with_async(move |device| device.preview_led_color(led, color)).await
+    }
+
+    pub async fn get_led_brightness_async(&self, led: Led) -> USBResult<u8> {
+        self.with_async(move |device| device.get_led_brightness(led)).await
+    }
+
+    pub async fn set_led_brightness_async(&self, led: Led, brightness: u8) -> USBResult<()> {
+        self.with_async(move |device| device.set_led_brightness(led, brightness)).await
+    }
+
+    pub async fn set_effect_async(&self, effect: Effect) -> USBResult<()> {
+        self.with_async(move |device| device.set_effect(&effect)).await
+    }
+}
+
+impl<D: RazerMouse + Send + 'static> SharedDevice<D> {
+    pub async fn get_dpi_async(&self) -> USBResult<(u16, u16)> {
+        self.with_async(|device| device.get_dpi()).await
+    }
+
+    pub async fn set_dpi_async(&self, dpi_x: u16, dpi_y: u16) -> USBResult<()> {
+        self.with_async(move |device| device.set_dpi(dpi_x, dpi_y)).await
+    }
+
+    pub async fn get_dpi_stages_async(&self) -> USBResult<(Vec<(u16, u16)>, u8)> {
+        self.with_async(|device| device.get_dpi_stages()).await
+    }
+
+    pub async fn set_dpi_stages_async(
+        &self,
+        dpi_stages: Vec<(u16, u16)>,
+        current: u8
+    ) -> USBResult<()> {
+        self.with_async(move |device| device.set_dpi_stages(&dpi_stages, current)).await
+    }
+
+    pub async fn set_active_dpi_stage_async(&self, index: u8) -> USBResult<()> {
+        self.with_async(move |device| device.set_active_dpi_stage(index)).await
+    }
+
+    pub async fn get_poll_rate_async(&self) -> USBResult<PollingRate> {
+        self.with_async(|device| device.get_poll_rate()).await
+    }
+
+    pub async fn set_poll_rate_async(&self, poll_rate: PollingRate) -> USBResult<()> {
+        self.with_async(move |device| device.set_poll_rate(poll_rate)).await
+    }
+}
@@ -0,0 +1,317 @@
+//! What the mouse buttons do, stored on the device so it works without any
+//! software running:
+//!
+//! ```ignore
+//! device.set_button(Button::Back, ButtonFunction::from_str("key:ctrl+c")?)?;
+//! ```
+
+use std::{fmt, str::FromStr};
+
+use crate::error::{ParseButtonError, ParseButtonFunctionError};
+
+/// A mouse button, by the id the firmware knows it by
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Button {
+    Left = 0x01,
+    Right = 0x02,
+    Middle = 0x03,
+    /// The rear side button
+    Back = 0x04,
+    /// The front side button
+    Forward = 0x05,
+    WheelUp = 0x09,
+    WheelDown = 0x0A,
+    /// Keys of a Naga's side plate, by the number printed on them
+    Side1 = 0x40,
+    Side2 = 0x41,
+    Side3 = 0x42,
+    Side4 = 0x43,
+    Side5 = 0x44,
+    Side6 = 0x45,
+    Side7 = 0x46,
+    Side8 = 0x47,
+    Side9 = 0x48,
+    Side10 = 0x49,
+    Side11 = 0x4A,
+    Side12 = 0x4B,
+}
+
+const SIDE: [Button; 12] = [Button::Side1, Button::Side2, Button::Side3, Button::Side4,
+    Button::Side5, Button::Side6, Button::Side7, Button::Side8, Button::Side9,
+    Button::Side10, Button::Side11, Button::Side12];
+
+impl Button {
+    /// The buttons of a mouse without a side plate
+    pub const STANDARD: &'static [Button] = &[Button::Left, Button::Right, Button::Middle,
+        Button::Back, Button::Forward, Button::WheelUp, Button::WheelDown];
+
+    pub fn all() -> Vec<Self> {
+        Self::iter().collect()
+    }
+
+    pub fn standard() -> Vec<Self> {
+        Self::STANDARD.to_vec()
+    }
+
+    /// The first `count` keys of a side plate
+    pub fn side(count: usize) -> Vec<Self> {
+        SIDE.iter().take(count).copied().collect()
+    }
+
+    pub fn iter() -> impl Iterator<Item = Self> {
+        Self::standard().into_iter().chain(SIDE)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Button::Left => "left",
+            Button::Right => "right",
+            Button::Middle => "middle",
+            Button::Back => "back",
+            Button::Forward => "forward",
+            Button::WheelUp => "wheel-up",
+            Button::WheelDown => "wheel-down",
+            Button::Side1 => "side-1",
+            Button::Side2 => "side-2",
+            Button::Side3 => "side-3",
+            Button::Side4 => "side-4",
+            Button::Side5 => "side-5",
+            Button::Side6 => "side-6",
+            Button::Side7 => "side-7",
+            Button::Side8 => "side-8",
+            Button::Side9 => "side-9",
+            Button::Side10 => "side-10",
+            Button::Side11 => "side-11",
+            Button::Side12 => "side-12",
+        }
+    }
+}
+
+impl TryFrom<u8> for Button {
+    type Error = u8;
+
+    fn try_from(byte: u8) -> Result<Button, Self::Error> {
+        Button::iter().find(|&button| button as u8 == byte).ok_or(byte)
+    }
+}
+
+impl fmt::Display for Button {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Button {
+    type Err = ParseButtonError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let wanted = s.trim().to_lowercase().replace([' ', '_'], "-");
+        Button::iter()
+            .find(|button| button.name() == wanted)
+            .ok_or_else(|| ParseButtonError(s.into()))
+    }
+}
+
+/// Which set of bindings a button function belongs to
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum Layer {
+    #[default]
+    Normal = 0x00,
+    /// Used while the button bound to `ButtonFunction::Hypershift` is held
+    Hypershift = 0x01,
+}
+
+/// Modifier bits of a key combination
+pub const MOD_CTRL: u8 = 0x01;
+pub const MOD_SHIFT: u8 = 0x02;
+pub const MOD_ALT: u8 = 0x04;
+pub const MOD_WIN: u8 = 0x08;
+
+const MODIFIERS: &[(u8, &str)] = &[
+    (MOD_CTRL, "ctrl"),
+    (MOD_SHIFT, "shift"),
+    (MOD_ALT, "alt"),
+    (MOD_WIN, "win"),
+];
+
+/// Keys other than letters, digits and F1-F12, by HID usage id
+const NAMED_KEYS: &[(u8, &str)] = &[
+    (0x28, "enter"),
+    (0x29, "esc"),
+    (0x2A, "backspace"),
+    (0x2B, "tab"),
+    (0x2C, "space"),
+    (0x2D, "minus"),
+    (0x2E, "equal"),
+    (0x46, "printscreen"),
+    (0x49, "insert"),
+    (0x4A, "home"),
+    (0x4B, "pageup"),
+    (0x4C, "delete"),
+    (0x4D, "end"),
+    (0x4E, "pagedown"),
+    (0x4F, "right"),
+    (0x50, "left"),
+    (0x51, "down"),
+    (0x52, "up"),
+    // modifiers as keys of their own, e.g. pressed and released in a macro
+    (0xE0, "ctrl"),
+    (0xE1, "shift"),
+    (0xE2, "alt"),
+    (0xE3, "win"),
+];
+
+/// The HID usage id of a key, e.g. "a", "5", "f5" or "pageup"
+pub fn key_code(name: &str) -> Option<u8> {
+    let name = name.trim().to_lowercase();
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c @ 'a'..='z'), None) => return Some(0x04 + (c as u8 - b'a')),
+        (Some('0'), None) => return Some(0x27),
+        (Some(c @ '1'..='9'), None) => return Some(0x1E + (c as u8 - b'1')),
+        _ => {},
+    }
+    if let Some(n) = name.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+        return (1..=12).contains(&n).then(|| 0x3A + n - 1);
+    }
+    NAMED_KEYS.iter().find(|&&(_, key)| key == name).map(|&(code, _)| code)
+}
+
+/// The name `key_code()` takes for a HID usage id
+pub fn key_name(code: u8) -> Option<String> {
+    match code {
+        0x04..=0x1D => Some(((b'a' + code - 0x04) as char).to_string()),
+        0x1E..=0x26 => Some(((b'1' + code - 0x1E) as char).to_string()),
+        0x27 => Some("0".into()),
+        0x3A..=0x45 => Some(format!("f{}", code - 0x3A + 1)),
+        _ => NAMED_KEYS.iter().find(|&&(c, _)| c == code).map(|&(_, key)| key.into()),
+    }
+}
+
+/// The key a `MOD_*` bit stands for, e.g. to press it in a macro
+pub fn modifier_key(bit: u8) -> u8 {
+    match bit {
+        MOD_SHIFT => 0xE1,
+        MOD_ALT => 0xE2,
+        MOD_WIN => 0xE3,
+        _ => 0xE0,
+    }
+}
+
+/// (modifiers, key) Of a combination like "ctrl+shift+t"; see
+/// `ButtonFunction::Key`
+pub(crate) fn parse_key_combination(combination: &str) -> Option<(u8, u8)> {
+    let mut modifiers = 0u8;
+    let mut key = 0u8;
+    for part in combination.split('+') {
+        match MODIFIERS.iter().find(|&&(_, name)| name == part.trim().to_lowercase()) {
+            Some(&(bit, _)) => modifiers |= bit,
+            // only one key that isn't a modifier
+            None if key == 0 => key = key_code(part)?,
+            None => return None,
+        }
+    }
+    if modifiers == 0 && key == 0 {
+        return None;
+    }
+    Some((modifiers, key))
+}
+
+/// What a button does when pressed
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ButtonFunction {
+    Disabled,
+    /// Act as this mouse button
+    Mouse(Button),
+    /// (modifiers, key) A key combination; modifiers are `MOD_*` bits and the
+    /// key a HID usage id, 0 for modifiers alone
+    Key(u8, u8),
+    /// Go to the next DPI stage
+    DpiUp,
+    /// Go to the previous DPI stage
+    DpiDown,
+    /// Play the macro stored on the device with this id; see
+    /// `RazerMouse::create_macro()`
+    Macro(u16),
+    /// Switch to the `Layer::Hypershift` bindings while held
+    Hypershift,
+}
+
+impl ButtonFunction {
+    /// (function type, parameters) As the firmware takes them
+    pub(crate) fn to_bytes(self) -> (u8, Vec<u8>) {
+        match self {
+            ButtonFunction::Disabled => (0x00, vec![]),
+            ButtonFunction::Mouse(button) => (0x01, vec![button as u8, 0x00]),
+            ButtonFunction::Key(modifiers, key) => (0x02, vec![modifiers, key]),
+            ButtonFunction::DpiUp => (0x06, vec![0x01]),
+            ButtonFunction::DpiDown => (0x06, vec![0x02]),
+            ButtonFunction::Macro(id) => (0x03, id.to_be_bytes().to_vec()),
+            ButtonFunction::Hypershift => (0x0C, vec![0x01]),
+        }
+    }
+
+    /// The reverse of `to_bytes()`; None for functions not modeled here
+    pub(crate) fn from_bytes(kind: u8, params: &[u8]) -> Option<Self> {
+        match (kind, params) {
+            (0x00, _) => Some(ButtonFunction::Disabled),
+            (0x01, [button, ..]) => Button::try_from(*button).ok().map(ButtonFunction::Mouse),
+            (0x02, [modifiers, key, ..]) => Some(ButtonFunction::Key(*modifiers, *key)),
+            (0x06, [0x01, ..]) => Some(ButtonFunction::DpiUp),
+            (0x06, [0x02, ..]) => Some(ButtonFunction::DpiDown),
+            (0x03, [hi, lo, ..]) => Some(ButtonFunction::Macro(u16::from_be_bytes([*hi, *lo]))),
+            (0x0C, _) => Some(ButtonFunction::Hypershift),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ButtonFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            ButtonFunction::Disabled => write!(f, "disabled"),
+            ButtonFunction::Mouse(button) => write!(f, "mouse:{}", button),
+            ButtonFunction::Key(modifiers, key) => {
+                let mut parts: Vec<String> = MODIFIERS.iter()
+                    .filter(|&&(bit, _)| modifiers & bit != 0)
+                    .map(|&(_, name)| name.into())
+                    .collect();
+                if key != 0 {
+                    parts.push(key_name(key).unwrap_or_else(|| format!("0x{:02x}", key)));
+                }
+                write!(f, "key:{}", parts.join("+"))
+            },
+            ButtonFunction::DpiUp => write!(f, "dpi-up"),
+            ButtonFunction::DpiDown => write!(f, "dpi-down"),
+            ButtonFunction::Macro(id) => write!(f, "macro:{}", id),
+            ButtonFunction::Hypershift => write!(f, "hypershift"),
+        }
+    }
+}
+
+/// "disabled", "mouse:<button>", "key:<combination>" (e.g. "key:ctrl+shift+t"),
+/// "dpi-up", "dpi-down", "macro:<id>" or "hypershift"
+impl FromStr for ButtonFunction {
+    type Err = ParseButtonFunctionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || ParseButtonFunctionError(s.into());
+        let lower = s.trim().to_lowercase();
+        match lower.split_once(':') {
+            None if lower == "disabled" => Ok(ButtonFunction::Disabled),
+            None if lower == "dpi-up" => Ok(ButtonFunction::DpiUp),
+            None if lower == "dpi-down" => Ok(ButtonFunction::DpiDown),
+            None if lower == "hypershift" => Ok(ButtonFunction::Hypershift),
+            Some(("mouse", button)) =>
+                button.parse().map(ButtonFunction::Mouse).map_err(|_| err()),
+            Some(("key", combination)) => parse_key_combination(combination)
+                .map(|(modifiers, key)| ButtonFunction::Key(modifiers, key))
+                .ok_or_else(err),
+            Some(("macro", id)) =>
+                id.trim().parse().map(ButtonFunction::Macro).map_err(|_| err()),
+            _ => Err(err()),
+        }
+    }
+}
@@ -0,0 +1,117 @@
+//! What a device can do, so front-ends can show only the controls that apply.
+
+use crate::buttons::Button;
+use crate::common::{Led, PollingRate, MAX_DPI_STAGES};
+use crate::error::DpiStagesError;
+
+/// What an LED can do
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LedKind {
+    /// Any color, brightness and off
+    Rgb,
+    /// Fixed color (e.g. white or green); brightness and off only
+    SingleColor,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct LedCapability {
+    pub led: Led,
+    pub kind: LedKind,
+}
+
+/// The DPI a sensor can do
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DpiRange {
+    pub min: u16,
+    pub max: u16,
+    /// Granularity of the values the device accepts
+    pub step: u16,
+}
+
+impl DpiRange {
+    /// Clamp `dpi` in the range and round it to the nearest step
+    pub fn fit(&self, dpi: u16) -> u16 {
+        let dpi = dpi.clamp(self.min, self.max);
+        let step = self.step.max(1);
+        let steps = ((dpi - self.min) as f32 / step as f32).round() as u16;
+        (self.min + steps * step).min(self.max)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Capabilities {
+    /// None for devices without a sensor
+    pub dpi: Option<DpiRange>,
+    /// How many DPI stages the device takes (0 if it has no stages), up to
+    /// `common::MAX_DPI_STAGES`
+    pub dpi_stages: usize,
+    pub leds: Vec<LedCapability>,
+    /// Polling rates the device accepts
+    pub polling_rates: Vec<PollingRate>,
+    /// Runs on a battery whose level can be queried
+    pub battery: bool,
+    /// Has a scroll wheel that switches between tactile and free spin, and
+    /// smart reel
+    pub scroll_mode: bool,
+    /// Has a sensor whose lift-off distance can be set
+    pub liftoff: bool,
+    /// Programmable buttons on the side, e.g. for remapping
+    pub side_buttons: usize,
+    /// Buttons whose function can be changed; see `RazerMouse::set_button()`
+    pub buttons: Vec<Button>,
+    /// Stores macros that buttons can play; see `RazerMouse::create_macro()`
+    pub macros: bool,
+    /// Charges a wireless mouse, and has lighting that can show how that's
+    /// going
+    pub dock: bool,
+    /// (rows, columns) of LEDs that take custom frames; see
+    /// `RazerDevice::draw_frame()`
+    pub matrix: Option<(usize, usize)>,
+}
+
+impl Capabilities {
+    pub fn led(&self, led: Led) -> Option<&LedCapability> {
+        self.leds.iter().find(|cap| cap.led == led)
+    }
+
+    pub fn has_led(&self, led: Led) -> bool {
+        self.led(led).is_some()
+    }
+
+    /// Check `stages` (and the 0-based `current` one) before they're sent
+    pub fn validate_dpi_stages(
+        &self,
+        stages: &[(u16, u16)],
+        current: u8
+    ) -> Result<(), DpiStagesError> {
+        if stages.is_empty() {
+            return Err(DpiStagesError::Empty);
+        }
+        let max = self.dpi_stages.min(MAX_DPI_STAGES);
+        if stages.len() > max {
+            return Err(DpiStagesError::TooMany(max));
+        }
+        if current as usize >= stages.len() {
+            return Err(DpiStagesError::CurrentOutOfRange(current));
+        }
+
+        for (i, &(dpi_x, dpi_y)) in stages.iter().enumerate() {
+            if let Some(range) = self.dpi {
+                for dpi in [dpi_x, dpi_y] {
+                    if dpi < range.min || dpi > range.max {
+                        return Err(DpiStagesError::OutOfRange(i, dpi));
+                    }
+                }
+            }
+            if let Some(earlier) = stages[..i].iter().position(|&s| s == (dpi_x, dpi_y)) {
+                return Err(DpiStagesError::Duplicate(i, earlier));
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the color of `led` can be set
+    pub fn has_rgb(&self, led: Led) -> bool {
+        self.led(led).is_some_and(|cap| cap.kind == LedKind::Rgb)
+    }
+}
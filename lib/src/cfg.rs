@@ -1,26 +1,463 @@
 use std::default::Default;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use serde::{Serialize, Deserialize};
 use confy::ConfyError;
+use toml_edit::{DocumentMut, Item, Table, Value};
 use rgb::RGB8;
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+use crate::common::{rgb_from_str, utc_string, Led};
+use crate::error::ConfigSetError;
+use crate::profile::Profile;
+use crate::transport::USB_TXFER_TIMEOUT;
+
+const APP_NAME: &str = "deathadder_v2";
+
+/// How many previous versions of the config to keep around
+const MAX_BACKUPS: usize = 10;
+
+/// Red, yellow, green, cyan, blue: from slow to fast
+pub const DEFAULT_STAGE_COLORS: [RGB8; 5] = [
+    RGB8::new(0xFF, 0x00, 0x00),
+    RGB8::new(0xFF, 0xFF, 0x00),
+    RGB8::new(0x00, 0xFF, 0x00),
+    RGB8::new(0x00, 0xFF, 0xFF),
+    RGB8::new(0x00, 0x00, 0xFF),
+];
+
+/// What front-ends do when the DPI button on the mouse is pressed. Stored as
+/// its textual form; see `from_str()`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub enum ButtonAction {
+    /// Let it step through the DPI stages, as usual
+    #[default]
+    Stages,
+    /// Activate these profiles in turn, by saved profile name or file path
+    CycleProfiles(Vec<String>),
+    /// Turn the lighting off and back on
+    ToggleLighting,
+    /// Run this through the shell
+    Command(String),
+}
+
+impl FromStr for ButtonAction {
+    type Err = String;
+
+    /// "stages", "toggle-lighting", "cycle-profiles:<a>,<b>,..." or
+    /// "command:<command line>"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (action, arg) = match s.split_once(':') {
+            Some((action, arg)) => (action, Some(arg)),
+            None => (s, None),
+        };
+        match (action.trim().to_lowercase().as_str(), arg) {
+            ("stages", None) => Ok(ButtonAction::Stages),
+            ("toggle-lighting", None) => Ok(ButtonAction::ToggleLighting),
+            ("cycle-profiles", Some(profiles)) => Ok(ButtonAction::CycleProfiles(
+                profiles.split(',').map(str::trim).filter(|p| !p.is_empty())
+                    .map(String::from).collect())),
+            ("command", Some(command)) if !command.trim().is_empty() =>
+                Ok(ButtonAction::Command(command.trim().into())),
+            _ => Err(format!("'{}' is not an action (stages, toggle-lighting, \
+                cycle-profiles:<a>,<b> or command:<command line>)", s)),
+        }
+    }
+}
+
+impl fmt::Display for ButtonAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ButtonAction::Stages => write!(f, "stages"),
+            ButtonAction::CycleProfiles(profiles) =>
+                write!(f, "cycle-profiles:{}", profiles.join(",")),
+            ButtonAction::ToggleLighting => write!(f, "toggle-lighting"),
+            ButtonAction::Command(command) => write!(f, "command:{}", command),
+        }
+    }
+}
+
+impl From<ButtonAction> for String {
+    fn from(action: ButtonAction) -> Self {
+        action.to_string()
+    }
+}
+
+impl TryFrom<String> for ButtonAction {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// A previous version of the config, saved before it got overwritten
+#[derive(Debug, Clone)]
+pub struct Backup {
+    pub path: PathBuf,
+    /// Milliseconds since the unix epoch
+    pub timestamp: u64,
+}
+
+impl Backup {
+    /// The timestamp as "YYYY-MM-DD HH:MM:SS UTC"
+    pub fn time_string(&self) -> String {
+        utc_string(self.timestamp / 1000)
+    }
+}
+
+/// Make `current` hold what `fresh` does, changing only what differs so that
+/// comments and formatting around the rest stay as they were. Keys missing
+/// from `fresh` are removed if `owned` says they're ours (e.g. an option that
+/// was unset) and kept otherwise (e.g. ones a newer version wrote)
+fn merge_table(current: &mut Table, fresh: &Table, owned: &dyn Fn(&str) -> bool) {
+    let gone: Vec<String> = current.iter()
+        .filter(|(key, _)| !fresh.contains_key(key) && owned(key))
+        .map(|(key, _)| key.to_string())
+        .collect();
+    for key in gone {
+        current.remove(&key);
+    }
+
+    for (key, item) in fresh.iter() {
+        match (current.get_mut(key), item) {
+            (Some(Item::Table(ours)), Item::Table(theirs)) =>
+                merge_table(ours, theirs, &|_| true),
+            (Some(Item::Value(ours)), Item::Value(theirs)) => {
+                // same value, maybe written differently (e.g. 0xff); leave it
+                if !same_value(ours, theirs) {
+                    let decor = ours.decor().clone();
+                    *ours = theirs.clone();
+                    *ours.decor_mut() = decor;
+                }
+            },
+            (Some(ours), _) => *ours = item.clone(),
+            (None, _) => {
+                current.insert(key, item.clone());
+            },
+        }
+    }
+}
+
+/// Whether `a` and `b` hold the same, however they're written
+fn same_value(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::String(a), Value::String(b)) => a.value() == b.value(),
+        (Value::Integer(a), Value::Integer(b)) => a.value() == b.value(),
+        (Value::Float(a), Value::Float(b)) => a.value() == b.value(),
+        (Value::Boolean(a), Value::Boolean(b)) => a.value() == b.value(),
+        (Value::Datetime(a), Value::Datetime(b)) => a.value() == b.value(),
+        (Value::Array(a), Value::Array(b)) =>
+            a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| same_value(a, b)),
+        (Value::InlineTable(a), Value::InlineTable(b)) =>
+            a.len() == b.len() && a.iter()
+                .all(|(key, a)| b.get(key).is_some_and(|b| same_value(a, b))),
+        _ => false,
+    }
+}
+
+/// Where the main window was when last closed
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WindowPlacement {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Config {
+    // plain values first: toml can't write one after a table
     pub same_color: bool,
     pub same_brightness: bool,
+    #[serde(with = "crate::hex_color")]
     pub logo_color: RGB8,
+    #[serde(with = "crate::hex_color")]
     pub scroll_color: RGB8,
+    /// Where long-running front-ends write the device status for desktop
+    /// widgets; see `status::Status`
+    pub status_path: Option<PathBuf>,
+    /// Whether lighting changes are saved on the device, or only last until
+    /// it's unplugged
+    pub onboard: bool,
+    /// Whether the color dialogs show the color on the device as it's being
+    /// picked, or only once it's confirmed
+    pub live_preview: bool,
+    /// Whether front-ends close the device when they're not using it, so
+    /// other programs can open it
+    pub release_when_idle: bool,
+    /// DPI values front-ends offer to switch to in one click, apart from the
+    /// stages
+    pub dpi_presets: Vec<u16>,
+    /// Whether front-ends show the active DPI stage on the scroll wheel, in
+    /// its color from `stage_colors`
+    pub stage_indicator: bool,
+    /// By DPI stage, first stage first
+    #[serde(with = "crate::hex_color::vec")]
+    pub stage_colors: Vec<RGB8>,
+    /// What the DPI button does
+    pub dpi_button: ButtonAction,
+    /// A fixed wait (in ms) between sending a command and reading the answer,
+    /// instead of the learned one, e.g. for a flaky hub
+    pub receiver_wait_ms: Option<u64>,
+    /// How long each USB transfer may take (in ms)
+    pub transfer_timeout_ms: u64,
+    /// Colors of LED zones other than the logo and scroll wheel, by
+    /// `Led` variant name (e.g. "LeftSide")
+    #[serde(with = "crate::hex_color::map")]
+    pub zone_colors: HashMap<String, RGB8>,
+    /// Learned receiver wait (in us) per device serial
+    pub receiver_waits: HashMap<String, u64>,
+    /// Transaction ids to use instead of the defaults, by "vid:pid" (e.g.
+    /// "1532:0084"), for firmware that only answers to another one
+    pub tx_ids: HashMap<String, u8>,
+    pub window: Option<WindowPlacement>,
+    /// Saved device settings to compare the device against
+    pub profile: Option<Profile>,
 }
 
 impl Config {
+    /// Save the config, keeping the one it replaces as a backup
     pub fn save(&self) -> Result<(), ConfyError> {
-        confy::store("deathadder_v2", None, self)
+        Self::backup_current()?;
+        let path = Self::path()?;
+        let fresh = toml::to_string(self).map_err(ConfyError::SerializeTomlError)?;
+
+        // keep the comments and formatting of the file on disk, if it parses
+        let contents = match fs::read_to_string(&path).ok()
+            .and_then(|current| current.parse::<DocumentMut>().ok())
+            .zip(fresh.parse::<DocumentMut>().ok()) {
+            Some((mut current, fresh)) => {
+                merge_table(current.as_table_mut(), fresh.as_table(),
+                    &|key| Self::FIELDS.contains(&key));
+                current.to_string()
+            },
+            None => fresh,
+        };
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(ConfyError::DirectoryCreationFailed)?;
+        }
+        fs::write(&path, contents).map_err(ConfyError::WriteConfigurationFileError)
+    }
+
+    /// Load the config, or create it with defaults if it doesn't exist
+    pub fn load() -> Result<Self, ConfyError> {
+        confy::load(APP_NAME, None)
+    }
+
+    /// Where the config is stored
+    pub fn path() -> Result<PathBuf, ConfyError> {
+        confy::get_configuration_file_path(APP_NAME, None)
+    }
+
+    /// Where front-ends write what they know when they crash; next to the
+    /// config so users find it
+    pub fn crash_log_path() -> Result<PathBuf, ConfyError> {
+        let path = Self::path()?;
+        let dir = path.parent().unwrap_or(Path::new("."));
+        Ok(dir.join("crash.log"))
+    }
+
+    fn backups_dir() -> Result<PathBuf, ConfyError> {
+        let path = Self::path()?;
+        let dir = path.parent().unwrap_or(Path::new("."));
+        Ok(dir.join("backups"))
     }
 
-    pub fn load() -> Option<Self> {
-        match confy::load("deathadder_v2", None) {
-            Ok(cfg) => Some(cfg),
-            Err(_) => None
+    /// Previous versions of the config, most recent first
+    pub fn backups() -> Result<Vec<Backup>, ConfyError> {
+        let dir = Self::backups_dir()?;
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(_) => return Ok(Vec::new()), // no backups yet
+        };
+
+        let mut backups = entries
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                let stamp = path.file_name()?.to_str()?
+                    .strip_prefix("config-")?
+                    .strip_suffix(".toml")?
+                    .parse::<u64>().ok()?;
+                // older backups were named in seconds
+                let timestamp = if stamp < 100_000_000_000 { stamp * 1000 } else { stamp };
+                Some(Backup { path, timestamp })
+            })
+            .collect::<Vec<Backup>>();
+        backups.sort_by_key(|b| Reverse(b.timestamp));
+        Ok(backups)
+    }
+
+    /// Load a backup and make it the current config again
+    pub fn restore(backup: &Backup) -> Result<Self, ConfyError> {
+        let cfg: Self = confy::load_path(&backup.path)?;
+        cfg.save()?;
+        Ok(cfg)
+    }
+
+    /// Copy the config file on disk (if any) to the backups, unless it's the
+    /// same as the latest one, and prune the oldest ones
+    fn backup_current() -> Result<(), ConfyError> {
+        let current = match fs::read(Self::path()?) {
+            Ok(current) => current,
+            Err(_) => return Ok(()), // nothing to back up
+        };
+
+        let backups = Self::backups()?;
+        let unchanged = backups.first()
+            .and_then(|latest| fs::read(&latest.path).ok())
+            .is_some_and(|latest| latest == current);
+        if unchanged {
+            return Ok(());
         }
+
+        let dir = Self::backups_dir()?;
+        fs::create_dir_all(&dir).map_err(ConfyError::DirectoryCreationFailed)?;
+        // milliseconds, so saves in quick succession don't overwrite each other
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis() as u64);
+        fs::write(dir.join(format!("config-{}.toml", timestamp)), current)
+            .map_err(ConfyError::WriteConfigurationFileError)?;
+
+        // the one we just wrote is not in `backups`, hence the - 1
+        for old in backups.iter().skip(MAX_BACKUPS - 1) {
+            _ = fs::remove_file(&old.path);
+        }
+        Ok(())
+    }
+
+    /// The keys of the file, one per field
+    const FIELDS: [&'static str; 19] = ["same_color", "same_brightness", "logo_color",
+        "scroll_color", "status_path", "onboard", "live_preview", "release_when_idle",
+        "dpi_presets", "stage_indicator", "stage_colors", "dpi_button", "receiver_wait_ms",
+        "transfer_timeout_ms", "zone_colors", "receiver_waits", "tx_ids", "window", "profile"];
+
+    /// Keys that `set()` understands
+    pub const KEYS: [&'static str; 16] = ["same_color", "same_brightness", "logo_color",
+        "scroll_color", "profile_name", "profile_scope", "status_path", "onboard",
+        "live_preview", "release_when_idle", "dpi_presets", "stage_indicator", "stage_colors",
+        "dpi_button", "receiver_wait_ms", "transfer_timeout_ms"];
+
+    /// Set a field from its textual representation, e.g. ("logo_color", "#f00")
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), ConfigSetError> {
+        let bad_value = || ConfigSetError::BadValue(key.into(), value.into());
+        match key {
+            "same_color" => self.same_color = value.parse().map_err(|_| bad_value())?,
+            "same_brightness" => self.same_brightness = value.parse().map_err(|_| bad_value())?,
+            "logo_color" => self.logo_color = rgb_from_str(value).map_err(|_| bad_value())?,
+            "scroll_color" => self.scroll_color = rgb_from_str(value).map_err(|_| bad_value())?,
+            "profile_name" => self.profile.get_or_insert_with(Default::default).name =
+                Some(value.into()).filter(|name: &String| !name.is_empty()),
+            "status_path" => self.status_path = Some(PathBuf::from(value))
+                .filter(|path| !path.as_os_str().is_empty()),
+            "onboard" => self.onboard = value.parse().map_err(|_| bad_value())?,
+            "live_preview" => self.live_preview = value.parse().map_err(|_| bad_value())?,
+            "release_when_idle" =>
+                self.release_when_idle = value.parse().map_err(|_| bad_value())?,
+            // comma separated, e.g. "400,800,1600"
+            "dpi_presets" => self.dpi_presets = value.split(',')
+                .map(str::trim)
+                .filter(|dpi| !dpi.is_empty())
+                .map(|dpi| dpi.parse().map_err(|_| bad_value()))
+                .collect::<Result<_, _>>()?,
+            "stage_indicator" => self.stage_indicator = value.parse().map_err(|_| bad_value())?,
+            // comma separated, e.g. "#f00,#0f0,#00f"
+            "stage_colors" => self.stage_colors = value.split(',')
+                .map(str::trim)
+                .filter(|color| !color.is_empty())
+                .map(|color| rgb_from_str(color).map_err(|_| bad_value()))
+                .collect::<Result<_, _>>()?,
+            "dpi_button" => self.dpi_button = value.parse().map_err(|_| bad_value())?,
+            // empty to go back to the learned wait
+            "receiver_wait_ms" => self.receiver_wait_ms = match value.trim() {
+                "" => None,
+                ms => Some(ms.parse().map_err(|_| bad_value())?),
+            },
+            "transfer_timeout_ms" => self.transfer_timeout_ms = value.parse()
+                .ok().filter(|&ms| ms > 0).ok_or_else(bad_value)?,
+            "profile_scope" => self.profile.get_or_insert_with(Default::default).scope =
+                value.parse().map_err(|_| bad_value())?,
+            _ => return Err(ConfigSetError::UnknownKey(key.into())),
+        }
+        Ok(())
+    }
+
+    pub fn zone_color(&self, led: Led) -> Option<RGB8> {
+        self.zone_colors.get(&format!("{:?}", led)).copied()
+    }
+
+    pub fn set_zone_color(&mut self, led: Led, color: RGB8) {
+        self.zone_colors.insert(format!("{:?}", led), color);
+    }
+
+    /// What the scroll wheel shows for DPI `stage` (0-based), if the stage
+    /// indicator is on and the stage has a color
+    pub fn stage_color(&self, stage: u8) -> Option<RGB8> {
+        self.stage_colors.get(stage as usize).copied().filter(|_| self.stage_indicator)
+    }
+
+    /// Color DPI `stage` (0-based); stages before it without one get the
+    /// default color
+    pub fn set_stage_color(&mut self, stage: u8, color: RGB8) {
+        let stage = stage as usize;
+        while self.stage_colors.len() <= stage {
+            let i = self.stage_colors.len();
+            self.stage_colors.push(DEFAULT_STAGE_COLORS[i % DEFAULT_STAGE_COLORS.len()]);
+        }
+        self.stage_colors[stage] = color;
+    }
+
+    /// The colors to apply, as a profile
+    pub fn profile(&self) -> Profile {
+        let mut profile = Profile {
+            colors: self.zone_colors.clone(),
+            ..Default::default()
+        };
+        profile.set_color(Led::Logo, self.logo_color);
+        profile.set_color(Led::ScrollWheel, self.scroll_color);
+        profile
+    }
+
+    /// Take the colors `profile` has for `zones`, e.g. after applying it
+    pub fn set_colors_from(&mut self, profile: &Profile, zones: &[Led]) {
+        for &led in zones {
+            match (led, profile.color(led)) {
+                (_, None) => {},
+                (Led::Logo, Some(color)) => self.logo_color = color,
+                (Led::ScrollWheel, Some(color)) => {
+                    self.scroll_color = color;
+                    self.same_color = profile.color(Led::Logo) == Some(color);
+                },
+                (_, Some(color)) => self.set_zone_color(led, color),
+            }
+        }
+    }
+
+    pub fn receiver_wait(&self, serial: &str) -> Option<Duration> {
+        self.receiver_waits.get(serial).map(|&us| Duration::from_micros(us))
+    }
+
+    pub fn set_receiver_wait(&mut self, serial: &str, wait: Duration) {
+        self.receiver_waits.insert(serial.into(), wait.as_micros() as u64);
+    }
+
+    pub fn tx_id(&self, vid: u16, pid: u16) -> Option<u8> {
+        self.tx_ids.get(&format!("{:04x}:{:04x}", vid, pid)).copied()
+    }
+
+    pub fn set_tx_id(&mut self, vid: u16, pid: u16, tx_id: Option<u8>) {
+        let key = format!("{:04x}:{:04x}", vid, pid);
+        match tx_id {
+            Some(tx_id) => self.tx_ids.insert(key, tx_id),
+            None => self.tx_ids.remove(&key),
+        };
     }
 }
 
@@ -31,6 +468,84 @@ impl Default for Config {
             same_brightness: true,
             logo_color: RGB8::new(0xAA, 0xAA, 0xAA),
             scroll_color: RGB8::new(0xAA, 0xAA, 0xAA),
+            status_path: None,
+            onboard: true,
+            live_preview: true,
+            release_when_idle: true,
+            dpi_presets: vec![400, 800, 1600, 3200],
+            stage_indicator: false,
+            stage_colors: DEFAULT_STAGE_COLORS.to_vec(),
+            dpi_button: ButtonAction::Stages,
+            receiver_wait_ms: None,
+            transfer_timeout_ms: USB_TXFER_TIMEOUT.as_millis() as u64,
+            zone_colors: HashMap::new(),
+            receiver_waits: HashMap::new(),
+            tx_ids: HashMap::new(),
+            window: None,
+            profile: None,
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_keeps_comments_and_unknown_keys() {
+        let mut current: DocumentMut = "\
+# edited by hand
+onboard = true # save on the mouse
+status_path = \"status.json\"
+from_a_newer_version = 1
+
+[zone_colors]
+# the underglow
+Backlight = \"#ff0000\"
+LeftSide = \"#00ff00\"
+".parse().unwrap();
+        // status_path unset, LeftSide renamed to RightSide
+        let fresh: DocumentMut = "\
+onboard = false
+
+[zone_colors]
+Backlight = \"#ff0000\"
+RightSide = \"#00ff00\"
+".parse().unwrap();
+
+        merge_table(current.as_table_mut(), fresh.as_table(),
+            &|key| Config::FIELDS.contains(&key));
+        assert_eq!(current.to_string(), "\
+# edited by hand
+onboard = false # save on the mouse
+from_a_newer_version = 1
+
+[zone_colors]
+# the underglow
+Backlight = \"#ff0000\"
+RightSide = \"#00ff00\"
+");
+    }
+
+    #[test]
+    fn merge_leaves_values_written_differently() {
+        let mut current: DocumentMut = "tx_ids = { \"1532:0084\" = 0x3f }\n".parse().unwrap();
+        let fresh: DocumentMut = "tx_ids = { \"1532:0084\" = 63 }\n".parse().unwrap();
+        merge_table(current.as_table_mut(), fresh.as_table(), &|_| true);
+        assert_eq!(current.to_string(), "tx_ids = { \"1532:0084\" = 0x3f }\n");
+    }
+
+    #[test]
+    fn fields_are_the_keys_of_the_file() {
+        let cfg = Config {
+            status_path: Some("status.json".into()),
+            receiver_wait_ms: Some(20),
+            window: Some(WindowPlacement { x: 0, y: 0, width: 800, height: 600 }),
+            profile: Some(Default::default()),
+            ..Default::default()
+        };
+        let doc: DocumentMut = toml::to_string(&cfg).unwrap().parse().unwrap();
+        let keys: Vec<&str> = doc.iter().map(|(key, _)| key).collect();
+        assert_eq!(keys, Config::FIELDS);
+    }
+}
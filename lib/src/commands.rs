@@ -0,0 +1,284 @@
+//! Catalog of the command class/id pairs understood by Razer devices.
+//!
+//! Most of these come from openrazer's driver; the comments note the devices
+//! they're known to work with. Report builders in `common` and anything that
+//! needs to make sense of captured traffic should reference these rather than
+//! repeating the magic numbers.
+
+use std::fmt;
+
+use crate::error::RawCommandError;
+
+/// A (command class, command id) pair as found in a `RazerReport`
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Command {
+    pub class: u8,
+    pub id: u8,
+}
+
+impl Command {
+    pub const fn new(class: u8, id: u8) -> Self {
+        Self { class, id }
+    }
+
+    /// Getters have the high bit of the command id set
+    pub const fn is_get(&self) -> bool {
+        self.id & 0x80 != 0
+    }
+
+    /// The name of this command if it's in the catalog
+    pub fn name(&self) -> Option<&'static str> {
+        ALL.iter().find(|(c, _)| c == self).map(|&(_, name)| name)
+    }
+
+    /// Whether this command can write to onboard memory, and when
+    pub fn persistence(&self) -> Option<Persistence> {
+        PERSISTING.iter().find(|(c, _)| c == self).map(|&(_, persistence)| persistence)
+    }
+}
+
+/// Arguments that fit in a report
+pub const MAX_ARGUMENTS: usize = 80;
+
+/// Any command with its arguments, e.g. to experiment with ones not in the
+/// catalog; see `RazerDevice::send_raw()`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawCommand {
+    command: Command,
+    args: Vec<u8>,
+}
+
+impl RawCommand {
+    pub fn new(class: u8, id: u8, args: &[u8]) -> Result<Self, RawCommandError> {
+        if args.len() > MAX_ARGUMENTS {
+            return Err(RawCommandError(args.len()));
+        }
+        Ok(Self {
+            command: Command::new(class, id),
+            args: args.to_vec(),
+        })
+    }
+
+    pub fn command(&self) -> Command {
+        self.command
+    }
+
+    pub fn args(&self) -> &[u8] {
+        &self.args
+    }
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name() {
+            Some(name) => write!(f, "{} ({:#04x}/{:#04x})", name, self.class, self.id),
+            None => write!(f, "unknown ({:#04x}/{:#04x})", self.class, self.id),
+        }
+    }
+}
+
+/// Read the serial number (22 bytes, NUL-padded). All devices.
+pub const GET_SERIAL: Command = Command::new(0x00, 0x82);
+
+/// Set the device mode (`DeviceMode` byte, then 0). All devices.
+pub const SET_DEVICE_MODE: Command = Command::new(0x00, 0x04);
+
+/// Get the device mode. All devices.
+pub const GET_DEVICE_MODE: Command = Command::new(0x00, 0x84);
+
+/// Set the polling rate (one `PollingRate` flag byte). All mice.
+pub const SET_POLLING_RATE: Command = Command::new(0x00, 0x05);
+
+/// Get the polling rate. All mice.
+pub const GET_POLLING_RATE: Command = Command::new(0x00, 0x85);
+
+/// Get the battery level (0-255 in the second argument). Wireless mice.
+pub const GET_BATTERY_LEVEL: Command = Command::new(0x07, 0x80);
+
+/// Whether the device is charging (0/1 in the second argument). Wireless
+/// mice.
+pub const GET_CHARGING_STATUS: Command = Command::new(0x07, 0x84);
+
+/// Set how long the device waits before going to sleep (seconds as big
+/// endian u16, 60-900). Wireless mice.
+pub const SET_IDLE_TIME: Command = Command::new(0x07, 0x03);
+
+/// Get the idle time before sleeping. Wireless mice.
+pub const GET_IDLE_TIME: Command = Command::new(0x07, 0x83);
+
+/// Get the mouse a wireless dongle is paired with (connected 0/1 in the
+/// first argument, the mouse's product id as big endian u16 in the next two).
+/// HyperSpeed dongles.
+pub const GET_PAIRED_DEVICE: Command = Command::new(0x00, 0xBF);
+
+/// Set the current DPI (storage, x, y as big endian u16). DeathAdder v2 and
+/// most mice from 2017 onwards.
+pub const SET_DPI_XY: Command = Command::new(0x04, 0x05);
+
+/// Get the current DPI. Same devices as `SET_DPI_XY`.
+pub const GET_DPI_XY: Command = Command::new(0x04, 0x85);
+
+/// Set all DPI stages and the active one. Found on the DeathAdder v2; not in
+/// openrazer.
+pub const SET_DPI_STAGES: Command = Command::new(0x04, 0x06);
+
+/// Get all DPI stages and the active one. DeathAdder v2.
+pub const GET_DPI_STAGES: Command = Command::new(0x04, 0x86);
+
+/// Set an effect on one LED through the extended matrix (storage, LED,
+/// effect, params). DeathAdder v2, Basilisk, Viper and newer mice.
+pub const SET_EXTENDED_MATRIX_EFFECT: Command = Command::new(0x0F, 0x02);
+
+/// Set the effect on logo and scroll wheel at once. Naga Trinity; also
+/// accepted by the DeathAdder v2 where it doesn't persist (preview).
+pub const SET_NAGA_TRINITY_EFFECT: Command = Command::new(0x0F, 0x03);
+
+/// Set one row (or part) of a custom frame (row, first and last column,
+/// colors), shown with the custom frame effect. The same command as
+/// `SET_NAGA_TRINITY_EFFECT`; the arguments tell them apart.
+pub const SET_EXTENDED_MATRIX_FRAME: Command = Command::new(0x0F, 0x03);
+
+/// Set the brightness (0-255) of one LED through the extended matrix.
+/// Same devices as `SET_EXTENDED_MATRIX_EFFECT`.
+pub const SET_EXTENDED_MATRIX_BRIGHTNESS: Command = Command::new(0x0F, 0x04);
+
+/// Get the brightness of one LED through the extended matrix.
+pub const GET_EXTENDED_MATRIX_BRIGHTNESS: Command = Command::new(0x0F, 0x84);
+
+/// Set how many LEDs the strip on an ARGB channel has (storage, channel LED,
+/// count). Chroma ARGB controller.
+pub const SET_ARGB_CHANNEL_SIZE: Command = Command::new(0x0F, 0x08);
+
+/// Get the LED count of an ARGB channel.
+pub const GET_ARGB_CHANNEL_SIZE: Command = Command::new(0x0F, 0x88);
+
+/// Turn one LED on or off (storage, LED, state). The classic LED commands,
+/// for devices without the extended matrix, e.g. the DeathAdder Essential.
+pub const SET_LED_STATE: Command = Command::new(0x03, 0x00);
+
+/// Set the color of one LED (storage, LED, r, g, b). Same devices as
+/// `SET_LED_STATE`, with RGB LEDs, e.g. the DeathAdder Elite.
+pub const SET_LED_RGB: Command = Command::new(0x03, 0x01);
+
+/// Set the effect of one LED (storage, LED, `ClassicLedEffect` byte). Same
+/// devices as `SET_LED_STATE`.
+pub const SET_LED_EFFECT: Command = Command::new(0x03, 0x02);
+
+/// Set the brightness (0-255) of one LED. Same devices as `SET_LED_STATE`.
+pub const SET_LED_BRIGHTNESS: Command = Command::new(0x03, 0x03);
+
+/// Get the brightness of one LED. Same devices as `SET_LED_STATE`.
+pub const GET_LED_BRIGHTNESS: Command = Command::new(0x03, 0x83);
+
+/// Set the scroll wheel mode (storage, `ScrollMode` byte). Basilisk V3 and
+/// others with a free-spinning wheel.
+pub const SET_SCROLL_MODE: Command = Command::new(0x02, 0x14);
+
+/// Get the scroll wheel mode (storage in, `ScrollMode` byte out).
+pub const GET_SCROLL_MODE: Command = Command::new(0x02, 0x94);
+
+/// Turn smart reel on/off (storage, 0/1); the wheel switches to free spin on
+/// its own when flicked. Same devices as the scroll mode.
+pub const SET_SCROLL_SMART_REEL: Command = Command::new(0x02, 0x17);
+
+/// Whether smart reel is on (storage in, 0/1 out).
+pub const GET_SCROLL_SMART_REEL: Command = Command::new(0x02, 0x97);
+
+/// Set the lift-off distance (storage, `LiftOff` byte); how high the mouse
+/// can be lifted before the sensor stops tracking. Mice with the Focus+
+/// sensor: DeathAdder v2, Basilisk V3, Naga Pro.
+pub const SET_LIFTOFF: Command = Command::new(0x0B, 0x05);
+
+/// Get the lift-off distance (storage in, `LiftOff` byte out).
+pub const GET_LIFTOFF: Command = Command::new(0x0B, 0x85);
+
+/// Set what a button does (storage, button, layer, function type, length,
+/// parameters). DeathAdder v2, Basilisk V3 and newer mice.
+pub const SET_BUTTON_FUNCTION: Command = Command::new(0x02, 0x0C);
+
+/// Get what a button does (storage, button, layer in; function type, length,
+/// parameters out).
+pub const GET_BUTTON_FUNCTION: Command = Command::new(0x02, 0x8C);
+
+/// Make room for a macro in flash (id, length of its data as u32), replacing
+/// any macro with the same id. Followed by `SET_MACRO_DATA`.
+pub const SET_MACRO_INFO: Command = Command::new(0x06, 0x0C);
+
+/// Write part of a macro's data (id, offset as u32, length, data).
+pub const SET_MACRO_DATA: Command = Command::new(0x06, 0x09);
+
+/// Delete a macro from flash (id). Buttons bound to it do nothing after.
+pub const DELETE_MACRO: Command = Command::new(0x06, 0x03);
+
+/// Every command in the catalog with a short name, e.g. for decoding captures
+pub const ALL: &[(Command, &str)] = &[
+    (GET_SERIAL, "get serial"),
+    (SET_DEVICE_MODE, "set device mode"),
+    (GET_DEVICE_MODE, "get device mode"),
+    (SET_POLLING_RATE, "set polling rate"),
+    (GET_POLLING_RATE, "get polling rate"),
+    (GET_BATTERY_LEVEL, "get battery level"),
+    (GET_CHARGING_STATUS, "get charging status"),
+    (SET_IDLE_TIME, "set idle time"),
+    (GET_IDLE_TIME, "get idle time"),
+    (GET_PAIRED_DEVICE, "get paired device"),
+    (SET_DPI_XY, "set DPI"),
+    (GET_DPI_XY, "get DPI"),
+    (SET_DPI_STAGES, "set DPI stages"),
+    (GET_DPI_STAGES, "get DPI stages"),
+    (SET_EXTENDED_MATRIX_EFFECT, "set extended matrix effect"),
+    (SET_NAGA_TRINITY_EFFECT, "set naga trinity effect or custom frame"),
+    (SET_EXTENDED_MATRIX_BRIGHTNESS, "set extended matrix brightness"),
+    (GET_EXTENDED_MATRIX_BRIGHTNESS, "get extended matrix brightness"),
+    (SET_ARGB_CHANNEL_SIZE, "set ARGB channel size"),
+    (GET_ARGB_CHANNEL_SIZE, "get ARGB channel size"),
+    (SET_LED_STATE, "set LED state"),
+    (SET_LED_RGB, "set LED color"),
+    (SET_LED_EFFECT, "set LED effect"),
+    (SET_LED_BRIGHTNESS, "set LED brightness"),
+    (GET_LED_BRIGHTNESS, "get LED brightness"),
+    (SET_SCROLL_MODE, "set scroll mode"),
+    (GET_SCROLL_MODE, "get scroll mode"),
+    (SET_SCROLL_SMART_REEL, "set smart reel"),
+    (GET_SCROLL_SMART_REEL, "get smart reel"),
+    (SET_LIFTOFF, "set lift-off distance"),
+    (GET_LIFTOFF, "get lift-off distance"),
+    (SET_BUTTON_FUNCTION, "set button function"),
+    (GET_BUTTON_FUNCTION, "get button function"),
+    (SET_MACRO_INFO, "set macro info"),
+    (SET_MACRO_DATA, "set macro data"),
+    (DELETE_MACRO, "delete macro"),
+];
+
+/// When a command writes to the device's onboard memory
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Persistence {
+    /// When its first argument, the storage, is `LedStorage::VarStore`
+    VarStore,
+    /// Whatever its arguments
+    Always,
+}
+
+/// Every command in the catalog that can write to onboard memory, e.g. for
+/// `hooks::BlockVarStore`
+pub const PERSISTING: &[(Command, Persistence)] = &[
+    (SET_POLLING_RATE, Persistence::Always),
+    (SET_IDLE_TIME, Persistence::Always),
+    (SET_DPI_XY, Persistence::VarStore),
+    (SET_DPI_STAGES, Persistence::VarStore),
+    (SET_EXTENDED_MATRIX_EFFECT, Persistence::VarStore),
+    (SET_NAGA_TRINITY_EFFECT, Persistence::VarStore),
+    (SET_EXTENDED_MATRIX_BRIGHTNESS, Persistence::VarStore),
+    (SET_ARGB_CHANNEL_SIZE, Persistence::VarStore),
+    (SET_LED_STATE, Persistence::VarStore),
+    (SET_LED_RGB, Persistence::VarStore),
+    (SET_LED_EFFECT, Persistence::VarStore),
+    (SET_LED_BRIGHTNESS, Persistence::VarStore),
+    (SET_SCROLL_MODE, Persistence::VarStore),
+    (SET_SCROLL_SMART_REEL, Persistence::VarStore),
+    (SET_LIFTOFF, Persistence::Always),
+    (SET_BUTTON_FUNCTION, Persistence::Always),
+    (SET_MACRO_INFO, Persistence::Always),
+    (SET_MACRO_DATA, Persistence::Always),
+    (DELETE_MACRO, Persistence::Always),
+];
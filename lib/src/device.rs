@@ -1,133 +1,490 @@
-use std::ops::Deref;
+use std::cell::Cell;
 use std::fmt;
-use rusb::{Context, UsbContext, DeviceHandle, Device, DeviceList};
+use std::time::Duration;
+#[cfg(feature = "rusb")]
+use rusb::{Context, UsbContext};
 use rgb::RGB8;
 
-use crate::error::{USBResult, USBError};
+use crate::error::{DpiStagesError, USBResult, USBError};
 use crate::common::*;
+use crate::hooks::Hooks;
+use crate::lighting::Effect;
+use crate::buttons::{Button, ButtonFunction, Layer};
+use crate::macros::Macro;
+use crate::commands::RawCommand;
+use crate::capabilities::Capabilities;
+use crate::quirks::{self, DeviceKind, EffectFamily, Quirks};
+use crate::transport::Transport;
+#[cfg(any(feature = "rusb", feature = "hidapi"))]
+use crate::transport::recording_from_env;
+#[cfg(feature = "rusb")]
+use crate::transport::{OnDemandUsbTransport, UsbTransport};
+#[cfg(feature = "hidapi")]
+use crate::transport::HidapiTransport;
+#[cfg(feature = "hidapi")]
+use crate::hid;
+#[cfg(feature = "rusb")]
+pub use crate::usb::UsbDevice;
+#[cfg(feature = "transport-stats")]
+use crate::stats::{StatsRecorder, TransportStats};
 
 pub(crate) const USB_VENDOR_ID_RAZER: u16 = 0x1532;
 pub(crate) const USB_DEVICE_ID_RAZER_DEATHADDER_V2: u16 = 0x0084;
 
-/// A wrapper for rusb:Device<Context> with Display, and Default
-pub struct UsbDevice(Option<Device<Context>>);
+// public so callers can tell `with_transport()` which model of a line it drives
+pub const USB_DEVICE_ID_RAZER_DEATHADDER_ESSENTIAL: u16 = 0x006E;
+pub const USB_DEVICE_ID_RAZER_DEATHADDER_ELITE: u16 = 0x005C;
+pub const USB_DEVICE_ID_RAZER_BASILISK_V2: u16 = 0x0085;
+pub const USB_DEVICE_ID_RAZER_BASILISK_V3: u16 = 0x0099;
+pub const USB_DEVICE_ID_RAZER_OROCHI_V2: u16 = 0x0094;
+pub const USB_DEVICE_ID_RAZER_COBRA: u16 = 0x00A3;
+pub const USB_DEVICE_ID_RAZER_COBRA_PRO_WIRED: u16 = 0x00AF;
+pub const USB_DEVICE_ID_RAZER_COBRA_PRO_WIRELESS: u16 = 0x00B0;
+pub const USB_DEVICE_ID_RAZER_TARTARUS_V2: u16 = 0x022B;
+pub const USB_DEVICE_ID_RAZER_TARTARUS_PRO: u16 = 0x0244;
+pub const USB_DEVICE_ID_RAZER_HUNTSMAN_TE: u16 = 0x0243;
+pub const USB_DEVICE_ID_RAZER_BASE_STATION_V2_CHROMA: u16 = 0x0F20;
+pub const USB_DEVICE_ID_RAZER_CHARGING_PAD_CHROMA: u16 = 0x0F26;
+pub const USB_DEVICE_ID_RAZER_CHROMA_ARGB_CONTROLLER: u16 = 0x0F1F;
+pub const USB_DEVICE_ID_RAZER_NAGA_PRO_WIRED: u16 = 0x008F;
+pub const USB_DEVICE_ID_RAZER_NAGA_PRO_WIRELESS: u16 = 0x0090;
+pub const USB_DEVICE_ID_RAZER_NAGA_TRINITY: u16 = 0x0067;
 
-impl Deref for UsbDevice {
-    type Target = Option<Device<Context>>;
+/// DPI stages (and the 0-based active one) that mice ship with
+const DEFAULT_DPI_STAGES: [(u16, u16); 5] =
+    [(400, 400), (800, 800), (1600, 1600), (3200, 3200), (6400, 6400)];
+const DEFAULT_DPI_STAGE: u8 = 2;
 
-    fn deref(&self) -> &Self::Target {
-        &self.0
+pub trait RazerDevice: fmt::Display {
+    #[cfg(feature = "rusb")]
+    fn list() -> USBResult<Vec<UsbDevice>> where Self: Sized {
+        UsbDevice::by_vendor(USB_VENDOR_ID_RAZER)
     }
-}
 
-fn get_device_name(handle: &DeviceHandle<Context>) -> String {
-    let dev = handle.device();
-    match dev.device_descriptor() {
-        Ok(dd) => {
-            let serial = handle.read_serial_number_string_ascii(&dd)
-                                        .unwrap_or_default();
-            let product = handle.read_product_string_ascii(&dd)
-                                        .unwrap_or_default();
-            format!("{}{}{}", product, if serial.len() > 0 {" "} else {""}, serial)
-        },
-        Err(_) => String::new(),
+    /// This device as a mouse, if it is one; for devices from `enumerate()`
+    fn as_mouse(&self) -> Option<&dyn RazerMouse> {
+        None
     }
-}
 
-impl fmt::Display for UsbDevice {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            UsbDevice(Some(dev)) => {
-                let devname = match dev.open() {
-                    Ok(h) => {
-                        get_device_name(&h)
-                    },
-                    Err(_) => String::new(),
-                };
-                write!(f, "{} ({}-{})", devname, dev.bus_number(), dev.address())
-            },
-            UsbDevice(None) => write!(f, "None")
-        }
+    /// This device as a keypad, if it is one
+    fn as_keypad(&self) -> Option<&dyn RazerKeypad> {
+        None
     }
-}
 
-impl Default for UsbDevice {
-    fn default() -> Self {
-        UsbDevice(None)
+    /// This device as a keyboard, if it is one
+    fn as_keyboard(&self) -> Option<&dyn RazerKeyboard> {
+        None
     }
-}
 
-impl UsbDevice {
-    /// List all usb devices
-    pub fn list() -> USBResult<Vec<UsbDevice>> {
-        let ctx = Context::new()?;
-        let device_list = DeviceList::new_with_context(ctx)?;
-        let res = device_list.iter()
-            .map(|d| UsbDevice(Some(d)))
-            .collect::<Vec<UsbDevice>>();
-        Ok(res)
+    /// This device as one with ARGB channels, if it has them
+    fn as_argb(&self) -> Option<&dyn RazerArgb> {
+        None
     }
 
-    /// List all usb devices of the specified vendor
-    pub fn by_vendor(vid: u16) -> USBResult<Vec<UsbDevice>> {
-        let ctx = Context::new()?;
-        let device_list = DeviceList::new_with_context(ctx)?;
-        let res = device_list.iter()
-            .filter_map(|device| {
-                match device.device_descriptor() {
-                    Ok(descr) => if descr.vendor_id() == vid {
-                        Some(UsbDevice(Some(device)))
-                    } else {
-                        None
-                    },
-                    Err(_) => None
-                }
-            })
-            .collect::<Vec<UsbDevice>>();
-        Ok(res)
+    fn vid(&self) -> u16 { USB_VENDOR_ID_RAZER }
+
+    fn pid(&self) -> u16;
+
+    fn name(&self) -> String {
+        self.transport().name()
     }
 
-    /// List all usb devices of the specified vendor and with the specified product ID
-    pub fn by_product(vid: u16, pid: u16) -> USBResult<Vec<UsbDevice>> {
-        let ctx = Context::new()?;
-        let device_list = DeviceList::new_with_context(ctx)?;
-        let res = device_list.iter()
-            .filter_map(|device| {
-                match device.device_descriptor() {
-                    Ok(descr) =>
-                        if descr.vendor_id() == vid && descr.product_id() == pid {
-                            Some(UsbDevice(Some(device)))
-                        } else {
-                            None
-                        },
-                    Err(_) => None
-                }
-            })
-            .collect::<Vec<UsbDevice>>();
-        Ok(res)
+    fn transport(&self) -> &dyn Transport;
+
+    /// What this device can do
+    fn capabilities(&self) -> Capabilities;
+
+    /// The LED zones the device has, in the order front-ends should list them
+    fn zones(&self) -> Vec<Led> {
+        self.capabilities().leds.iter().map(|cap| cap.led).collect()
     }
-}
 
-pub trait RazerDevice: fmt::Display {
-    fn list() -> USBResult<Vec<UsbDevice>> {
-        UsbDevice::by_vendor(USB_VENDOR_ID_RAZER)
+    /// How this model is driven; see `quirks::QUIRKS`
+    fn quirks(&self) -> Quirks {
+        quirks::for_pid(self.pid())
     }
 
-    fn vid(&self) -> u16 { USB_VENDOR_ID_RAZER }
+    fn default_tx_id(&self) -> u8 {
+        self.quirks().tx_id
+    }
 
-    fn pid(&self) -> u16;
+    /// Set with `set_tx_id()`
+    fn tx_id_override(&self) -> &TxIdOverride;
 
-    fn name(&self) -> String {
-        get_device_name(self.handle())
+    /// The transaction id requests go out with
+    fn tx_id(&self) -> u8 {
+        self.tx_id_override().get().unwrap_or(self.default_tx_id())
+    }
+
+    /// Send requests with `tx_id` instead of the default one, or with the
+    /// default again if None
+    fn set_tx_id(&self, tx_id: Option<u8>) {
+        self.tx_id_override().set(tx_id);
+    }
+
+    /// Find a transaction id the device answers to: the current one, or else
+    /// the first of `TX_ID_CANDIDATES` that works, which then stays set
+    fn probe_tx_id(&self) -> USBResult<u8> {
+        let previous = self.tx_id_override().get();
+        let current = self.tx_id();
+        let candidates = TX_ID_CANDIDATES.iter().filter(|&&tx_id| tx_id != current);
+        let mut last_error = USBError::DeviceNotFound;
+        for tx_id in std::iter::once(current).chain(candidates.copied()) {
+            self.set_tx_id(Some(tx_id));
+            match self.get_serial() {
+                Ok(_) => {
+                    if tx_id == self.default_tx_id() {
+                        self.set_tx_id(None);
+                    }
+                    return Ok(tx_id);
+                },
+                Err(e) => last_error = e,
+            }
+        }
+        self.set_tx_id(previous);
+        Err(last_error)
     }
 
-    fn handle(&self) -> &DeviceHandle<Context>;
+    /// Hooks called around every transaction with this device
+    fn hooks(&self) -> &Hooks;
+
+    /// The (adaptive) wait between sending a report and reading the response
+    fn receiver_wait(&self) -> &ReceiverWait;
+
+    #[cfg(feature = "transport-stats")]
+    fn stats_recorder(&self) -> &StatsRecorder;
+
+    /// Round-trip time stats of the transactions so far
+    #[cfg(feature = "transport-stats")]
+    fn transport_stats(&self) -> TransportStats {
+        self.stats_recorder().snapshot()
+    }
+
+    /// The current timing, with the receiver wait as adapted so far
+    fn protocol_options(&self) -> ProtocolOptions {
+        let defaults = ProtocolOptions::default();
+        ProtocolOptions {
+            receiver_wait: self.receiver_wait().get(),
+            adaptive_wait: self.receiver_wait().is_adaptive(),
+            transfer_timeout: self.transport().transfer_timeout()
+                .unwrap_or(defaults.transfer_timeout),
+        }
+    }
 
-    fn default_tx_id(&self) -> u8;
+    fn set_protocol_options(&self, options: &ProtocolOptions) {
+        self.receiver_wait().set(options.receiver_wait);
+        self.receiver_wait().set_adaptive(options.adaptive_wait);
+        self.transport().set_transfer_timeout(options.transfer_timeout);
+    }
 
     fn send_payload(&self, request: &mut RazerReport) -> USBResult<RazerReport> {
-        request.transaction_id = self.default_tx_id();
-        razer_send_payload(self.handle(), request)
+        request.transaction_id = self.tx_id();
+        request.update_crc();
+        self.hooks().before_send(request)?;
+
+        #[cfg(feature = "transport-stats")]
+        let start = std::time::Instant::now();
+
+        let response = razer_send_payload(self.transport(), request,
+            self.quirks().report_index, self.receiver_wait().get());
+        self.receiver_wait().update(&response);
+
+        #[cfg(feature = "transport-stats")]
+        self.stats_recorder().record(
+            request.command(), start.elapsed(), response.is_ok());
+
+        self.hooks().after_receive(request, response)
+    }
+
+    /// Send any command, e.g. one not in the catalog; the response's status
+    /// has been checked, its arguments are in `RazerReport::args()`. Use with
+    /// care: some commands write to flash, or leave the device in a state it
+    /// only recovers from when replugged
+    fn send_raw(&self, command: &RawCommand) -> USBResult<RazerReport> {
+        let mut request = RazerReport::new(command.command(), command.args());
+        self.send_payload(&mut request)
+    }
+
+    fn get_device_mode(&self) -> USBResult<DeviceMode> {
+        let mut request = razer_chroma_standard_get_device_mode();
+        let response = self.send_payload(&mut request)?;
+        DeviceMode::try_from(response.arguments[0])
+            .or(Err(USBError::ResponseUnknownValue(response.arguments[0])))
+    }
+
+    /// Put the device in driver mode, e.g. for events the firmware would act
+    /// on otherwise; put it back in normal mode when done, or it may not
+    /// work as usual without the app until replugged
+    fn set_device_mode(&self, mode: DeviceMode) -> USBResult<()> {
+        let mut request = razer_chroma_standard_set_device_mode(mode);
+        self.send_payload(&mut request)?;
+        Ok(())
+    }
+
+    /// Battery level in the [0-100] range
+    fn get_battery_level(&self) -> USBResult<u8> {
+        if !self.capabilities().battery {
+            return Err(USBError::Unsupported(String::from("battery")));
+        }
+        let mut request = razer_chroma_misc_get_battery_level();
+        let response = self.send_payload(&mut request)?;
+        Ok((100.0 * response.arguments[1] as f32 / 255.0).round() as u8)
+    }
+
+    /// Whether the battery is charging
+    fn get_charging_status(&self) -> USBResult<bool> {
+        if !self.capabilities().battery {
+            return Err(USBError::Unsupported(String::from("battery")));
+        }
+        let mut request = razer_chroma_misc_get_charging_status();
+        let response = self.send_payload(&mut request)?;
+        Ok(response.arguments[1] == 0x01)
+    }
+
+    /// The charging state of the battery, None if it's neither charging nor
+    /// full; see `ChargingState::from_battery()`
+    fn get_charging_state(&self) -> USBResult<Option<ChargingState>> {
+        let level = self.get_battery_level()?;
+        Ok(ChargingState::from_battery(level, self.get_charging_status()?))
+    }
+
+    /// The mouse a HyperSpeed dongle is paired with, and whether it's
+    /// connected; for devices that talk through one
+    fn get_pairing(&self) -> USBResult<Pairing> {
+        if !self.quirks().dongle {
+            return Err(USBError::Unsupported(String::from("pairing")));
+        }
+        let mut request = razer_chroma_misc_get_paired_device();
+        let response = self.send_payload(&mut request)?;
+        Ok(Pairing {
+            pid: u16::from_be_bytes([response.arguments[1], response.arguments[2]]),
+            connected: response.arguments[0] == 0x01,
+        })
+    }
+
+    /// How long the device waits without use before going to sleep
+    fn get_idle_time(&self) -> USBResult<Duration> {
+        if !self.capabilities().battery {
+            return Err(USBError::Unsupported(String::from("idle time")));
+        }
+        let mut request = razer_chroma_misc_get_idle_time();
+        let response = self.send_payload(&mut request)?;
+        let secs = u16::from_be_bytes([response.arguments[0], response.arguments[1]]);
+        Ok(Duration::from_secs(secs as u64))
+    }
+
+    /// Within `IDLE_TIMES`; saved on the device
+    fn set_idle_time(&self, idle_time: Duration) -> USBResult<()> {
+        if !self.capabilities().battery {
+            return Err(USBError::Unsupported(String::from("idle time")));
+        }
+        let mut request = razer_chroma_misc_set_idle_time(idle_time);
+        self.send_payload(&mut request)?;
+        Ok(())
+    }
+
+    /// The color a dock or wireless mouse shows while in `state`, saved on
+    /// the device
+    fn set_charging_color(&self, state: ChargingState, color: RGB8) -> USBResult<()> {
+        let led = state.led();
+        if !self.capabilities().has_rgb(led) {
+            return Err(USBError::Unsupported(format!("{} LED", led.name())));
+        }
+        let mut request = razer_chroma_extended_matrix_effect_static(
+            LedStorage::VarStore, led, color);
+        self.send_payload(&mut request)?;
+        Ok(())
+    }
+
+    /// Set the colors for all charging states the device has
+    fn set_charging_colors(
+        &self,
+        charging: RGB8,
+        fast_charging: RGB8,
+        fully_charged: RGB8
+    ) -> USBResult<()> {
+        let caps = self.capabilities();
+        for (state, color) in [
+            (ChargingState::Charging, charging),
+            (ChargingState::FastCharging, fast_charging),
+            (ChargingState::FullyCharged, fully_charged),
+        ] {
+            if caps.has_rgb(state.led()) {
+                self.set_charging_color(state, color)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Put only the lighting back the way devices ship: spectrum cycling
+    /// at full brightness on every LED, or just on for single color ones
+    /// that only take the classic LED commands
+    fn restore_default_lighting(&self) -> USBResult<()> {
+        let caps = self.capabilities();
+        for led in self.zones() {
+            set_led_brightness_in(self, LedStorage::VarStore, led, 100)?;
+            let mut request = match self.quirks().effects {
+                EffectFamily::Standard if caps.has_rgb(led) => {
+                    self.set_effect(&Effect::spectrum().on(led))?;
+                    continue;
+                },
+                EffectFamily::Standard =>
+                    razer_chroma_standard_set_led_state(LedStorage::VarStore, led, true),
+                _ => razer_chroma_extended_matrix_effect_spectrum(LedStorage::VarStore, led),
+            };
+            self.send_payload(&mut request)?;
+        }
+        Ok(())
+    }
+
+    /// Static color on an RGB LED
+    fn set_led_color(&self, led: Led, color: RGB8) -> USBResult<()> {
+        if !self.capabilities().has_rgb(led) {
+            return Err(USBError::Unsupported(format!("color on {:?} LED", led)));
+        }
+        if self.quirks().effects == EffectFamily::Standard {
+            return self.set_effect(&Effect::static_color(color).on(led));
+        }
+        let mut request = razer_chroma_extended_matrix_effect_static(
+            LedStorage::VarStore, led, color);
+        self.send_payload(&mut request)?;
+        Ok(())
+    }
+
+    /// Like `set_led_color()` but not saved on the device, e.g. for live
+    /// previews
+    fn preview_led_color(&self, led: Led, color: RGB8) -> USBResult<()> {
+        if !self.capabilities().has_rgb(led) {
+            return Err(USBError::Unsupported(format!("color on {:?} LED", led)));
+        }
+        if self.quirks().effects == EffectFamily::Standard {
+            return self.set_effect(&Effect::static_color(color).on(led).store(LedStorage::NoStore));
+        }
+        let mut request = razer_chroma_extended_matrix_effect_static(
+            LedStorage::NoStore, led, color);
+        self.send_payload(&mut request)?;
+        Ok(())
+    }
+
+    /// Breathing effect on an RGB LED
+    fn set_led_breathing(&self, led: Led, breathing: Breathing) -> USBResult<()> {
+        if !self.capabilities().has_rgb(led) {
+            return Err(USBError::Unsupported(format!("breathing on {:?} LED", led)));
+        }
+        if self.quirks().effects == EffectFamily::Standard {
+            return self.set_effect(&Effect::breathing_in(breathing).on(led));
+        }
+        let mut request = razer_chroma_extended_matrix_effect_breathing(
+            LedStorage::VarStore, led, breathing);
+        self.send_payload(&mut request)?;
+        Ok(())
+    }
+
+    /// Cycle an RGB LED through the colors of the spectrum, on its own
+    fn set_led_spectrum(&self, led: Led) -> USBResult<()> {
+        if !self.capabilities().has_rgb(led) {
+            return Err(USBError::Unsupported(format!("spectrum on {:?} LED", led)));
+        }
+        if self.quirks().effects == EffectFamily::Standard {
+            return self.set_effect(&Effect::spectrum().on(led));
+        }
+        let mut request = razer_chroma_extended_matrix_effect_spectrum(
+            LedStorage::VarStore, led);
+        self.send_payload(&mut request)?;
+        Ok(())
+    }
+
+    /// Light an RGB LED up in `color` on clicks; `speed` (see
+    /// `REACTIVE_SPEEDS`) is how long it stays lit
+    fn set_led_reactive(&self, led: Led, color: RGB8, speed: u8) -> USBResult<()> {
+        if !self.capabilities().has_rgb(led) {
+            return Err(USBError::Unsupported(format!("reactive lighting on {:?} LED", led)));
+        }
+        if self.quirks().effects == EffectFamily::Standard {
+            return self.set_effect(&Effect::reactive(color).speed(speed).on(led));
+        }
+        let mut request = razer_chroma_extended_matrix_effect_reactive(
+            LedStorage::VarStore, led, speed, color);
+        self.send_payload(&mut request)?;
+        Ok(())
+    }
+
+    /// Set any of the effects the firmware runs; see `Effect`
+    fn set_effect(&self, effect: &Effect) -> USBResult<()> {
+        let led = effect.led();
+        let caps = self.capabilities();
+        if !caps.has_led(led) || (effect.needs_rgb() && !caps.has_rgb(led)) {
+            return Err(USBError::Unsupported(format!("this effect on {:?} LED", led)));
+        }
+        let requests = match self.quirks().effects {
+            EffectFamily::Standard => effect.classic_reports().ok_or_else(||
+                USBError::Unsupported(format!("this effect on {:?} LED", led)))?,
+            _ => vec![effect.report()],
+        };
+        for mut request in requests {
+            self.send_payload(&mut request)?;
+        }
+        Ok(())
+    }
+
+    /// Show `rows` of colors on the LED matrix, from the top left, until
+    /// another effect is set; not saved on the device. See
+    /// `Capabilities::matrix` for how many fit.
+    fn draw_frame(&self, rows: &[Vec<RGB8>]) -> USBResult<()> {
+        let (max_rows, max_cols) = match self.capabilities().matrix {
+            Some(dims) => dims,
+            None => return Err(USBError::Unsupported(String::from("custom frames"))),
+        };
+        if rows.len() > max_rows || rows.iter().any(|row| row.len() > max_cols) {
+            return Err(USBError::Unsupported(
+                format!("frames larger than {}x{}", max_rows, max_cols)));
+        }
+        for mut request in razer_chroma_extended_matrix_custom_frame_reports(rows) {
+            self.send_payload(&mut request)?;
+        }
+        let mut request = razer_chroma_extended_matrix_effect_custom_frame();
+        self.send_payload(&mut request)?;
+        Ok(())
+    }
+
+    /// Brightness in the [0-100] range
+    fn get_led_brightness(&self, led: Led) -> USBResult<u8> {
+        if !self.capabilities().has_led(led) {
+            return Err(USBError::Unsupported(format!("{:?} LED", led)));
+        }
+        let mut request = match self.quirks().effects {
+            EffectFamily::Standard =>
+                razer_chroma_standard_get_led_brightness(LedStorage::VarStore, led),
+            _ => razer_chroma_extended_matrix_get_brightness(LedStorage::VarStore, led),
+        };
+
+        let response = self.send_payload(&mut request)?;
+        Ok((100.0 * response.arguments[2] as f32 / 255.0).round() as u8)
+    }
+
+    /// Brightness in the [0-100] range
+    fn set_led_brightness(&self, led: Led, brightness: u8) -> USBResult<()> {
+        set_led_brightness_in(self, LedStorage::VarStore, led, brightness)
+    }
+
+    /// Like `set_led_brightness()` but not saved on the device
+    fn preview_led_brightness(&self, led: Led, brightness: u8) -> USBResult<()> {
+        set_led_brightness_in(self, LedStorage::NoStore, led, brightness)
+    }
+
+    /// Turn an LED off, or on with a static full color; that's white for RGB
+    /// LEDs (see `set_led_color()`) and the only color of single color ones
+    fn set_led_on(&self, led: Led, on: bool) -> USBResult<()> {
+        let mut request = if self.quirks().effects == EffectFamily::Standard {
+            razer_chroma_standard_set_led_state(LedStorage::VarStore, led, on)
+        } else if on {
+            razer_chroma_extended_matrix_effect_static(
+                LedStorage::VarStore, led, RGB8::new(0xFF, 0xFF, 0xFF))
+        } else {
+            razer_chroma_extended_matrix_effect_none(LedStorage::VarStore, led)
+        };
+        self.send_payload(&mut request)?;
+        Ok(())
     }
 
     fn get_serial(&self) -> USBResult<String> {
@@ -143,14 +500,79 @@ pub trait RazerDevice: fmt::Display {
     }
 }
 
+fn set_led_brightness_in<D: RazerDevice + ?Sized>(
+    device: &D,
+    storage: LedStorage,
+    led: Led,
+    brightness: u8
+) -> USBResult<()> {
+    if !device.capabilities().has_led(led) {
+        return Err(USBError::Unsupported(format!("{:?} LED", led)));
+    }
+    let b = (255.0 * brightness.clamp(0, 100) as f32 / 100.0).round() as u8;
+    let mut request = match device.quirks().effects {
+        EffectFamily::Standard => razer_chroma_standard_set_led_brightness(storage, led, b),
+        _ => razer_chroma_extended_matrix_brightness(storage, led, b),
+    };
+    device.send_payload(&mut request)?;
+    Ok(())
+}
+
+fn set_dpi_in<M: RazerMouse + ?Sized>(
+    mouse: &M,
+    storage: LedStorage,
+    dpi_x: u16,
+    dpi_y: u16
+) -> USBResult<()> {
+    let dpi_x = dpi_x.clamp(mouse.min_dpi(), mouse.max_dpi());
+    let dpi_y = dpi_y.clamp(mouse.min_dpi(), mouse.max_dpi());
+
+    let mut request = razer_chroma_misc_set_dpi_xy(storage, dpi_x, dpi_y);
+    mouse.send_payload(&mut request)?;
+    Ok(())
+}
+
+fn set_dpi_stages_in<M: RazerMouse + ?Sized>(
+    mouse: &M,
+    storage: LedStorage,
+    dpi_stages: &[(u16, u16)],
+    current: u8
+) -> USBResult<()> {
+    mouse.capabilities().validate_dpi_stages(dpi_stages, current)?;
+
+    // device expects current index to be 1-based
+    let mut request = razer_chroma_misc_set_dpi_xy_stages(
+        storage, dpi_stages, current + 1)?;
+    mouse.send_payload(&mut request)?;
+    Ok(())
+}
+
+/// `DEFAULT_DPI_STAGES` fitted in the DPI range of a model, without the ones
+/// that end up the same or don't fit in its stages, and the active one
+fn default_dpi_stages(caps: &Capabilities) -> (Vec<(u16, u16)>, u8) {
+    let mut stages: Vec<(u16, u16)> = Vec::with_capacity(DEFAULT_DPI_STAGES.len());
+    for (dpi_x, dpi_y) in DEFAULT_DPI_STAGES {
+        let stage = match caps.dpi {
+            Some(range) => (range.fit(dpi_x), range.fit(dpi_y)),
+            None => (dpi_x, dpi_y),
+        };
+        if !stages.contains(&stage) {
+            stages.push(stage);
+        }
+    }
+    stages.truncate(caps.dpi_stages.min(MAX_DPI_STAGES));
+    let current = DEFAULT_DPI_STAGE.min(stages.len().saturating_sub(1) as u8);
+    (stages, current)
+}
+
 /// A default implementation; Most mice would need some specialization
 pub trait RazerMouse: RazerDevice {
     fn min_dpi(&self) -> u16 {
-        100
+        self.capabilities().dpi.map_or(100, |dpi| dpi.min)
     }
 
     fn max_dpi(&self) -> u16 {
-        30000
+        self.capabilities().dpi.map_or(30000, |dpi| dpi.max)
     }
 
     fn get_dpi(&self) -> USBResult<(u16, u16)> {
@@ -164,13 +586,12 @@ pub trait RazerMouse: RazerDevice {
     }
 
     fn set_dpi(&self, dpi_x: u16, dpi_y: u16) -> USBResult<()> {
-        let dpi_x = dpi_x.clamp(self.min_dpi(), self.max_dpi());
-        let dpi_y = dpi_y.clamp(self.min_dpi(), self.max_dpi());
+        set_dpi_in(self, LedStorage::NoStore, dpi_x, dpi_y)
+    }
 
-        let mut request = razer_chroma_misc_set_dpi_xy(
-            LedStorage::NoStore, dpi_x, dpi_y);
-        self.send_payload(&mut request)?;
-        Ok(())
+    /// Like `set_dpi()`, but kept in the device's memory across replugs
+    fn set_dpi_persistent(&self, dpi_x: u16, dpi_y: u16) -> USBResult<()> {
+        set_dpi_in(self, LedStorage::VarStore, dpi_x, dpi_y)
     }
 
     /// Return a vector of the DPI stages in (dpiX, dpiY) tuples, and an
@@ -181,8 +602,8 @@ pub trait RazerMouse: RazerDevice {
 
         // index reported by the device (at least DeathAdderV2) is 1-based
         let current = response.arguments[1] - 1;
-        let num_stages = response.arguments[2];
-        let mut dpi_stages: Vec<(u16, u16)> = Vec::with_capacity(num_stages as usize);
+        let num_stages = (response.arguments[2] as usize).min(MAX_DPI_STAGES);
+        let mut dpi_stages: Vec<(u16, u16)> = Vec::with_capacity(num_stages);
         let mut arg_idx = 3;
         for _i in 1..=num_stages {
             let dpi_x = ((response.arguments[arg_idx+1] as u16) << 8) |
@@ -201,17 +622,25 @@ pub trait RazerMouse: RazerDevice {
         dpi_stages: &[(u16, u16)],
         current: u8
     ) -> USBResult<()> {
-        let mut dpi_stages: Vec<(u16, u16)> = dpi_stages.to_vec();
-        for (dpi_x, dpi_y) in &mut dpi_stages {
-            *dpi_x = (*dpi_x).clamp(self.min_dpi(), self.max_dpi());
-            *dpi_y = (*dpi_y).clamp(self.min_dpi(), self.max_dpi());
-        }
+        set_dpi_stages_in(self, LedStorage::NoStore, dpi_stages, current)
+    }
 
-        // device expects current index to be 1-based
-        let mut request = razer_chroma_misc_set_dpi_xy_stages(
-            LedStorage::NoStore, &dpi_stages, current + 1);
-        self.send_payload(&mut request)?;
-        Ok(())
+    /// Like `set_dpi_stages()`, but kept in the device's memory across replugs
+    fn set_dpi_stages_persistent(
+        &self,
+        dpi_stages: &[(u16, u16)],
+        current: u8
+    ) -> USBResult<()> {
+        set_dpi_stages_in(self, LedStorage::VarStore, dpi_stages, current)
+    }
+
+    /// Make `index` (0-based) the active stage, keeping the stages as they are
+    fn set_active_dpi_stage(&self, index: u8) -> USBResult<()> {
+        let (stages, _) = self.get_dpi_stages()?;
+        if index as usize >= stages.len() {
+            return Err(DpiStagesError::CurrentOutOfRange(index).into());
+        }
+        self.set_dpi_stages(&stages, index)
     }
 
     fn get_poll_rate(&self) -> USBResult<PollingRate> {
@@ -227,54 +656,374 @@ pub trait RazerMouse: RazerDevice {
         Ok(())
     }
 
-    fn preview_static(&self, logo_color: RGB8, scroll_color: RGB8) -> USBResult<()>;
+    /// The polling rate command carries no storage byte; the firmware keeps
+    /// the rate across replugs either way. Here for symmetry with
+    /// `set_dpi_persistent()`
+    fn set_poll_rate_persistent(&self, poll_rate: PollingRate) -> USBResult<()> {
+        self.set_poll_rate(poll_rate)
+    }
 
-    fn set_logo_color(&self, color: RGB8) -> USBResult<()> {
-        let mut request = razer_chroma_extended_matrix_effect_static(
-            LedStorage::VarStore, Led::Logo, color);
+    fn get_scroll_mode(&self) -> USBResult<ScrollMode> {
+        if !self.capabilities().scroll_mode {
+            return Err(USBError::Unsupported(String::from("scroll mode")));
+        }
+        let mut request = razer_chroma_misc_get_scroll_mode();
+        let response = self.send_payload(&mut request)?;
+        ScrollMode::try_from(response.arguments[1])
+            .or(Err(USBError::ResponseUnknownValue(response.arguments[1])))
+    }
+
+    fn set_scroll_mode(&self, mode: ScrollMode) -> USBResult<()> {
+        if !self.capabilities().scroll_mode {
+            return Err(USBError::Unsupported(String::from("scroll mode")));
+        }
+        let mut request = razer_chroma_misc_set_scroll_mode(mode);
         self.send_payload(&mut request)?;
         Ok(())
     }
 
-    fn set_scroll_color(&self, color: RGB8) -> USBResult<()> {
-        let mut request = razer_chroma_extended_matrix_effect_static(
-            LedStorage::VarStore, Led::ScrollWheel, color);
+    fn get_liftoff(&self) -> USBResult<LiftOff> {
+        if !self.capabilities().liftoff {
+            return Err(USBError::Unsupported(String::from("lift-off distance")));
+        }
+        let mut request = razer_chroma_misc_get_liftoff();
+        let response = self.send_payload(&mut request)?;
+        LiftOff::try_from(response.arguments[1])
+            .or(Err(USBError::ResponseUnknownValue(response.arguments[1])))
+    }
+
+    fn set_liftoff(&self, liftoff: LiftOff) -> USBResult<()> {
+        if !self.capabilities().liftoff {
+            return Err(USBError::Unsupported(String::from("lift-off distance")));
+        }
+        let mut request = razer_chroma_misc_set_liftoff(liftoff);
         self.send_payload(&mut request)?;
         Ok(())
     }
 
-    fn get_logo_brightness(&self) -> USBResult<u8> {
-        let mut request = razer_chroma_extended_matrix_get_brightness(
-            LedStorage::VarStore, Led::Logo);
+    /// None if the button does something not modeled by `ButtonFunction`
+    /// (e.g. set up in Synapse)
+    fn get_button(&self, button: Button) -> USBResult<Option<ButtonFunction>> {
+        self.get_button_on(Layer::Normal, button)
+    }
+
+    /// Stored on the device, so it stays after a replug
+    fn set_button(&self, button: Button, function: ButtonFunction) -> USBResult<()> {
+        self.set_button_on(Layer::Normal, button, function)
+    }
 
+    fn get_button_on(&self, layer: Layer, button: Button) -> USBResult<Option<ButtonFunction>> {
+        if !self.capabilities().buttons.contains(&button) {
+            return Err(USBError::Unsupported(format!("remapping the {} button", button)));
+        }
+        let mut request = razer_chroma_misc_get_button_function(layer, button);
         let response = self.send_payload(&mut request)?;
-        Ok((100.0 * response.arguments[2] as f32 / 255.0).round() as u8)
+        let len = (response.arguments[4] as usize).min(response.arguments.len() - 5);
+        Ok(ButtonFunction::from_bytes(response.arguments[3], &response.arguments[5..5 + len]))
     }
 
-    fn set_logo_brightness(&self, brightness: u8) -> USBResult<()> {
-        let b = (255.0 * brightness.clamp(0, 100) as f32 / 100.0).round() as u8;
-        let mut request = razer_chroma_extended_matrix_brightness(
-            LedStorage::VarStore, Led::Logo, b);
+    fn set_button_on(
+        &self,
+        layer: Layer,
+        button: Button,
+        function: ButtonFunction
+    ) -> USBResult<()> {
+        if !self.capabilities().buttons.contains(&button) {
+            return Err(USBError::Unsupported(format!("remapping the {} button", button)));
+        }
+        // the shift button itself has to stay in the normal layer
+        if layer == Layer::Hypershift && function == ButtonFunction::Hypershift {
+            return Err(USBError::Unsupported(String::from("hypershift within hypershift")));
+        }
+        let mut request = razer_chroma_misc_set_button_function(layer, button, function);
         self.send_payload(&mut request)?;
         Ok(())
     }
 
-    fn get_scroll_brightness(&self) -> USBResult<u8> {
-        let mut request = razer_chroma_extended_matrix_get_brightness(
-            LedStorage::VarStore, Led::ScrollWheel);
+    /// Make `shift` the Hypershift button and bind the others on that layer;
+    /// buttons not in `bindings` keep their Hypershift functions
+    fn set_hypershift(
+        &self,
+        shift: Button,
+        bindings: &[(Button, ButtonFunction)]
+    ) -> USBResult<()> {
+        self.set_button(shift, ButtonFunction::Hypershift)?;
+        for &(button, function) in bindings {
+            self.set_button_on(Layer::Hypershift, button, function)?;
+        }
+        Ok(())
+    }
+
+    /// Store `m` in flash as macro `id`, replacing any with the same id; bind
+    /// it to a button with `ButtonFunction::Macro(id)`
+    fn create_macro(&self, id: u16, m: &Macro) -> USBResult<()> {
+        if !self.capabilities().macros {
+            return Err(USBError::Unsupported(String::from("macros")));
+        }
+        for mut request in razer_chroma_misc_macro_reports(id, m) {
+            self.send_payload(&mut request)?;
+        }
+        Ok(())
+    }
+
+    fn delete_macro(&self, id: u16) -> USBResult<()> {
+        if !self.capabilities().macros {
+            return Err(USBError::Unsupported(String::from("macros")));
+        }
+        let mut request = razer_chroma_misc_delete_macro(id);
+        self.send_payload(&mut request)?;
+        Ok(())
+    }
 
+    fn get_smart_reel(&self) -> USBResult<bool> {
+        if !self.capabilities().scroll_mode {
+            return Err(USBError::Unsupported(String::from("smart reel")));
+        }
+        let mut request = razer_chroma_misc_get_scroll_smart_reel();
         let response = self.send_payload(&mut request)?;
-        Ok((100.0 * response.arguments[2] as f32 / 255.0).round() as u8)
+        Ok(response.arguments[1] != 0)
+    }
+
+    /// With smart reel on, the wheel goes into free spin by itself when
+    /// flicked, and back when it slows down
+    fn set_smart_reel(&self, on: bool) -> USBResult<()> {
+        if !self.capabilities().scroll_mode {
+            return Err(USBError::Unsupported(String::from("smart reel")));
+        }
+        let mut request = razer_chroma_misc_set_scroll_smart_reel(on);
+        self.send_payload(&mut request)?;
+        Ok(())
+    }
+
+    /// Put DPI, polling rate and lighting back the way the mouse ships, as
+    /// far as the model goes: the default stages that fit its DPI range and
+    /// stage count, and its fastest polling rate
+    fn reset_to_defaults(&self) -> USBResult<()> {
+        let caps = self.capabilities();
+        if caps.dpi_stages > 0 {
+            let (stages, current) = default_dpi_stages(&caps);
+            self.set_dpi_stages(&stages, current)?;
+        }
+        if let Some(&rate) = PollingRate::ALL.iter().rev()
+            .find(|rate| caps.polling_rates.contains(rate)) {
+            self.set_poll_rate(rate)?;
+        }
+        self.restore_default_lighting()
+    }
+
+    /// Preview the logo and scroll wheel colors, without storing them. The
+    /// model's other zones (e.g. an underglow) follow the logo.
+    fn preview_static(&self, logo_color: RGB8, scroll_color: RGB8) -> USBResult<()> {
+        match self.quirks().effects {
+            EffectFamily::NagaTrinity => {
+                let mut request = razer_naga_trinity_effect_static(
+                    LedStorage::NoStore, LedEffect::Static, logo_color, scroll_color);
+                self.send_payload(&mut request)?;
+            },
+            EffectFamily::ExtendedMatrix | EffectFamily::Standard => {
+                for led in self.quirks().leds.iter().map(|cap| cap.led) {
+                    let color = match led {
+                        Led::ScrollWheel => scroll_color,
+                        _ => logo_color,
+                    };
+                    self.preview_led_color(led, color)?;
+                }
+            },
+        }
+        Ok(())
+    }
+
+    fn set_logo_color(&self, color: RGB8) -> USBResult<()> {
+        self.set_led_color(Led::Logo, color)
+    }
+
+    fn set_scroll_color(&self, color: RGB8) -> USBResult<()> {
+        self.set_led_color(Led::ScrollWheel, color)
+    }
+
+    fn set_logo_breathing(&self, breathing: Breathing) -> USBResult<()> {
+        self.set_led_breathing(Led::Logo, breathing)
+    }
+
+    fn set_scroll_breathing(&self, breathing: Breathing) -> USBResult<()> {
+        self.set_led_breathing(Led::ScrollWheel, breathing)
+    }
+
+    fn set_spectrum(&self, led: Led) -> USBResult<()> {
+        self.set_led_spectrum(led)
+    }
+
+    fn set_reactive(&self, led: Led, color: RGB8, speed: u8) -> USBResult<()> {
+        self.set_led_reactive(led, color, speed)
+    }
+
+    fn get_logo_brightness(&self) -> USBResult<u8> {
+        self.get_led_brightness(Led::Logo)
+    }
+
+    fn set_logo_brightness(&self, brightness: u8) -> USBResult<()> {
+        self.set_led_brightness(Led::Logo, brightness)
+    }
+
+    fn get_scroll_brightness(&self) -> USBResult<u8> {
+        self.get_led_brightness(Led::ScrollWheel)
     }
 
     fn set_scroll_brightness(&self, brightness: u8) -> USBResult<()> {
-        let b = (255.0 * brightness.clamp(0, 100) as f32 / 100.0).round() as u8;
-        let mut request = razer_chroma_extended_matrix_brightness(
-            LedStorage::VarStore, Led::ScrollWheel, b);
+        self.set_led_brightness(Led::ScrollWheel, brightness)
+    }
+
+
+}
+
+/// Gaming keypads, lit as a whole through their backlight
+pub trait RazerKeypad: RazerDevice {
+    fn set_backlight_color(&self, color: RGB8) -> USBResult<()> {
+        self.set_led_color(Led::Backlight, color)
+    }
+
+    fn preview_backlight_color(&self, color: RGB8) -> USBResult<()> {
+        self.preview_led_color(Led::Backlight, color)
+    }
+
+    fn get_backlight_brightness(&self) -> USBResult<u8> {
+        self.get_led_brightness(Led::Backlight)
+    }
+
+    fn set_backlight_brightness(&self, brightness: u8) -> USBResult<()> {
+        self.set_led_brightness(Led::Backlight, brightness)
+    }
+}
+
+/// Keyboards, lit through their backlight as a whole or key by key through
+/// custom frames, a row at a time
+pub trait RazerKeyboard: RazerDevice {
+    fn set_backlight_color(&self, color: RGB8) -> USBResult<()> {
+        self.set_led_color(Led::Backlight, color)
+    }
+
+    fn preview_backlight_color(&self, color: RGB8) -> USBResult<()> {
+        self.preview_led_color(Led::Backlight, color)
+    }
+
+    fn get_backlight_brightness(&self) -> USBResult<u8> {
+        self.get_led_brightness(Led::Backlight)
+    }
+
+    fn set_backlight_brightness(&self, brightness: u8) -> USBResult<()> {
+        self.set_led_brightness(Led::Backlight, brightness)
+    }
+
+    /// `effect` on the whole backlight, whichever LED it was for
+    fn set_backlight_effect(&self, effect: Effect) -> USBResult<()> {
+        self.set_effect(&effect.on(Led::Backlight))
+    }
+
+    /// Like `draw_frame()`, but only changes row `row` (from the top) and
+    /// leaves the others as they were
+    fn draw_row(&self, row: usize, colors: &[RGB8]) -> USBResult<()> {
+        draw_row_in(self, row, colors)
+    }
+}
+
+/// Devices with addressable RGB headers, each driving a strip of LEDs on its
+/// own channel, e.g. the Chroma ARGB controller. A channel is lit as a whole
+/// through its LED (see `Led::argb_channel()`), or one LED at a time as a row
+/// of custom frames.
+pub trait RazerArgb: RazerDevice {
+    /// The channels the device has, from 1
+    fn argb_channels(&self) -> Vec<u8> {
+        let caps = self.capabilities();
+        (1..=6).filter(|&channel| Led::argb_channel(channel)
+            .is_some_and(|led| caps.has_rgb(led))).collect()
+    }
+
+    /// Static color on the whole strip of `channel`
+    fn set_channel_color(&self, channel: u8, color: RGB8) -> USBResult<()> {
+        self.set_led_color(argb_led(self, channel)?, color)
+    }
+
+    fn preview_channel_color(&self, channel: u8, color: RGB8) -> USBResult<()> {
+        self.preview_led_color(argb_led(self, channel)?, color)
+    }
+
+    /// How many LEDs the strip on `channel` has, as far as the device knows
+    fn get_channel_size(&self, channel: u8) -> USBResult<u8> {
+        let led = argb_led(self, channel)?;
+        let mut request = razer_chroma_extended_matrix_get_channel_size(LedStorage::VarStore, led);
+        let response = self.send_payload(&mut request)?;
+        Ok(response.arguments[2])
+    }
+
+    /// Tell the device how many LEDs the strip on `channel` has, within
+    /// `ARGB_CHANNEL_SIZES`; saved on the device
+    fn set_channel_size(&self, channel: u8, size: u8) -> USBResult<()> {
+        let led = argb_led(self, channel)?;
+        if !ARGB_CHANNEL_SIZES.contains(&size) {
+            return Err(USBError::Unsupported(
+                format!("strips of more than {} LEDs", ARGB_CHANNEL_SIZES.end())));
+        }
+        let mut request = razer_chroma_extended_matrix_set_channel_size(
+            LedStorage::VarStore, led, size);
         self.send_payload(&mut request)?;
         Ok(())
     }
 
+    /// A color for each LED of the strip on `channel`, from the one nearest
+    /// the header; not saved on the device
+    fn draw_channel(&self, channel: u8, colors: &[RGB8]) -> USBResult<()> {
+        argb_led(self, channel)?;
+        draw_row_in(self, channel as usize - 1, colors)
+    }
+}
+
+/// The LED of ARGB channel `channel`, if `device` has it
+fn argb_led<D: RazerDevice + ?Sized>(device: &D, channel: u8) -> USBResult<Led> {
+    Led::argb_channel(channel)
+        .filter(|&led| device.capabilities().has_rgb(led))
+        .ok_or_else(|| USBError::Unsupported(format!("ARGB channel {}", channel)))
+}
+
+/// One row of a custom frame on `device`, leaving the others as they were
+fn draw_row_in<D: RazerDevice + ?Sized>(device: &D, row: usize, colors: &[RGB8]) -> USBResult<()> {
+    let (max_rows, max_cols) = match device.capabilities().matrix {
+        Some(dims) => dims,
+        None => return Err(USBError::Unsupported(String::from("custom frames"))),
+    };
+    if row >= max_rows || colors.len() > max_cols {
+        return Err(USBError::Unsupported(
+            format!("rows outside of {}x{}", max_rows, max_cols)));
+    }
+    for mut request in razer_chroma_extended_matrix_set_custom_frame_row(row as u8, colors) {
+        device.send_payload(&mut request)?;
+    }
+    let mut request = razer_chroma_extended_matrix_effect_custom_frame();
+    device.send_payload(&mut request)?;
+    Ok(())
+}
+
+/// Keeps a device in a mode, and puts it back in the one it was in when
+/// dropped, e.g. on exit or a panic
+pub struct DeviceModeGuard<'a> {
+    device: &'a dyn RazerDevice,
+    previous: DeviceMode,
+}
+
+impl<'a> DeviceModeGuard<'a> {
+    pub fn new(device: &'a dyn RazerDevice, mode: DeviceMode) -> USBResult<Self> {
+        let previous = device.get_device_mode()?;
+        device.set_device_mode(mode)?;
+        Ok(Self {
+            device: device,
+            previous: previous,
+        })
+    }
+}
+
+impl Drop for DeviceModeGuard<'_> {
+    fn drop(&mut self) {
+        _ = self.device.set_device_mode(self.previous);
+    }
 }
 
 /// A default "to_string()" implementation for all RazerDevices
@@ -283,42 +1032,249 @@ fn razer_dev_default_fmt<T: RazerDevice>(dev: &T, f: &mut fmt::Formatter<'_>) ->
     write!(f, "{} ({})", dev.name(), serial)
 }
 
-pub struct DeathAdderV2 {
-    handle: DeviceHandle<Context>,
+/// Open every connected device of a supported model. Devices that can't be
+/// opened are left out; see `RazerDevice::list()` to find those.
+#[cfg(feature = "rusb")]
+pub fn enumerate() -> USBResult<Vec<Box<dyn RazerDevice>>> {
+    let mut devices: Vec<Box<dyn RazerDevice>> = Vec::new();
+    for device in UsbDevice::by_vendor(USB_VENDOR_ID_RAZER)? {
+        let device = match device.as_ref() {
+            Some(device) => device,
+            None => continue,
+        };
+        let pid = match device.device_descriptor() {
+            Ok(desc) => desc.product_id(),
+            Err(_) => continue,
+        };
+        if let (Some(_), Ok(handle)) = (quirks::find(pid), device.open()) {
+            let transport = recording_from_env(Box::new(UsbTransport::new(handle)))?;
+            devices.push(Box::new(GenericDevice::with_transport(pid, transport)));
+        }
+    }
+    Ok(devices)
 }
 
-impl RazerDevice for DeathAdderV2 {
-    fn pid(&self) -> u16 { USB_DEVICE_ID_RAZER_DEATHADDER_V2 }
+/// The connected wireless dongles, of supported models; see
+/// `RazerDevice::get_pairing()`
+#[cfg(feature = "rusb")]
+pub fn dongles() -> USBResult<Vec<Box<dyn RazerDevice>>> {
+    Ok(enumerate()?.into_iter().filter(|device| device.quirks().dongle).collect())
+}
 
-    fn handle(&self) -> &DeviceHandle<Context> {
-        &self.handle
-    }
+/// Open `device` as its model, like `enumerate()` does
+#[cfg(feature = "rusb")]
+pub fn open(device: &UsbDevice) -> USBResult<Box<dyn RazerDevice>> {
+    let (pid, device) = supported(device)?;
+    let transport = UsbTransport::new(device.open()?);
+    Ok(Box::new(GenericDevice::with_transport(pid, recording_from_env(Box::new(transport))?)))
+}
 
-    fn default_tx_id(&self) -> u8 {
-        0x3f // except for razer_naga_trinity_effect_static which is 0x1f
+/// Like `open()`, but the device is closed whenever it's been idle for
+/// `idle_timeout`, for other programs to use; see `OnDemandUsbTransport`
+#[cfg(feature = "rusb")]
+pub fn open_on_demand(device: &UsbDevice, idle_timeout: Duration) -> USBResult<Box<dyn RazerDevice>> {
+    let (pid, device) = supported(device)?;
+    let transport = OnDemandUsbTransport::new(device.clone(), idle_timeout)?;
+    Ok(Box::new(GenericDevice::with_transport(pid, recording_from_env(Box::new(transport))?)))
+}
+
+/// The product id of `device`, if it's of a model in `quirks::QUIRKS`
+#[cfg(feature = "rusb")]
+fn supported(device: &UsbDevice) -> USBResult<(u16, &rusb::Device<Context>)> {
+    let device = match device.as_ref() {
+        Some(device) => Ok(device),
+        None => Err(USBError::DeviceNotFound),
+    }?;
+
+    let desc = device.device_descriptor()?;
+    match quirks::find(desc.product_id()) {
+        Some(quirks) if desc.vendor_id() == USB_VENDOR_ID_RAZER => Ok((quirks.pid, device)),
+        _ => Err(USBError::NonCompatibleDevice),
     }
 }
 
-impl RazerMouse for DeathAdderV2 {
-    fn max_dpi(&self) -> u16 {
-        20000
+/// Any supported model, driven as its entry in `quirks::QUIRKS` says; what
+/// `enumerate()` and `open()` return. A model that takes side plates has the
+/// 12-button one attached until told otherwise with `set_side_plate()`, as
+/// no command is known to report it.
+pub struct GenericDevice {
+    pid: u16,
+    side_plate: Cell<SidePlate>,
+    transport: Box<dyn Transport>,
+    hooks: Hooks,
+    receiver_wait: ReceiverWait,
+    tx_id_override: TxIdOverride,
+    #[cfg(feature = "transport-stats")]
+    stats: StatsRecorder,
+}
+
+impl RazerDevice for GenericDevice {
+    fn pid(&self) -> u16 { self.pid }
+
+    fn as_mouse(&self) -> Option<&dyn RazerMouse> {
+        (self.quirks().kind == DeviceKind::Mouse).then_some(self)
     }
 
-    fn preview_static(&self, logo_color: RGB8, scroll_color: RGB8) -> USBResult<()> {
-        let mut request = razer_naga_trinity_effect_static(
-            LedStorage::NoStore, LedEffect::Static, logo_color, scroll_color);
-        self.send_payload(&mut request)?;
-        Ok(())
+    fn as_keypad(&self) -> Option<&dyn RazerKeypad> {
+        (self.quirks().kind == DeviceKind::Keypad).then_some(self)
+    }
+
+    fn as_keyboard(&self) -> Option<&dyn RazerKeyboard> {
+        (self.quirks().kind == DeviceKind::Keyboard).then_some(self)
+    }
+
+    fn as_argb(&self) -> Option<&dyn RazerArgb> {
+        (self.quirks().kind == DeviceKind::Argb).then_some(self)
+    }
+
+    fn transport(&self) -> &dyn Transport {
+        self.transport.as_ref()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.quirks().capabilities(self.side_plate())
+    }
+
+    fn hooks(&self) -> &Hooks {
+        &self.hooks
+    }
+
+    fn receiver_wait(&self) -> &ReceiverWait {
+        &self.receiver_wait
+    }
+
+    fn tx_id_override(&self) -> &TxIdOverride {
+        &self.tx_id_override
+    }
+
+    #[cfg(feature = "transport-stats")]
+    fn stats_recorder(&self) -> &StatsRecorder {
+        &self.stats
     }
 }
 
-impl fmt::Display for DeathAdderV2 {
+impl RazerMouse for GenericDevice {}
+impl RazerKeypad for GenericDevice {}
+impl RazerKeyboard for GenericDevice {}
+impl RazerArgb for GenericDevice {}
+
+impl fmt::Display for GenericDevice {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         razer_dev_default_fmt(self, f)
     }
 }
 
+impl GenericDevice {
+    /// Drive the model with product id `pid` through any transport; see
+    /// `enumerate()` to find connected ones
+    pub fn with_transport(pid: u16, transport: Box<dyn Transport>) -> Self {
+        Self {
+            pid: pid,
+            side_plate: Cell::new(SidePlate::default()),
+            transport: transport,
+            hooks: Default::default(),
+            receiver_wait: Default::default(),
+            tx_id_override: Default::default(),
+            #[cfg(feature = "transport-stats")]
+            stats: Default::default(),
+        }
+    }
+
+    pub fn side_plate(&self) -> SidePlate {
+        self.side_plate.get()
+    }
+
+    /// Say which side plate is attached, e.g. as picked by the user
+    pub fn set_side_plate(&self, plate: SidePlate) {
+        self.side_plate.set(plate);
+    }
+}
+
+/// A model of its own type, for callers that know what they drive: a
+/// `GenericDevice` that is also the given kind of device, e.g. `RazerMouse`.
+/// Models of a single product id get `with_transport(transport)` and
+/// `list()`, lines of models `with_transport(pid, transport)`.
+macro_rules! razer_model {
+    ($(#[$meta:meta])* $name:ident $(: $kind:ident as $as_kind:ident)?, $pid:expr) => {
+        razer_model!(@device $(#[$meta])* $name $(: $kind as $as_kind)?);
+
+        impl $name {
+            /// Drive one through any transport, e.g. a recorded session; see
+            /// `enumerate()` to find connected ones
+            pub fn with_transport(transport: Box<dyn Transport>) -> Self {
+                Self(GenericDevice::with_transport($pid, transport))
+            }
+
+            #[cfg(feature = "rusb")]
+            pub fn list() -> USBResult<Vec<UsbDevice>> {
+                UsbDevice::by_product(USB_VENDOR_ID_RAZER, $pid)
+            }
+        }
+    };
+    ($(#[$meta:meta])* $name:ident $(: $kind:ident as $as_kind:ident)?) => {
+        razer_model!(@device $(#[$meta])* $name $(: $kind as $as_kind)?);
+
+        impl $name {
+            /// Drive one of model `pid` through any transport; see
+            /// `enumerate()` to find connected ones
+            pub fn with_transport(pid: u16, transport: Box<dyn Transport>) -> Self {
+                Self(GenericDevice::with_transport(pid, transport))
+            }
+        }
+    };
+    (@device $(#[$meta:meta])* $name:ident $(: $kind:ident as $as_kind:ident)?) => {
+        $(#[$meta])*
+        pub struct $name(GenericDevice);
+
+        impl RazerDevice for $name {
+            fn pid(&self) -> u16 { self.0.pid() }
+
+            $(
+            fn $as_kind(&self) -> Option<&dyn $kind> {
+                Some(self)
+            }
+            )?
+
+            fn transport(&self) -> &dyn Transport {
+                self.0.transport()
+            }
+
+            fn capabilities(&self) -> Capabilities {
+                self.0.capabilities()
+            }
+
+            fn hooks(&self) -> &Hooks {
+                self.0.hooks()
+            }
+
+            fn receiver_wait(&self) -> &ReceiverWait {
+                self.0.receiver_wait()
+            }
+
+            fn tx_id_override(&self) -> &TxIdOverride {
+                self.0.tx_id_override()
+            }
+
+            #[cfg(feature = "transport-stats")]
+            fn stats_recorder(&self) -> &StatsRecorder {
+                self.0.stats_recorder()
+            }
+        }
+
+        $(impl $kind for $name {})?
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                self.0.fmt(f)
+            }
+        }
+    };
+}
+
+razer_model!(DeathAdderV2: RazerMouse as as_mouse, USB_DEVICE_ID_RAZER_DEATHADDER_V2);
+
 impl DeathAdderV2 {
+    #[cfg(feature = "rusb")]
     pub fn new() -> USBResult<Self> {
         let ctx = Context::new()?;
         let handle = match ctx.open_device_with_vid_pid(
@@ -326,27 +1282,139 @@ impl DeathAdderV2 {
             Some(handle) => Ok(handle),
             None => Err(USBError::DeviceNotFound),
         }?;
-        Ok(Self { handle: handle })
+        Ok(Self::with_transport(recording_from_env(Box::new(UsbTransport::new(handle)))?))
     }
 
-    pub fn list() -> USBResult<Vec<UsbDevice>> {
-        UsbDevice::by_product(
-            USB_VENDOR_ID_RAZER, USB_DEVICE_ID_RAZER_DEATHADDER_V2)
+    /// Open the first device through hidapi, i.e. with the stock HID driver
+    #[cfg(feature = "hidapi")]
+    pub fn open_hidapi() -> USBResult<Self> {
+        let transport = HidapiTransport::open(&*hid::api()?,
+            USB_VENDOR_ID_RAZER, USB_DEVICE_ID_RAZER_DEATHADDER_V2, 0)?;
+        Ok(Self::with_transport(recording_from_env(Box::new(transport))?))
     }
 
+    #[cfg(feature = "rusb")]
     pub fn from(device: &UsbDevice) -> USBResult<Self> {
-        let device = match device.as_ref() {
-            Some(device) => Ok(device),
-            None => Err(USBError::DeviceNotFound),
-        }?;
+        let handle = Self::supported(device)?.open()?;
+        Ok(Self::with_transport(recording_from_env(Box::new(UsbTransport::new(handle)))?))
+    }
+
+    /// Like `from()`, but the device is closed whenever it's been idle for
+    /// `idle_timeout`, for other programs to use; see `OnDemandUsbTransport`
+    #[cfg(feature = "rusb")]
+    pub fn from_on_demand(device: &UsbDevice, idle_timeout: Duration) -> USBResult<Self> {
+        let device = Self::supported(device)?;
+        let transport = OnDemandUsbTransport::new(device.clone(), idle_timeout)?;
+        Ok(Self::with_transport(recording_from_env(Box::new(transport))?))
+    }
 
-        let desc = device.device_descriptor()?;
-        if desc.vendor_id() != USB_VENDOR_ID_RAZER ||
-            desc.product_id() != USB_DEVICE_ID_RAZER_DEATHADDER_V2 {
-            return Err(USBError::NonCompatibleDevice);
+    #[cfg(feature = "rusb")]
+    fn supported(device: &UsbDevice) -> USBResult<&rusb::Device<Context>> {
+        match supported(device)? {
+            (USB_DEVICE_ID_RAZER_DEATHADDER_V2, device) => Ok(device),
+            _ => Err(USBError::NonCompatibleDevice),
         }
+    }
+}
+
+razer_model!(
+    /// The DeathAdder Elite. Its LEDs take the classic LED commands, which
+    /// have static, breathing in one color and spectrum, but not reactive
+    /// lighting.
+    DeathAdderElite: RazerMouse as as_mouse, USB_DEVICE_ID_RAZER_DEATHADDER_ELITE);
+
+razer_model!(
+    /// The DeathAdder Essential. Its LEDs are green only and take the classic
+    /// LED commands, so they can be turned on and off and dimmed, but not
+    /// colored.
+    DeathAdderEssential: RazerMouse as as_mouse, USB_DEVICE_ID_RAZER_DEATHADDER_ESSENTIAL);
+
+razer_model!(BasiliskV3: RazerMouse as as_mouse, USB_DEVICE_ID_RAZER_BASILISK_V3);
+
+razer_model!(
+    /// The Basilisk V2: the V3 without underglow or a free-spinning wheel
+    BasiliskV2: RazerMouse as as_mouse, USB_DEVICE_ID_RAZER_BASILISK_V2);
+
+razer_model!(
+    /// The Orochi V2, through its wireless dongle. It has no LEDs, so the
+    /// lighting calls all fail with `USBError::Unsupported`.
+    OrochiV2: RazerMouse as as_mouse, USB_DEVICE_ID_RAZER_OROCHI_V2);
+
+razer_model!(
+    /// The Cobra line: the wired Cobra and the Cobra Pro, either on its cable
+    /// or through its wireless dongle. They share a protocol and differ in
+    /// zones.
+    Cobra: RazerMouse as as_mouse);
+
+impl Cobra {
+    /// A Cobra Pro, wired or wireless
+    pub fn is_pro(&self) -> bool {
+        self.pid() != USB_DEVICE_ID_RAZER_COBRA
+    }
+}
+
+razer_model!(
+    /// The Tartarus V2 and Tartarus Pro keypads. Key remapping isn't
+    /// supported yet; only the lighting is.
+    Tartarus: RazerKeypad as as_keypad);
+
+razer_model!(
+    /// The Huntsman Tournament Edition, a tenkeyless keyboard. Its keys take
+    /// custom frames of 6 rows by 18 columns, see `RazerKeyboard::draw_row()`;
+    /// key remapping isn't supported.
+    HuntsmanTE: RazerKeyboard as as_keyboard, USB_DEVICE_ID_RAZER_HUNTSMAN_TE);
+
+razer_model!(
+    /// The Base Station V2 Chroma headset stand. Its LED strip takes custom
+    /// frames, which aren't supported yet, so it's lit as a single zone.
+    BaseStationV2, USB_DEVICE_ID_RAZER_BASE_STATION_V2_CHROMA);
+
+razer_model!(
+    /// The Mouse Charging Pad Chroma. It doesn't know how charged the mouse on
+    /// it is; front-ends read that from the mouse and light the ring to match.
+    ChargingPad, USB_DEVICE_ID_RAZER_CHARGING_PAD_CHROMA);
+
+razer_model!(
+    /// The Chroma Addressable RGB Controller: six ARGB channels, each a row of
+    /// custom frames as long as the longest strip it takes
+    ArgbController: RazerArgb as as_argb, USB_DEVICE_ID_RAZER_CHROMA_ARGB_CONTROLLER);
+
+razer_model!(
+    /// The Naga Pro, on its cable or through its wireless dongle. No command
+    /// is known to report which side plate is attached, so it's the 12-button
+    /// one until told otherwise with `set_side_plate()`.
+    NagaPro: RazerMouse as as_mouse);
+
+impl NagaPro {
+    pub const SIDE_PLATES: [SidePlate; 3] =
+        [SidePlate::TwoButton, SidePlate::SixButton, SidePlate::TwelveButton];
+
+    pub fn side_plate(&self) -> SidePlate {
+        self.0.side_plate()
+    }
+
+    /// Say which side plate is attached, e.g. as picked by the user
+    pub fn set_side_plate(&self, plate: SidePlate) {
+        self.0.set_side_plate(plate);
+    }
+}
+
+razer_model!(
+    /// The Naga Trinity. Like the Naga Pro, it can't tell which side plate is
+    /// attached, so it's the 12-button one until told otherwise with
+    /// `set_side_plate()`.
+    NagaTrinity: RazerMouse as as_mouse, USB_DEVICE_ID_RAZER_NAGA_TRINITY);
+
+impl NagaTrinity {
+    pub const SIDE_PLATES: [SidePlate; 3] =
+        [SidePlate::TwoButton, SidePlate::SevenButton, SidePlate::TwelveButton];
+
+    pub fn side_plate(&self) -> SidePlate {
+        self.0.side_plate()
+    }
 
-        let handle = device.open()?;
-        Ok(Self { handle: handle })
+    /// Say which side plate is attached, e.g. as picked by the user
+    pub fn set_side_plate(&self, plate: SidePlate) {
+        self.0.set_side_plate(plate);
     }
 }
@@ -0,0 +1,204 @@
+//! Exercising a device to see how it copes, e.g. to measure a change to the
+//! transport or to gather evidence of a flaky hub or cable. Only reads are
+//! sent, so nothing on the device changes.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::common::{utc_string, Led};
+use crate::device::RazerMouse;
+use crate::error::{USBError, USBResult};
+
+/// A read that changes nothing on the device
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Getter {
+    Serial,
+    Dpi,
+    DpiStages,
+    PollRate,
+    Brightness(Led),
+    Battery,
+    ScrollMode,
+}
+
+impl Getter {
+    /// The ones `device` has
+    pub fn supported(device: &dyn RazerMouse) -> Vec<Getter> {
+        let caps = device.capabilities();
+        let mut getters = vec![Getter::Serial, Getter::PollRate];
+        if caps.dpi.is_some() {
+            getters.extend([Getter::Dpi, Getter::DpiStages]);
+        }
+        getters.extend(device.zones().into_iter().map(Getter::Brightness));
+        if caps.battery {
+            getters.push(Getter::Battery);
+        }
+        if caps.scroll_mode {
+            getters.push(Getter::ScrollMode);
+        }
+        getters
+    }
+
+    pub fn call(&self, device: &dyn RazerMouse) -> USBResult<()> {
+        match *self {
+            Getter::Serial => device.get_serial().map(drop),
+            Getter::Dpi => device.get_dpi().map(drop),
+            Getter::DpiStages => device.get_dpi_stages().map(drop),
+            Getter::PollRate => device.get_poll_rate().map(drop),
+            Getter::Brightness(led) => device.get_led_brightness(led).map(drop),
+            Getter::Battery => device.get_battery_level().map(drop),
+            Getter::ScrollMode => device.get_scroll_mode().map(drop),
+        }
+    }
+}
+
+impl fmt::Display for Getter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Getter::Brightness(led) => write!(f, "{} brightness", led.name()),
+            getter => write!(f, "{:?}", getter),
+        }
+    }
+}
+
+/// The outcome of `stress()`
+#[derive(Debug, Default, Clone)]
+pub struct StressReport {
+    pub elapsed: Duration,
+    /// Round-trip time of each transaction, failed ones included
+    pub latencies: Vec<Duration>,
+    /// How many times each error occurred
+    pub errors: BTreeMap<String, u32>,
+}
+
+impl StressReport {
+    /// Add up `other`, e.g. to total a soak run hour by hour
+    pub fn extend(&mut self, other: StressReport) {
+        self.elapsed += other.elapsed;
+        self.latencies.extend(other.latencies);
+        for (error, count) in other.errors {
+            *self.errors.entry(error).or_default() += count;
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.latencies.len()
+    }
+
+    pub fn failures(&self) -> u32 {
+        self.errors.values().sum()
+    }
+
+    /// Failed transactions, in [0-1]
+    pub fn error_rate(&self) -> f64 {
+        match self.count() {
+            0 => 0.0,
+            n => self.failures() as f64 / n as f64,
+        }
+    }
+
+    /// The latency that `p` (in [0-1]) of the transactions took at most
+    pub fn percentile(&self, p: f64) -> Option<Duration> {
+        let mut sorted = self.latencies.clone();
+        sorted.sort();
+        let last = sorted.len().checked_sub(1)?;
+        Some(sorted[((last as f64 * p.clamp(0.0, 1.0)).round() as usize).min(last)])
+    }
+}
+
+impl fmt::Display for StressReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ms = |d: Option<Duration>| d.map_or(0.0, |d| d.as_secs_f64() * 1000.0);
+        writeln!(f, "{} transactions in {:.1} s ({:.1}/s), {} failed ({:.2}%)",
+            self.count(), self.elapsed.as_secs_f64(),
+            self.count() as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON),
+            self.failures(), self.error_rate() * 100.0)?;
+        writeln!(f, "latency p50/p90/p99/max {:.2}/{:.2}/{:.2}/{:.2} ms",
+            ms(self.percentile(0.5)), ms(self.percentile(0.9)),
+            ms(self.percentile(0.99)), ms(self.percentile(1.0)))?;
+        for (error, count) in &self.errors {
+            writeln!(f, "  {}: {}", error, count)?;
+        }
+        Ok(())
+    }
+}
+
+/// Send the supported getters in turn, back to back, for `duration`
+pub fn stress(device: &dyn RazerMouse, duration: Duration) -> StressReport {
+    let getters = Getter::supported(device);
+    let mut report = StressReport::default();
+    let start = Instant::now();
+    for getter in getters.iter().cycle() {
+        if start.elapsed() >= duration {
+            break;
+        }
+        let sent = Instant::now();
+        let res = getter.call(device);
+        report.latencies.push(sent.elapsed());
+        if let Err(e) = res {
+            *report.errors.entry(e.to_string()).or_default() += 1;
+        }
+    }
+    report.elapsed = start.elapsed();
+    report
+}
+
+/// A getter that failed during `soak()`
+#[derive(Debug)]
+pub struct SoakFailure {
+    pub time: SystemTime,
+    pub getter: Getter,
+    pub error: USBError,
+    /// How long it took to fail
+    pub latency: Duration,
+}
+
+impl fmt::Display for SoakFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let secs = self.time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        write!(f, "{}  {}: {} (after {:.2} ms)", utc_string(secs), self.getter,
+            self.error, self.latency.as_secs_f64() * 1000.0)
+    }
+}
+
+/// Send the supported getters in turn every `interval`, for `duration`,
+/// calling `on_failure` as soon as one fails. Meant to run for hours, to
+/// catch the odd busy, timed out or mismatched response.
+pub fn soak<F>(
+    device: &dyn RazerMouse,
+    interval: Duration,
+    duration: Duration,
+    mut on_failure: F,
+) -> StressReport
+where
+    F: FnMut(&SoakFailure),
+{
+    let getters = Getter::supported(device);
+    let mut report = StressReport::default();
+    let start = Instant::now();
+    while start.elapsed() < duration {
+        let round = Instant::now();
+        for &getter in &getters {
+            let sent = Instant::now();
+            let res = getter.call(device);
+            let latency = sent.elapsed();
+            report.latencies.push(latency);
+            if let Err(e) = res {
+                *report.errors.entry(e.to_string()).or_default() += 1;
+                on_failure(&SoakFailure {
+                    time: SystemTime::now(),
+                    getter: getter,
+                    error: e,
+                    latency: latency,
+                });
+            }
+        }
+        // next round, unless it'd start after the end
+        let left = duration.saturating_sub(start.elapsed());
+        thread::sleep(interval.saturating_sub(round.elapsed()).min(left));
+    }
+    report.elapsed = start.elapsed();
+    report
+}
@@ -0,0 +1,206 @@
+//! Effects computed here and sent to the device frame by frame, for what the
+//! firmware doesn't offer. Frames are not saved on the device, so nothing
+//! wears out and the saved lighting comes back on a replug.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use rgb::RGB8;
+
+use crate::animation::{Animation, AnimationTrack};
+use crate::common::Led;
+use crate::device::RazerMouse;
+use crate::error::USBResult;
+
+const BLACK: RGB8 = RGB8::new(0, 0, 0);
+
+pub trait SoftwareEffect: Send {
+    /// The color at `elapsed` since the effect started
+    fn color(&self, elapsed: Duration) -> RGB8;
+}
+
+/// On/off at `frequency` (in Hz), on for `duty_cycle` (in [0-1]) of each
+/// period
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Blink {
+    pub color: RGB8,
+    pub frequency: f32,
+    pub duty_cycle: f32,
+}
+
+impl Blink {
+    pub fn new(color: RGB8, frequency: f32, duty_cycle: f32) -> Self {
+        Self {
+            color: color,
+            frequency: frequency.max(0.0),
+            duty_cycle: duty_cycle.clamp(0.0, 1.0),
+        }
+    }
+}
+
+impl SoftwareEffect for Blink {
+    fn color(&self, elapsed: Duration) -> RGB8 {
+        let phase = (elapsed.as_secs_f32() * self.frequency).fract();
+        if phase < self.duty_cycle { self.color } else { BLACK }
+    }
+}
+
+/// From one color to another over `duration`, then stays there
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Fade {
+    pub from: RGB8,
+    pub to: RGB8,
+    pub duration: Duration,
+}
+
+impl SoftwareEffect for Fade {
+    fn color(&self, elapsed: Duration) -> RGB8 {
+        if elapsed >= self.duration {
+            return self.to;
+        }
+        mix(self.from, self.to, elapsed.as_secs_f32() / self.duration.as_secs_f32())
+    }
+}
+
+/// `amount` (in [0-1]) of the way from `from` to `to`
+pub(crate) fn mix(from: RGB8, to: RGB8, amount: f32) -> RGB8 {
+    let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * amount).round() as u8;
+    RGB8::new(mix(from.r, to.r), mix(from.g, to.g), mix(from.b, to.b))
+}
+
+/// How long static color changes fade for, so they don't just jump
+pub const FADE_DURATION: Duration = Duration::from_millis(300);
+
+/// Fades between static colors without blocking, for front-ends that call
+/// `tick()` from their event loop. Fades are previews; once one is done, the
+/// caller sets the color for good.
+#[derive(Debug, Default)]
+pub struct Fader {
+    fades: Vec<(Led, Fade, Instant)>,
+}
+
+impl Fader {
+    /// Fade `led` to `to`, from `from` or from wherever it is if it's
+    /// already fading
+    pub fn start(&mut self, led: Led, from: RGB8, to: RGB8, duration: Duration) {
+        let now = Instant::now();
+        let from = match self.fades.iter().position(|(l, _, _)| *l == led) {
+            Some(i) => {
+                let (_, fade, start) = self.fades.remove(i);
+                fade.color(now - start)
+            },
+            None => from,
+        };
+        self.fades.push((led, Fade { from, to, duration }, now));
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.fades.is_empty()
+    }
+
+    /// Send the frames that are due; returns the fades that finished, by
+    /// zone and color, to be set for good
+    pub fn tick(&mut self, device: &dyn RazerMouse) -> USBResult<Vec<(Led, RGB8)>> {
+        let now = Instant::now();
+        for (led, fade, start) in &self.fades {
+            if now - *start < fade.duration {
+                device.preview_led_color(*led, fade.color(now - *start))?;
+            }
+        }
+        let (done, fading) = self.fades.drain(..)
+            .partition(|(_, fade, start)| now - *start >= fade.duration);
+        self.fades = fading;
+        Ok(done.into_iter().map(|(led, fade, _)| (led, fade.to)).collect())
+    }
+}
+
+/// Fade each (zone, from, to) at once, blocking until done; the colors are
+/// left previewed, not set
+pub fn fade(
+    device: &dyn RazerMouse,
+    fades: &[(Led, RGB8, RGB8)],
+    duration: Duration
+) -> USBResult<()> {
+    let mut engine = EffectEngine::new(Duration::from_millis(20));
+    for &(led, from, to) in fades {
+        engine.set_effect(led, Fade { from, to, duration });
+    }
+    let start = Instant::now();
+    while start.elapsed() < duration {
+        engine.render(device, start.elapsed())?;
+        thread::sleep(engine.frame_interval);
+    }
+    engine.render(device, duration)
+}
+
+/// Runs one effect per zone
+pub struct EffectEngine {
+    effects: Vec<(Led, Box<dyn SoftwareEffect>)>,
+    /// What each zone was last set to, to only send changes
+    shown: Vec<(Led, RGB8)>,
+    frame_interval: Duration,
+}
+
+impl EffectEngine {
+    /// `frame_interval` is the time between frames; each frame costs a
+    /// transaction per changed zone
+    pub fn new(frame_interval: Duration) -> Self {
+        Self {
+            effects: Vec::new(),
+            shown: Vec::new(),
+            frame_interval: frame_interval,
+        }
+    }
+
+    /// Replaces the effect on `led`, if any
+    pub fn set_effect<E: SoftwareEffect + 'static>(&mut self, led: Led, effect: E) {
+        self.remove_effect(led);
+        self.effects.push((led, Box::new(effect)));
+    }
+
+    /// Replaces the effects on the zones `animation` sets and `device` has
+    /// in color
+    pub fn play(&mut self, animation: Animation, device: &dyn RazerMouse) {
+        let caps = device.capabilities();
+        let animation = Arc::new(animation);
+        for led in animation.zones().into_iter().filter(|&led| caps.has_rgb(led)) {
+            self.set_effect(led, AnimationTrack::new(Arc::clone(&animation), led));
+        }
+    }
+
+    pub fn remove_effect(&mut self, led: Led) {
+        self.effects.retain(|(l, _)| *l != led);
+        self.shown.retain(|(l, _)| *l != led);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.effects.is_empty()
+    }
+
+    /// Show the frame at `elapsed` since the effects started
+    pub fn render(&mut self, device: &dyn RazerMouse, elapsed: Duration) -> USBResult<()> {
+        for (led, effect) in &self.effects {
+            let color = effect.color(elapsed);
+            match self.shown.iter_mut().find(|(l, _)| l == led) {
+                Some((_, shown)) if *shown == color => continue,
+                Some((_, shown)) => *shown = color,
+                None => self.shown.push((*led, color)),
+            }
+            device.preview_led_color(*led, color)?;
+        }
+        Ok(())
+    }
+
+    /// Render frames until `stop` is set
+    pub fn run(&mut self, device: &dyn RazerMouse, stop: &AtomicBool) -> USBResult<()> {
+        let start = Instant::now();
+        let mut next_frame = start;
+        while !stop.load(Ordering::Relaxed) {
+            self.render(device, start.elapsed())?;
+            next_frame += self.frame_interval;
+            thread::sleep(next_frame.saturating_duration_since(Instant::now()));
+        }
+        Ok(())
+    }
+}
@@ -1,4 +1,4 @@
-use std::{num::ParseIntError, fmt, result, error};
+use std::{num::ParseIntError, fmt, result, error, io};
 
 #[derive(Debug)]
 pub enum ParseRGBError {
@@ -33,6 +33,134 @@ impl From<ParseIntError> for ParseRGBError {
     }
 }
 
+/// (input) Not one of the supported rates
+#[derive(Debug)]
+pub struct ParsePollingRateError(pub String);
+
+impl fmt::Display for ParsePollingRateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "'{}' is not a polling rate (125, 250, 500 or 1000 Hz)", self.0)
+    }
+}
+
+impl error::Error for ParsePollingRateError {}
+
+/// (input) Not the name of an LED
+#[derive(Debug)]
+pub struct ParseLedError(pub String);
+
+impl fmt::Display for ParseLedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "'{}' is not an LED (e.g. logo, scroll wheel, left side)", self.0)
+    }
+}
+
+impl error::Error for ParseLedError {}
+
+/// (input) Not the name of a mouse button
+#[derive(Debug)]
+pub struct ParseButtonError(pub String);
+
+impl fmt::Display for ParseButtonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "'{}' is not a button (left, right, middle, back, forward, \
+            wheel-up or wheel-down)", self.0)
+    }
+}
+
+impl error::Error for ParseButtonError {}
+
+/// (input) Not something a button can do
+#[derive(Debug)]
+pub struct ParseButtonFunctionError(pub String);
+
+impl fmt::Display for ParseButtonFunctionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "'{}' is not a button function (disabled, mouse:<button>, \
+            key:<e.g. ctrl+c>, dpi-up, dpi-down, macro:<id> or hypershift)", self.0)
+    }
+}
+
+impl error::Error for ParseButtonFunctionError {}
+
+/// (input) Not a sequence of key combinations and delays
+#[derive(Debug)]
+pub struct ParseMacroError(pub String);
+
+impl fmt::Display for ParseMacroError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "'{}' is neither a key combination (e.g. ctrl+c) nor a delay \
+            (e.g. 50ms)", self.0)
+    }
+}
+
+impl error::Error for ParseMacroError {}
+
+/// (length) More arguments than fit in a report
+#[derive(Debug)]
+pub struct RawCommandError(pub usize);
+
+impl fmt::Display for RawCommandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} arguments given; a report takes at most {}",
+            self.0, crate::commands::MAX_ARGUMENTS)
+    }
+}
+
+impl error::Error for RawCommandError {}
+
+/// (input) A set of DPI stages the device would not take
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DpiStagesError {
+    Empty,
+    /// (max) More stages than the device supports
+    TooMany(usize),
+    /// (stage, dpi) A stage outside the device's DPI range
+    OutOfRange(usize, u16),
+    /// (stage, earlier stage) Same DPI as an earlier stage
+    Duplicate(usize, usize),
+    /// (current) The active stage is not one of the stages
+    CurrentOutOfRange(u8),
+}
+
+impl fmt::Display for DpiStagesError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // stages are 1-based for humans
+        match *self {
+            DpiStagesError::Empty => write!(f, "no DPI stages"),
+            DpiStagesError::TooMany(max) =>
+                write!(f, "the device takes at most {} DPI stages", max),
+            DpiStagesError::OutOfRange(stage, dpi) =>
+                write!(f, "stage {}: {} DPI is out of range", stage + 1, dpi),
+            DpiStagesError::Duplicate(stage, earlier) =>
+                write!(f, "stage {} is the same as stage {}", stage + 1, earlier + 1),
+            DpiStagesError::CurrentOutOfRange(current) =>
+                write!(f, "there is no stage {} to make active", current as usize + 1),
+        }
+    }
+}
+
+impl error::Error for DpiStagesError {}
+
+#[derive(Debug)]
+pub enum ConfigSetError {
+    UnknownKey(String),
+    /// (key, value) The value can't be parsed for that key
+    BadValue(String, String),
+}
+
+impl fmt::Display for ConfigSetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConfigSetError::UnknownKey(ref key) => write!(f, "unknown key '{}'", key),
+            ConfigSetError::BadValue(ref key, ref value) =>
+                write!(f, "invalid value '{}' for '{}'", value, key),
+        }
+    }
+}
+
+impl error::Error for ConfigSetError {}
+
 /// A result of a function that may return a `Error`.
 pub type USBResult<T> = result::Result<T, USBError>;
 
@@ -51,8 +179,23 @@ pub enum USBError {
     CommandTimeout,
     ResponseUnknownStatus(u8),
     ResponseUnknownValue(u8),
+    /// (what) The device doesn't have the capability
+    Unsupported(String),
+    InvalidDpiStages(DpiStagesError),
+    /// A transaction hook refused the transaction (reason)
+    Vetoed(String),
+    /// (line) A replayed session file could not be parsed
+    ReplayBadSession(usize),
+    ReplayExhausted,
+    ReplayMismatch,
     /// Wrapper for rusb::Error
+    #[cfg(feature = "rusb")]
     RUSBError(rusb::Error),
+    /// Wrapper for hidapi::HidError
+    #[cfg(feature = "hidapi")]
+    HidError(hidapi::HidError),
+    /// Wrapper for std::io::Error
+    IOError(io::Error),
 }
 
 impl fmt::Display for USBError {
@@ -75,7 +218,58 @@ impl fmt::Display for USBError {
                 write!(f, "unrecognized status in response: {:#02X}", status),
             USBError::ResponseUnknownValue(value) =>
                 write!(f, "unrecognized value in response: {:#02X}", value),
+            USBError::Unsupported(ref what) =>
+                write!(f, "not supported by the device: {}", what),
+            USBError::InvalidDpiStages(ref e) => write!(f, "{}", e),
+            USBError::Vetoed(ref reason) =>
+                write!(f, "transaction vetoed: {}", reason),
+            USBError::ReplayBadSession(line) =>
+                write!(f, "malformed session file at line {}", line),
+            USBError::ReplayExhausted => write!(f, "no more recorded exchanges to replay"),
+            USBError::ReplayMismatch => write!(f, "request differs from the recorded one"),
+            #[cfg(feature = "rusb")]
             USBError::RUSBError(ref e) => write!(f, "{}", e),
+            #[cfg(feature = "hidapi")]
+            USBError::HidError(ref e) => write!(f, "{}", e),
+            USBError::IOError(ref e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl USBError {
+    /// Whether this looks like the device is there but its driver setup
+    /// keeps us from opening it, as opposed to a protocol or transfer error
+    pub fn driver_problem(&self) -> Option<DriverProblem> {
+        match *self {
+            // libusb on Windows reports these when no WinUSB/libusb-win32
+            // driver is bound to the interface
+            #[cfg(feature = "rusb")]
+            USBError::RUSBError(rusb::Error::NotSupported) |
+            USBError::RUSBError(rusb::Error::NotFound) => Some(DriverProblem::NotBound),
+            #[cfg(feature = "rusb")]
+            USBError::RUSBError(rusb::Error::Access) |
+            USBError::RUSBError(rusb::Error::Busy) => Some(DriverProblem::Access),
+            _ => None,
+        }
+    }
+}
+
+/// See `USBError::driver_problem()`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DriverProblem {
+    /// No libusb-compatible driver is bound to the control interface
+    NotBound,
+    /// Another program holds the device, or we lack the permissions
+    Access,
+}
+
+impl fmt::Display for DriverProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DriverProblem::NotBound => write!(f, "the WinUSB (or libusb-win32) \
+                driver does not seem to be installed for the device"),
+            DriverProblem::Access => write!(f, "the device is in use by another \
+                program or access to it was denied"),
         }
     }
 }
@@ -83,14 +277,224 @@ impl fmt::Display for USBError {
 impl error::Error for USBError {
     fn source(&self) -> Option<&(dyn error::Error + 'static)> {
         match *self {
+            #[cfg(feature = "rusb")]
             USBError::RUSBError(ref e) => Some(e),
+            #[cfg(feature = "hidapi")]
+            USBError::HidError(ref e) => Some(e),
+            USBError::IOError(ref e) => Some(e),
+            USBError::InvalidDpiStages(ref e) => Some(e),
             _ => None
         }
     }
 }
 
+#[cfg(feature = "rusb")]
 impl From<rusb::Error> for USBError {
     fn from(err: rusb::Error) -> USBError {
         USBError::RUSBError(err)
     }
-}
\ No newline at end of file
+}
+
+impl From<DpiStagesError> for USBError {
+    fn from(err: DpiStagesError) -> USBError {
+        USBError::InvalidDpiStages(err)
+    }
+}
+
+impl From<io::Error> for USBError {
+    fn from(err: io::Error) -> USBError {
+        USBError::IOError(err)
+    }
+}
+
+#[cfg(feature = "hidapi")]
+impl From<hidapi::HidError> for USBError {
+    fn from(err: hidapi::HidError) -> USBError {
+        USBError::HidError(err)
+    }
+}
+
+/// Any error from this crate, for callers that would rather handle one type
+/// than one per layer
+#[derive(Debug)]
+pub enum Error {
+    Usb(USBError),
+    #[cfg(feature = "config")]
+    Config(confy::ConfyError),
+    ConfigSet(ConfigSetError),
+    ParseRGB(ParseRGBError),
+    ParsePollingRate(ParsePollingRateError),
+    ParseLed(ParseLedError),
+    ParseButton(ParseButtonError),
+    ParseButtonFunction(ParseButtonFunctionError),
+    ParseMacro(ParseMacroError),
+    RawCommand(RawCommandError),
+    /// From outside this crate, e.g. a front-end's HID event listener
+    Other(Box<dyn error::Error + Send + Sync>),
+    /// (what was being done, why it failed)
+    Context(String, Box<Error>),
+}
+
+/// A result of a function that may return an `Error`
+pub type Result<T> = result::Result<T, Error>;
+
+impl Error {
+    pub fn other<E: Into<Box<dyn error::Error + Send + Sync>>>(err: E) -> Error {
+        Error::Other(err.into())
+    }
+
+    /// The error without any context around it
+    pub fn root(&self) -> &Error {
+        match *self {
+            Error::Context(_, ref inner) => inner.root(),
+            _ => self,
+        }
+    }
+
+    /// See `USBError::driver_problem()`
+    pub fn driver_problem(&self) -> Option<DriverProblem> {
+        match *self.root() {
+            Error::Usb(ref e) => e.driver_problem(),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Usb(ref e) => write!(f, "{}", e),
+            #[cfg(feature = "config")]
+            Error::Config(ref e) => write!(f, "{}", e),
+            Error::ConfigSet(ref e) => write!(f, "{}", e),
+            Error::ParseRGB(ref e) => write!(f, "{}", e),
+            Error::ParsePollingRate(ref e) => write!(f, "{}", e),
+            Error::ParseLed(ref e) => write!(f, "{}", e),
+            Error::ParseButton(ref e) => write!(f, "{}", e),
+            Error::ParseButtonFunction(ref e) => write!(f, "{}", e),
+            Error::ParseMacro(ref e) => write!(f, "{}", e),
+            Error::RawCommand(ref e) => write!(f, "{}", e),
+            Error::Other(ref e) => write!(f, "{}", e),
+            Error::Context(ref context, ref e) => write!(f, "{}: {}", context, e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Usb(ref e) => Some(e),
+            #[cfg(feature = "config")]
+            Error::Config(ref e) => Some(e),
+            Error::ConfigSet(ref e) => Some(e),
+            Error::ParseRGB(ref e) => Some(e),
+            Error::ParsePollingRate(ref e) => Some(e),
+            Error::ParseLed(ref e) => Some(e),
+            Error::ParseButton(ref e) => Some(e),
+            Error::ParseButtonFunction(ref e) => Some(e),
+            Error::ParseMacro(ref e) => Some(e),
+            Error::RawCommand(ref e) => Some(e),
+            Error::Other(ref e) => Some(e.as_ref()),
+            Error::Context(_, ref e) => Some(e.as_ref()),
+        }
+    }
+}
+
+/// Add what was being done to an error, e.g.
+/// `dav2.get_dpi().context("failed to get DPI")?`
+pub trait Context<T> {
+    fn context<C: Into<String>>(self, context: C) -> Result<T>;
+}
+
+impl<T, E: Into<Error>> Context<T> for result::Result<T, E> {
+    fn context<C: Into<String>>(self, context: C) -> Result<T> {
+        self.map_err(|e| Error::Context(context.into(), Box::new(e.into())))
+    }
+}
+
+impl From<USBError> for Error {
+    fn from(err: USBError) -> Error {
+        Error::Usb(err)
+    }
+}
+
+#[cfg(feature = "rusb")]
+impl From<rusb::Error> for Error {
+    fn from(err: rusb::Error) -> Error {
+        Error::Usb(USBError::RUSBError(err))
+    }
+}
+
+#[cfg(feature = "hidapi")]
+impl From<hidapi::HidError> for Error {
+    fn from(err: hidapi::HidError) -> Error {
+        Error::Usb(USBError::HidError(err))
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Usb(USBError::IOError(err))
+    }
+}
+
+impl From<DpiStagesError> for Error {
+    fn from(err: DpiStagesError) -> Error {
+        Error::Usb(USBError::InvalidDpiStages(err))
+    }
+}
+
+#[cfg(feature = "config")]
+impl From<confy::ConfyError> for Error {
+    fn from(err: confy::ConfyError) -> Error {
+        Error::Config(err)
+    }
+}
+
+impl From<ConfigSetError> for Error {
+    fn from(err: ConfigSetError) -> Error {
+        Error::ConfigSet(err)
+    }
+}
+
+impl From<ParseRGBError> for Error {
+    fn from(err: ParseRGBError) -> Error {
+        Error::ParseRGB(err)
+    }
+}
+
+impl From<ParsePollingRateError> for Error {
+    fn from(err: ParsePollingRateError) -> Error {
+        Error::ParsePollingRate(err)
+    }
+}
+
+impl From<ParseLedError> for Error {
+    fn from(err: ParseLedError) -> Error {
+        Error::ParseLed(err)
+    }
+}
+
+impl From<ParseButtonError> for Error {
+    fn from(err: ParseButtonError) -> Error {
+        Error::ParseButton(err)
+    }
+}
+
+impl From<ParseButtonFunctionError> for Error {
+    fn from(err: ParseButtonFunctionError) -> Error {
+        Error::ParseButtonFunction(err)
+    }
+}
+
+impl From<ParseMacroError> for Error {
+    fn from(err: ParseMacroError) -> Error {
+        Error::ParseMacro(err)
+    }
+}
+
+impl From<RawCommandError> for Error {
+    fn from(err: RawCommandError) -> Error {
+        Error::RawCommand(err)
+    }
+}
@@ -0,0 +1,137 @@
+//! (De)serializes colors as "#RRGGBB" strings. Also reads the r/g/b tables
+//! older versions wrote, so existing configs keep loading.
+
+// only profiles and animations use it without the config
+#![cfg_attr(not(feature = "config"), allow(dead_code))]
+
+use serde::{Deserialize, Deserializer, Serializer};
+use serde::de::Error;
+use rgb::RGB8;
+
+use crate::common::rgb_from_hex;
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Repr {
+    Hex(String),
+    Table { r: u8, g: u8, b: u8 },
+}
+
+pub fn serialize<S: Serializer>(color: &RGB8, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&format!("#{:02X}{:02X}{:02X}", color.r, color.g, color.b))
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<RGB8, D::Error> {
+    match Repr::deserialize(deserializer)? {
+        Repr::Hex(hex) => rgb_from_hex(&hex)
+            .map_err(|e| D::Error::custom(format!("bad color '{}': {}", hex, e))),
+        Repr::Table { r, g, b } => Ok(RGB8::new(r, g, b)),
+    }
+}
+
+/// The same for the values of a map
+pub mod map {
+    use std::collections::HashMap;
+    use serde::{Deserialize, Deserializer, Serializer};
+    use serde::de::Error;
+    use serde::ser::SerializeMap;
+    use rgb::RGB8;
+
+    use crate::common::rgb_from_hex;
+
+    pub fn serialize<S: Serializer>(
+        colors: &HashMap<String, RGB8>,
+        serializer: S
+    ) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(colors.len()))?;
+        for (key, color) in colors {
+            map.serialize_entry(key,
+                &format!("#{:02X}{:02X}{:02X}", color.r, color.g, color.b))?;
+        }
+        map.end()
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D
+    ) -> Result<HashMap<String, RGB8>, D::Error> {
+        HashMap::<String, String>::deserialize(deserializer)?
+            .into_iter()
+            .map(|(key, hex)| match rgb_from_hex(&hex) {
+                Ok(color) => Ok((key, color)),
+                Err(e) => Err(D::Error::custom(format!("bad color '{}': {}", hex, e))),
+            })
+            .collect()
+    }
+}
+
+/// The same for the items of a list
+pub mod vec {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use serde::de::Error;
+    use serde::ser::SerializeSeq;
+    use rgb::RGB8;
+
+    use crate::common::rgb_from_hex;
+
+    pub fn serialize<S: Serializer>(colors: &[RGB8], serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(colors.len()))?;
+        for color in colors {
+            seq.serialize_element(
+                &format!("#{:02X}{:02X}{:02X}", color.r, color.g, color.b))?;
+        }
+        seq.end()
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<RGB8>, D::Error> {
+        Vec::<String>::deserialize(deserializer)?
+            .into_iter()
+            .map(|hex| rgb_from_hex(&hex)
+                .map_err(|e| D::Error::custom(format!("bad color '{}': {}", hex, e))))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use serde::{Deserialize, Serialize};
+    use rgb::RGB8;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Colors {
+        #[serde(with = "crate::hex_color")]
+        one: RGB8,
+        #[serde(with = "crate::hex_color::vec")]
+        list: Vec<RGB8>,
+        #[serde(with = "crate::hex_color::map")]
+        map: HashMap<String, RGB8>,
+    }
+
+    #[test]
+    fn colors_round_trip_as_hex() {
+        let colors = Colors {
+            one: RGB8::new(0x12, 0xab, 0xff),
+            list: vec![RGB8::new(0xff, 0, 0), RGB8::new(0, 0, 0)],
+            map: HashMap::from([(String::from("Backlight"), RGB8::new(0, 0x80, 0))]),
+        };
+        let json = serde_json::to_string(&colors).unwrap();
+        assert_eq!(json, r##"{"one":"#12ABFF","list":["#FF0000","#000000"],"map":{"Backlight":"#008000"}}"##);
+        assert_eq!(serde_json::from_str::<Colors>(&json).unwrap(), colors);
+    }
+
+    #[test]
+    fn short_hex_and_old_tables_are_read() {
+        let colors: Colors = serde_json::from_str(
+            r##"{"one":{"r":1,"g":2,"b":3},"list":["#f00","0f0"],"map":{}}"##).unwrap();
+        assert_eq!(colors.one, RGB8::new(1, 2, 3));
+        assert_eq!(colors.list, [RGB8::new(0xff, 0, 0), RGB8::new(0, 0xff, 0)]);
+    }
+
+    #[test]
+    fn bad_colors_are_errors() {
+        for one in [r##""#12345""##, r##""#ggg""##, "42"] {
+            let json = format!(r#"{{"one":{},"list":[],"map":{{}}}}"#, one);
+            assert!(serde_json::from_str::<Colors>(&json).is_err(), "{} was accepted", one);
+        }
+    }
+}
@@ -0,0 +1,86 @@
+//! hidapi specifics (`hidapi` feature). One `HidApi` is shared by the
+//! transport and event listening, rather than each creating its own.
+
+use std::sync::{Mutex, MutexGuard, OnceLock};
+use std::time::Duration;
+use hidapi::{DeviceInfo, HidApi, HidDevice};
+
+use crate::error::USBResult;
+
+/// Size of the input reports on the event interface
+pub const EVENT_REPORT_SIZE: usize = 16;
+
+static API: OnceLock<Mutex<HidApi>> = OnceLock::new();
+
+/// The shared `HidApi`, created on first use, with an up to date device list
+pub fn api() -> USBResult<MutexGuard<'static, HidApi>> {
+    let api = match API.get() {
+        Some(api) => api,
+        None => {
+            let api = HidApi::new()?;
+            API.get_or_init(|| Mutex::new(api))
+        },
+    };
+    let mut api = api.lock().unwrap_or_else(|e| e.into_inner());
+    api.refresh_devices()?;
+    Ok(api)
+}
+
+/// The interfaces of devices with these ids
+pub fn device_infos(vid: u16, pid: u16) -> USBResult<Vec<DeviceInfo>> {
+    Ok(api()?.device_list()
+        .filter(|d| d.vendor_id() == vid && d.product_id() == pid)
+        .cloned()
+        .collect())
+}
+
+/// Whether a report from the event interface is about a DPI change
+pub fn is_dpi_change(report: &[u8]) -> bool {
+    report.len() >= 2 && report[0] == 0x05 && report[1] == 0x02
+}
+
+/// Listens to the input reports of one or more interfaces. A device may
+/// have more than one interface that looks the same and there's no telling
+/// which one reports what, so all are read in turn.
+pub struct EventListener {
+    devices: Vec<HidDevice>,
+    /// (device index, report) The last one seen, to skip repeats
+    last: Option<(usize, [u8; EVENT_REPORT_SIZE])>,
+}
+
+impl EventListener {
+    /// Interfaces that can't be opened are left out
+    pub fn open(infos: &[DeviceInfo]) -> USBResult<Self> {
+        let api = api()?;
+        Ok(Self {
+            devices: infos.iter().filter_map(|info| info.open_device(&api).ok()).collect(),
+            last: None,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.devices.is_empty()
+    }
+
+    /// Wait up to `timeout`, split among the interfaces, for a DPI change
+    pub fn wait_dpi_change(&mut self, timeout: Duration) -> bool {
+        if self.devices.is_empty() {
+            std::thread::sleep(timeout);
+            return false;
+        }
+
+        let per_device = (timeout.as_millis() / self.devices.len() as u128) as i32;
+        for (i, device) in self.devices.iter().enumerate() {
+            let mut report = [0; EVENT_REPORT_SIZE];
+            match device.read_timeout(&mut report, per_device) {
+                Ok(EVENT_REPORT_SIZE) if is_dpi_change(&report) &&
+                    self.last != Some((i, report)) => {
+                    self.last = Some((i, report));
+                    return true;
+                },
+                _ => {},
+            }
+        }
+        false
+    }
+}
@@ -0,0 +1,239 @@
+//! Hooks that observe (and may veto) every transaction with a device.
+//!
+//! Useful for logging, metrics, safety checks or test instrumentation without
+//! touching the device code.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::commands::Persistence;
+use crate::common::{LedStorage, RazerReport};
+use crate::error::{USBResult, USBError};
+
+pub trait TransactionHook: Send + Sync {
+    /// Called right before `request` is sent; returning an error vetoes the
+    /// transaction and nothing reaches the device
+    fn before_send(&self, _request: &RazerReport) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Called with the outcome of `request`; returning an error turns a
+    /// successful response into a failed transaction
+    fn after_receive(
+        &self,
+        _request: &RazerReport,
+        _response: &USBResult<RazerReport>
+    ) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// So a hook can be registered and still be looked at by its owner
+impl<H: TransactionHook + ?Sized> TransactionHook for Arc<H> {
+    fn before_send(&self, request: &RazerReport) -> Result<(), String> {
+        (**self).before_send(request)
+    }
+
+    fn after_receive(
+        &self,
+        request: &RazerReport,
+        response: &USBResult<RazerReport>
+    ) -> Result<(), String> {
+        (**self).after_receive(request, response)
+    }
+}
+
+/// Handle returned by `Hooks::register()` to unregister a hook later
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct HookId(usize);
+
+/// The hooks registered on a device, called in registration order
+#[derive(Default)]
+pub struct Hooks {
+    hooks: RwLock<Vec<(HookId, Arc<dyn TransactionHook>)>>,
+    next_id: AtomicUsize,
+}
+
+impl Hooks {
+    pub fn register<H: TransactionHook + 'static>(&self, hook: H) -> HookId {
+        let id = HookId(self.next_id.fetch_add(1, Ordering::Relaxed));
+        self.hooks.write().unwrap().push((id, Arc::new(hook)));
+        id
+    }
+
+    /// Returns false if there was no such hook
+    pub fn unregister(&self, id: HookId) -> bool {
+        let mut hooks = self.hooks.write().unwrap();
+        let len = hooks.len();
+        hooks.retain(|(hid, _)| *hid != id);
+        hooks.len() != len
+    }
+
+    pub fn clear(&self) {
+        self.hooks.write().unwrap().clear();
+    }
+
+    pub(crate) fn before_send(&self, request: &RazerReport) -> USBResult<()> {
+        for (_, hook) in self.hooks.read().unwrap().iter() {
+            hook.before_send(request).map_err(USBError::Vetoed)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn after_receive(
+        &self,
+        request: &RazerReport,
+        response: USBResult<RazerReport>
+    ) -> USBResult<RazerReport> {
+        let mut response = response;
+        for (_, hook) in self.hooks.read().unwrap().iter() {
+            if let Err(reason) = hook.after_receive(request, &response) {
+                if response.is_ok() {
+                    response = Err(USBError::Vetoed(reason));
+                }
+            }
+        }
+        response
+    }
+}
+
+/// Vetoes any command that would write to the device's onboard memory; see
+/// `commands::PERSISTING`
+pub struct BlockVarStore;
+
+impl TransactionHook for BlockVarStore {
+    fn before_send(&self, request: &RazerReport) -> Result<(), String> {
+        let cmd = request.command();
+        let writes = match cmd.persistence() {
+            Some(Persistence::Always) => true,
+            Some(Persistence::VarStore) =>
+                request.args().first() == Some(&(LedStorage::VarStore as u8)),
+            None => false,
+        };
+        if writes {
+            return Err(format!("{} would write to onboard memory", cmd));
+        }
+        Ok(())
+    }
+}
+
+/// Keeps a text line for each of the last few transactions, e.g. for crash
+/// reports. Register it through an `Arc` to be able to read it back.
+pub struct RecentTransactions {
+    capacity: usize,
+    lines: Mutex<VecDeque<String>>,
+}
+
+impl RecentTransactions {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity,
+            lines: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    /// Oldest first
+    pub fn lines(&self) -> Vec<String> {
+        self.lines.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Seconds and milliseconds since the epoch, e.g. "1700000000.123"
+fn timestamp() -> String {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    format!("{}.{:03}", now.as_secs(), now.subsec_millis())
+}
+
+impl TransactionHook for RecentTransactions {
+    fn after_receive(
+        &self,
+        request: &RazerReport,
+        response: &USBResult<RazerReport>
+    ) -> Result<(), String> {
+        let outcome = match response {
+            Ok(response) => hex(response.args()),
+            Err(e) => format!("error: {}", e),
+        };
+        let line = format!("{} {} [{}] -> {}", timestamp(),
+            request.command(), hex(request.args()), outcome);
+
+        let mut lines = self.lines.lock().unwrap();
+        lines.push_back(line);
+        while lines.len() > self.capacity {
+            lines.pop_front();
+        }
+        Ok(())
+    }
+}
+
+/// Hands a line for every report sent and received to a callback, e.g. to
+/// log the traffic when adding support for a new device. Whole reports as
+/// they go over the wire, in hex:
+///
+/// ```text
+/// 1700000000.123 > 003f000000010085000000...
+/// 1700000000.135 < 023f000000010085010000...
+/// ```
+///
+/// Hooks are called in the order they're registered, and a veto skips the
+/// ones after it: register it first to also trace requests that get vetoed.
+pub struct Tracer<F: Fn(&str) + Send + Sync> {
+    callback: F,
+}
+
+impl<F: Fn(&str) + Send + Sync> Tracer<F> {
+    pub fn new(callback: F) -> Self {
+        Self {
+            callback: callback,
+        }
+    }
+}
+
+impl<F: Fn(&str) + Send + Sync> TransactionHook for Tracer<F> {
+    fn before_send(&self, request: &RazerReport) -> Result<(), String> {
+        (self.callback)(&format!("{} > {}", timestamp(), hex(&request.pack())));
+        Ok(())
+    }
+
+    fn after_receive(
+        &self,
+        _request: &RazerReport,
+        response: &USBResult<RazerReport>
+    ) -> Result<(), String> {
+        let line = match response {
+            Ok(response) => format!("{} < {}", timestamp(), hex(&response.pack())),
+            Err(e) => format!("{} < error: {}", timestamp(), e),
+        };
+        (self.callback)(&line);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands;
+
+    fn vetoed(cmd: commands::Command, storage: LedStorage) -> bool {
+        BlockVarStore.before_send(&RazerReport::new(cmd, &[storage as u8])).is_err()
+    }
+
+    #[test]
+    fn block_var_store_blocks_every_persisting_command() {
+        assert!(vetoed(commands::SET_POLLING_RATE, LedStorage::NoStore));
+        for &(cmd, persistence) in commands::PERSISTING {
+            assert!(!cmd.is_get() && cmd.name().is_some(), "{}", cmd);
+            assert!(vetoed(cmd, LedStorage::VarStore), "{}", cmd);
+            assert_eq!(vetoed(cmd, LedStorage::NoStore), persistence == Persistence::Always, "{}", cmd);
+        }
+        for &(cmd, _) in commands::ALL.iter().filter(|(cmd, _)| cmd.persistence().is_none()) {
+            assert!(!vetoed(cmd, LedStorage::VarStore), "{}", cmd);
+        }
+    }
+}
@@ -0,0 +1,201 @@
+//! Notifications when Razer devices are plugged in or out (`rusb` feature):
+//!
+//! ```ignore
+//! let _watcher = watch_devices(|event| match event {
+//!     DeviceEvent::Connected(info) if info.is_supported() => {
+//!         let device = info.open()?;
+//!         ...
+//!     },
+//!     DeviceEvent::Disconnected(info) => ...,
+//!     _ => {},
+//! })?;
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use rusb::{Context, Device, Hotplug, HotplugBuilder, UsbContext};
+
+use crate::device::{GenericDevice, RazerDevice, USB_VENDOR_ID_RAZER};
+use crate::quirks;
+use crate::error::{USBError, USBResult};
+use crate::transport::{recording_from_env, UsbTransport};
+use crate::usb::UsbDevice;
+
+/// How soon the watcher thread notices it should stop
+const STOP_INTERVAL: Duration = Duration::from_millis(250);
+/// How often to list the devices where libusb has no hotplug support
+const LIST_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A device with the Razer vendor id, of a supported model or not
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct DeviceInfo {
+    pub pid: u16,
+    pub bus: u8,
+    pub address: u8,
+}
+
+impl DeviceInfo {
+    fn of(device: &Device<Context>) -> Option<Self> {
+        let desc = device.device_descriptor().ok()?;
+        Some(Self {
+            pid: desc.product_id(),
+            bus: device.bus_number(),
+            address: device.address(),
+        })
+    }
+
+    /// Whether `open()` knows how to drive it
+    pub fn is_supported(&self) -> bool {
+        quirks::find(self.pid).is_some()
+    }
+
+    /// Open it as its model, the way `device::enumerate()` does
+    pub fn open(&self) -> USBResult<Box<dyn RazerDevice>> {
+        if !self.is_supported() {
+            return Err(USBError::Unsupported(format!("product id {:04x}", self.pid)));
+        }
+        let device = UsbDevice::by_product(USB_VENDOR_ID_RAZER, self.pid)?.iter()
+            .filter_map(|device| device.as_ref())
+            .find(|device| device.bus_number() == self.bus && device.address() == self.address)
+            .cloned()
+            .ok_or(USBError::DeviceNotFound)?;
+        let transport = recording_from_env(Box::new(UsbTransport::new(device.open()?)))?;
+        Ok(Box::new(GenericDevice::with_transport(self.pid, transport)))
+    }
+}
+
+impl fmt::Display for DeviceInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04x}:{:04x} ({}-{})", USB_VENDOR_ID_RAZER, self.pid, self.bus, self.address)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DeviceEvent {
+    Connected(DeviceInfo),
+    Disconnected(DeviceInfo),
+}
+
+/// Watches until dropped
+pub struct DeviceWatcher {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for DeviceWatcher {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            _ = thread.join();
+        }
+    }
+}
+
+/// Call `on_event` (from a thread of its own) whenever a Razer device is
+/// plugged in or out, starting with a `Connected` for each one already there.
+/// Uses libusb's hotplug callbacks where it has them and lists the devices
+/// every second where it doesn't (Windows).
+pub fn watch_devices<F>(on_event: F) -> USBResult<DeviceWatcher>
+where
+    F: FnMut(DeviceEvent) + Send + 'static
+{
+    // before has_hotplug(), which panics if libusb can't be initialized
+    let context = Context::new()?;
+    let stop = Arc::new(AtomicBool::new(false));
+    let thread = if rusb::has_hotplug() {
+        watch_hotplug(context, on_event, Arc::clone(&stop))?
+    } else {
+        watch_listing(on_event, Arc::clone(&stop))
+    };
+    Ok(DeviceWatcher {
+        stop: stop,
+        thread: Some(thread),
+    })
+}
+
+/// Passes libusb's callbacks on, to be handled outside of them where the
+/// devices can be opened
+struct Forwarder {
+    events: Sender<DeviceEvent>,
+    // a device that left can't always be asked for its descriptor anymore
+    connected: HashMap<(u8, u8), DeviceInfo>,
+}
+
+impl Hotplug<Context> for Forwarder {
+    fn device_arrived(&mut self, device: Device<Context>) {
+        if let Some(info) = DeviceInfo::of(&device) {
+            self.connected.insert((info.bus, info.address), info);
+            _ = self.events.send(DeviceEvent::Connected(info));
+        }
+    }
+
+    fn device_left(&mut self, device: Device<Context>) {
+        if let Some(info) = self.connected.remove(&(device.bus_number(), device.address())) {
+            _ = self.events.send(DeviceEvent::Disconnected(info));
+        }
+    }
+}
+
+fn watch_hotplug<F>(
+    context: Context,
+    mut on_event: F,
+    stop: Arc<AtomicBool>
+) -> USBResult<JoinHandle<()>>
+where
+    F: FnMut(DeviceEvent) + Send + 'static
+{
+    let (events, received) = mpsc::channel();
+    let forwarder = Forwarder {
+        events: events,
+        connected: HashMap::new(),
+    };
+    let registration = HotplugBuilder::new()
+        .vendor_id(USB_VENDOR_ID_RAZER)
+        .enumerate(true)
+        .register::<Context, _>(&context, Box::new(forwarder))?;
+
+    Ok(thread::spawn(move || {
+        // deregisters when dropped
+        let _registration = registration;
+        while !stop.load(Ordering::Relaxed) {
+            // fails only if interrupted; the next round picks up
+            _ = context.handle_events(Some(STOP_INTERVAL));
+            received.try_iter().for_each(&mut on_event);
+        }
+    }))
+}
+
+fn watch_listing<F>(mut on_event: F, stop: Arc<AtomicBool>) -> JoinHandle<()>
+where
+    F: FnMut(DeviceEvent) + Send + 'static
+{
+    thread::spawn(move || {
+        let mut connected: Vec<DeviceInfo> = Vec::new();
+        while !stop.load(Ordering::Relaxed) {
+            // a failed listing doesn't mean everything's gone; skip it
+            if let Ok(devices) = UsbDevice::by_vendor(USB_VENDOR_ID_RAZER) {
+                let present: Vec<DeviceInfo> = devices.iter()
+                    .filter_map(|device| device.as_ref())
+                    .filter_map(DeviceInfo::of)
+                    .collect();
+                for info in connected.iter().filter(|info| !present.contains(info)) {
+                    on_event(DeviceEvent::Disconnected(*info));
+                }
+                for info in present.iter().filter(|info| !connected.contains(info)) {
+                    on_event(DeviceEvent::Connected(*info));
+                }
+                connected = present;
+            }
+
+            let listed = Instant::now();
+            while listed.elapsed() < LIST_INTERVAL && !stop.load(Ordering::Relaxed) {
+                thread::sleep(STOP_INTERVAL);
+            }
+        }
+    })
+}
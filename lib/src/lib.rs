@@ -1,13 +1,123 @@
+#[cfg(feature = "config")]
 pub mod cfg;
 pub mod error;
 pub mod device;
+pub mod commands;
+pub mod capabilities;
+pub mod hooks;
+pub mod profile;
+pub mod effects;
+pub mod lighting;
+pub mod buttons;
+pub mod macros;
+pub mod animation;
+pub mod status;
+pub mod watchdog;
+pub mod diagnostics;
+pub mod worker;
+pub mod shared;
+pub mod mock;
+pub mod quirks;
+#[cfg(feature = "tokio")]
+mod asynchronous;
+mod hex_color;
+pub mod transport;
+#[cfg(feature = "rusb")]
+mod usb;
+#[cfg(feature = "rusb")]
+pub mod hotplug;
+#[cfg(feature = "hidapi")]
+pub mod hid;
+#[cfg(feature = "transport-stats")]
+pub mod stats;
+
+#[cfg(feature = "rusb")]
+pub use device::enumerate;
 
 pub mod common {
-    use std::{num::ParseIntError, thread, time::Duration, fmt::Display};
-    use rusb::{DeviceHandle, UsbContext};
+    use std::{num::ParseIntError, thread, time::{Duration, Instant}, fmt::Display, str::FromStr};
+    use std::sync::atomic::{AtomicBool, AtomicU16, AtomicU32, AtomicU64, Ordering};
     use core::mem::{size_of, size_of_val, MaybeUninit};
-    use rgb::{RGB8, FromSlice};
-    use crate::error::{ParseRGBError, USBResult, USBError};
+    use rgb::{RGB8, ComponentBytes, FromSlice};
+    use serde::{Serialize, Deserialize};
+    use crate::error::{DpiStagesError, ParseRGBError, ParsePollingRateError, ParseLedError, USBResult,
+        USBError};
+    use crate::commands::{self, Command};
+    use crate::buttons::{Button, ButtonFunction, Layer};
+    use crate::macros::{Macro, MACRO_CHUNK};
+    use crate::transport::Transport;
+
+    /// Like `rgb_from_hex()`, but also takes a white preset ("warm",
+    /// "neutral" or "cool") or a color temperature (e.g. "4000K")
+    pub fn rgb_from_str(input: &str) -> Result<RGB8, ParseRGBError> {
+        let s = input.trim().to_lowercase();
+        if let Some(preset) = WhitePreset::iter().find(|p| p.name().to_lowercase() == s) {
+            return Ok(preset.rgb());
+        }
+        if let Some(Ok(kelvin)) = s.strip_suffix('k').map(|k| k.trim().parse::<u16>()) {
+            return Ok(rgb_from_kelvin(kelvin));
+        }
+        rgb_from_hex(input)
+    }
+
+    /// The white of a light at `kelvin` ([1000-40000]); see
+    /// https://tannerhelland.com/2012/09/18/convert-temperature-rgb-algorithm-code.html
+    pub fn rgb_from_kelvin(kelvin: u16) -> RGB8 {
+        let t = kelvin.clamp(1000, 40000) as f64 / 100.0;
+        let r = if t <= 66.0 {
+            255.0
+        } else {
+            329.698727446 * (t - 60.0).powf(-0.1332047592)
+        };
+        let g = if t <= 66.0 {
+            99.4708025861 * t.ln() - 161.1195681661
+        } else {
+            288.1221695283 * (t - 60.0).powf(-0.0755148492)
+        };
+        let b = if t >= 66.0 {
+            255.0
+        } else if t <= 19.0 {
+            0.0
+        } else {
+            138.5177312231 * (t - 10.0).ln() - 305.0447927307
+        };
+        let channel = |c: f64| c.round().clamp(0.0, 255.0) as u8;
+        RGB8::new(channel(r), channel(g), channel(b))
+    }
+
+    /// Whites that look white on the LEDs, unlike #FFFFFF which is bluish
+    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+    pub enum WhitePreset {
+        Warm,
+        Neutral,
+        Cool,
+    }
+
+    impl WhitePreset {
+        pub fn iter() -> impl Iterator<Item = Self> {
+            [WhitePreset::Warm, WhitePreset::Neutral, WhitePreset::Cool].into_iter()
+        }
+
+        pub fn name(&self) -> &'static str {
+            match self {
+                WhitePreset::Warm => "Warm",
+                WhitePreset::Neutral => "Neutral",
+                WhitePreset::Cool => "Cool",
+            }
+        }
+
+        pub fn kelvin(&self) -> u16 {
+            match self {
+                WhitePreset::Warm => 2700,
+                WhitePreset::Neutral => 4000,
+                WhitePreset::Cool => 6500,
+            }
+        }
+
+        pub fn rgb(&self) -> RGB8 {
+            rgb_from_kelvin(self.kelvin())
+        }
+    }
 
     pub fn rgb_from_hex(input: &str) -> Result<RGB8, ParseRGBError> {
         let s = input
@@ -39,9 +149,154 @@ pub mod common {
         }
     }
 
-    // tried also 1ms with varying results
+    /// Seconds since the Unix epoch as "YYYY-MM-DD HH:MM:SS UTC"
+    pub fn utc_string(timestamp: u64) -> String {
+        let (days, secs) = (timestamp / 86400, timestamp % 86400);
+
+        // days to civil date; see http://howardhinnant.github.io/date_algorithms.html
+        let z = days as i64 + 719468;
+        let era = z.div_euclid(146097);
+        let doe = z.rem_euclid(146097);
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC", year, month, day,
+            secs / 3600, (secs % 3600) / 60, secs % 60)
+    }
+
+    /// The most DPI stages that fit in a report (3 + 7 bytes per stage)
+    pub const MAX_DPI_STAGES: usize = 11;
+
+    // how long to poll for; tried also 1ms with varying results
     static USB_RECEIVER_WAIT: Duration = Duration::from_millis(10);
-    static USB_TXFER_TIMEOUT: Duration = Duration::from_secs(1);
+
+    /// How long to poll for the response to a control write, adapted
+    /// to how the device behaves: it shrinks a little after a run of
+    /// successful transactions and grows quickly on failures
+    pub struct ReceiverWait {
+        wait_us: AtomicU64,
+        successes: AtomicU32,
+        adaptive: AtomicBool,
+    }
+
+    impl ReceiverWait {
+        pub const MIN: Duration = Duration::from_millis(1);
+        pub const MAX: Duration = Duration::from_millis(50);
+
+        /// Consecutive successes before trying a shorter wait
+        const SHRINK_AFTER: u32 = 20;
+
+        pub fn new(wait: Duration) -> Self {
+            let wait = wait.clamp(Self::MIN, Self::MAX);
+            Self {
+                wait_us: AtomicU64::new(wait.as_micros() as u64),
+                successes: AtomicU32::new(0),
+                adaptive: AtomicBool::new(true),
+            }
+        }
+
+        pub fn get(&self) -> Duration {
+            Duration::from_micros(self.wait_us.load(Ordering::Relaxed))
+        }
+
+        /// Start over from the given wait, e.g. one learned in a previous run
+        pub fn set(&self, wait: Duration) {
+            let wait = wait.clamp(Self::MIN, Self::MAX);
+            self.wait_us.store(wait.as_micros() as u64, Ordering::Relaxed);
+            self.successes.store(0, Ordering::Relaxed);
+        }
+
+        pub fn is_adaptive(&self) -> bool {
+            self.adaptive.load(Ordering::Relaxed)
+        }
+
+        /// Not adaptive, the wait stays at what it was last `set()` to
+        pub fn set_adaptive(&self, adaptive: bool) {
+            self.adaptive.store(adaptive, Ordering::Relaxed);
+        }
+
+        /// Adapt to the outcome of a transaction
+        pub(crate) fn update<T>(&self, result: &USBResult<T>) {
+            if !self.is_adaptive() {
+                return;
+            }
+            let wait = self.get();
+            match result {
+                Ok(_) => {
+                    let n = self.successes.fetch_add(1, Ordering::Relaxed) + 1;
+                    if n >= Self::SHRINK_AFTER {
+                        self.set(wait.mul_f32(0.9));
+                    }
+                },
+                // the device has answered; not a matter of timing
+                Err(USBError::CommandFailed) |
+                Err(USBError::CommandNotSupported) |
+                Err(USBError::Vetoed(_)) => (),
+                Err(_) => self.set(wait.mul_f32(1.5)),
+            }
+        }
+    }
+
+    impl Default for ReceiverWait {
+        fn default() -> Self {
+            Self::new(USB_RECEIVER_WAIT)
+        }
+    }
+
+    /// Timing of the conversation with a device, e.g. longer for a flaky hub
+    /// or shorter for low latency; see `RazerDevice::set_protocol_options()`
+    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+    pub struct ProtocolOptions {
+        /// How long to poll for the response to a report, within
+        /// `ReceiverWait::MIN` and `ReceiverWait::MAX`
+        pub receiver_wait: Duration,
+        /// Whether the receiver wait adapts to how the device behaves, or
+        /// stays as set
+        pub adaptive_wait: bool,
+        /// How long each USB transfer may take; transports without timeouts
+        /// (e.g. hidapi) ignore it
+        pub transfer_timeout: Duration,
+    }
+
+    impl Default for ProtocolOptions {
+        fn default() -> Self {
+            Self {
+                receiver_wait: USB_RECEIVER_WAIT,
+                adaptive_wait: true,
+                transfer_timeout: crate::transport::USB_TXFER_TIMEOUT,
+            }
+        }
+    }
+
+    /// Transaction ids Razer devices are known to answer to, most common first
+    pub const TX_ID_CANDIDATES: [u8; 3] = [0x1f, 0x3f, 0x9f];
+
+    /// A transaction id to use instead of the device's default, for firmware
+    /// revisions that only answer to another one
+    pub struct TxIdOverride(AtomicU16);
+
+    impl TxIdOverride {
+        /// Stands for no override; out of the range of ids
+        const NONE: u16 = 0x100;
+
+        pub fn get(&self) -> Option<u8> {
+            u8::try_from(self.0.load(Ordering::Relaxed)).ok()
+        }
+
+        pub fn set(&self, tx_id: Option<u8>) {
+            self.0.store(tx_id.map_or(Self::NONE, u16::from), Ordering::Relaxed);
+        }
+    }
+
+    impl Default for TxIdOverride {
+        fn default() -> Self {
+            Self(AtomicU16::new(Self::NONE))
+        }
+    }
 
     // const RAZER_USB_REPORT_LEN: usize = 0x5A;
 
@@ -53,14 +308,14 @@ pub mod common {
     }
 
     #[repr(u8)]
-    #[derive(Debug, Copy, Clone)]
+    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
     pub enum LedStorage {
         NoStore = 0x00,
         VarStore = 0x01,
     }
 
     #[repr(u8)]
-    #[derive(Debug, Copy, Clone)]
+    #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
     pub enum Led {
         Zero = 0x00,
         ScrollWheel = 0x01,
@@ -85,6 +340,108 @@ pub mod common {
         FullyCharged = 0x22
     }
 
+    impl Led {
+        /// All LEDs, in id order
+        pub fn iter() -> impl Iterator<Item = Self> {
+            [
+                Led::Zero, Led::ScrollWheel, Led::Battery, Led::Logo, Led::Backlight,
+                Led::Macro, Led::Game, Led::RedProfile, Led::GreenProfile,
+                Led::BlueProfile, Led::RightSide, Led::LeftSide, Led::ArgbCh1,
+                Led::ArgbCh2, Led::ArgbCh3, Led::ArgbCh4, Led::ArgbCh5, Led::ArgbCh6,
+                Led::Charging, Led::FastCharging, Led::FullyCharged,
+            ].into_iter()
+        }
+
+        /// For display purposes
+        pub fn name(&self) -> &'static str {
+            match self {
+                Led::Zero => "All",
+                Led::ScrollWheel => "Scroll wheel",
+                Led::Battery => "Battery",
+                Led::Logo => "Logo",
+                Led::Backlight => "Backlight",
+                Led::Macro => "Macro",
+                Led::Game => "Game",
+                Led::RedProfile => "Red profile",
+                Led::GreenProfile => "Green profile",
+                Led::BlueProfile => "Blue profile",
+                Led::RightSide => "Right side",
+                Led::LeftSide => "Left side",
+                Led::ArgbCh1 => "ARGB channel 1",
+                Led::ArgbCh2 => "ARGB channel 2",
+                Led::ArgbCh3 => "ARGB channel 3",
+                Led::ArgbCh4 => "ARGB channel 4",
+                Led::ArgbCh5 => "ARGB channel 5",
+                Led::ArgbCh6 => "ARGB channel 6",
+                Led::Charging => "Charging",
+                Led::FastCharging => "Fast charging",
+                Led::FullyCharged => "Fully charged",
+            }
+        }
+
+        /// The LED of ARGB channel `channel` (1-6)
+        pub fn argb_channel(channel: u8) -> Option<Self> {
+            match channel {
+                1..=6 => Led::iter().find(|&led| led as u8 == Led::ArgbCh1 as u8 + channel - 1),
+                _ => None,
+            }
+        }
+    }
+
+    /// Parses either name, ignoring case and spaces (e.g. "scroll wheel" or
+    /// "ScrollWheel")
+    impl FromStr for Led {
+        type Err = ParseLedError;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            let normalize = |name: &str| name.to_lowercase().replace([' ', '_', '-'], "");
+            let wanted = normalize(s);
+            Led::iter()
+                .find(|led| normalize(led.name()) == wanted ||
+                    normalize(&format!("{:?}", led)) == wanted)
+                .ok_or_else(|| ParseLedError(s.into()))
+        }
+    }
+
+    /// States a dock or wireless mouse can show with its charging LEDs
+    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+    pub enum ChargingState {
+        Charging,
+        FastCharging,
+        FullyCharged,
+    }
+
+    impl ChargingState {
+        /// The LED that lights up in this state
+        pub fn led(&self) -> Led {
+            match self {
+                ChargingState::Charging => Led::Charging,
+                ChargingState::FastCharging => Led::FastCharging,
+                ChargingState::FullyCharged => Led::FullyCharged,
+            }
+        }
+
+        /// The state of a battery at `level` (in [0-100]), or None if it's
+        /// neither charging nor full. Fast charging isn't reported, so it
+        /// shows as charging.
+        pub fn from_battery(level: u8, charging: bool) -> Option<Self> {
+            match (level, charging) {
+                (100.., _) => Some(ChargingState::FullyCharged),
+                (_, true) => Some(ChargingState::Charging),
+                _ => None,
+            }
+        }
+    }
+
+    /// The mouse a wireless dongle is paired with
+    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+    pub struct Pairing {
+        /// The mouse's product id, as it shows up on its cable
+        pub pid: u16,
+        /// Whether the mouse is on and in range
+        pub connected: bool,
+    }
+
     #[repr(u8)]
     #[derive(Debug, Copy, Clone)]
     pub enum LedEffect {
@@ -98,9 +455,44 @@ pub mod common {
         CustomFrame = 0x08,
     }
 
+    /// Effects of the classic LED commands; see `commands::SET_LED_EFFECT`
+    #[repr(u8)]
+    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+    pub enum ClassicLedEffect {
+        Static = 0x00,
+        Blinking = 0x01,
+        Pulsating = 0x02,
+        Spectrum = 0x04,
+    }
+
+    /// How long an LED with the reactive effect stays lit after a click,
+    /// shortest to longest
+    pub const REACTIVE_SPEEDS: std::ops::RangeInclusive<u8> = 1..=4;
+
+    /// How long wireless devices can be set to wait before going to sleep
+    pub const IDLE_TIMES: std::ops::RangeInclusive<Duration> =
+        Duration::from_secs(60)..=Duration::from_secs(900);
+
+    /// How many LEDs the strip on an ARGB channel can have
+    pub const ARGB_CHANNEL_SIZES: std::ops::RangeInclusive<u8> = 0..=80;
+
+    /// The most columns of a custom frame row that fit in one report (5 + 3
+    /// bytes per column); longer rows take more than one
+    pub const MAX_FRAME_COLUMNS: usize = 25;
+
+    /// The colors an LED breathes in
+    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+    pub enum Breathing {
+        Single(RGB8),
+        /// Alternating between the two
+        Dual(RGB8, RGB8),
+        /// A different color every breath, picked by the device
+        Random,
+    }
+
     #[repr(u8)]
     #[derive(Debug, Copy, Clone)]
-    enum CmdStatus {
+    pub(crate) enum CmdStatus {
         Busy = 0x01,
         Successful = 0x02,
         Failure = 0x03,
@@ -123,8 +515,10 @@ pub mod common {
         }
     }
 
+    /// Serialized as the rate in Hz
     #[repr(u8)]
-    #[derive(Debug, Copy, Clone, PartialEq)]
+    #[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+    #[serde(into = "u16", try_from = "u16")]
     pub enum PollingRate {
         Hz1000 = 0x01,
         Hz500 = 0x02,
@@ -164,8 +558,221 @@ pub mod common {
     }
 
     impl PollingRate {
+        /// All rates, slowest first
+        pub const ALL: &'static [PollingRate] =
+            &[PollingRate::Hz125, PollingRate::Hz250, PollingRate::Hz500, PollingRate::Hz1000];
+
         pub fn all() -> Vec<Self> {
-            vec![PollingRate::Hz125, PollingRate::Hz250, PollingRate::Hz500, PollingRate::Hz1000]
+            Self::iter().collect()
+        }
+
+        pub fn iter() -> impl Iterator<Item = Self> {
+            Self::ALL.iter().copied()
+        }
+
+        pub fn hz(&self) -> u16 {
+            match self {
+                PollingRate::Hz1000 => 1000,
+                PollingRate::Hz500 => 500,
+                PollingRate::Hz250 => 250,
+                PollingRate::Hz125 => 125,
+            }
+        }
+    }
+
+    /// From the rate in Hz
+    impl TryFrom<u16> for PollingRate {
+        type Error = u16;
+
+        fn try_from(hz: u16) -> Result<PollingRate, Self::Error> {
+            Self::iter().find(|rate| rate.hz() == hz).ok_or(hz)
+        }
+    }
+
+    impl From<PollingRate> for u16 {
+        fn from(rate: PollingRate) -> u16 {
+            rate.hz()
+        }
+    }
+
+    /// Parses "500", "500hz" or "500 Hz"
+    impl FromStr for PollingRate {
+        type Err = ParsePollingRateError;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            let s = s.trim();
+            let digits = s.strip_suffix("Hz")
+                .or_else(|| s.strip_suffix("hz"))
+                .unwrap_or(s)
+                .trim();
+            let hz = digits.parse::<u16>()
+                .map_err(|_| ParsePollingRateError(s.into()))?;
+            PollingRate::try_from(hz).map_err(|_| ParsePollingRateError(s.into()))
+        }
+    }
+
+    /// Who handles the device: the firmware on its own, or a driver (e.g. to
+    /// get events the firmware would otherwise act on)
+    #[repr(u8)]
+    #[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+    pub enum DeviceMode {
+        #[default]
+        Normal = 0x00,
+        Driver = 0x03,
+    }
+
+    impl TryFrom<u8> for DeviceMode {
+        type Error = u8;
+
+        fn try_from(byte: u8) -> Result<DeviceMode, Self::Error> {
+            match byte {
+                x if x == DeviceMode::Normal as u8 => Ok(DeviceMode::Normal),
+                x if x == DeviceMode::Driver as u8 => Ok(DeviceMode::Driver),
+                _ => Err(byte),
+            }
+        }
+    }
+
+    impl Display for DeviceMode {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                DeviceMode::Normal => write!(f, "normal"),
+                DeviceMode::Driver => write!(f, "driver"),
+            }
+        }
+    }
+
+    /// How the scroll wheel turns, on mice that can switch
+    #[repr(u8)]
+    #[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+    pub enum ScrollMode {
+        /// Notched
+        #[default]
+        Tactile = 0x00,
+        FreeSpin = 0x01,
+    }
+
+    impl TryFrom<u8> for ScrollMode {
+        type Error = u8;
+
+        fn try_from(byte: u8) -> Result<ScrollMode, Self::Error> {
+            match byte {
+                x if x == ScrollMode::Tactile as u8 => Ok(ScrollMode::Tactile),
+                x if x == ScrollMode::FreeSpin as u8 => Ok(ScrollMode::FreeSpin),
+                _ => Err(byte),
+            }
+        }
+    }
+
+    impl Display for ScrollMode {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                ScrollMode::Tactile => write!(f, "tactile"),
+                ScrollMode::FreeSpin => write!(f, "free spin"),
+            }
+        }
+    }
+
+    /// When the sensor stops tracking as the mouse is lifted
+    #[repr(u8)]
+    #[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+    pub enum LiftOff {
+        /// About 1 mm
+        #[default]
+        Low = 0x01,
+        /// About 2 mm
+        High = 0x02,
+        /// Stops tracking at 2 mm going up, resumes at 1 mm coming down
+        Asymmetric = 0x03,
+    }
+
+    impl TryFrom<u8> for LiftOff {
+        type Error = u8;
+
+        fn try_from(byte: u8) -> Result<LiftOff, Self::Error> {
+            match byte {
+                x if x == LiftOff::Low as u8 => Ok(LiftOff::Low),
+                x if x == LiftOff::High as u8 => Ok(LiftOff::High),
+                x if x == LiftOff::Asymmetric as u8 => Ok(LiftOff::Asymmetric),
+                _ => Err(byte),
+            }
+        }
+    }
+
+    impl Display for LiftOff {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                LiftOff::Low => write!(f, "1 mm"),
+                LiftOff::High => write!(f, "2 mm"),
+                LiftOff::Asymmetric => write!(f, "asymmetric"),
+            }
+        }
+    }
+
+    impl LiftOff {
+        pub fn all() -> Vec<Self> {
+            vec![LiftOff::Low, LiftOff::High, LiftOff::Asymmetric]
+        }
+    }
+
+    /// Swappable side plates of the Naga Pro and Naga Trinity; see
+    /// `NagaPro::SIDE_PLATES` and `NagaTrinity::SIDE_PLATES` for which fits
+    /// which
+    #[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+    pub enum SidePlate {
+        TwoButton,
+        SixButton,
+        /// The Naga Trinity's wheel of 7
+        SevenButton,
+        /// The MMO plate, the only lit one
+        #[default]
+        TwelveButton,
+    }
+
+    impl SidePlate {
+        pub fn iter() -> impl Iterator<Item = Self> {
+            [SidePlate::TwoButton, SidePlate::SixButton, SidePlate::SevenButton,
+                SidePlate::TwelveButton].into_iter()
+        }
+
+        pub fn buttons(&self) -> usize {
+            match self {
+                SidePlate::TwoButton => 2,
+                SidePlate::SixButton => 6,
+                SidePlate::SevenButton => 7,
+                SidePlate::TwelveButton => 12,
+            }
+        }
+
+        /// The buttons of a mouse with this plate attached; the 2-button
+        /// plate's are the usual back and forward
+        pub fn remappable(&self) -> Vec<Button> {
+            let mut buttons = Button::standard();
+            if *self != SidePlate::TwoButton {
+                buttons.retain(|&button| button != Button::Back && button != Button::Forward);
+                buttons.extend(Button::side(self.buttons()));
+            }
+            buttons
+        }
+
+        /// (rows, columns) Of custom frames on a Naga: the scroll wheel, the
+        /// logo and, if lit, the plate
+        pub fn matrix(&self) -> (usize, usize) {
+            (1, 2 + self.leds().len())
+        }
+
+        /// The LEDs the plate adds to the mouse
+        pub fn leds(&self) -> &'static [Led] {
+            match self {
+                SidePlate::TwelveButton => &[Led::Backlight],
+                _ => &[],
+            }
+        }
+    }
+
+    impl Display for SidePlate {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{}-button", self.buttons())
         }
     }
 
@@ -194,19 +801,20 @@ pub mod common {
     }
 
     impl RazerReport {
-        fn init(cmd_cls: u8, cmd_id: u8, data_size: u8) -> Self {
+        fn init(cmd: Command, data_size: u8) -> Self {
             Self {
-                command_class: cmd_cls,
-                command_id: cmd_id,
+                command_class: cmd.class,
+                command_id: cmd.id,
                 data_size: data_size,
                 ..Default::default()
             }
         }
 
-        fn new(cmd_cls: u8, cmd_id: u8, args: &[u8]) -> Self {
+        /// A request for `cmd` with these arguments (at most 80 bytes)
+        pub(crate) fn new(cmd: Command, args: &[u8]) -> Self {
             let mut r = Self {
-                command_class: cmd_cls,
-                command_id: cmd_id,
+                command_class: cmd.class,
+                command_id: cmd.id,
                 data_size: args.len() as u8,
                 ..Default::default()
             };
@@ -214,7 +822,47 @@ pub mod common {
             r
         }
 
-        fn update_crc(&mut self) -> &mut Self {
+        /// The command class/id pair of this report
+        pub fn command(&self) -> Command {
+            Command::new(self.command_class, self.command_id)
+        }
+
+        /// The response a device would give to this request: the same
+        /// header, with `status` and these arguments (at most 80 bytes)
+        pub(crate) fn answer(&self, status: CmdStatus, args: &[u8]) -> Self {
+            let mut r = *self;
+            r.status = status as u8;
+            r.data_size = r.data_size.max(args.len() as u8);
+            r.arguments = [0u8; 80];
+            r.arguments[..args.len()].copy_from_slice(args);
+            r.update_crc();
+            r
+        }
+
+        /// Whether this is the response to `request` rather than one left
+        /// over from an earlier request: same transaction, command and packet
+        pub(crate) fn answers(&self, request: &RazerReport) -> bool {
+            self.transaction_id == request.transaction_id &&
+                self.remaining_packets == request.remaining_packets &&
+                self.command_class == request.command_class &&
+                self.command_id == request.command_id
+        }
+
+        pub fn status(&self) -> u8 {
+            self.status
+        }
+
+        pub fn transaction_id(&self) -> u8 {
+            self.transaction_id
+        }
+
+        /// The arguments actually in use, i.e. the first `data_size` bytes
+        pub fn args(&self) -> &[u8] {
+            let len = (self.data_size as usize).min(self.arguments.len());
+            &self.arguments[..len]
+        }
+
+        pub(crate) fn update_crc(&mut self) -> &mut Self {
             let s = self.bytes();
 
             self.crc = s[2..88].iter().fold(0, |crc, x| crc ^ x);
@@ -272,7 +920,7 @@ pub mod common {
         }
 
         /// Converts to network byte order and returns a copy as_slice
-        fn pack(self) -> Vec<u8> {
+        pub(crate) fn pack(self) -> Vec<u8> {
             self.to_network_byte_order().bytes().into()
         }
 
@@ -284,7 +932,7 @@ pub mod common {
         }
 
         /// Construct from slice and return a copy in host byte order
-        fn unpack(buffer: &[u8]) -> Option<Self> {
+        pub(crate) fn unpack(buffer: &[u8]) -> Option<Self> {
             match Self::from(buffer) {
                 Some(rep) => Some(rep.to_host_byte_order()),
                 None => None
@@ -293,72 +941,104 @@ pub mod common {
 
     }
 
-    fn razer_send_control_msg<C: UsbContext>(
-        usb_dev: &DeviceHandle<C>,
+    /// Between reads of a response the device hasn't finished yet
+    const USB_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+    fn razer_send_control_msg(
+        transport: &dyn Transport,
         data: &RazerReport,
         report_index: u16
     ) -> USBResult<usize> {
-        let request = 0x09u8; // HID_REQ_SET_REPORT
-        let request_type = 0x21u8; // USB_TYPE_CLASS | USB_RECIP_INTERFACE | USB_DIR_OUT
-        let value = 0x300u16;
+        transport.send_report(&data.pack(), report_index)
+    }
 
-        let written = usb_dev.write_control(
-                                    request_type, request, value, report_index,
-                                    &data.pack(), USB_TXFER_TIMEOUT)?;
+    fn razer_read_response(
+        transport: &dyn Transport,
+        response_index: u16
+    ) -> USBResult<RazerReport> {
+        let mut buffer = [0u8; size_of::<RazerReport>()];
+        let read = transport.get_report(&mut buffer, response_index)?;
+        if read != size_of::<RazerReport>() {
+            return Err(USBError::IncompleteRead(
+                        size_of::<RazerReport>(), read));
+        }
 
-        // wait here otherwise we fail on any subsequent HID_REQ_GET_REPORTs
-        thread::sleep(USB_RECEIVER_WAIT);
+        // RazerReport::from() won't fail with this buf
+        Ok(RazerReport::unpack(&buffer).unwrap())
+    }
+
+    /// Ends the transaction on the transport however it went
+    struct Transaction<'a>(&'a dyn Transport);
 
-        Ok(written)
+    impl<'a> Transaction<'a> {
+        fn begin(transport: &'a dyn Transport) -> Self {
+            transport.begin_transaction();
+            Self(transport)
+        }
     }
 
-    fn razer_get_usb_response<C: UsbContext>(
-        usb_dev: &DeviceHandle<C>,
+    impl Drop for Transaction<'_> {
+        fn drop(&mut self) {
+            self.0.end_transaction();
+        }
+    }
+
+    /// Polls for the response until the device is done with the request,
+    /// for up to `wait`. If it still isn't (or the reads kept failing), falls
+    /// back to waiting `wait` once more before a last read, as some devices
+    /// fail any HID_REQ_GET_REPORT that comes too early.
+    fn razer_get_usb_response(
+        transport: &dyn Transport,
         report_index: u16,
         request_report: &RazerReport,
-        response_index: u16
+        response_index: u16,
+        wait: Duration
     ) -> USBResult<RazerReport> {
+        let _transaction = Transaction::begin(transport);
         let written = razer_send_control_msg(
-                                usb_dev, request_report, report_index)?;
+                                transport, request_report, report_index)?;
         if written != size_of_val(request_report) {
             return Err(USBError::IncompleteWrite(
                         size_of_val(request_report), written));
         }
 
-        let request = 0x01u8; // HID_REQ_GET_REPORT
-        let request_type = 0xA1u8; // USB_TYPE_CLASS | USB_RECIP_INTERFACE | USB_DIR_IN
-        let value = 0x300u16;
-        let mut buffer = [0u8; size_of::<RazerReport>()];
-        let read = usb_dev.read_control(
-                            request_type, request, value, response_index,
-                            &mut buffer, USB_TXFER_TIMEOUT)?;
-        if read != size_of::<RazerReport>() {
-            return Err(USBError::IncompleteRead(
-                        size_of::<RazerReport>(), read));
+        let deadline = Instant::now() + wait;
+        loop {
+            thread::sleep(USB_POLL_INTERVAL.min(wait));
+            match razer_read_response(transport, response_index) {
+                // 0x00: not picked up by the firmware yet; a response to
+                // another request: ours hasn't replaced it yet
+                Ok(response) if response.answers(request_report) &&
+                    response.status != 0x00 &&
+                    response.status != CmdStatus::Busy as u8 => return Ok(response),
+                _ if Instant::now() < deadline => continue,
+                _ => break,
+            }
         }
 
-        // RazerReport::from() won't fail with this buf
-        Ok(RazerReport::unpack(&buffer).unwrap())
+        thread::sleep(wait);
+        razer_read_response(transport, response_index)
     }
 
-    fn razer_get_report<C: UsbContext>(
-        usb_dev: &DeviceHandle<C>,
-        request: &RazerReport
+    fn razer_get_report(
+        transport: &dyn Transport,
+        request: &RazerReport,
+        index: u16,
+        wait: Duration
     ) -> USBResult<RazerReport> {
-        let index = 0u16;
-        razer_get_usb_response(usb_dev, index, request, index)
+        razer_get_usb_response(transport, index, request, index, wait)
     }
 
-    pub(crate) fn razer_send_payload<C: UsbContext>(
-        usb_dev: &DeviceHandle<C>,
-        request: &mut RazerReport
+    pub(crate) fn razer_send_payload(
+        transport: &dyn Transport,
+        request: &mut RazerReport,
+        index: u16,
+        wait: Duration
     ) -> USBResult<RazerReport> {
         request.update_crc();
-        let response = razer_get_report(usb_dev, request)?;
+        let response = razer_get_report(transport, request, index, wait)?;
 
-        if response.remaining_packets != request.remaining_packets ||
-            response.command_class != request.command_class ||
-            response.command_id != request.command_id {
+        if !response.answers(request) {
             return Err(USBError::ResponseMismatch);
         }
 
@@ -373,11 +1053,115 @@ pub mod common {
     }
 
     pub(crate) fn razer_chroma_standard_get_serial() -> RazerReport {
-        RazerReport::init(0x00, 0x82, 0x16)
+        RazerReport::init(commands::GET_SERIAL, 0x16)
+    }
+
+    pub(crate) fn razer_chroma_standard_get_device_mode() -> RazerReport {
+        RazerReport::init(commands::GET_DEVICE_MODE, 0x02)
+    }
+
+    pub(crate) fn razer_chroma_standard_set_device_mode(mode: DeviceMode) -> RazerReport {
+        RazerReport::new(commands::SET_DEVICE_MODE, &[mode as u8, 0x00])
+    }
+
+    pub(crate) fn razer_chroma_standard_set_led_state(
+        variable_storage: LedStorage,
+        led: Led,
+        on: bool,
+    ) -> RazerReport {
+        RazerReport::new(commands::SET_LED_STATE, &[
+            variable_storage as u8,
+            led as u8,
+            on as u8,
+        ])
+    }
+
+    pub(crate) fn razer_chroma_standard_set_led_rgb(
+        variable_storage: LedStorage,
+        led: Led,
+        rgb: RGB8,
+    ) -> RazerReport {
+        RazerReport::new(commands::SET_LED_RGB, &[
+            variable_storage as u8,
+            led as u8,
+            rgb.r, rgb.g, rgb.b,
+        ])
+    }
+
+    pub(crate) fn razer_chroma_standard_set_led_effect(
+        variable_storage: LedStorage,
+        led: Led,
+        effect: ClassicLedEffect,
+    ) -> RazerReport {
+        RazerReport::new(commands::SET_LED_EFFECT, &[
+            variable_storage as u8,
+            led as u8,
+            effect as u8,
+        ])
+    }
+
+    /// The classic way to set an effect: the color (if it takes one), the
+    /// effect, then the LED on, which the effect alone doesn't do
+    pub(crate) fn razer_chroma_standard_effect_reports(
+        variable_storage: LedStorage,
+        led: Led,
+        effect: ClassicLedEffect,
+        rgb: Option<RGB8>,
+    ) -> Vec<RazerReport> {
+        rgb.map(|rgb| razer_chroma_standard_set_led_rgb(variable_storage, led, rgb)).into_iter()
+            .chain([
+                razer_chroma_standard_set_led_effect(variable_storage, led, effect),
+                razer_chroma_standard_set_led_state(variable_storage, led, true),
+            ])
+            .collect()
+    }
+
+    pub(crate) fn razer_chroma_standard_set_led_brightness(
+        variable_storage: LedStorage,
+        led: Led,
+        brightness: u8,
+    ) -> RazerReport {
+        RazerReport::new(commands::SET_LED_BRIGHTNESS, &[
+            variable_storage as u8,
+            led as u8,
+            brightness, // in the [0-255] range
+        ])
+    }
+
+    pub(crate) fn razer_chroma_standard_get_led_brightness(
+        variable_storage: LedStorage,
+        led: Led,
+    ) -> RazerReport {
+        RazerReport::new(commands::GET_LED_BRIGHTNESS, &[
+            variable_storage as u8,
+            led as u8,
+            0x00, // brightness
+        ])
+    }
+
+    pub(crate) fn razer_chroma_misc_get_battery_level() -> RazerReport {
+        RazerReport::init(commands::GET_BATTERY_LEVEL, 0x02)
+    }
+
+    pub(crate) fn razer_chroma_misc_get_charging_status() -> RazerReport {
+        RazerReport::init(commands::GET_CHARGING_STATUS, 0x02)
+    }
+
+    pub(crate) fn razer_chroma_misc_get_idle_time() -> RazerReport {
+        RazerReport::init(commands::GET_IDLE_TIME, 0x02)
+    }
+
+    pub(crate) fn razer_chroma_misc_get_paired_device() -> RazerReport {
+        RazerReport::init(commands::GET_PAIRED_DEVICE, 0x03)
+    }
+
+    pub(crate) fn razer_chroma_misc_set_idle_time(idle_time: Duration) -> RazerReport {
+        let secs = idle_time.clamp(*IDLE_TIMES.start(), *IDLE_TIMES.end()).as_secs() as u16;
+        RazerReport::new(commands::SET_IDLE_TIME, &secs.to_be_bytes())
     }
 
     pub(crate) fn razer_chroma_misc_get_dpi_xy(variable_storage: LedStorage) -> RazerReport {
-        let mut report = RazerReport::init(0x04, 0x85, 0x07);
+        let mut report = RazerReport::init(commands::GET_DPI_XY, 0x07);
         report.arguments[0] = variable_storage as u8;
         report
     }
@@ -387,10 +1171,8 @@ pub mod common {
         dpi_x: u16,
         dpi_y: u16
     ) -> RazerReport {
-        // Keep the DPI within bounds
-        let dpi_x = dpi_x.clamp(100, 30000);
-        let dpi_y = dpi_y.clamp(100, 30000);
-        RazerReport::new(0x04, 0x05, &[
+        // bounds depend on the device; see RazerMouse::min_dpi()/max_dpi()
+        RazerReport::new(commands::SET_DPI_XY, &[
             variable_storage as u8,
             ((dpi_x >> 8) & 0xFF) as u8,
             (dpi_x & 0xFF) as u8,
@@ -402,21 +1184,25 @@ pub mod common {
     }
 
     pub(crate) fn razer_chroma_misc_get_dpi_xy_stages(variable_storage: LedStorage) -> RazerReport {
-        RazerReport::new(0x04, 0x86, &[variable_storage as u8])
+        RazerReport::new(commands::GET_DPI_STAGES, &[variable_storage as u8])
     }
 
     pub(crate) fn razer_chroma_misc_set_dpi_xy_stages(
         variable_storage: LedStorage,
         dpi_stages: &[(u16, u16)],
         current: u8
-    ) -> RazerReport {
+    ) -> Result<RazerReport, DpiStagesError> {
         let num_stages = dpi_stages.len();
-        assert!(num_stages > 0, "num stages must be greater than 0");
-        assert!(num_stages < 6, "num stages cannot be more than 5");
+        if num_stages == 0 {
+            return Err(DpiStagesError::Empty);
+        }
+        if num_stages > MAX_DPI_STAGES {
+            return Err(DpiStagesError::TooMany(MAX_DPI_STAGES));
+        }
         let num_stages = num_stages as u8;
 
         let current = current.clamp(1, num_stages);
-        let mut report = RazerReport::init(0x04, 0x06, 3 + num_stages * 7);
+        let mut report = RazerReport::init(commands::SET_DPI_STAGES, 3 + num_stages * 7);
         report.arguments[0] = variable_storage as u8;
         report.arguments[1] = current;
         report.arguments[2] = num_stages;
@@ -425,10 +1211,6 @@ pub mod common {
         let mut stage_idx = 1;
 
         for &(dpi_x, dpi_y) in dpi_stages {
-            // Keep the DPI within bounds
-            let dpi_x = dpi_x.clamp(100, 30000);
-            let dpi_y = dpi_y.clamp(100, 30000);
-
             report.arguments[report_idx+0] = stage_idx;
             report.arguments[report_idx+1] = ((dpi_x >> 8) & 0xFF) as u8;
             report.arguments[report_idx+2] = (dpi_x & 0xFF) as u8;
@@ -441,26 +1223,109 @@ pub mod common {
             report_idx += 7;
         }
 
-        report
+        Ok(report)
     }
 
     pub(crate) fn razer_chroma_misc_get_polling_rate() -> RazerReport {
-        RazerReport::init(0x00, 0x85, 0x01)
+        RazerReport::init(commands::GET_POLLING_RATE, 0x01)
     }
 
     pub(crate) fn razer_chroma_misc_set_polling_rate(polling_rate: PollingRate) -> RazerReport {
-        RazerReport::new(0x00, 0x05, &[
+        RazerReport::new(commands::SET_POLLING_RATE, &[
             polling_rate as u8,
         ])
     }
 
+    pub(crate) fn razer_chroma_misc_get_scroll_mode() -> RazerReport {
+        RazerReport::new(commands::GET_SCROLL_MODE, &[LedStorage::VarStore as u8, 0x00])
+    }
+
+    pub(crate) fn razer_chroma_misc_set_scroll_mode(mode: ScrollMode) -> RazerReport {
+        RazerReport::new(commands::SET_SCROLL_MODE, &[
+            LedStorage::VarStore as u8,
+            mode as u8,
+        ])
+    }
+
+    pub(crate) fn razer_chroma_misc_get_liftoff() -> RazerReport {
+        RazerReport::new(commands::GET_LIFTOFF, &[LedStorage::VarStore as u8, 0x00])
+    }
+
+    pub(crate) fn razer_chroma_misc_set_liftoff(liftoff: LiftOff) -> RazerReport {
+        RazerReport::new(commands::SET_LIFTOFF, &[
+            LedStorage::VarStore as u8,
+            liftoff as u8,
+        ])
+    }
+
+    pub(crate) fn razer_chroma_misc_get_button_function(
+        layer: Layer,
+        button: Button
+    ) -> RazerReport {
+        let mut report = RazerReport::init(commands::GET_BUTTON_FUNCTION, 0x0A);
+        report.arguments[0] = LedStorage::VarStore as u8;
+        report.arguments[1] = button as u8;
+        report.arguments[2] = layer as u8;
+        report
+    }
+
+    pub(crate) fn razer_chroma_misc_set_button_function(
+        layer: Layer,
+        button: Button,
+        function: ButtonFunction
+    ) -> RazerReport {
+        let (kind, params) = function.to_bytes();
+        let mut args = vec![
+            LedStorage::VarStore as u8,
+            button as u8,
+            layer as u8,
+            kind,
+            params.len() as u8,
+        ];
+        args.extend(params);
+        RazerReport::new(commands::SET_BUTTON_FUNCTION, &args)
+    }
+
+    /// The reports that store `m` as macro `id`: its size, then the data in
+    /// chunks
+    pub(crate) fn razer_chroma_misc_macro_reports(id: u16, m: &Macro) -> Vec<RazerReport> {
+        let data = m.to_bytes();
+        let mut args = id.to_be_bytes().to_vec();
+        args.extend((data.len() as u32).to_be_bytes());
+        let mut reports = vec![RazerReport::new(commands::SET_MACRO_INFO, &args)];
+
+        for (i, chunk) in data.chunks(MACRO_CHUNK).enumerate() {
+            let mut args = id.to_be_bytes().to_vec();
+            args.extend(((i * MACRO_CHUNK) as u32).to_be_bytes());
+            args.push(chunk.len() as u8);
+            args.extend(chunk);
+            reports.push(RazerReport::new(commands::SET_MACRO_DATA, &args));
+        }
+        reports
+    }
+
+    pub(crate) fn razer_chroma_misc_delete_macro(id: u16) -> RazerReport {
+        RazerReport::new(commands::DELETE_MACRO, &id.to_be_bytes())
+    }
+
+    pub(crate) fn razer_chroma_misc_get_scroll_smart_reel() -> RazerReport {
+        RazerReport::new(commands::GET_SCROLL_SMART_REEL, &[LedStorage::VarStore as u8, 0x00])
+    }
+
+    pub(crate) fn razer_chroma_misc_set_scroll_smart_reel(on: bool) -> RazerReport {
+        RazerReport::new(commands::SET_SCROLL_SMART_REEL, &[
+            LedStorage::VarStore as u8,
+            on as u8,
+        ])
+    }
+
     pub(crate) fn razer_naga_trinity_effect_static(
         variable_storage: LedStorage,
         effect: LedEffect,
         logo_rgb: RGB8,
         scroll_rgb: RGB8,
     ) -> RazerReport {
-        RazerReport::new(0x0f, 0x03, &[
+        RazerReport::new(commands::SET_NAGA_TRINITY_EFFECT, &[
             variable_storage as u8,
             0x00, // LED ID ?
             0x00, // Unknown
@@ -477,14 +1342,13 @@ pub mod common {
         led: Led,
         effect: LedEffect,
     ) -> RazerReport {
-        let mut report = RazerReport::init(0x0f, 0x02, arg_size);
+        let mut report = RazerReport::init(commands::SET_EXTENDED_MATRIX_EFFECT, arg_size);
         report.arguments[0] = variable_storage as u8;
         report.arguments[1] = led as u8;
         report.arguments[2] = effect as u8;
         report
     }
 
-    #[allow(dead_code)]
     pub(crate) fn razer_chroma_extended_matrix_effect_none(
         variable_storage: LedStorage,
         led: Led,
@@ -493,6 +1357,14 @@ pub mod common {
             0x06, variable_storage, led, LedEffect::None)
     }
 
+    pub(crate) fn razer_chroma_extended_matrix_effect_spectrum(
+        variable_storage: LedStorage,
+        led: Led,
+    ) -> RazerReport {
+        razer_chroma_extended_matrix_effect_base(
+            0x03, variable_storage, led, LedEffect::Spectrum)
+    }
+
     pub(crate) fn razer_chroma_extended_matrix_effect_static(
         variable_storage: LedStorage,
         led: Led,
@@ -507,26 +1379,310 @@ pub mod common {
         report
     }
 
+    pub(crate) fn razer_chroma_extended_matrix_effect_breathing_random(
+        variable_storage: LedStorage,
+        led: Led,
+    ) -> RazerReport {
+        razer_chroma_extended_matrix_effect_base(
+            0x06, variable_storage, led, LedEffect::Breathing)
+    }
+
+    pub(crate) fn razer_chroma_extended_matrix_effect_breathing_single(
+        variable_storage: LedStorage,
+        led: Led,
+        rgb: RGB8,
+    ) -> RazerReport {
+        let mut report = razer_chroma_extended_matrix_effect_base(
+            0x09, variable_storage, led, LedEffect::Breathing);
+        report.arguments[3] = 0x01;
+        report.arguments[5] = 0x01;
+        report.arguments[6] = rgb.r;
+        report.arguments[7] = rgb.g;
+        report.arguments[8] = rgb.b;
+        report
+    }
+
+    pub(crate) fn razer_chroma_extended_matrix_effect_breathing_dual(
+        variable_storage: LedStorage,
+        led: Led,
+        rgb1: RGB8,
+        rgb2: RGB8,
+    ) -> RazerReport {
+        let mut report = razer_chroma_extended_matrix_effect_base(
+            0x0C, variable_storage, led, LedEffect::Breathing);
+        report.arguments[3] = 0x02;
+        report.arguments[5] = 0x02;
+        report.arguments[6] = rgb1.r;
+        report.arguments[7] = rgb1.g;
+        report.arguments[8] = rgb1.b;
+        report.arguments[9] = rgb2.r;
+        report.arguments[10] = rgb2.g;
+        report.arguments[11] = rgb2.b;
+        report
+    }
+
+    /// `speed` is how long the LED stays lit after a click, from 1 (shortest)
+    /// to 4 (longest)
+    pub(crate) fn razer_chroma_extended_matrix_effect_reactive(
+        variable_storage: LedStorage,
+        led: Led,
+        speed: u8,
+        rgb: RGB8,
+    ) -> RazerReport {
+        let mut report = razer_chroma_extended_matrix_effect_base(
+            0x09, variable_storage, led, LedEffect::Reactive);
+        report.arguments[4] = speed.clamp(*REACTIVE_SPEEDS.start(), *REACTIVE_SPEEDS.end());
+        report.arguments[5] = 0x01;
+        report.arguments[6] = rgb.r;
+        report.arguments[7] = rgb.g;
+        report.arguments[8] = rgb.b;
+        report
+    }
+
+    /// Show the custom frame last sent; see `razer_chroma_extended_matrix_set_custom_frame()`
+    pub(crate) fn razer_chroma_extended_matrix_effect_custom_frame() -> RazerReport {
+        razer_chroma_extended_matrix_effect_base(
+            0x06, LedStorage::NoStore, Led::Zero, LedEffect::CustomFrame)
+    }
+
+    /// One row (or part of one, from `start_col`) of a custom frame, with at
+    /// most `MAX_FRAME_COLUMNS` colors. `remaining_packets` is how many more
+    /// reports of the frame follow this one.
+    pub(crate) fn razer_chroma_extended_matrix_set_custom_frame(
+        row_index: u8,
+        start_col: u8,
+        rgb: &[RGB8],
+        remaining_packets: u16,
+    ) -> RazerReport {
+        const START_ARG_OFFSET: usize = 5;
+        let rgb = &rgb[..rgb.len().min(MAX_FRAME_COLUMNS)];
+        let row_length = rgb.len() * 3;
+        let mut report = RazerReport::init(commands::SET_EXTENDED_MATRIX_FRAME,
+            (START_ARG_OFFSET + row_length).max(0x47) as u8);
+        report.remaining_packets = remaining_packets;
+        report.arguments[2] = row_index;
+        report.arguments[3] = start_col;
+        report.arguments[4] = start_col + rgb.len().saturating_sub(1) as u8;
+        report.arguments[START_ARG_OFFSET..START_ARG_OFFSET + row_length]
+            .copy_from_slice(rgb.as_bytes());
+        report
+    }
+
+    /// The reports for one row of a custom frame, from its first column;
+    /// split like `razer_chroma_extended_matrix_custom_frame_reports()` does
+    pub(crate) fn razer_chroma_extended_matrix_set_custom_frame_row(
+        row_index: u8,
+        rgb: &[RGB8],
+    ) -> Vec<RazerReport> {
+        let total = rgb.chunks(MAX_FRAME_COLUMNS).len();
+        rgb.chunks(MAX_FRAME_COLUMNS).enumerate()
+            .map(|(i, chunk)| razer_chroma_extended_matrix_set_custom_frame(
+                row_index, (i * MAX_FRAME_COLUMNS) as u8, chunk, (total - i - 1) as u16))
+            .collect()
+    }
+
+    /// The reports for a whole frame, `rows` of colors from the top left;
+    /// rows longer than `MAX_FRAME_COLUMNS` are split
+    pub(crate) fn razer_chroma_extended_matrix_custom_frame_reports(
+        rows: &[Vec<RGB8>],
+    ) -> Vec<RazerReport> {
+        let chunks: Vec<(usize, usize, &[RGB8])> = rows.iter().enumerate()
+            .flat_map(|(row, colors)| colors.chunks(MAX_FRAME_COLUMNS).enumerate()
+                .map(move |(i, chunk)| (row, i * MAX_FRAME_COLUMNS, chunk)))
+            .collect();
+        let total = chunks.len();
+        chunks.into_iter().enumerate()
+            .map(|(i, (row, start_col, chunk))| razer_chroma_extended_matrix_set_custom_frame(
+                row as u8, start_col as u8, chunk, (total - i - 1) as u16))
+            .collect()
+    }
+
+    /// The report for `breathing` on `led`
+    pub(crate) fn razer_chroma_extended_matrix_effect_breathing(
+        variable_storage: LedStorage,
+        led: Led,
+        breathing: Breathing,
+    ) -> RazerReport {
+        match breathing {
+            Breathing::Single(rgb) =>
+                razer_chroma_extended_matrix_effect_breathing_single(variable_storage, led, rgb),
+            Breathing::Dual(rgb1, rgb2) => razer_chroma_extended_matrix_effect_breathing_dual(
+                variable_storage, led, rgb1, rgb2),
+            Breathing::Random =>
+                razer_chroma_extended_matrix_effect_breathing_random(variable_storage, led),
+        }
+    }
+
     pub(crate) fn razer_chroma_extended_matrix_brightness(
         variable_storage: LedStorage,
         led: Led,
         brightness: u8,
     ) -> RazerReport {
-        RazerReport::new(0x0F, 0x04, &[
+        RazerReport::new(commands::SET_EXTENDED_MATRIX_BRIGHTNESS, &[
             variable_storage as u8,
             led as u8,
             brightness, // in the [0-255] range
         ])
     }
 
+    pub(crate) fn razer_chroma_extended_matrix_set_channel_size(
+        variable_storage: LedStorage,
+        led: Led,
+        size: u8,
+    ) -> RazerReport {
+        RazerReport::new(commands::SET_ARGB_CHANNEL_SIZE, &[
+            variable_storage as u8,
+            led as u8,
+            size.min(*ARGB_CHANNEL_SIZES.end()),
+        ])
+    }
+
+    pub(crate) fn razer_chroma_extended_matrix_get_channel_size(
+        variable_storage: LedStorage,
+        led: Led,
+    ) -> RazerReport {
+        RazerReport::new(commands::GET_ARGB_CHANNEL_SIZE, &[
+            variable_storage as u8,
+            led as u8,
+            0x00, // size
+        ])
+    }
+
     pub(crate) fn razer_chroma_extended_matrix_get_brightness(
         variable_storage: LedStorage,
         led: Led,
     ) -> RazerReport {
-        RazerReport::new(0x0F, 0x84, &[
+        RazerReport::new(commands::GET_EXTENDED_MATRIX_BRIGHTNESS, &[
             variable_storage as u8,
             led as u8,
             0x00, // brightness
         ])
     }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use std::sync::atomic::AtomicUsize;
+        use crate::mock::{MockReply, MockTransport};
+
+        #[test]
+        fn kelvin_goes_from_red_to_white_to_blue() {
+            assert_eq!(rgb_from_kelvin(1000), RGB8::new(255, 68, 0));
+            assert_eq!(rgb_from_kelvin(6600), RGB8::new(255, 255, 255));
+            let cool = rgb_from_kelvin(20000);
+            assert!(cool.b == 255 && cool.r < cool.b);
+            // clamped to the range
+            assert_eq!(rgb_from_kelvin(500), rgb_from_kelvin(1000));
+            assert_eq!(rgb_from_kelvin(50000), rgb_from_kelvin(40000));
+        }
+
+        #[test]
+        fn color_strings_take_presets_and_temperatures() {
+            assert_eq!(rgb_from_str("Warm").unwrap(), WhitePreset::Warm.rgb());
+            assert_eq!(rgb_from_str("4000K").unwrap(), rgb_from_kelvin(4000));
+            assert_eq!(rgb_from_str("#f00").unwrap(), RGB8::new(255, 0, 0));
+            assert!(rgb_from_str("hot").is_err());
+        }
+
+        #[test]
+        fn utc_string_counts_leap_days() {
+            assert_eq!(utc_string(0), "1970-01-01 00:00:00 UTC");
+            assert_eq!(utc_string(951782400), "2000-02-29 00:00:00 UTC");
+            assert_eq!(utc_string(1700000000), "2023-11-14 22:13:20 UTC");
+            assert_eq!(utc_string(4102444799), "2099-12-31 23:59:59 UTC");
+        }
+
+        #[test]
+        fn receiver_wait_shrinks_after_a_run_of_successes() {
+            let wait = ReceiverWait::new(Duration::from_millis(10));
+            for _ in 1..ReceiverWait::SHRINK_AFTER {
+                wait.update(&Ok(()));
+            }
+            assert_eq!(wait.get(), Duration::from_millis(10));
+            wait.update(&Ok(()));
+            assert_eq!(wait.get(), Duration::from_millis(9));
+
+            // and starts counting again
+            wait.update(&Ok(()));
+            assert_eq!(wait.get(), Duration::from_millis(9));
+        }
+
+        #[test]
+        fn receiver_wait_grows_on_timing_errors_only() {
+            let wait = ReceiverWait::new(Duration::from_millis(10));
+            wait.update::<()>(&Err(USBError::CommandFailed));
+            wait.update::<()>(&Err(USBError::Vetoed(String::from("test"))));
+            assert_eq!(wait.get(), Duration::from_millis(10));
+
+            wait.update::<()>(&Err(USBError::DeviceBusy));
+            assert_eq!(wait.get(), Duration::from_millis(15));
+            for _ in 0..10 {
+                wait.update::<()>(&Err(USBError::CommandTimeout));
+            }
+            assert_eq!(wait.get(), ReceiverWait::MAX);
+        }
+
+        #[test]
+        fn receiver_wait_stays_put_unless_adaptive() {
+            let wait = ReceiverWait::new(Duration::from_millis(10));
+            wait.set_adaptive(false);
+            wait.update::<()>(&Err(USBError::DeviceBusy));
+            assert_eq!(wait.get(), Duration::from_millis(10));
+            assert_eq!(ReceiverWait::new(Duration::ZERO).get(), ReceiverWait::MIN);
+        }
+
+        #[test]
+        fn polling_rate_parses_with_or_without_unit() {
+            assert_eq!("1000".parse::<PollingRate>().unwrap(), PollingRate::Hz1000);
+            assert_eq!("500Hz".parse::<PollingRate>().unwrap(), PollingRate::Hz500);
+            assert_eq!(" 250 hz ".parse::<PollingRate>().unwrap(), PollingRate::Hz250);
+            for rate in PollingRate::iter() {
+                assert_eq!(rate.to_string().parse::<PollingRate>().unwrap(), rate);
+            }
+        }
+
+        #[test]
+        fn polling_rate_rejects_other_rates() {
+            assert!("300".parse::<PollingRate>().is_err());
+            assert!("fast".parse::<PollingRate>().is_err());
+            assert!("".parse::<PollingRate>().is_err());
+        }
+
+        /// A `MockTransport` that checks every transfer is in a transaction
+        struct InTransaction(MockTransport, AtomicUsize);
+
+        impl Transport for InTransaction {
+            fn send_report(&self, report: &[u8], index: u16) -> USBResult<usize> {
+                assert_eq!(self.1.load(Ordering::Relaxed), 1);
+                self.0.send_report(report, index)
+            }
+
+            fn get_report(&self, buffer: &mut [u8], index: u16) -> USBResult<usize> {
+                assert_eq!(self.1.load(Ordering::Relaxed), 1);
+                self.0.get_report(buffer, index)
+            }
+
+            fn begin_transaction(&self) {
+                self.1.fetch_add(1, Ordering::Relaxed);
+            }
+
+            fn end_transaction(&self) {
+                self.1.fetch_sub(1, Ordering::Relaxed);
+            }
+        }
+
+        #[test]
+        fn transfers_are_held_in_a_transaction() {
+            let transport = InTransaction(MockTransport::new()
+                .expect(commands::GET_SERIAL, MockReply::Echo), AtomicUsize::new(0));
+            let wait = Duration::from_millis(1);
+            razer_send_payload(&transport, &mut razer_chroma_standard_get_serial(), 0, wait).unwrap();
+            assert_eq!(transport.1.load(Ordering::Relaxed), 0);
+
+            // and ended when the request fails
+            let res = razer_send_payload(&transport, &mut razer_chroma_standard_get_serial(), 0, wait);
+            assert!(matches!(res, Err(USBError::ReplayExhausted)));
+            assert_eq!(transport.1.load(Ordering::Relaxed), 0);
+        }
+    }
 }
@@ -0,0 +1,143 @@
+//! Effects the firmware runs on its own, described with one builder instead
+//! of a method per effect and LED (see `effects` for the ones computed here):
+//!
+//! ```ignore
+//! let effect = Effect::breathing().colors(a, b).on(Led::Logo).store(LedStorage::NoStore);
+//! device.set_effect(&effect)?;
+//! ```
+
+use rgb::RGB8;
+
+use crate::common::*;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Kind {
+    None,
+    Static(RGB8),
+    Breathing(Breathing),
+    Spectrum,
+    Reactive(RGB8),
+}
+
+/// An effect on one LED. Settings the effect doesn't take (e.g. a speed
+/// for breathing) are ignored.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Effect {
+    kind: Kind,
+    speed: u8,
+    led: Led,
+    storage: LedStorage,
+}
+
+impl Effect {
+    fn new(kind: Kind) -> Self {
+        Self {
+            kind: kind,
+            speed: *REACTIVE_SPEEDS.start(),
+            led: Led::Logo,
+            storage: LedStorage::VarStore,
+        }
+    }
+
+    /// The LED off
+    pub fn none() -> Self {
+        Self::new(Kind::None)
+    }
+
+    pub fn static_color(color: RGB8) -> Self {
+        Self::new(Kind::Static(color))
+    }
+
+    /// In random colors, unless given some with `color()` or `colors()`
+    pub fn breathing() -> Self {
+        Self::new(Kind::Breathing(Breathing::Random))
+    }
+
+    pub(crate) fn breathing_in(breathing: Breathing) -> Self {
+        Self::new(Kind::Breathing(breathing))
+    }
+
+    pub fn spectrum() -> Self {
+        Self::new(Kind::Spectrum)
+    }
+
+    /// Lights up in `color` on clicks, for as long as `speed()` says
+    pub fn reactive(color: RGB8) -> Self {
+        Self::new(Kind::Reactive(color))
+    }
+
+    /// The color of a static, breathing or reactive effect
+    pub fn color(mut self, color: RGB8) -> Self {
+        self.kind = match self.kind {
+            Kind::Static(_) => Kind::Static(color),
+            Kind::Breathing(_) => Kind::Breathing(Breathing::Single(color)),
+            Kind::Reactive(_) => Kind::Reactive(color),
+            kind => kind,
+        };
+        self
+    }
+
+    /// The two colors breathing alternates between
+    pub fn colors(mut self, first: RGB8, second: RGB8) -> Self {
+        if let Kind::Breathing(_) = self.kind {
+            self.kind = Kind::Breathing(Breathing::Dual(first, second));
+        }
+        self
+    }
+
+    /// How long a reactive effect stays lit; see `REACTIVE_SPEEDS`
+    pub fn speed(mut self, speed: u8) -> Self {
+        self.speed = speed.clamp(*REACTIVE_SPEEDS.start(), *REACTIVE_SPEEDS.end());
+        self
+    }
+
+    /// The logo by default
+    pub fn on(mut self, led: Led) -> Self {
+        self.led = led;
+        self
+    }
+
+    /// Saved on the device by default; `LedStorage::NoStore` to preview
+    pub fn store(mut self, storage: LedStorage) -> Self {
+        self.storage = storage;
+        self
+    }
+
+    pub fn led(&self) -> Led {
+        self.led
+    }
+
+    /// Whether the effect needs an RGB LED, rather than any
+    pub fn needs_rgb(&self) -> bool {
+        !matches!(self.kind, Kind::None)
+    }
+
+    /// The report that sets the effect
+    pub fn report(&self) -> RazerReport {
+        let (storage, led) = (self.storage, self.led);
+        match self.kind {
+            Kind::None => razer_chroma_extended_matrix_effect_none(storage, led),
+            Kind::Static(color) =>
+                razer_chroma_extended_matrix_effect_static(storage, led, color),
+            Kind::Breathing(breathing) =>
+                razer_chroma_extended_matrix_effect_breathing(storage, led, breathing),
+            Kind::Spectrum => razer_chroma_extended_matrix_effect_spectrum(storage, led),
+            Kind::Reactive(color) =>
+                razer_chroma_extended_matrix_effect_reactive(storage, led, self.speed, color),
+        }
+    }
+
+    /// The reports that set the effect through the classic LED commands;
+    /// None if they don't have it
+    pub(crate) fn classic_reports(&self) -> Option<Vec<RazerReport>> {
+        let (storage, led) = (self.storage, self.led);
+        let (effect, color) = match self.kind {
+            Kind::None => return Some(vec![razer_chroma_standard_set_led_state(storage, led, false)]),
+            Kind::Static(color) => (ClassicLedEffect::Static, Some(color)),
+            Kind::Breathing(Breathing::Single(color)) => (ClassicLedEffect::Pulsating, Some(color)),
+            Kind::Spectrum => (ClassicLedEffect::Spectrum, None),
+            Kind::Breathing(_) | Kind::Reactive(_) => return None,
+        };
+        Some(razer_chroma_standard_effect_reports(storage, led, effect, color))
+    }
+}
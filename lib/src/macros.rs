@@ -0,0 +1,123 @@
+//! Key sequences stored on the device and played by a button, without any
+//! software running:
+//!
+//! ```ignore
+//! let paste = Macro::new().press(MOD_CTRL, key_code("v").unwrap());
+//! device.create_macro(1, &paste)?;
+//! device.set_button(Button::Forward, ButtonFunction::Macro(1))?;
+//! ```
+
+use std::{fmt, str::FromStr, time::Duration};
+
+use crate::buttons::{self, key_name, modifier_key};
+use crate::error::ParseMacroError;
+
+/// Most bytes of macro data sent in one report
+pub(crate) const MACRO_CHUNK: usize = 64;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MacroStep {
+    /// A HID usage id; see `buttons::key_code()`
+    KeyDown(u8),
+    KeyUp(u8),
+    /// Up to `u16::MAX` milliseconds
+    Delay(Duration),
+}
+
+/// A sequence of key presses and delays
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Macro {
+    steps: Vec<MacroStep>,
+}
+
+impl Macro {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn step(mut self, step: MacroStep) -> Self {
+        self.steps.push(step);
+        self
+    }
+
+    /// Press and release `key` while holding the modifiers (`MOD_*` bits);
+    /// `key` 0 for the modifiers alone
+    pub fn press(mut self, modifiers: u8, key: u8) -> Self {
+        let held: Vec<u8> = (0..4).map(|i| 1 << i)
+            .filter(|&bit| modifiers & bit != 0)
+            .map(modifier_key)
+            .collect();
+        self.steps.extend(held.iter().map(|&key| MacroStep::KeyDown(key)));
+        if key != 0 {
+            self.steps.push(MacroStep::KeyDown(key));
+            self.steps.push(MacroStep::KeyUp(key));
+        }
+        self.steps.extend(held.iter().rev().map(|&key| MacroStep::KeyUp(key)));
+        self
+    }
+
+    pub fn delay(self, delay: Duration) -> Self {
+        self.step(MacroStep::Delay(delay))
+    }
+
+    pub fn steps(&self) -> &[MacroStep] {
+        &self.steps
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// As stored on the device
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.steps.len() * 2);
+        for step in &self.steps {
+            match *step {
+                MacroStep::KeyDown(key) => bytes.extend([0x01, key]),
+                MacroStep::KeyUp(key) => bytes.extend([0x02, key]),
+                MacroStep::Delay(delay) => {
+                    let ms = delay.as_millis().min(u16::MAX as u128) as u16;
+                    bytes.push(0x03);
+                    bytes.extend(ms.to_be_bytes());
+                },
+            }
+        }
+        bytes
+    }
+}
+
+impl fmt::Display for MacroStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = |key: u8| key_name(key).unwrap_or_else(|| format!("0x{:02x}", key));
+        match *self {
+            MacroStep::KeyDown(key) => write!(f, "{} down", name(key)),
+            MacroStep::KeyUp(key) => write!(f, "{} up", name(key)),
+            MacroStep::Delay(delay) => write!(f, "{}ms", delay.as_millis()),
+        }
+    }
+}
+
+/// Key combinations and delays separated by commas or spaces, e.g.
+/// "ctrl+a, ctrl+c, 50ms, alt+tab"
+impl FromStr for Macro {
+    type Err = ParseMacroError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut m = Macro::new();
+        for token in s.split([',', ' ']).map(str::trim).filter(|t| !t.is_empty()) {
+            let err = || ParseMacroError(token.into());
+            m = match token.to_lowercase().strip_suffix("ms") {
+                Some(ms) => m.delay(Duration::from_millis(ms.parse().map_err(|_| err())?)),
+                None => {
+                    let (modifiers, key) = buttons::parse_key_combination(token)
+                        .ok_or_else(err)?;
+                    m.press(modifiers, key)
+                },
+            };
+        }
+        if m.is_empty() {
+            return Err(ParseMacroError(s.into()));
+        }
+        Ok(m)
+    }
+}
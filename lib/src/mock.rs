@@ -0,0 +1,251 @@
+//! Devices that aren't there, for testing protocol code and front-ends
+//! without hardware:
+//!
+//! ```ignore
+//! let transport = MockTransport::new()
+//!     .expect(commands::GET_DPI_XY, MockReply::Args(vec![0x00, 0x03, 0x20, 0x03, 0x20]));
+//! let device = DeathAdderV2::with_transport(Box::new(transport.clone()));
+//! assert_eq!(device.get_dpi()?, (800, 800));
+//! assert_eq!(transport.remaining(), 0);
+//! ```
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use crate::capabilities::Capabilities;
+use crate::commands::Command;
+use crate::common::*;
+use crate::device::{RazerDevice, RazerMouse};
+use crate::error::{USBError, USBResult};
+use crate::hooks::Hooks;
+#[cfg(feature = "transport-stats")]
+use crate::stats::StatsRecorder;
+use crate::transport::Transport;
+
+/// How a `MockTransport` answers a request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MockReply {
+    /// Successful, with these arguments
+    Args(Vec<u8>),
+    /// Successful, with the arguments of the request, as setters answer
+    Echo,
+    Busy,
+    Failure,
+    Timeout,
+    NotSupported,
+}
+
+impl MockReply {
+    fn answer(&self, request: &RazerReport) -> RazerReport {
+        match self {
+            MockReply::Args(args) => request.answer(CmdStatus::Successful, args),
+            MockReply::Echo => request.answer(CmdStatus::Successful, request.args()),
+            MockReply::Busy => request.answer(CmdStatus::Busy, &[]),
+            MockReply::Failure => request.answer(CmdStatus::Failure, &[]),
+            MockReply::Timeout => request.answer(CmdStatus::Timeout, &[]),
+            MockReply::NotSupported => request.answer(CmdStatus::NotSupported, &[]),
+        }
+    }
+}
+
+struct Exchange {
+    command: Command,
+    /// None for any arguments
+    args: Option<Vec<u8>>,
+    reply: MockReply,
+}
+
+#[derive(Default)]
+struct Script {
+    exchanges: VecDeque<Exchange>,
+    otherwise: Option<MockReply>,
+    response: Option<Vec<u8>>,
+    leftovers: VecDeque<Vec<u8>>,
+    sent: Vec<RazerReport>,
+    indexes: Vec<u16>,
+}
+
+/// Answers requests from a script, in order. Requests the script doesn't
+/// expect fail like a replay that went off track. Clones share the script, so
+/// a test can keep one to check on what the device was sent.
+#[derive(Clone, Default)]
+pub struct MockTransport {
+    script: Arc<Mutex<Script>>,
+}
+
+impl MockTransport {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Answer the next request, which must be for `command`, with `reply`
+    pub fn expect(self, command: Command, reply: MockReply) -> Self {
+        self.push(command, None, reply)
+    }
+
+    /// Like `expect()`, but the request must also have these arguments
+    pub fn expect_args(self, command: Command, args: &[u8], reply: MockReply) -> Self {
+        self.push(command, Some(args.to_vec()), reply)
+    }
+
+    /// Answer requests the script doesn't expect with `reply` instead of
+    /// failing them, e.g. `MockReply::Echo` for a device that takes anything
+    pub fn otherwise(self, reply: MockReply) -> Self {
+        self.script.lock().unwrap().otherwise = Some(reply);
+        self
+    }
+
+    /// Read a response to an earlier `command` first, as if one was still
+    /// in the device from before
+    pub fn leftover(self, command: Command, reply: MockReply) -> Self {
+        let stale = reply.answer(&RazerReport::new(command, &[])).pack();
+        self.script.lock().unwrap().leftovers.push_back(stale);
+        self
+    }
+
+    fn push(self, command: Command, args: Option<Vec<u8>>, reply: MockReply) -> Self {
+        self.script.lock().unwrap().exchanges.push_back(Exchange {
+            command: command,
+            args: args,
+            reply: reply,
+        });
+        self
+    }
+
+    /// The requests sent so far, expected or not
+    pub fn sent(&self) -> Vec<RazerReport> {
+        self.script.lock().unwrap().sent.clone()
+    }
+
+    /// The report index each request went to, in order
+    pub fn sent_indexes(&self) -> Vec<u16> {
+        self.script.lock().unwrap().indexes.clone()
+    }
+
+    /// Scripted exchanges not used yet
+    pub fn remaining(&self) -> usize {
+        self.script.lock().unwrap().exchanges.len()
+    }
+}
+
+impl Transport for MockTransport {
+    fn send_report(&self, report: &[u8], index: u16) -> USBResult<usize> {
+        let request = RazerReport::unpack(report).ok_or(USBError::ReplayMismatch)?;
+        let mut script = self.script.lock().unwrap();
+        script.sent.push(request);
+        script.indexes.push(index);
+        script.response = None;
+
+        let expected = script.exchanges.front().is_some_and(|exchange| {
+            exchange.command == request.command() && match &exchange.args {
+                Some(args) => args[..] == *request.args(),
+                None => true,
+            }
+        });
+        let reply = match (expected, &script.otherwise) {
+            (true, _) => script.exchanges.pop_front().map(|exchange| exchange.reply),
+            (false, otherwise) => otherwise.clone(),
+        };
+        let reply = match reply {
+            Some(reply) => reply,
+            None if script.exchanges.is_empty() => return Err(USBError::ReplayExhausted),
+            None => return Err(USBError::ReplayMismatch),
+        };
+
+        script.response = Some(reply.answer(&request).pack());
+        Ok(report.len())
+    }
+
+    fn get_report(&self, buffer: &mut [u8], _index: u16) -> USBResult<usize> {
+        // the same answer to every read, e.g. polled while busy, once any
+        // leftovers have been read
+        let mut script = self.script.lock().unwrap();
+        if let Some(stale) = script.leftovers.pop_front() {
+            let len = stale.len().min(buffer.len());
+            buffer[..len].copy_from_slice(&stale[..len]);
+            return Ok(len);
+        }
+        let response = script.response.as_ref().ok_or(USBError::ReplayMismatch)?;
+        let len = response.len().min(buffer.len());
+        buffer[..len].copy_from_slice(&response[..len]);
+        Ok(len)
+    }
+
+    fn name(&self) -> String {
+        String::from("Mock device")
+    }
+}
+
+/// A mouse with whatever capabilities a test needs, answering through a
+/// `MockTransport`
+pub struct MockRazerDevice {
+    pid: u16,
+    capabilities: Capabilities,
+    transport: MockTransport,
+    hooks: Hooks,
+    receiver_wait: ReceiverWait,
+    tx_id_override: TxIdOverride,
+    #[cfg(feature = "transport-stats")]
+    stats: StatsRecorder,
+}
+
+impl MockRazerDevice {
+    pub fn new(pid: u16, capabilities: Capabilities, transport: MockTransport) -> Self {
+        Self {
+            pid: pid,
+            capabilities: capabilities,
+            transport: transport,
+            hooks: Default::default(),
+            // nothing to wait for
+            receiver_wait: ReceiverWait::new(ReceiverWait::MIN),
+            tx_id_override: Default::default(),
+            #[cfg(feature = "transport-stats")]
+            stats: Default::default(),
+        }
+    }
+}
+
+impl RazerDevice for MockRazerDevice {
+    fn pid(&self) -> u16 {
+        self.pid
+    }
+
+    fn as_mouse(&self) -> Option<&dyn RazerMouse> {
+        Some(self)
+    }
+
+    fn transport(&self) -> &dyn Transport {
+        &self.transport
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.capabilities.clone()
+    }
+
+    fn hooks(&self) -> &Hooks {
+        &self.hooks
+    }
+
+    fn receiver_wait(&self) -> &ReceiverWait {
+        &self.receiver_wait
+    }
+
+    fn tx_id_override(&self) -> &TxIdOverride {
+        &self.tx_id_override
+    }
+
+    #[cfg(feature = "transport-stats")]
+    fn stats_recorder(&self) -> &StatsRecorder {
+        &self.stats
+    }
+}
+
+impl RazerMouse for MockRazerDevice {}
+
+impl fmt::Display for MockRazerDevice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // not razer_dev_default_fmt(), which would use up a scripted exchange
+        write!(f, "{} ({:04x})", self.name(), self.pid)
+    }
+}
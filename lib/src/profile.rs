@@ -0,0 +1,343 @@
+//! A set of mouse settings that can be applied to a device, read back from
+//! one, and compared. Front-ends should go through this rather than handle
+//! each setting themselves.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use serde::{Serialize, Deserialize};
+use rgb::RGB8;
+
+use crate::common::{Led, PollingRate};
+use crate::device::{RazerDevice, RazerMouse};
+use crate::error::{self, Error, USBResult, USBError};
+
+/// Which settings a profile manages, so that e.g. a lighting profile can be
+/// applied on top of another without touching the DPI
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProfileScope {
+    /// Colors and brightness
+    Lighting,
+    /// DPI and polling rate
+    Performance,
+    #[default]
+    All,
+}
+
+impl ProfileScope {
+    pub fn includes(&self, field: ProfileField) -> bool {
+        matches!((self, field),
+            (ProfileScope::All, _) |
+            (ProfileScope::Lighting, ProfileField::Color(_) | ProfileField::Brightness(_)) |
+            (ProfileScope::Performance,
+                ProfileField::Dpi | ProfileField::DpiStages | ProfileField::PollRate))
+    }
+}
+
+impl FromStr for ProfileScope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "lighting" => Ok(ProfileScope::Lighting),
+            "performance" => Ok(ProfileScope::Performance),
+            "all" => Ok(ProfileScope::All),
+            _ => Err(format!("'{}' is not a scope (lighting, performance or all)", s)),
+        }
+    }
+}
+
+/// Unset fields, and fields outside the scope, are left alone when applying
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Profile {
+    /// For display, e.g. "movie night"
+    pub name: Option<String>,
+    pub scope: ProfileScope,
+    pub dpi: Option<(u16, u16)>,
+    /// (stages, active stage)
+    pub dpi_stages: Option<(Vec<(u16, u16)>, u8)>,
+    pub poll_rate: Option<PollingRate>,
+    /// By `Led` variant name (e.g. "ScrollWheel"), as in the config
+    #[serde(with = "crate::hex_color::map")]
+    pub colors: HashMap<String, RGB8>,
+    /// In the [0-100] range, keyed like `colors`
+    pub brightness: HashMap<String, u8>,
+}
+
+/// One of the settings in a `Profile`
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ProfileField {
+    Dpi,
+    DpiStages,
+    PollRate,
+    Color(Led),
+    Brightness(Led),
+}
+
+impl fmt::Display for ProfileField {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ProfileField::Dpi => write!(f, "DPI"),
+            ProfileField::DpiStages => write!(f, "DPI stages"),
+            ProfileField::PollRate => write!(f, "polling rate"),
+            ProfileField::Color(led) => write!(f, "{} color", led.name()),
+            ProfileField::Brightness(led) => write!(f, "{} brightness", led.name()),
+        }
+    }
+}
+
+fn key(led: Led) -> String {
+    format!("{:?}", led)
+}
+
+impl Profile {
+    /// Whatever can be read back from `device`. That's all but the colors,
+    /// which the device doesn't report.
+    pub fn snapshot(device: &dyn RazerMouse) -> USBResult<Self> {
+        let mut profile = Profile {
+            dpi: Some(device.get_dpi()?),
+            dpi_stages: Some(device.get_dpi_stages()?),
+            poll_rate: Some(device.get_poll_rate()?),
+            ..Default::default()
+        };
+        for led in device.zones() {
+            match device.get_led_brightness(led) {
+                Ok(brightness) => profile.set_brightness(led, brightness),
+                Err(USBError::Unsupported(_)) => {},
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(profile)
+    }
+
+    pub fn from_json(json: &str) -> error::Result<Self> {
+        serde_json::from_str(json).map_err(Error::other)
+    }
+
+    pub fn to_json(&self) -> error::Result<String> {
+        serde_json::to_string_pretty(self).map_err(Error::other)
+    }
+
+    pub fn load(path: &Path) -> error::Result<Self> {
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+
+    pub fn color(&self, led: Led) -> Option<RGB8> {
+        self.colors.get(&key(led)).copied()
+    }
+
+    pub fn set_color(&mut self, led: Led, color: RGB8) {
+        self.colors.insert(key(led), color);
+    }
+
+    pub fn brightness(&self, led: Led) -> Option<u8> {
+        self.brightness.get(&key(led)).copied()
+    }
+
+    pub fn set_brightness(&mut self, led: Led, brightness: u8) {
+        self.brightness.insert(key(led), brightness);
+    }
+
+    /// Apply the fields that are set and in scope, skipping zones the device
+    /// doesn't have
+    pub fn apply(&self, device: &dyn RazerMouse) -> USBResult<()> {
+        let scope = self.scope;
+        // stages first; setting them also sets the DPI to the active stage
+        if let Some((stages, current)) = &self.dpi_stages {
+            if scope.includes(ProfileField::DpiStages) {
+                device.set_dpi_stages(stages, *current)?;
+            }
+        }
+        if let Some((dpi_x, dpi_y)) = self.dpi {
+            if scope.includes(ProfileField::Dpi) {
+                device.set_dpi(dpi_x, dpi_y)?;
+            }
+        }
+        if let Some(rate) = self.poll_rate {
+            if scope.includes(ProfileField::PollRate) {
+                device.set_poll_rate(rate)?;
+            }
+        }
+        self.apply_lighting(device)
+    }
+
+    /// Apply only the colors and brightness, e.g. to devices other than mice
+    pub fn apply_lighting<D: RazerDevice + ?Sized>(&self, device: &D) -> USBResult<()> {
+        let scope = self.scope;
+        for led in device.zones() {
+            if let Some(color) = self.color(led) {
+                if scope.includes(ProfileField::Color(led)) {
+                    device.set_led_color(led, color)?;
+                }
+            }
+            if let Some(brightness) = self.brightness(led) {
+                if scope.includes(ProfileField::Brightness(led)) {
+                    device.set_led_brightness(led, brightness)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The fields set here and in scope that differ in `snapshot`. Fields
+    /// the snapshot doesn't have (e.g. colors read from a device) don't count.
+    pub fn diff(&self, snapshot: &Profile) -> Vec<ProfileField> {
+        fn differs<T: PartialEq>(ours: Option<T>, theirs: Option<T>) -> bool {
+            matches!((ours, theirs), (Some(a), Some(b)) if a != b)
+        }
+
+        let mut fields = Vec::new();
+        if differs(self.dpi, snapshot.dpi) {
+            fields.push(ProfileField::Dpi);
+        }
+        if differs(self.dpi_stages.as_ref(), snapshot.dpi_stages.as_ref()) {
+            fields.push(ProfileField::DpiStages);
+        }
+        if differs(self.poll_rate, snapshot.poll_rate) {
+            fields.push(ProfileField::PollRate);
+        }
+        for led in Led::iter() {
+            if differs(self.color(led), snapshot.color(led)) {
+                fields.push(ProfileField::Color(led));
+            }
+            if differs(self.brightness(led), snapshot.brightness(led)) {
+                fields.push(ProfileField::Brightness(led));
+            }
+        }
+        fields.retain(|&field| self.scope.includes(field));
+        fields
+    }
+
+    /// A copy with only `fields` set, e.g. to apply just the differences
+    pub fn only(&self, fields: &[ProfileField]) -> Profile {
+        let mut profile = Profile {
+            scope: self.scope,
+            ..Default::default()
+        };
+        for field in fields {
+            match *field {
+                ProfileField::Dpi => profile.dpi = self.dpi,
+                ProfileField::DpiStages => profile.dpi_stages = self.dpi_stages.clone(),
+                ProfileField::PollRate => profile.poll_rate = self.poll_rate,
+                ProfileField::Color(led) => if let Some(color) = self.color(led) {
+                    profile.set_color(led, color);
+                },
+                ProfileField::Brightness(led) => if let Some(b) = self.brightness(led) {
+                    profile.set_brightness(led, b);
+                },
+            }
+        }
+        profile
+    }
+}
+
+/// Whether a device is at the settings of the profile the app thinks it's at
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyncStatus {
+    /// (profile name, if any)
+    InSync(Option<String>),
+    /// (fields that differ) Changed since the profile was saved or applied,
+    /// or there is no profile; no fields in that case
+    Modified(Vec<ProfileField>),
+    /// (why) The device could not be read
+    Unknown(String),
+}
+
+impl SyncStatus {
+    /// Compare `snapshot` with `profile`. The current DPI and active stage
+    /// are left out, since the DPI button on the mouse changes them.
+    pub fn compare(profile: Option<&Profile>, snapshot: &USBResult<Profile>) -> Self {
+        let (profile, snapshot) = match (profile, snapshot) {
+            (_, Err(e)) => return SyncStatus::Unknown(e.to_string()),
+            (None, Ok(_)) => return SyncStatus::Modified(Vec::new()),
+            (Some(profile), Ok(snapshot)) => (profile, snapshot),
+        };
+
+        let mut wanted = profile.clone();
+        if let (Some((_, current)), Some((_, device_current))) =
+            (wanted.dpi_stages.as_mut(), snapshot.dpi_stages.as_ref()) {
+            *current = *device_current;
+        }
+        let mut changed = wanted.diff(snapshot);
+        changed.retain(|&field| field != ProfileField::Dpi);
+        match changed.is_empty() {
+            true => SyncStatus::InSync(profile.name.clone()),
+            false => SyncStatus::Modified(changed),
+        }
+    }
+}
+
+impl fmt::Display for SyncStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SyncStatus::InSync(Some(name)) => write!(f, "in sync with profile '{}'", name),
+            SyncStatus::InSync(None) => write!(f, "in sync with the profile"),
+            SyncStatus::Modified(_) => write!(f, "modified (unsaved)"),
+            SyncStatus::Unknown(_) => write!(f, "unknown (device not readable)"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile() -> Profile {
+        let mut profile = Profile {
+            dpi: Some((800, 800)),
+            dpi_stages: Some((vec![(400, 400), (800, 800)], 1)),
+            poll_rate: Some(PollingRate::Hz1000),
+            ..Default::default()
+        };
+        profile.set_color(Led::Logo, RGB8::new(0xff, 0, 0));
+        profile.set_brightness(Led::Logo, 50);
+        profile
+    }
+
+    #[test]
+    fn diff_lists_what_differs() {
+        let mut snapshot = profile();
+        assert!(profile().diff(&snapshot).is_empty());
+
+        snapshot.poll_rate = Some(PollingRate::Hz500);
+        snapshot.set_brightness(Led::Logo, 100);
+        assert_eq!(profile().diff(&snapshot),
+            [ProfileField::PollRate, ProfileField::Brightness(Led::Logo)]);
+    }
+
+    #[test]
+    fn diff_skips_what_either_side_lacks() {
+        // as read from a device: no colors
+        let mut snapshot = profile();
+        snapshot.colors.clear();
+        snapshot.set_brightness(Led::ScrollWheel, 10);
+        assert!(profile().diff(&snapshot).is_empty());
+    }
+
+    #[test]
+    fn diff_stays_in_scope() {
+        let mut snapshot = profile();
+        snapshot.dpi = Some((1600, 1600));
+        snapshot.set_color(Led::Logo, RGB8::new(0, 0, 0xff));
+
+        let lighting = Profile { scope: ProfileScope::Lighting, ..profile() };
+        assert_eq!(lighting.diff(&snapshot), [ProfileField::Color(Led::Logo)]);
+        let performance = Profile { scope: ProfileScope::Performance, ..profile() };
+        assert_eq!(performance.diff(&snapshot), [ProfileField::Dpi]);
+    }
+
+    #[test]
+    fn only_keeps_the_given_fields() {
+        let only = profile().only(&[ProfileField::PollRate, ProfileField::Color(Led::Logo),
+            ProfileField::Brightness(Led::ScrollWheel)]);
+        assert_eq!(only, Profile {
+            poll_rate: Some(PollingRate::Hz1000),
+            colors: profile().colors,
+            ..Default::default()
+        });
+    }
+}
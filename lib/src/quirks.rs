@@ -0,0 +1,331 @@
+//! What sets models apart, by product id: how they're driven and what they
+//! can do. A model that talks like one already supported is a new entry in
+//! `QUIRKS` rather than new code; `device::GenericDevice` drives it from
+//! there.
+
+use crate::buttons::Button;
+use crate::capabilities::{Capabilities, DpiRange, LedCapability, LedKind};
+use crate::common::{Led, PollingRate, ARGB_CHANNEL_SIZES, SidePlate};
+use crate::device::*;
+
+/// The LED commands a device takes
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum EffectFamily {
+    /// The extended matrix effect command, one LED at a time
+    #[default]
+    ExtendedMatrix,
+    /// The Naga Trinity effect command, logo and scroll wheel at once
+    NagaTrinity,
+    /// The classic LED commands, one LED at a time; colors only on RGB LEDs
+    Standard,
+}
+
+/// Which of `RazerMouse`, `RazerKeypad`, `RazerKeyboard` and `RazerArgb` a
+/// device is, if any
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum DeviceKind {
+    Mouse,
+    Keypad,
+    Keyboard,
+    Argb,
+    /// None of them, e.g. a dock or a stand
+    #[default]
+    Other,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Quirks {
+    pub pid: u16,
+    pub kind: DeviceKind,
+    /// The transaction id requests go out with, unless overridden; see
+    /// `RazerDevice::set_tx_id()`
+    pub tx_id: u8,
+    /// wIndex of the control transfers, i.e. the interface they go to
+    pub report_index: u16,
+    /// None for devices without a sensor
+    pub dpi: Option<DpiRange>,
+    pub dpi_stages: usize,
+    /// The LEDs the device always has, in the order front-ends should list
+    /// them; e.g. side plates may add more
+    pub leds: &'static [LedCapability],
+    pub effects: EffectFamily,
+    /// Talks through a wireless dongle, which can say which mouse it's paired
+    /// with; see `RazerDevice::get_pairing()`
+    pub dongle: bool,
+    pub polling_rates: &'static [PollingRate],
+    pub battery: bool,
+    pub scroll_mode: bool,
+    pub liftoff: bool,
+    pub side_buttons: usize,
+    pub buttons: &'static [Button],
+    pub macros: bool,
+    pub dock: bool,
+    pub matrix: Option<(usize, usize)>,
+    /// The side plates that fit, which then decide the side buttons and
+    /// some of the LEDs; empty for devices that take none
+    pub side_plates: &'static [SidePlate],
+}
+
+impl Quirks {
+    /// What most models get along with
+    pub const DEFAULT: Quirks = Quirks {
+        pid: 0,
+        kind: DeviceKind::Other,
+        tx_id: 0x1f,
+        report_index: 0,
+        dpi: None,
+        dpi_stages: 0,
+        leds: &[],
+        effects: EffectFamily::ExtendedMatrix,
+        dongle: false,
+        polling_rates: &[],
+        battery: false,
+        scroll_mode: false,
+        liftoff: false,
+        side_buttons: 0,
+        buttons: &[],
+        macros: false,
+        dock: false,
+        matrix: None,
+        side_plates: &[],
+    };
+
+    /// What a device of this model can do, with `plate` attached if it takes
+    /// side plates
+    pub fn capabilities(&self, plate: SidePlate) -> Capabilities {
+        let mut caps = Capabilities {
+            dpi: self.dpi,
+            dpi_stages: self.dpi_stages,
+            leds: self.leds.to_vec(),
+            polling_rates: self.polling_rates.to_vec(),
+            battery: self.battery,
+            scroll_mode: self.scroll_mode,
+            liftoff: self.liftoff,
+            side_buttons: self.side_buttons,
+            buttons: self.buttons.to_vec(),
+            macros: self.macros,
+            dock: self.dock,
+            matrix: self.matrix,
+        };
+        if !self.side_plates.is_empty() {
+            caps.leds.extend(plate.leds().iter().map(|&led| rgb(led)));
+            caps.side_buttons = plate.buttons();
+            caps.buttons = plate.remappable();
+            caps.matrix = Some(plate.matrix());
+        }
+        caps
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// What most mice have, to start their entries from
+const MOUSE: Quirks = Quirks {
+    kind: DeviceKind::Mouse,
+    dpi_stages: 5,
+    polling_rates: PollingRate::ALL,
+    side_buttons: 2,
+    ..Quirks::DEFAULT
+};
+
+/// wIndex of the control transfers to a HyperSpeed dongle, whose control
+/// interface isn't the first one
+pub const DONGLE_REPORT_INDEX: u16 = 0x02;
+
+const fn rgb(led: Led) -> LedCapability {
+    LedCapability { led: led, kind: LedKind::Rgb }
+}
+
+const fn single_color(led: Led) -> LedCapability {
+    LedCapability { led: led, kind: LedKind::SingleColor }
+}
+
+const fn dpi(max: u16) -> Option<DpiRange> {
+    Some(DpiRange { min: 100, max: max, step: 50 })
+}
+
+pub const QUIRKS: &[Quirks] = &[
+    Quirks {
+        pid: USB_DEVICE_ID_RAZER_DEATHADDER_V2,
+        // except for razer_naga_trinity_effect_static which is 0x1f
+        tx_id: 0x3f,
+        dpi: dpi(20000),
+        leds: &[rgb(Led::Logo), rgb(Led::ScrollWheel)],
+        effects: EffectFamily::NagaTrinity,
+        liftoff: true,
+        buttons: Button::STANDARD,
+        macros: true,
+        matrix: Some((1, 2)),
+        ..MOUSE
+    },
+    // green LEDs, and no extended matrix
+    Quirks {
+        pid: USB_DEVICE_ID_RAZER_DEATHADDER_ESSENTIAL,
+        tx_id: 0x3f,
+        dpi: dpi(6400),
+        leds: &[single_color(Led::Logo), single_color(Led::ScrollWheel)],
+        effects: EffectFamily::Standard,
+        ..MOUSE
+    },
+    Quirks {
+        pid: USB_DEVICE_ID_RAZER_DEATHADDER_ELITE,
+        tx_id: 0x3f,
+        dpi: dpi(16000),
+        leds: &[rgb(Led::Logo), rgb(Led::ScrollWheel)],
+        effects: EffectFamily::Standard,
+        ..MOUSE
+    },
+    Quirks {
+        pid: USB_DEVICE_ID_RAZER_BASILISK_V2,
+        dpi: dpi(20000),
+        leds: &[rgb(Led::Logo), rgb(Led::ScrollWheel)],
+        liftoff: true,
+        buttons: Button::STANDARD,
+        macros: true,
+        ..MOUSE
+    },
+    // the underglow answers to the backlight LED as a single zone, like the
+    // Cobra's ring; in custom frames, the 11 columns are the scroll wheel,
+    // the logo and 9 underglow zones
+    Quirks {
+        pid: USB_DEVICE_ID_RAZER_BASILISK_V3,
+        dpi: dpi(26000),
+        leds: &[rgb(Led::Logo), rgb(Led::ScrollWheel), rgb(Led::Backlight)],
+        scroll_mode: true,
+        liftoff: true,
+        buttons: Button::STANDARD,
+        macros: true,
+        matrix: Some((1, 11)),
+        ..MOUSE
+    },
+    // no LEDs at all
+    Quirks {
+        pid: USB_DEVICE_ID_RAZER_OROCHI_V2,
+        report_index: DONGLE_REPORT_INDEX,
+        dpi: dpi(18000),
+        dongle: true,
+        battery: true,
+        ..MOUSE
+    },
+    // the underglow ring answers to the backlight LED as a single zone
+    Quirks {
+        pid: USB_DEVICE_ID_RAZER_COBRA,
+        dpi: dpi(8500),
+        leds: &[rgb(Led::Logo), rgb(Led::Backlight)],
+        buttons: Button::STANDARD,
+        macros: true,
+        ..MOUSE
+    },
+    Quirks {
+        pid: USB_DEVICE_ID_RAZER_COBRA_PRO_WIRED,
+        dpi: dpi(30000),
+        leds: &[rgb(Led::Logo), rgb(Led::ScrollWheel), rgb(Led::Backlight)],
+        battery: true,
+        buttons: Button::STANDARD,
+        macros: true,
+        ..MOUSE
+    },
+    Quirks {
+        pid: USB_DEVICE_ID_RAZER_COBRA_PRO_WIRELESS,
+        report_index: DONGLE_REPORT_INDEX,
+        dpi: dpi(30000),
+        leds: &[rgb(Led::Logo), rgb(Led::ScrollWheel), rgb(Led::Backlight)],
+        dongle: true,
+        battery: true,
+        buttons: Button::STANDARD,
+        macros: true,
+        ..MOUSE
+    },
+    Quirks {
+        pid: USB_DEVICE_ID_RAZER_TARTARUS_V2,
+        kind: DeviceKind::Keypad,
+        leds: &[rgb(Led::Backlight)],
+        matrix: Some((4, 6)),
+        ..Quirks::DEFAULT
+    },
+    Quirks {
+        pid: USB_DEVICE_ID_RAZER_TARTARUS_PRO,
+        kind: DeviceKind::Keypad,
+        leds: &[rgb(Led::Backlight)],
+        matrix: Some((4, 6)),
+        ..Quirks::DEFAULT
+    },
+    Quirks {
+        pid: USB_DEVICE_ID_RAZER_HUNTSMAN_TE,
+        kind: DeviceKind::Keyboard,
+        leds: &[rgb(Led::Backlight)],
+        polling_rates: PollingRate::ALL,
+        matrix: Some((6, 18)),
+        ..Quirks::DEFAULT
+    },
+    // the LED strip takes custom frames, which aren't supported yet
+    Quirks {
+        pid: USB_DEVICE_ID_RAZER_BASE_STATION_V2_CHROMA,
+        leds: &[rgb(Led::Zero)],
+        ..Quirks::DEFAULT
+    },
+    // the ring is a single zone
+    Quirks {
+        pid: USB_DEVICE_ID_RAZER_CHARGING_PAD_CHROMA,
+        leds: &[rgb(Led::Zero)],
+        dock: true,
+        ..Quirks::DEFAULT
+    },
+    // each channel is a row of custom frames, as long as the longest strip
+    Quirks {
+        pid: USB_DEVICE_ID_RAZER_CHROMA_ARGB_CONTROLLER,
+        kind: DeviceKind::Argb,
+        tx_id: 0x3f,
+        leds: &[rgb(Led::ArgbCh1), rgb(Led::ArgbCh2), rgb(Led::ArgbCh3),
+            rgb(Led::ArgbCh4), rgb(Led::ArgbCh5), rgb(Led::ArgbCh6)],
+        matrix: Some((6, *ARGB_CHANNEL_SIZES.end() as usize)),
+        ..Quirks::DEFAULT
+    },
+    // side plates add to the LEDs; see `SidePlate::leds()`
+    Quirks {
+        pid: USB_DEVICE_ID_RAZER_NAGA_PRO_WIRED,
+        dpi: dpi(20000),
+        leds: &[rgb(Led::Logo), rgb(Led::ScrollWheel)],
+        battery: true,
+        liftoff: true,
+        macros: true,
+        side_plates: &NagaPro::SIDE_PLATES,
+        ..MOUSE
+    },
+    Quirks {
+        pid: USB_DEVICE_ID_RAZER_NAGA_PRO_WIRELESS,
+        report_index: DONGLE_REPORT_INDEX,
+        dpi: dpi(20000),
+        leds: &[rgb(Led::Logo), rgb(Led::ScrollWheel)],
+        dongle: true,
+        battery: true,
+        liftoff: true,
+        macros: true,
+        side_plates: &NagaPro::SIDE_PLATES,
+        ..MOUSE
+    },
+    Quirks {
+        pid: USB_DEVICE_ID_RAZER_NAGA_TRINITY,
+        dpi: dpi(16000),
+        leds: &[rgb(Led::Logo), rgb(Led::ScrollWheel)],
+        effects: EffectFamily::NagaTrinity,
+        macros: true,
+        side_plates: &NagaTrinity::SIDE_PLATES,
+        ..MOUSE
+    },
+];
+
+/// The quirks of the model with this product id, if it's supported
+pub fn find(pid: u16) -> Option<Quirks> {
+    QUIRKS.iter().find(|quirks| quirks.pid == pid).copied()
+}
+
+/// The quirks of the model with this product id; the defaults for unknown
+/// ones
+pub fn for_pid(pid: u16) -> Quirks {
+    find(pid).unwrap_or(Quirks { pid: pid, ..Default::default() })
+}
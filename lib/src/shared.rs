@@ -0,0 +1,54 @@
+//! One device for several threads, e.g. a UI, a tray menu and a listener:
+//!
+//! ```ignore
+//! let device = SharedDevice::new(DeathAdderV2::new()?);
+//! let tray = device.clone();
+//! thread::spawn(move || tray.with(|dav2| dav2.set_active_dpi_stage(0)));
+//! let dpi = device.with(|dav2| dav2.get_dpi())?;
+//! ```
+
+use std::sync::{Arc, Mutex, MutexGuard, TryLockError};
+
+/// A device behind a mutex. Clones share the device, and what one thread does
+/// with it (e.g. read the DPI stages, then switch to the next one) doesn't
+/// interleave with what another does. Send and Sync if the device is Send.
+pub struct SharedDevice<D> {
+    device: Arc<Mutex<D>>,
+}
+
+impl<D> SharedDevice<D> {
+    pub fn new(device: D) -> Self {
+        Self {
+            device: Arc::new(Mutex::new(device)),
+        }
+    }
+
+    /// Run `f` with the device, while other threads wait for it. Not
+    /// reentrant: `f` must not use the device through this (or a clone).
+    pub fn with<R, F: FnOnce(&D) -> R>(&self, f: F) -> R {
+        f(&self.lock())
+    }
+
+    /// Like `with()`, but None instead of waiting if another thread has it
+    pub fn try_with<R, F: FnOnce(&D) -> R>(&self, f: F) -> Option<R> {
+        match self.device.try_lock() {
+            Ok(device) => Some(f(&device)),
+            Err(TryLockError::Poisoned(e)) => Some(f(&e.into_inner())),
+            Err(TryLockError::WouldBlock) => None,
+        }
+    }
+
+    /// Keep the device to this thread until the guard is dropped
+    pub fn lock(&self) -> MutexGuard<'_, D> {
+        // a panic on another thread doesn't leave the device any less usable
+        self.device.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl<D> Clone for SharedDevice<D> {
+    fn clone(&self) -> Self {
+        Self {
+            device: Arc::clone(&self.device),
+        }
+    }
+}
@@ -0,0 +1,87 @@
+//! Round-trip time metrics of device transactions (`transport-stats` feature).
+//!
+//! Meant for tuning the receiver wait and for spotting slow hubs or other
+//! software fighting over the device.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::commands::Command;
+
+/// Aggregate stats of a set of transactions
+#[derive(Debug, Default, Copy, Clone)]
+pub struct CommandStats {
+    pub count: u32,
+    pub failures: u32,
+    pub total: Duration,
+    pub min: Option<Duration>,
+    pub max: Option<Duration>,
+}
+
+impl CommandStats {
+    fn record(&mut self, rtt: Duration, ok: bool) {
+        self.count += 1;
+        if !ok {
+            self.failures += 1;
+        }
+        self.total += rtt;
+        self.min = Some(self.min.map_or(rtt, |min| min.min(rtt)));
+        self.max = Some(self.max.map_or(rtt, |max| max.max(rtt)));
+    }
+
+    pub fn mean(&self) -> Option<Duration> {
+        match self.count {
+            0 => None,
+            n => Some(self.total / n),
+        }
+    }
+}
+
+impl fmt::Display for CommandStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ms = |d: Option<Duration>| d.map_or(0.0, |d| d.as_secs_f64() * 1000.0);
+        write!(f, "{} txns, {} failed, rtt min/avg/max {:.2}/{:.2}/{:.2} ms",
+            self.count, self.failures, ms(self.min), ms(self.mean()), ms(self.max))
+    }
+}
+
+/// A snapshot of the stats of a device, overall and per command
+#[derive(Debug, Default, Clone)]
+pub struct TransportStats {
+    pub overall: CommandStats,
+    pub per_command: HashMap<Command, CommandStats>,
+}
+
+impl fmt::Display for TransportStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "overall: {}", self.overall)?;
+        let mut commands = self.per_command.iter().collect::<Vec<_>>();
+        commands.sort_by_key(|(cmd, _)| (cmd.class, cmd.id));
+        for (cmd, stats) in commands {
+            writeln!(f, "  {}: {}", cmd, stats)?;
+        }
+        Ok(())
+    }
+}
+
+/// Collects the stats of one device
+#[derive(Default)]
+pub struct StatsRecorder(Mutex<TransportStats>);
+
+impl StatsRecorder {
+    pub(crate) fn record(&self, cmd: Command, rtt: Duration, ok: bool) {
+        let mut stats = self.0.lock().unwrap();
+        stats.overall.record(rtt, ok);
+        stats.per_command.entry(cmd).or_default().record(rtt, ok);
+    }
+
+    pub fn snapshot(&self) -> TransportStats {
+        self.0.lock().unwrap().clone()
+    }
+
+    pub fn reset(&self) {
+        *self.0.lock().unwrap() = Default::default();
+    }
+}
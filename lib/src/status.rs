@@ -0,0 +1,49 @@
+//! A small JSON file with the state of the device, for desktop widgets
+//! (Rainmeter and the like) that can't talk to it themselves.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::Serialize;
+
+use crate::device::RazerMouse;
+
+/// Fields the device couldn't report are null
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Status {
+    pub device: String,
+    pub dpi: Option<u16>,
+    /// 1-based, as shown to users
+    pub stage: Option<u8>,
+    pub stages: Option<usize>,
+    pub battery: Option<u8>,
+    /// Name of the applied profile, if any
+    pub profile: Option<String>,
+    /// Seconds since the unix epoch
+    pub updated: u64,
+}
+
+impl Status {
+    pub fn read(device: &dyn RazerMouse, profile: Option<&str>) -> Self {
+        let stages = device.get_dpi_stages().ok();
+        Self {
+            device: device.name(),
+            dpi: device.get_dpi().ok().map(|(dpi_x, _)| dpi_x),
+            stage: stages.as_ref().map(|(_, current)| current + 1),
+            stages: stages.as_ref().map(|(stages, _)| stages.len()),
+            battery: device.get_battery_level().ok(),
+            profile: profile.map(String::from),
+            updated: SystemTime::now().duration_since(UNIX_EPOCH)
+                .unwrap_or_default().as_secs(),
+        }
+    }
+
+    /// Replaces the file at once, so readers never see half of it
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, json)?;
+        fs::rename(&tmp, path)
+    }
+}
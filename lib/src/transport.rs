@@ -0,0 +1,530 @@
+//! How reports get to and from a device.
+//!
+//! `UsbTransport` talks to the device through rusb control transfers, and
+//! `OnDemandUsbTransport` does the same but lets go of the device when idle. The
+//! recording and replay transports capture a real session to a file and serve
+//! it back later, so whole GUI/CLI flows can be regression-tested without the
+//! device attached.
+//!
+//! Setting `LIBRAZER_RECORD=<file>` records every device opened through the
+//! usual constructors; front-ends may honor `LIBRAZER_REPLAY=<file>` to run
+//! against a recording instead of hardware.
+//!
+//! Session files are plain text: one exchange per line, the request and the
+//! response report in hex separated by a space. Lines starting with `#` are
+//! comments.
+
+use std::collections::VecDeque;
+use std::env;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::Mutex;
+#[cfg(feature = "rusb")]
+use std::sync::{Arc, Weak};
+#[cfg(feature = "rusb")]
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+#[cfg(feature = "rusb")]
+use std::thread;
+use std::time::Duration;
+#[cfg(feature = "rusb")]
+use std::time::Instant;
+#[cfg(feature = "rusb")]
+use rusb::{Context, Device, DeviceHandle};
+#[cfg(feature = "hidapi")]
+use hidapi::{HidApi, HidDevice};
+
+use crate::error::{USBResult, USBError};
+
+/// How long a control transfer may take by default; see
+/// `Transport::set_transfer_timeout()`
+pub const USB_TXFER_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The shortest idle timeout `OnDemandUsbTransport` takes; shorter ones would
+/// have it reopen the device all the time
+#[cfg(feature = "rusb")]
+pub const MIN_IDLE_TIMEOUT: Duration = Duration::from_millis(100);
+
+pub const RECORD_ENV: &str = "LIBRAZER_RECORD";
+pub const REPLAY_ENV: &str = "LIBRAZER_REPLAY";
+
+pub trait Transport: Send + Sync {
+    /// Send a report to the device (HID SET_REPORT); returns the bytes written
+    fn send_report(&self, report: &[u8], index: u16) -> USBResult<usize>;
+
+    /// Read a report from the device (HID GET_REPORT); returns the bytes read
+    fn get_report(&self, buffer: &mut [u8], index: u16) -> USBResult<usize>;
+
+    /// Product name and serial, if available, for display purposes
+    fn name(&self) -> String {
+        String::new()
+    }
+
+    /// How long each transfer may take; None for transports without a
+    /// timeout of their own
+    fn transfer_timeout(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Ignored by transports without a timeout of their own
+    fn set_transfer_timeout(&self, _timeout: Duration) {}
+
+    /// Called before a transaction, i.e. a request and the reads polling for
+    /// its response, e.g. to keep the device open until `end_transaction()`
+    fn begin_transaction(&self) {}
+
+    fn end_transaction(&self) {}
+}
+
+/// Control transfers through rusb (`rusb` feature); needs the libusb driver
+/// on Windows
+#[cfg(feature = "rusb")]
+pub struct UsbTransport {
+    handle: DeviceHandle<Context>,
+    timeout_us: AtomicU64,
+}
+
+#[cfg(feature = "rusb")]
+impl UsbTransport {
+    pub fn new(handle: DeviceHandle<Context>) -> Self {
+        Self {
+            handle: handle,
+            timeout_us: AtomicU64::new(USB_TXFER_TIMEOUT.as_micros() as u64),
+        }
+    }
+
+    fn timeout(&self) -> Duration {
+        Duration::from_micros(self.timeout_us.load(Ordering::Relaxed))
+    }
+
+    pub fn handle(&self) -> &DeviceHandle<Context> {
+        &self.handle
+    }
+}
+
+#[cfg(feature = "rusb")]
+impl Transport for UsbTransport {
+    fn send_report(&self, report: &[u8], index: u16) -> USBResult<usize> {
+        let request = 0x09u8; // HID_REQ_SET_REPORT
+        let request_type = 0x21u8; // USB_TYPE_CLASS | USB_RECIP_INTERFACE | USB_DIR_OUT
+        let value = 0x300u16;
+
+        Ok(self.handle.write_control(
+            request_type, request, value, index, report, self.timeout())?)
+    }
+
+    fn get_report(&self, buffer: &mut [u8], index: u16) -> USBResult<usize> {
+        let request = 0x01u8; // HID_REQ_GET_REPORT
+        let request_type = 0xA1u8; // USB_TYPE_CLASS | USB_RECIP_INTERFACE | USB_DIR_IN
+        let value = 0x300u16;
+
+        Ok(self.handle.read_control(
+            request_type, request, value, index, buffer, self.timeout())?)
+    }
+
+    fn name(&self) -> String {
+        crate::usb::get_device_name(&self.handle)
+    }
+
+    fn transfer_timeout(&self) -> Option<Duration> {
+        Some(self.timeout())
+    }
+
+    fn set_transfer_timeout(&self, timeout: Duration) {
+        self.timeout_us.store(timeout.as_micros() as u64, Ordering::Relaxed);
+    }
+}
+
+/// Like `UsbTransport`, but the device is only kept open while in use: it is
+/// closed after `idle_timeout` without transfers and reopened on the next one,
+/// so other programs (e.g. OpenRGB, firmware updaters) can get to it meanwhile
+#[cfg(feature = "rusb")]
+pub struct OnDemandUsbTransport {
+    inner: Arc<OnDemandInner>,
+    name: String,
+}
+
+#[cfg(feature = "rusb")]
+struct OnDemandInner {
+    device: Device<Context>,
+    idle_timeout: Duration,
+    /// For the transport, including when it's reopened
+    transfer_timeout: Mutex<Duration>,
+    /// The open device and when it was last used
+    open: Mutex<Option<(UsbTransport, Instant)>>,
+    /// Transactions under way, which keep the device open
+    transactions: AtomicUsize,
+}
+
+#[cfg(feature = "rusb")]
+impl OnDemandInner {
+    fn with<T, F>(&self, f: F) -> USBResult<T>
+    where
+        F: FnOnce(&UsbTransport) -> USBResult<T>,
+    {
+        let mut open = self.open.lock().unwrap();
+        if open.is_none() {
+            let transport = UsbTransport::new(self.device.open()?);
+            transport.set_transfer_timeout(*self.transfer_timeout.lock().unwrap());
+            *open = Some((transport, Instant::now()));
+        }
+        let (transport, last_used) = open.as_mut().unwrap();
+        let res = f(transport);
+        *last_used = Instant::now();
+        res
+    }
+
+    /// Close the device if it's been idle long enough
+    fn release_if_idle(&self) {
+        let mut open = self.open.lock().unwrap();
+        let idle = self.transactions.load(Ordering::Acquire) == 0 && open.as_ref()
+            .is_some_and(|(_, last_used)| last_used.elapsed() >= self.idle_timeout);
+        if idle {
+            *open = None;
+        }
+    }
+}
+
+#[cfg(feature = "rusb")]
+impl OnDemandUsbTransport {
+    /// Opens `device` right away, to fail early if it can't be. The idle
+    /// timeout is at least `MIN_IDLE_TIMEOUT`.
+    pub fn new(device: Device<Context>, idle_timeout: Duration) -> USBResult<Self> {
+        let idle_timeout = idle_timeout.max(MIN_IDLE_TIMEOUT);
+        let handle = device.open()?;
+        let name = crate::usb::get_device_name(&handle);
+        let inner = Arc::new(OnDemandInner {
+            device: device,
+            idle_timeout: idle_timeout,
+            transfer_timeout: Mutex::new(USB_TXFER_TIMEOUT),
+            open: Mutex::new(Some((UsbTransport::new(handle), Instant::now()))),
+            transactions: AtomicUsize::new(0),
+        });
+
+        // checks on the device until the transport is dropped
+        let weak: Weak<OnDemandInner> = Arc::downgrade(&inner);
+        thread::spawn(move || loop {
+            thread::sleep(idle_timeout / 2);
+            match weak.upgrade() {
+                Some(inner) => inner.release_if_idle(),
+                None => break,
+            }
+        });
+
+        Ok(Self {
+            inner: inner,
+            name: name,
+        })
+    }
+
+    /// Whether the device is open right now
+    pub fn is_open(&self) -> bool {
+        self.inner.open.lock().unwrap().is_some()
+    }
+}
+
+#[cfg(feature = "rusb")]
+impl Transport for OnDemandUsbTransport {
+    fn send_report(&self, report: &[u8], index: u16) -> USBResult<usize> {
+        self.inner.with(|transport| transport.send_report(report, index))
+    }
+
+    fn get_report(&self, buffer: &mut [u8], index: u16) -> USBResult<usize> {
+        self.inner.with(|transport| transport.get_report(buffer, index))
+    }
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn transfer_timeout(&self) -> Option<Duration> {
+        Some(*self.inner.transfer_timeout.lock().unwrap())
+    }
+
+    fn set_transfer_timeout(&self, timeout: Duration) {
+        *self.inner.transfer_timeout.lock().unwrap() = timeout;
+        if let Some((transport, _)) = self.inner.open.lock().unwrap().as_ref() {
+            transport.set_transfer_timeout(timeout);
+        }
+    }
+
+    fn begin_transaction(&self) {
+        self.inner.transactions.fetch_add(1, Ordering::AcqRel);
+    }
+
+    fn end_transaction(&self) {
+        self.inner.transactions.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// Feature reports through hidapi (`hidapi` feature); works with the stock
+/// driver, no libusb needed
+#[cfg(feature = "hidapi")]
+pub struct HidapiTransport {
+    device: Mutex<HidDevice>,
+    name: String,
+}
+
+#[cfg(feature = "hidapi")]
+impl HidapiTransport {
+    /// Open the control interface of the first device with the given ids
+    pub fn open(api: &HidApi, vid: u16, pid: u16, interface: i32) -> USBResult<Self> {
+        let info = api.device_list()
+            .find(|d| d.vendor_id() == vid && d.product_id() == pid &&
+                d.interface_number() == interface)
+            .ok_or(USBError::DeviceNotFound)?;
+        let name = format!("{}{}",
+            info.product_string().unwrap_or_default(),
+            info.serial_number().map_or(String::new(), |s| format!(" {}", s)));
+        Ok(Self {
+            device: Mutex::new(info.open_device(api)?),
+            name: name,
+        })
+    }
+}
+
+#[cfg(feature = "hidapi")]
+impl Transport for HidapiTransport {
+    fn send_report(&self, report: &[u8], _index: u16) -> USBResult<usize> {
+        // prefix with the report id; razer devices use unnumbered reports
+        let mut buffer = Vec::with_capacity(report.len() + 1);
+        buffer.push(0x00);
+        buffer.extend_from_slice(report);
+        self.device.lock().unwrap().send_feature_report(&buffer)?;
+        Ok(report.len())
+    }
+
+    fn get_report(&self, buffer: &mut [u8], _index: u16) -> USBResult<usize> {
+        let mut report = vec![0u8; buffer.len() + 1];
+        let read = self.device.lock().unwrap().get_feature_report(&mut report)?;
+        // skip the report id
+        let read = read.saturating_sub(1).min(buffer.len());
+        buffer[..read].copy_from_slice(&report[1..read + 1]);
+        Ok(read)
+    }
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Passes everything through to another transport and appends each
+/// request/response pair to a session file
+pub struct RecordingTransport {
+    inner: Box<dyn Transport>,
+    file: Mutex<File>,
+    last_request: Mutex<Vec<u8>>,
+}
+
+impl RecordingTransport {
+    /// Starts a new session file at `path`, overwriting any existing one
+    pub fn new<P: AsRef<Path>>(inner: Box<dyn Transport>, path: P) -> USBResult<Self> {
+        let mut file = OpenOptions::new()
+            .write(true).create(true).truncate(true)
+            .open(path)?;
+        writeln!(file, "# {}", inner.name())?;
+        Ok(Self {
+            inner: inner,
+            file: Mutex::new(file),
+            last_request: Mutex::new(Vec::new()),
+        })
+    }
+}
+
+impl Transport for RecordingTransport {
+    fn send_report(&self, report: &[u8], index: u16) -> USBResult<usize> {
+        *self.last_request.lock().unwrap() = report.to_vec();
+        self.inner.send_report(report, index)
+    }
+
+    fn get_report(&self, buffer: &mut [u8], index: u16) -> USBResult<usize> {
+        let read = self.inner.get_report(buffer, index)?;
+        let request = self.last_request.lock().unwrap();
+        writeln!(self.file.lock().unwrap(), "{} {}",
+            to_hex(&request), to_hex(&buffer[..read]))?;
+        Ok(read)
+    }
+
+    fn name(&self) -> String {
+        self.inner.name()
+    }
+
+    fn transfer_timeout(&self) -> Option<Duration> {
+        self.inner.transfer_timeout()
+    }
+
+    fn set_transfer_timeout(&self, timeout: Duration) {
+        self.inner.set_transfer_timeout(timeout)
+    }
+
+    fn begin_transaction(&self) {
+        self.inner.begin_transaction()
+    }
+
+    fn end_transaction(&self) {
+        self.inner.end_transaction()
+    }
+}
+
+/// Serves back a session recorded by `RecordingTransport`. Requests must come
+/// in the same order as they were recorded.
+pub struct ReplayTransport {
+    name: String,
+    exchanges: Mutex<VecDeque<(Vec<u8>, Vec<u8>)>>,
+    response: Mutex<Option<Vec<u8>>>,
+    last_request: Mutex<Vec<u8>>,
+}
+
+impl ReplayTransport {
+    pub fn load<P: AsRef<Path>>(path: P) -> USBResult<Self> {
+        let reader = BufReader::new(File::open(path)?);
+        let mut name = String::new();
+        let mut exchanges = VecDeque::new();
+
+        for (lineno, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if let Some(comment) = line.strip_prefix('#') {
+                if lineno == 0 {
+                    name = comment.trim().into();
+                }
+                continue;
+            }
+            if line.is_empty() {
+                continue;
+            }
+
+            let exchange = line.split_once(' ')
+                .and_then(|(req, resp)| Some((from_hex(req)?, from_hex(resp.trim())?)));
+            match exchange {
+                Some(exchange) => exchanges.push_back(exchange),
+                None => return Err(USBError::ReplayBadSession(lineno + 1)),
+            }
+        }
+
+        Ok(Self {
+            name: name,
+            exchanges: Mutex::new(exchanges),
+            response: Mutex::new(None),
+            last_request: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// Exchanges not yet replayed
+    pub fn remaining(&self) -> usize {
+        self.exchanges.lock().unwrap().len()
+    }
+}
+
+impl Transport for ReplayTransport {
+    fn send_report(&self, report: &[u8], _index: u16) -> USBResult<usize> {
+        let (request, response) = self.exchanges.lock().unwrap()
+            .pop_front()
+            .ok_or(USBError::ReplayExhausted)?;
+        if request != report {
+            return Err(USBError::ReplayMismatch);
+        }
+        *self.response.lock().unwrap() = Some(response);
+        *self.last_request.lock().unwrap() = request;
+        Ok(report.len())
+    }
+
+    fn get_report(&self, buffer: &mut [u8], _index: u16) -> USBResult<usize> {
+        let response = self.response.lock().unwrap().take()
+            .or_else(|| {
+                // another read of the same request, e.g. polled while busy
+                let mut exchanges = self.exchanges.lock().unwrap();
+                let last_request = self.last_request.lock().unwrap();
+                match exchanges.front() {
+                    Some((request, _)) if *request == *last_request =>
+                        exchanges.pop_front().map(|(_, response)| response),
+                    _ => None,
+                }
+            })
+            .ok_or(USBError::ReplayMismatch)?;
+        let len = response.len().min(buffer.len());
+        buffer[..len].copy_from_slice(&response[..len]);
+        Ok(len)
+    }
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+}
+
+/// Wraps `transport` in a `RecordingTransport` if `RECORD_ENV` is set
+pub fn recording_from_env(transport: Box<dyn Transport>) -> USBResult<Box<dyn Transport>> {
+    match env::var_os(RECORD_ENV) {
+        Some(path) => Ok(Box::new(RecordingTransport::new(transport, path)?)),
+        None => Ok(transport),
+    }
+}
+
+/// Loads the session file named by `REPLAY_ENV`, if set
+pub fn replay_from_env() -> Option<USBResult<ReplayTransport>> {
+    env::var_os(REPLAY_ENV).map(ReplayTransport::load)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use super::*;
+    use crate::commands;
+    use crate::device::{DeathAdderV2, RazerMouse};
+    use crate::mock::{MockReply, MockTransport};
+
+    fn session_path(name: &str) -> std::path::PathBuf {
+        env::temp_dir().join(format!("librazer-{}-{}.txt", name, std::process::id()))
+    }
+
+    #[test]
+    fn recorded_session_replays() {
+        let path = session_path("round-trip");
+        let mock = MockTransport::new()
+            .expect(commands::GET_DPI_XY, MockReply::Args(vec![0x00, 0x03, 0x20, 0x03, 0x20]));
+        let recording = RecordingTransport::new(Box::new(mock), &path).unwrap();
+        let dpi = DeathAdderV2::with_transport(Box::new(recording)).get_dpi().unwrap();
+
+        let replay = ReplayTransport::load(&path).unwrap();
+        assert_eq!(replay.name(), "Mock device");
+        assert_eq!(replay.remaining(), 1);
+        let device = DeathAdderV2::with_transport(Box::new(replay));
+        assert_eq!(device.get_dpi().unwrap(), dpi);
+        assert!(matches!(device.get_dpi(), Err(USBError::ReplayExhausted)));
+        _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn bad_lines_are_rejected() {
+        let lines = [("odd", "00ff 0ff"), ("not-hex", "00fg 00ff"), ("one-half", "00ff")];
+        for (name, line) in lines {
+            let path = session_path(name);
+            fs::write(&path, format!("# device\n\n{}\n", line)).unwrap();
+            let res = ReplayTransport::load(&path);
+            _ = fs::remove_file(&path);
+            assert!(matches!(res, Err(USBError::ReplayBadSession(3))), "'{}' was accepted", line);
+        }
+    }
+
+    #[test]
+    fn hex_round_trips() {
+        let bytes = [0x00, 0x0a, 0xff, 0x3f];
+        assert_eq!(to_hex(&bytes), "000aff3f");
+        assert_eq!(from_hex("000aFF3f").unwrap(), bytes);
+        assert_eq!(from_hex(""), Some(vec![]));
+    }
+}
@@ -0,0 +1,106 @@
+//! rusb specifics (`rusb` feature)
+
+use std::ops::Deref;
+use std::fmt;
+use rusb::{Context, DeviceHandle, Device, DeviceList};
+
+use crate::error::USBResult;
+
+/// A wrapper for rusb:Device<Context> with Display, and Default
+pub struct UsbDevice(Option<Device<Context>>);
+
+impl Deref for UsbDevice {
+    type Target = Option<Device<Context>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+pub(crate) fn get_device_name(handle: &DeviceHandle<Context>) -> String {
+    let dev = handle.device();
+    match dev.device_descriptor() {
+        Ok(dd) => {
+            let serial = handle.read_serial_number_string_ascii(&dd)
+                                        .unwrap_or_default();
+            let product = handle.read_product_string_ascii(&dd)
+                                        .unwrap_or_default();
+            format!("{}{}{}", product, if serial.len() > 0 {" "} else {""}, serial)
+        },
+        Err(_) => String::new(),
+    }
+}
+
+impl fmt::Display for UsbDevice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UsbDevice(Some(dev)) => {
+                let devname = match dev.open() {
+                    Ok(h) => {
+                        get_device_name(&h)
+                    },
+                    Err(_) => String::new(),
+                };
+                write!(f, "{} ({}-{})", devname, dev.bus_number(), dev.address())
+            },
+            UsbDevice(None) => write!(f, "None")
+        }
+    }
+}
+
+impl Default for UsbDevice {
+    fn default() -> Self {
+        UsbDevice(None)
+    }
+}
+
+impl UsbDevice {
+    /// List all usb devices
+    pub fn list() -> USBResult<Vec<UsbDevice>> {
+        let ctx = Context::new()?;
+        let device_list = DeviceList::new_with_context(ctx)?;
+        let res = device_list.iter()
+            .map(|d| UsbDevice(Some(d)))
+            .collect::<Vec<UsbDevice>>();
+        Ok(res)
+    }
+
+    /// List all usb devices of the specified vendor
+    pub fn by_vendor(vid: u16) -> USBResult<Vec<UsbDevice>> {
+        let ctx = Context::new()?;
+        let device_list = DeviceList::new_with_context(ctx)?;
+        let res = device_list.iter()
+            .filter_map(|device| {
+                match device.device_descriptor() {
+                    Ok(descr) => if descr.vendor_id() == vid {
+                        Some(UsbDevice(Some(device)))
+                    } else {
+                        None
+                    },
+                    Err(_) => None
+                }
+            })
+            .collect::<Vec<UsbDevice>>();
+        Ok(res)
+    }
+
+    /// List all usb devices of the specified vendor and with the specified product ID
+    pub fn by_product(vid: u16, pid: u16) -> USBResult<Vec<UsbDevice>> {
+        let ctx = Context::new()?;
+        let device_list = DeviceList::new_with_context(ctx)?;
+        let res = device_list.iter()
+            .filter_map(|device| {
+                match device.device_descriptor() {
+                    Ok(descr) =>
+                        if descr.vendor_id() == vid && descr.product_id() == pid {
+                            Some(UsbDevice(Some(device)))
+                        } else {
+                            None
+                        },
+                    Err(_) => None
+                }
+            })
+            .collect::<Vec<UsbDevice>>();
+        Ok(res)
+    }
+}
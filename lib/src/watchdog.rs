@@ -0,0 +1,60 @@
+//! Puts settings back when something else (Synapse, OpenRGB, a game)
+//! changes them behind our back.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use crate::device::RazerMouse;
+use crate::error::USBResult;
+use crate::profile::{Profile, ProfileField};
+
+/// Watches the DPI stages and polling rate. The active stage is left alone,
+/// since the DPI button on the mouse changes it.
+pub struct Watchdog {
+    profile: Profile,
+    interval: Duration,
+}
+
+impl Watchdog {
+    /// Keep the device at the DPI stages and polling rate of `profile`
+    pub fn new(profile: &Profile, interval: Duration) -> Self {
+        Self {
+            profile: profile.only(&[ProfileField::DpiStages, ProfileField::PollRate]),
+            interval: interval,
+        }
+    }
+
+    /// Check the device once and restore what changed; returns what did
+    pub fn check(&self, device: &dyn RazerMouse) -> USBResult<Vec<ProfileField>> {
+        let snapshot = Profile {
+            dpi_stages: device.get_dpi_stages().map(Some)?,
+            poll_rate: device.get_poll_rate().map(Some)?,
+            ..Default::default()
+        };
+
+        let mut wanted = self.profile.clone();
+        if let (Some((_, current)), Some((_, device_current))) =
+            (wanted.dpi_stages.as_mut(), snapshot.dpi_stages.as_ref()) {
+            *current = *device_current;
+        }
+
+        let changed = wanted.diff(&snapshot);
+        if !changed.is_empty() {
+            wanted.only(&changed).apply(device)?;
+        }
+        Ok(changed)
+    }
+
+    /// Check every interval until `stop` is set. `on_check` gets the outcome
+    /// of each check; errors don't stop the watch.
+    pub fn run<F>(&self, device: &dyn RazerMouse, stop: &AtomicBool, mut on_check: F)
+    where
+        F: FnMut(USBResult<Vec<ProfileField>>)
+    {
+        while !stop.load(Ordering::Relaxed) {
+            thread::sleep(self.interval);
+            on_check(self.check(device));
+        }
+    }
+}
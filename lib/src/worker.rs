@@ -0,0 +1,200 @@
+//! Sends commands to a device from a thread of its own, so front-ends don't
+//! block on it.
+//!
+//! Commands that save to the device (persists) are never dropped and go
+//! before anything else. Previews, e.g. from dragging a color picker, are
+//! only worth sending while fresh: when they queue up faster than the device
+//! takes them, the oldest are dropped.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+
+use crate::device::RazerDevice;
+use crate::error::{USBError, USBResult};
+
+/// How many previews wait by default before the oldest is dropped
+pub const PREVIEW_CAPACITY: usize = 4;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Priority {
+    /// Saved on the device; never dropped
+    Persist,
+    /// Shown until the next change; dropped if newer ones pile up
+    Preview,
+}
+
+pub type Command = Box<dyn FnOnce(&dyn RazerDevice) -> USBResult<()> + Send>;
+
+/// Persists first, in order; then previews, in order, at most `capacity` of
+/// them
+pub struct CommandQueue {
+    persists: VecDeque<Command>,
+    previews: VecDeque<Command>,
+    capacity: usize,
+    dropped: usize,
+}
+
+impl CommandQueue {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            persists: VecDeque::new(),
+            previews: VecDeque::new(),
+            capacity: capacity.max(1),
+            dropped: 0,
+        }
+    }
+
+    /// Returns false if a preview had to be dropped to make room
+    pub fn push(&mut self, priority: Priority, command: Command) -> bool {
+        match priority {
+            Priority::Persist => {
+                self.persists.push_back(command);
+                true
+            },
+            Priority::Preview => {
+                let full = self.previews.len() >= self.capacity;
+                if full {
+                    self.previews.pop_front();
+                    self.dropped += 1;
+                }
+                self.previews.push_back(command);
+                !full
+            },
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<Command> {
+        self.persists.pop_front().or_else(|| self.previews.pop_front())
+    }
+
+    pub fn len(&self) -> usize {
+        self.persists.len() + self.previews.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Previews dropped so far
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+}
+
+struct State {
+    queue: CommandQueue,
+    /// A command is being sent
+    busy: bool,
+    stop: bool,
+    last_error: Option<USBError>,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    /// Signalled when there's a command or a stop request
+    wake: Condvar,
+    /// Signalled when the queue runs empty
+    idle: Condvar,
+}
+
+/// Owns a device and sends it the commands queued with `submit()`
+pub struct DeviceWorker {
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl DeviceWorker {
+    pub fn new<D: RazerDevice + Send + 'static>(device: D, preview_capacity: usize) -> Self {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                queue: CommandQueue::new(preview_capacity),
+                busy: false,
+                stop: false,
+                last_error: None,
+            }),
+            wake: Condvar::new(),
+            idle: Condvar::new(),
+        });
+
+        let thread_shared = Arc::clone(&shared);
+        let thread = thread::spawn(move || Self::run(&device, &thread_shared));
+
+        Self {
+            shared: shared,
+            thread: Some(thread),
+        }
+    }
+
+    fn run(device: &dyn RazerDevice, shared: &Shared) {
+        let mut state = shared.state.lock().unwrap();
+        loop {
+            // on stop, pending persists still go out; previews don't matter
+            if state.stop {
+                state.queue.previews.clear();
+            }
+            match state.queue.pop() {
+                Some(command) => {
+                    state.busy = true;
+                    drop(state);
+                    let res = command(device);
+                    state = shared.state.lock().unwrap();
+                    state.busy = false;
+                    if let Err(e) = res {
+                        state.last_error = Some(e);
+                    }
+                },
+                None if state.stop => break,
+                None => {
+                    shared.idle.notify_all();
+                    state = shared.wake.wait(state).unwrap();
+                },
+            }
+        }
+        shared.idle.notify_all();
+    }
+
+    /// Queue `command`; returns false if a preview had to be dropped for it
+    pub fn submit<F>(&self, priority: Priority, command: F) -> bool
+    where
+        F: FnOnce(&dyn RazerDevice) -> USBResult<()> + Send + 'static,
+    {
+        let pushed = self.shared.state.lock().unwrap().queue.push(priority, Box::new(command));
+        self.shared.wake.notify_one();
+        pushed
+    }
+
+    /// Block until everything queued so far has been sent
+    pub fn flush(&self) {
+        let mut state = self.shared.state.lock().unwrap();
+        while state.busy || !state.queue.is_empty() {
+            state = self.shared.idle.wait(state).unwrap();
+        }
+    }
+
+    /// Commands waiting to be sent
+    pub fn pending(&self) -> usize {
+        self.shared.state.lock().unwrap().queue.len()
+    }
+
+    /// Previews dropped so far
+    pub fn dropped(&self) -> usize {
+        self.shared.state.lock().unwrap().queue.dropped()
+    }
+
+    /// The error of the last command that failed, if any since the last call
+    pub fn take_error(&self) -> Option<USBError> {
+        self.shared.state.lock().unwrap().last_error.take()
+    }
+}
+
+impl Drop for DeviceWorker {
+    /// Sends the pending persists and stops the thread
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().stop = true;
+        self.shared.wake.notify_one();
+        if let Some(thread) = self.thread.take() {
+            _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,76 @@
+//! Hardware-in-the-loop tests; need a connected DeathAdder v2 (with the libusb
+//! driver) and are only built with `cargo test -p librazer --features hw-tests`.
+//!
+//! Apart from brightness, which is written back unchanged, only read-only and
+//! NoStore commands are sent, so nothing on the device changes for good.
+#![cfg(feature = "hw-tests")]
+
+use std::sync::Mutex;
+use librazer::device::{DeathAdderV2, RazerDevice, RazerMouse};
+
+// there's one device; don't let the tests talk to it concurrently
+static DEVICE_LOCK: Mutex<()> = Mutex::new(());
+
+fn with_device<F: FnOnce(&DeathAdderV2)>(test: F) {
+    let _guard = DEVICE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let dav2 = DeathAdderV2::new().expect("failed to open device");
+    test(&dav2);
+}
+
+#[test]
+fn serial_is_readable() {
+    with_device(|dav2| {
+        let serial = dav2.get_serial().expect("failed to get serial");
+        assert!(!serial.is_empty());
+        assert!(serial.chars().all(|c| c.is_ascii_alphanumeric()), "{:?}", serial);
+    });
+}
+
+#[test]
+fn poll_rate_is_readable() {
+    with_device(|dav2| {
+        dav2.get_poll_rate().expect("failed to get polling rate");
+    });
+}
+
+#[test]
+fn dpi_round_trip() {
+    with_device(|dav2| {
+        let (dpi_x, dpi_y) = dav2.get_dpi().expect("failed to get DPI");
+        let probe = if dpi_x == 800 { 1600 } else { 800 };
+
+        dav2.set_dpi(probe, probe).expect("failed to set DPI");
+        let read = dav2.get_dpi();
+        dav2.set_dpi(dpi_x, dpi_y).expect("failed to restore DPI");
+
+        assert_eq!(read.expect("failed to get DPI"), (probe, probe));
+        assert_eq!(dav2.get_dpi().expect("failed to get DPI"), (dpi_x, dpi_y));
+    });
+}
+
+#[test]
+fn dpi_stages_round_trip() {
+    with_device(|dav2| {
+        let (stages, current) = dav2.get_dpi_stages().expect("failed to get stages");
+        assert!(!stages.is_empty());
+        assert!((current as usize) < stages.len());
+
+        dav2.set_dpi_stages(&stages, current).expect("failed to set stages");
+        assert_eq!(dav2.get_dpi_stages().expect("failed to get stages"),
+            (stages, current));
+    });
+}
+
+#[test]
+fn brightness_round_trip() {
+    with_device(|dav2| {
+        let logo = dav2.get_logo_brightness().expect("failed to get logo brightness");
+        let scroll = dav2.get_scroll_brightness().expect("failed to get scroll brightness");
+        assert!(logo <= 100 && scroll <= 100);
+
+        dav2.set_logo_brightness(logo).expect("failed to set logo brightness");
+        dav2.set_scroll_brightness(scroll).expect("failed to set scroll brightness");
+        assert_eq!(dav2.get_logo_brightness().expect("failed to get logo brightness"), logo);
+        assert_eq!(dav2.get_scroll_brightness().expect("failed to get scroll brightness"), scroll);
+    });
+}
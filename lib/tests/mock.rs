@@ -0,0 +1,299 @@
+//! Protocol tests against `MockTransport`; no hardware needed.
+
+use librazer::capabilities::{Capabilities, DpiRange};
+use librazer::commands;
+use librazer::buttons::{Button, ButtonFunction};
+use librazer::common::{Led, LiftOff, PollingRate, SidePlate, MAX_DPI_STAGES};
+use librazer::device::{ArgbController, BasiliskV2, BasiliskV3, Cobra, DeathAdderElite, DeathAdderEssential, DeathAdderV2, HuntsmanTE, NagaTrinity, OrochiV2, RazerArgb, RazerDevice,
+    RazerKeyboard, RazerMouse};
+use librazer::device::{USB_DEVICE_ID_RAZER_COBRA, USB_DEVICE_ID_RAZER_COBRA_PRO_WIRED,
+    USB_DEVICE_ID_RAZER_COBRA_PRO_WIRELESS};
+use librazer::error::{DpiStagesError, USBError};
+use librazer::hooks::BlockVarStore;
+use librazer::quirks::DONGLE_REPORT_INDEX;
+use librazer::mock::{MockRazerDevice, MockReply, MockTransport};
+use rgb::RGB8;
+
+fn dav2(transport: &MockTransport) -> DeathAdderV2 {
+    DeathAdderV2::with_transport(Box::new(transport.clone()))
+}
+
+#[test]
+fn dpi_is_read_from_the_response() {
+    let transport = MockTransport::new()
+        .expect(commands::GET_DPI_XY, MockReply::Args(vec![0x00, 0x03, 0x20, 0x06, 0x40]));
+    assert_eq!(dav2(&transport).get_dpi().unwrap(), (800, 1600));
+    assert_eq!(transport.remaining(), 0);
+}
+
+#[test]
+fn leftover_responses_are_skipped() {
+    // one for another command, one for this command but an earlier request
+    let transport = MockTransport::new()
+        .leftover(commands::GET_POLLING_RATE, MockReply::Args(vec![0x01]))
+        .leftover(commands::GET_DPI_XY, MockReply::Args(vec![0x00, 0x01, 0x90, 0x01, 0x90]))
+        .expect(commands::GET_DPI_XY, MockReply::Args(vec![0x00, 0x03, 0x20, 0x06, 0x40]));
+    assert_eq!(dav2(&transport).get_dpi().unwrap(), (800, 1600));
+}
+
+#[test]
+fn setter_sends_its_arguments() {
+    let transport = MockTransport::new()
+        .expect_args(commands::SET_POLLING_RATE, &[PollingRate::Hz1000 as u8], MockReply::Echo);
+    dav2(&transport).set_poll_rate(PollingRate::Hz1000).unwrap();
+    let sent = transport.sent();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].command(), commands::SET_POLLING_RATE);
+}
+
+#[test]
+fn unexpected_requests_fail() {
+    let transport = MockTransport::new()
+        .expect(commands::GET_DPI_XY, MockReply::Echo);
+    let device = dav2(&transport);
+    assert!(matches!(device.get_poll_rate(), Err(USBError::ReplayMismatch)));
+    device.get_dpi().unwrap();
+    assert!(matches!(device.get_dpi(), Err(USBError::ReplayExhausted)));
+}
+
+#[test]
+fn statuses_map_to_errors() {
+    let transport = MockTransport::new()
+        .expect(commands::GET_POLLING_RATE, MockReply::Failure)
+        .expect(commands::GET_POLLING_RATE, MockReply::NotSupported)
+        .expect(commands::GET_POLLING_RATE, MockReply::Timeout);
+    let device = dav2(&transport);
+    assert!(matches!(device.get_poll_rate(), Err(USBError::CommandFailed)));
+    assert!(matches!(device.get_poll_rate(), Err(USBError::CommandNotSupported)));
+    assert!(matches!(device.get_poll_rate(), Err(USBError::CommandTimeout)));
+}
+
+#[test]
+fn busy_device_gives_up() {
+    let transport = MockTransport::new()
+        .expect(commands::GET_SERIAL, MockReply::Busy);
+    let device = MockRazerDevice::new(0x0084, Capabilities::default(), transport);
+    assert!(matches!(device.get_serial(), Err(USBError::DeviceBusy)));
+}
+
+#[test]
+fn missing_capability_sends_nothing() {
+    let transport = MockTransport::new().otherwise(MockReply::Echo);
+    let device = MockRazerDevice::new(0x0084, Capabilities::default(), transport.clone());
+    assert!(matches!(device.set_liftoff(LiftOff::High), Err(USBError::Unsupported(_))));
+    assert!(transport.sent().is_empty());
+
+    let capabilities = Capabilities { liftoff: true, ..Default::default() };
+    let device = MockRazerDevice::new(0x0084, capabilities, transport.clone());
+    device.set_liftoff(LiftOff::High).unwrap();
+    assert_eq!(transport.sent()[0].command(), commands::SET_LIFTOFF);
+}
+
+#[test]
+fn defaults_fit_the_model() {
+    // 6400 is out of range and 3200 twice after fitting; three stages only
+    let stages = [0x00, 0x03, 0x03,
+        0x01, 0x01, 0x90, 0x01, 0x90, 0x00, 0x00,
+        0x02, 0x03, 0x20, 0x03, 0x20, 0x00, 0x00,
+        0x03, 0x06, 0x40, 0x06, 0x40, 0x00, 0x00];
+    let transport = MockTransport::new()
+        .expect_args(commands::SET_DPI_STAGES, &stages, MockReply::Echo)
+        .expect_args(commands::SET_POLLING_RATE, &[PollingRate::Hz500 as u8], MockReply::Echo)
+        .otherwise(MockReply::Echo);
+    let capabilities = Capabilities {
+        dpi: Some(DpiRange { min: 100, max: 3200, step: 50 }),
+        dpi_stages: 3,
+        polling_rates: vec![PollingRate::Hz125, PollingRate::Hz500],
+        ..Default::default()
+    };
+    let device = MockRazerDevice::new(0x0084, capabilities, transport.clone());
+    device.reset_to_defaults().unwrap();
+    assert_eq!(transport.remaining(), 0);
+}
+
+#[test]
+fn stage_count_is_checked_before_sending() {
+    let transport = MockTransport::new().otherwise(MockReply::Echo);
+    // more than fit in a report
+    let capabilities = Capabilities { dpi_stages: 20, ..Default::default() };
+    let device = MockRazerDevice::new(0x0084, capabilities, transport.clone());
+
+    let stages: Vec<(u16, u16)> = (1..=MAX_DPI_STAGES as u16 + 1)
+        .map(|i| (i * 100, i * 100))
+        .collect();
+    assert!(matches!(device.set_dpi_stages(&stages, 0),
+        Err(USBError::InvalidDpiStages(DpiStagesError::TooMany(MAX_DPI_STAGES)))));
+    assert!(matches!(device.set_dpi_stages(&[], 0),
+        Err(USBError::InvalidDpiStages(DpiStagesError::Empty))));
+    assert!(transport.sent().is_empty());
+
+    device.set_dpi_stages(&stages[..MAX_DPI_STAGES], 0).unwrap();
+    assert_eq!(transport.sent()[0].command(), commands::SET_DPI_STAGES);
+}
+
+#[test]
+fn preview_follows_the_quirks() {
+    let (logo, scroll) = (RGB8::new(0xff, 0, 0), RGB8::new(0, 0, 0xff));
+
+    let transport = MockTransport::new().otherwise(MockReply::Echo);
+    dav2(&transport).preview_static(logo, scroll).unwrap();
+    let sent = transport.sent();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].command(), commands::SET_NAGA_TRINITY_EFFECT);
+    assert_eq!(sent[0].transaction_id(), 0x3f);
+
+    // logo and underglow, no scroll wheel
+    let transport = MockTransport::new().otherwise(MockReply::Echo);
+    let cobra = Cobra::with_transport(USB_DEVICE_ID_RAZER_COBRA, Box::new(transport.clone()));
+    cobra.preview_static(logo, scroll).unwrap();
+    let sent = transport.sent();
+    assert_eq!(sent.len(), 2);
+    assert!(sent.iter().all(|request| request.command() == commands::SET_EXTENDED_MATRIX_EFFECT));
+    assert_eq!(sent[0].transaction_id(), 0x1f);
+}
+
+#[test]
+fn single_color_leds_take_the_classic_commands() {
+    let transport = MockTransport::new()
+        .expect_args(commands::SET_LED_BRIGHTNESS, &[0x01, Led::Logo as u8, 0xff], MockReply::Echo)
+        .expect_args(commands::SET_LED_STATE, &[0x01, Led::ScrollWheel as u8, 0x00], MockReply::Echo);
+    let essential = DeathAdderEssential::with_transport(Box::new(transport.clone()));
+    essential.set_led_brightness(Led::Logo, 100).unwrap();
+    essential.set_led_on(Led::ScrollWheel, false).unwrap();
+    assert!(matches!(essential.set_logo_color(RGB8::new(0xff, 0, 0)), Err(USBError::Unsupported(_))));
+    assert_eq!(transport.remaining(), 0);
+    assert_eq!(transport.sent().len(), 2);
+}
+
+#[test]
+fn block_var_store_covers_the_classic_commands() {
+    let transport = MockTransport::new().otherwise(MockReply::Echo);
+    let essential = DeathAdderEssential::with_transport(Box::new(transport.clone()));
+    essential.hooks().register(BlockVarStore);
+    assert!(matches!(essential.set_led_brightness(Led::Logo, 50), Err(USBError::Vetoed(_))));
+    assert!(matches!(essential.set_led_on(Led::Logo, false), Err(USBError::Vetoed(_))));
+    assert!(transport.sent().is_empty());
+    essential.preview_led_brightness(Led::Logo, 50).unwrap();
+    assert_eq!(transport.sent().len(), 1);
+}
+
+#[test]
+fn classic_colors_take_color_effect_and_state() {
+    let transport = MockTransport::new()
+        .expect_args(commands::SET_LED_RGB, &[0x01, Led::Logo as u8, 0xff, 0x00, 0x00], MockReply::Echo)
+        .expect_args(commands::SET_LED_EFFECT, &[0x01, Led::Logo as u8, 0x00], MockReply::Echo)
+        .expect_args(commands::SET_LED_STATE, &[0x01, Led::Logo as u8, 0x01], MockReply::Echo);
+    let elite = DeathAdderElite::with_transport(Box::new(transport.clone()));
+    elite.set_logo_color(RGB8::new(0xff, 0, 0)).unwrap();
+    assert_eq!(transport.remaining(), 0);
+
+    assert!(matches!(elite.set_reactive(Led::Logo, RGB8::new(0xff, 0, 0), 1),
+        Err(USBError::Unsupported(_))));
+    assert_eq!(transport.sent().len(), 3);
+}
+
+#[test]
+fn basilisks_have_their_own_zones() {
+    let transport = MockTransport::new().otherwise(MockReply::Echo);
+    let v2 = BasiliskV2::with_transport(Box::new(transport.clone()));
+    assert_eq!(v2.zones(), [Led::Logo, Led::ScrollWheel]);
+    assert!(matches!(v2.draw_frame(&[vec![RGB8::default(); 11]]), Err(USBError::Unsupported(_))));
+
+    let v3 = BasiliskV3::with_transport(Box::new(transport.clone()));
+    assert_eq!(v3.zones(), [Led::Logo, Led::ScrollWheel, Led::Backlight]);
+    assert!(matches!(v3.draw_frame(&[vec![RGB8::default(); 12]]), Err(USBError::Unsupported(_))));
+    assert!(transport.sent().is_empty());
+    v3.draw_frame(&[vec![RGB8::default(); 11]]).unwrap();
+    assert_eq!(transport.sent().last().unwrap().command(), commands::SET_EXTENDED_MATRIX_EFFECT);
+}
+
+#[test]
+fn naga_side_keys_follow_the_plate() {
+    let transport = MockTransport::new().otherwise(MockReply::Echo);
+    let naga = NagaTrinity::with_transport(Box::new(transport.clone()));
+    assert_eq!(naga.zones(), [Led::Logo, Led::ScrollWheel, Led::Backlight]);
+    assert_eq!(naga.capabilities().matrix, Some((1, 3)));
+    naga.set_button(Button::Side12, ButtonFunction::DpiUp).unwrap();
+    assert_eq!(transport.sent().len(), 1);
+
+    naga.set_side_plate(SidePlate::SevenButton);
+    assert_eq!(naga.zones(), [Led::Logo, Led::ScrollWheel]);
+    assert!(matches!(naga.set_button(Button::Side12, ButtonFunction::DpiUp),
+        Err(USBError::Unsupported(_))));
+    naga.set_side_plate(SidePlate::TwoButton);
+    assert!(naga.capabilities().buttons.contains(&Button::Back));
+    assert!(!naga.capabilities().buttons.contains(&Button::Side1));
+
+    assert!(matches!(dav2(&transport).set_button(Button::Side1, ButtonFunction::DpiUp),
+        Err(USBError::Unsupported(_))));
+    assert_eq!(transport.sent().len(), 1);
+}
+
+#[test]
+fn orochi_has_nothing_to_light() {
+    let transport = MockTransport::new().otherwise(MockReply::Echo);
+    let orochi = OrochiV2::with_transport(Box::new(transport.clone()));
+    assert!(orochi.zones().is_empty());
+    assert!(orochi.capabilities().battery);
+    assert!(matches!(orochi.set_logo_color(RGB8::new(0xff, 0, 0)), Err(USBError::Unsupported(_))));
+    assert!(matches!(orochi.set_led_brightness(Led::ScrollWheel, 50), Err(USBError::Unsupported(_))));
+    assert!(matches!(orochi.set_button(Button::Back, ButtonFunction::DpiUp),
+        Err(USBError::Unsupported(_))));
+    assert!(transport.sent().is_empty());
+}
+
+#[test]
+fn dongles_say_what_they_are_paired_with() {
+    let transport = MockTransport::new()
+        .expect(commands::GET_PAIRED_DEVICE, MockReply::Args(vec![0x01, 0x00, 0xAF]));
+    let cobra = Cobra::with_transport(USB_DEVICE_ID_RAZER_COBRA_PRO_WIRELESS,
+        Box::new(transport.clone()));
+    let pairing = cobra.get_pairing().unwrap();
+    assert_eq!(pairing.pid, USB_DEVICE_ID_RAZER_COBRA_PRO_WIRED);
+    assert!(pairing.connected);
+    assert_eq!(transport.sent_indexes(), [DONGLE_REPORT_INDEX]);
+
+    assert!(matches!(dav2(&transport).get_pairing(), Err(USBError::Unsupported(_))));
+    assert_eq!(transport.sent().len(), 1);
+}
+
+#[test]
+fn keyboards_draw_a_row_at_a_time() {
+    let transport = MockTransport::new().otherwise(MockReply::Echo);
+    let huntsman = HuntsmanTE::with_transport(Box::new(transport.clone()));
+    assert!(huntsman.as_keyboard().is_some());
+    assert!(huntsman.as_mouse().is_none());
+    huntsman.draw_row(2, &[RGB8::new(0xff, 0, 0); 18]).unwrap();
+    let sent = transport.sent();
+    assert_eq!(sent.len(), 2);
+    assert_eq!(sent[0].command(), commands::SET_EXTENDED_MATRIX_FRAME);
+    assert_eq!(sent[0].args()[2..5], [2, 0, 17]);
+    assert_eq!(sent[1].command(), commands::SET_EXTENDED_MATRIX_EFFECT);
+
+    assert!(matches!(huntsman.draw_row(6, &[RGB8::default(); 18]), Err(USBError::Unsupported(_))));
+    assert!(matches!(huntsman.draw_row(0, &[RGB8::default(); 19]), Err(USBError::Unsupported(_))));
+    assert_eq!(transport.sent().len(), 2);
+}
+
+#[test]
+fn argb_channels_are_rows_of_their_own() {
+    let transport = MockTransport::new()
+        .expect_args(commands::SET_ARGB_CHANNEL_SIZE, &[0x01, Led::ArgbCh2 as u8, 30], MockReply::Echo)
+        .otherwise(MockReply::Echo);
+    let controller = ArgbController::with_transport(Box::new(transport.clone()));
+    assert_eq!(controller.argb_channels(), [1, 2, 3, 4, 5, 6]);
+    controller.set_channel_size(2, 30).unwrap();
+    assert_eq!(transport.remaining(), 0);
+
+    // 30 LEDs take two reports, then the effect shows them
+    controller.draw_channel(2, &[RGB8::new(0, 0xff, 0); 30]).unwrap();
+    let sent = transport.sent();
+    assert_eq!(sent.len(), 4);
+    assert_eq!(sent[1].args()[2..5], [1, 0, 24]);
+    assert_eq!(sent[2].args()[2..5], [1, 25, 29]);
+    assert_eq!(sent[3].command(), commands::SET_EXTENDED_MATRIX_EFFECT);
+
+    assert!(matches!(controller.set_channel_color(7, RGB8::default()), Err(USBError::Unsupported(_))));
+    assert!(matches!(controller.set_channel_size(1, 81), Err(USBError::Unsupported(_))));
+    assert_eq!(transport.sent().len(), 4);
+}